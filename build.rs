@@ -0,0 +1,11 @@
+use std::error::Error;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    // the Node addon resolves napi symbols from the host process at load time
+    #[cfg(feature = "node")]
+    napi_build::setup();
+    // protoc must be on PATH, or named by PROTOC
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/nftimg.proto")?;
+    Ok(())
+}
@@ -0,0 +1,91 @@
+/* Generated with cbindgen --config cbindgen.toml --output include/nftimg.h */
+
+#ifndef NFTIMG_H
+#define NFTIMG_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Version of [`NftImgOptions`] this library understands.
+ */
+#define NFTIMG_OPTIONS_VERSION 1
+
+typedef enum NftImgStatus {
+  NFTIMG_STATUS_OK = 0,
+  NFTIMG_STATUS_INVALID_ARGUMENT = 1,
+  NFTIMG_STATUS_DECODE_FAILED = 2,
+  NFTIMG_STATUS_UNSUPPORTED_FORMAT = 3,
+  NFTIMG_STATUS_FAILED = 4,
+  NFTIMG_STATUS_PANIC = 5,
+} NftImgStatus;
+
+/**
+ * The most used parameters, plus an optional recipe for everything else.
+ * Fill with [`nftimg_options_default`] and change what is needed.
+ */
+typedef struct NftImgOptions {
+  /**
+   * Must be [`NFTIMG_OPTIONS_VERSION`]; fields are only ever appended.
+   */
+  uint32_t version;
+  double spatial_radius;
+  double color_radius;
+  int32_t max_pyramid_level;
+  int32_t threshold_block_size;
+  double threshold_c;
+  int32_t dilate_kernel_size;
+  int32_t dilate_iterations;
+  /**
+   * NUL-terminated `key=value` lines as written by
+   * `ConvertOptions::recipe`, applied instead of the fields above; NULL
+   * for none.
+   */
+  const char *recipe;
+} NftImgOptions;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Fills `options` with the library defaults.
+ */
+enum NftImgStatus nftimg_options_default(struct NftImgOptions *options);
+
+/**
+ * Converts the image at `path` (UTF-8) and writes the output next to it,
+ * like the command line tool.
+ */
+enum NftImgStatus nftimg_convert_file(const char *path, const struct NftImgOptions *options);
+
+/**
+ * Converts the encoded image in `data` and returns the output, encoded in
+ * the input's format, in `*output` / `*output_len`. Release it with
+ * [`nftimg_buffer_free`].
+ */
+enum NftImgStatus nftimg_convert_buffer(const uint8_t *data,
+                                        size_t len,
+                                        const struct NftImgOptions *options,
+                                        uint8_t **output,
+                                        size_t *output_len);
+
+/**
+ * Releases a buffer returned by [`nftimg_convert_buffer`].
+ */
+void nftimg_buffer_free(uint8_t *data, size_t len);
+
+/**
+ * Message of the last failed call on this thread, or NULL. Valid until
+ * the next call on the same thread.
+ */
+const char *nftimg_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* NFTIMG_H */
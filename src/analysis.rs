@@ -0,0 +1,236 @@
+use std::error::Error;
+use std::f64::consts::PI;
+
+use opencv::core::{
+    mean, mean_std_dev, no_array, norm, Point, Scalar, BORDER_DEFAULT, BORDER_REPLICATE, CV_32F, CV_64F, CV_8U, NORM_L1,
+};
+use opencv::imgproc::{cvt_color, filter_2d, laplacian, COLOR_BGR2GRAY, COLOR_BGR2HSV};
+use opencv::prelude::*;
+
+use crate::{AdjustOptions, ClaheOptions, ConvertOptions, Denoise, Iterations, NftImgError};
+
+/// Noise above this standard deviation, in gray levels, is denoised.
+const NOISY: f64 = 3.0;
+/// Most that noise raises `threshold_c` by.
+const NOISE_C_MAX: f64 = 6.0;
+/// Laplacian variance below which an image counts as soft.
+const SOFT: f64 = 100.0;
+/// Gray levels between the darkest and brightest percent below which an
+/// image counts as flat and is equalized.
+const LOW_RANGE: f64 = 120.0;
+/// Share of the three largest coarse colors above which an image counts as
+/// already flat, e.g. a graphic.
+const FEW_COLORS: f64 = 0.75;
+/// Mean luminance below which an image counts as dim and is equalized.
+const DIM: f64 = 70.0;
+/// Mean saturation below which an image counts as washed out.
+const WASHED_OUT: f64 = 0.2;
+/// Saturation factor washed-out images get on the output.
+const SATURATION_BOOST: f64 = 1.3;
+
+/// Exposure statistics of an image, see [`analyze`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ImageStats {
+    /// Pixels per luminance level, 256 levels of Rec. 601 luma as OpenCV
+    /// converts to gray.
+    pub histogram: Vec<u64>,
+    /// Percentage of pixels at luminance 0, crushed shadows.
+    pub shadows_clipped: f64,
+    /// Percentage of pixels at luminance 255, blown highlights.
+    pub highlights_clipped: f64,
+    /// Mean HSV saturation, 0 for a gray image to 1.
+    pub mean_saturation: f64,
+}
+
+impl ImageStats {
+    /// Mean luminance, 0 to 255.
+    pub fn mean_luminance(&self) -> f64 {
+        let (sum, pixels) = self
+            .histogram
+            .iter()
+            .enumerate()
+            .fold((0.0, 0u64), |(sum, pixels), (level, &n)| (sum + level as f64 * n as f64, pixels + n));
+        if pixels > 0 { sum / pixels as f64 } else { 0.0 }
+    }
+
+    /// Luminance below which a share `p`, 0 to 1, of the pixels lies.
+    pub fn percentile(&self, p: f64) -> u8 {
+        let pixels: u64 = self.histogram.iter().sum();
+        let target = (pixels as f64 * p.clamp(0.0, 1.0)) as u64;
+        let mut seen = 0;
+        let level = self.histogram.iter().position(|&n| {
+            seen += n;
+            seen > target
+        });
+        level.unwrap_or(255) as u8
+    }
+}
+
+/// Luminance histogram, clipping and mean saturation of an 8-bit BGR or
+/// grayscale image; the measurements `--auto` adapts to, for embedders
+/// building their own heuristics:
+///
+/// ```ignore
+/// let stats = nftimg::analyze(&image)?;
+/// if stats.highlights_clipped > 5.0 {
+///     options.adjust = Some(AdjustOptions { brightness: -0.1, ..AdjustOptions::default() });
+/// }
+/// ```
+pub fn analyze(image: &Mat) -> Result<ImageStats, Box<dyn Error>> {
+    if image.depth() != CV_8U {
+        return Err("expected an 8-bit image".into());
+    }
+    match image.channels() {
+        1 => stats(&image.try_clone()?, 0.0),
+        3 => {
+            let mut gray = Mat::default();
+            cvt_color(image, &mut gray, COLOR_BGR2GRAY, 0)?;
+            stats(&gray, mean_saturation(image)?)
+        }
+        found => Err(NftImgError::UnexpectedChannels { expected: 3, found }.into()),
+    }
+}
+
+/*
+ * Mean HSV saturation of a BGR image, 0 to 1
+ */
+fn mean_saturation(image: &Mat) -> Result<f64, Box<dyn Error>> {
+    let mut hsv = Mat::default();
+    cvt_color(image, &mut hsv, COLOR_BGR2HSV, 0)?;
+    Ok(mean(&hsv, &no_array())?[1] / 255.0)
+}
+
+/*
+ * ImageStats of a continuous gray image
+ */
+fn stats(gray: &Mat, mean_saturation: f64) -> Result<ImageStats, Box<dyn Error>> {
+    let mut histogram = vec![0u64; 256];
+    for &v in gray.data_bytes()? {
+        histogram[v as usize] += 1;
+    }
+    let percent = |n: u64| 100.0 * n as f64 / gray.total().max(1) as f64;
+    Ok(ImageStats {
+        shadows_clipped: percent(histogram[0]),
+        highlights_clipped: percent(histogram[255]),
+        histogram,
+        mean_saturation,
+    })
+}
+
+/*
+ * What auto mode measures on an input before choosing its parameters
+ */
+#[derive(Debug, Clone)]
+pub(crate) struct Analysis {
+    /// Standard deviation of the sensor noise in gray levels, Immerkær's
+    /// fast estimate.
+    pub noise: f64,
+    /// Variance of the Laplacian, low for soft or out-of-focus images.
+    pub sharpness: f64,
+    /// Gray levels between the darkest and the brightest percent of pixels.
+    pub dynamic_range: f64,
+    /// Coarse colors, 4 levels per channel, as `(#rrggbb, share)`, largest
+    /// share first.
+    pub dominant_colors: Vec<(String, f64)>,
+    pub stats: ImageStats,
+}
+
+/*
+ * Measures an 8-bit BGR image at its own resolution, the one the filters run
+ * at
+ */
+pub(crate) fn analyze_input(image: &Mat) -> Result<Analysis, Box<dyn Error>> {
+    let mut gray = Mat::default();
+    cvt_color(image, &mut gray, COLOR_BGR2GRAY, 0)?;
+    let (width, height) = (f64::from(gray.cols()), f64::from(gray.rows()));
+
+    // Immerkær 1996: the mask cancels image structure up to second order
+    let mask = Mat::from_slice_2d(&[[1.0f32, -2.0, 1.0], [-2.0, 4.0, -2.0], [1.0, -2.0, 1.0]])?;
+    let mut residual = Mat::default();
+    filter_2d(&gray, &mut residual, CV_32F, &mask, Point::new(-1, -1), 0.0, BORDER_REPLICATE)?;
+    let pixels = ((width - 2.0) * (height - 2.0)).max(1.0);
+    let noise = norm(&residual, NORM_L1, &no_array())? * (PI / 2.0).sqrt() / (6.0 * pixels);
+
+    let mut edges = Mat::default();
+    laplacian(&gray, &mut edges, CV_64F, 1, 1.0, 0.0, BORDER_DEFAULT)?;
+    let (mut mean, mut stddev) = (Scalar::default(), Scalar::default());
+    mean_std_dev(&edges, &mut mean, &mut stddev, &no_array())?;
+    let sharpness = stddev[0] * stddev[0];
+
+    let stats = stats(&gray, mean_saturation(image)?)?;
+    let dynamic_range = f64::from(stats.percentile(0.99)) - f64::from(stats.percentile(0.01));
+
+    let mut bins = [0usize; 64];
+    for px in image.data_bytes()?.chunks_exact(3) {
+        bins[(px[2] >> 6) as usize * 16 + (px[1] >> 6) as usize * 4 + (px[0] >> 6) as usize] += 1;
+    }
+    let total = image.total().max(1) as f64;
+    let mut dominant_colors: Vec<(String, f64)> = bins
+        .iter()
+        .enumerate()
+        .filter(|(_, n)| **n > 0)
+        .map(|(i, &n)| {
+            let center = |level: usize| (level * 64 + 32) as u8;
+            let hex = format!("#{:02x}{:02x}{:02x}", center(i / 16), center(i / 4 % 4), center(i % 4));
+            (hex, n as f64 / total)
+        })
+        .collect();
+    dominant_colors.sort_by(|a, b| b.1.total_cmp(&a.1));
+    dominant_colors.truncate(8);
+
+    Ok(Analysis { noise, sharpness, dynamic_range, dominant_colors, stats })
+}
+
+impl Analysis {
+    /*
+     * `options` adapted to the image: noisy images are denoised and get fewer
+     * speckle outlines, soft ones stronger outlines, flat or dim ones are
+     * equalized, washed-out ones more saturated and images of a few colors
+     * keep them apart. A denoiser, CLAHE or adjustment set explicitly is
+     * kept.
+     */
+    pub(crate) fn adapt(&self, options: &ConvertOptions) -> ConvertOptions {
+        let mut adapted = options.clone();
+        if self.noise > NOISY {
+            if adapted.denoise.is_none() {
+                let h = (self.noise * 1.5).clamp(4.0, 15.0) as f32;
+                adapted.denoise = Some(Denoise::NlMeans { h, h_color: h });
+            }
+            // what denoising leaves breaks into specks under a low C
+            adapted.threshold_c += (self.noise - NOISY).min(NOISE_C_MAX);
+        }
+        if self.sharpness < SOFT {
+            adapted.threshold_c *= (self.sharpness / SOFT).max(0.5);
+            let passes = adapted.dilate_iterations.map_or(0, Iterations::get) + 1;
+            if let Ok(iterations) = Iterations::new(passes) {
+                adapted.dilate_iterations = Some(iterations);
+            }
+        }
+        let dim = self.stats.mean_luminance() < DIM;
+        if (self.dynamic_range < LOW_RANGE || dim) && adapted.clahe.is_none() {
+            adapted.clahe = Some(ClaheOptions::default());
+        }
+        if self.stats.mean_saturation < WASHED_OUT && adapted.adjust.is_none() {
+            adapted.adjust = Some(AdjustOptions { saturation: SATURATION_BOOST, ..AdjustOptions::default() });
+        }
+        let few: f64 = self.dominant_colors.iter().take(3).map(|(_, share)| share).sum();
+        if few > FEW_COLORS {
+            adapted.color_radius = adapted.color_radius.scaled(0.6);
+        }
+        adapted
+    }
+
+    /*
+     * The measurements as a debug dump file name part
+     */
+    pub(crate) fn params(&self) -> String {
+        format!(
+            "noise{:.1}_sharpness{:.0}_range{:.0}_{}",
+            self.noise,
+            self.sharpness,
+            self.dynamic_range,
+            self.dominant_colors.first().map_or("", |(hex, _)| hex.trim_start_matches('#'))
+        )
+    }
+}
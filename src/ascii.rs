@@ -0,0 +1,146 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use opencv::core::{Point, Scalar, Size, CV_8UC3};
+use opencv::imgproc::{put_text, resize, FONT_HERSHEY_PLAIN, INTER_AREA, LINE_AA};
+use opencv::prelude::*;
+
+/// Glyphs from least to most ink.
+const RAMP: &[u8] = b" .:-=+*#%@";
+
+/// Where the ASCII rendering goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "kebab-case"))]
+pub enum AsciiMode {
+    /// Plain text next to the output, dark glyphs for dark cells.
+    #[default]
+    Text,
+    /// 24-bit ANSI colored text for a terminal, `cat` it to view.
+    Ansi,
+    /// Colored glyphs drawn on black, replacing the output image.
+    Image,
+}
+
+impl AsciiMode {
+    /// Extension of the text file written next to the output, if any.
+    pub(crate) fn extension(self) -> Option<&'static str> {
+        match self {
+            AsciiMode::Text => Some("txt"),
+            AsciiMode::Ansi => Some("ans"),
+            AsciiMode::Image => None,
+        }
+    }
+}
+
+impl fmt::Display for AsciiMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AsciiMode::Text => write!(f, "text"),
+            AsciiMode::Ansi => write!(f, "ansi"),
+            AsciiMode::Image => write!(f, "image"),
+        }
+    }
+}
+
+impl FromStr for AsciiMode {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(AsciiMode::Text),
+            "ansi" => Ok(AsciiMode::Ansi),
+            "image" => Ok(AsciiMode::Image),
+            _ => Err(format!("unknown ascii mode: {}", s).into()),
+        }
+    }
+}
+
+/// ASCII art renderer: one glyph per cell of the merged output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct AsciiOptions {
+    /// Glyphs per line; cells are twice as tall as wide to match the font.
+    pub columns: i32,
+    pub mode: AsciiMode,
+}
+
+impl Default for AsciiOptions {
+    fn default() -> Self {
+        AsciiOptions { columns: 100, mode: AsciiMode::Text }
+    }
+}
+
+/*
+ * BGR image -> mean color of each glyph cell, columns x rows
+ */
+fn cells(input: &Mat, columns: i32) -> Result<Mat, Box<dyn Error>> {
+    if columns < 1 {
+        return Err("ascii art needs at least one column".into());
+    }
+    let columns = columns.min(input.cols()).max(1);
+    let rows = (columns * input.rows() / input.cols() / 2).max(1);
+    let mut cells = Mat::default();
+    resize(input, &mut cells, Size::new(columns, rows), 0.0, 0.0, INTER_AREA)?;
+    Ok(cells)
+}
+
+/// Glyph for a BGR cell; `ink_is_light` picks the ramp direction.
+fn glyph(px: &[u8], ink_is_light: bool) -> u8 {
+    let luma = 0.114 * px[0] as f64 + 0.587 * px[1] as f64 + 0.299 * px[2] as f64;
+    let ink = if ink_is_light { luma / 255.0 } else { 1.0 - luma / 255.0 };
+    RAMP[(ink * (RAMP.len() - 1) as f64).round() as usize]
+}
+
+/*
+ * BGR image -> text, plain or with ANSI truecolor escapes
+ */
+pub(crate) fn to_text(input: &Mat, options: &AsciiOptions) -> Result<String, Box<dyn Error>> {
+    let cells = cells(input, options.columns)?;
+    let columns = cells.cols() as usize;
+    let mut text = String::new();
+    for row in cells.data_bytes()?.chunks_exact(columns * 3) {
+        for px in row.chunks_exact(3) {
+            match options.mode {
+                // terminals are dark: bright cells get the dense glyphs
+                AsciiMode::Ansi => {
+                    text.push_str(&format!("\x1b[38;2;{};{};{}m", px[2], px[1], px[0]));
+                    text.push(glyph(px, true) as char);
+                }
+                _ => text.push(glyph(px, false) as char),
+            }
+        }
+        if options.mode == AsciiMode::Ansi {
+            text.push_str("\x1b[0m");
+        }
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+/*
+ * BGR image -> BGR image of the same size, rendered as colored glyphs
+ */
+pub(crate) fn to_image(input: &Mat, options: &AsciiOptions) -> Result<Mat, Box<dyn Error>> {
+    let cells = cells(input, options.columns)?;
+    let cell_width = input.cols() as f64 / cells.cols() as f64;
+    let cell_height = input.rows() as f64 / cells.rows() as f64;
+    // Hershey plain glyphs are about 12 pixels tall at scale 1
+    let scale = cell_height / 12.0 * 0.8;
+
+    let mut output = Mat::new_rows_cols_with_default(input.rows(), input.cols(), CV_8UC3, Scalar::all(0.0))?;
+    let columns = cells.cols() as usize;
+    for (i, px) in cells.data_bytes()?.chunks_exact(3).enumerate() {
+        let c = glyph(px, true);
+        if c == b' ' {
+            continue;
+        }
+        let origin = Point::new(
+            ((i % columns) as f64 * cell_width) as i32,
+            ((i / columns + 1) as f64 * cell_height) as i32 - 1,
+        );
+        let color = Scalar::new(px[0] as f64, px[1] as f64, px[2] as f64, 0.0);
+        put_text(&mut output, &(c as char).to_string(), origin, FONT_HERSHEY_PLAIN, scale, color, 1, LINE_AA, false)?;
+    }
+    Ok(output)
+}
@@ -0,0 +1,54 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use opencv::prelude::*;
+
+use crate::metrics::stage_times;
+use crate::{convert_file, ConvertOptions};
+
+/// What converting one file of a batch produced.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ConvertOutcome {
+    pub input: PathBuf,
+    /// Where the output was written, named like the command line names it.
+    pub output: PathBuf,
+    pub width: i32,
+    pub height: i32,
+    pub elapsed: Duration,
+    /// Latency of each stage, in the order they ran.
+    pub stages: Vec<(&'static str, Duration)>,
+}
+
+/// Converts `paths` one at a time as the iterator is advanced, one item per
+/// path and in the same order, so huge lists never sit in memory and the
+/// caller can interleave its own work or stop early by dropping it. A
+/// failed file is an `Err` item, the following ones are still converted.
+///
+/// ```ignore
+/// for outcome in nftimg::convert_iter(["a.jpg", "b.png"], &options) {
+///     let outcome = outcome?;
+///     println!("{} -> {}", outcome.input.display(), outcome.output.display());
+/// }
+/// ```
+pub fn convert_iter<'a, P: AsRef<Path>>(
+    paths: impl IntoIterator<Item = P> + 'a,
+    options: &'a ConvertOptions,
+) -> impl Iterator<Item = Result<ConvertOutcome, Box<dyn Error>>> + 'a {
+    paths.into_iter().map(move |path| convert_outcome(path.as_ref(), options))
+}
+
+pub(crate) fn convert_outcome(input: &Path, options: &ConvertOptions) -> Result<ConvertOutcome, Box<dyn Error>> {
+    let started = Instant::now();
+    let (converted, stages) = stage_times(|| convert_file(input, options));
+    let converted = converted?;
+    Ok(ConvertOutcome {
+        input: input.to_path_buf(),
+        output: converted.output_path,
+        width: converted.output.cols(),
+        height: converted.output.rows(),
+        elapsed: started.elapsed(),
+        stages,
+    })
+}
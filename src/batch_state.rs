@@ -0,0 +1,169 @@
+use std::env;
+use std::error::Error;
+use std::path::Path;
+use std::process;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+
+use crate::collection::{convert_timed, inputs};
+use crate::ConvertOptions;
+
+/// Time after which an image claimed by a worker that never finished it is
+/// handed to another; longer than any conversion a --timeout would allow.
+const CLAIM_LEASE: Duration = Duration::from_secs(30 * 60);
+
+/// How long a worker waits for another to release the database.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(60);
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS images (
+    input TEXT PRIMARY KEY,             -- file name inside the folder
+    state TEXT NOT NULL DEFAULT 'pending', -- pending | processing | done | error
+    output TEXT,
+    recipe TEXT,                        -- parameters it was converted with
+    error TEXT,
+    worker TEXT,                        -- host:pid that claimed it last
+    claimed_at REAL,                    -- Unix seconds
+    updated_at REAL,
+    seconds REAL                        -- whole conversion
+);
+CREATE TABLE IF NOT EXISTS stages (
+    input TEXT NOT NULL REFERENCES images(input),
+    stage TEXT NOT NULL,
+    seconds REAL NOT NULL
+);
+CREATE INDEX IF NOT EXISTS images_state ON images(state);
+";
+
+/// Images per state in a batch database once [`convert_batch`] returns.
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct BatchCounts {
+    pub pending: usize,
+    /// Claimed by workers still running, or by ones that died within the lease.
+    pub processing: usize,
+    pub done: usize,
+    pub error: usize,
+}
+
+/// Converts the images of `dir` like [`convert_collection`](crate::convert_collection),
+/// keeping the state of each in the SQLite database at `db`, created if
+/// missing: its `images` table holds the state, output, recipe, error and
+/// time of every file, `stages` the time of each of its stages.
+///
+/// Images already done are skipped, so an interrupted batch resumes where it
+/// stopped, and any number of processes may share one database on a shared
+/// volume, each claiming the next pending image. A failing image is recorded
+/// as `error` and the batch goes on; set failed rows back to `pending` to
+/// retry them. No collection report is written, the database is the record.
+pub fn convert_batch(dir: &Path, db: &Path, options: &ConvertOptions) -> Result<BatchCounts, Box<dyn Error>> {
+    let mut db = Connection::open(db)?;
+    db.busy_timeout(BUSY_TIMEOUT)?;
+    db.execute_batch(SCHEMA)?;
+    register(&mut db, dir, options)?;
+
+    let worker = format!("{}:{}", env::var("HOSTNAME").unwrap_or_else(|_| "localhost".to_string()), process::id());
+    let recipe = options.recipe();
+    while let Some(name) = claim(&mut db, &worker)? {
+        let input = dir.join(&name);
+        let started = Instant::now();
+        let result = convert_timed(&input, options).and_then(|converted| {
+            converted.ok_or_else(|| format!("gave up after {:?}", options.timeout.unwrap_or_default()).into())
+        });
+        let seconds = started.elapsed().as_secs_f64();
+
+        let tx = db.transaction()?;
+        match &result {
+            Ok((converted, _)) => tx.execute(
+                "UPDATE images SET state = 'done', output = ?2, recipe = ?3, error = NULL, seconds = ?4, updated_at = ?5
+                 WHERE input = ?1",
+                params![name, converted.output_path.display().to_string(), recipe, seconds, now()],
+            )?,
+            Err(e) => {
+                eprintln!("warning: {} failed: {}", input.display(), e);
+                tx.execute(
+                    "UPDATE images SET state = 'error', output = NULL, recipe = ?2, error = ?3, seconds = ?4, updated_at = ?5
+                     WHERE input = ?1",
+                    params![name, recipe, e.to_string(), seconds, now()],
+                )?
+            }
+        };
+        tx.execute("DELETE FROM stages WHERE input = ?1", [&name])?;
+        for &(stage, elapsed) in result.iter().flat_map(|(_, times)| times) {
+            tx.execute(
+                "INSERT INTO stages (input, stage, seconds) VALUES (?1, ?2, ?3)",
+                params![name, stage, elapsed.as_secs_f64()],
+            )?;
+        }
+        tx.commit()?;
+    }
+    counts(&db)
+}
+
+/*
+ * Adds the images of `dir` not yet in the database as pending
+ */
+fn register(db: &mut Connection, dir: &Path, options: &ConvertOptions) -> Result<(), Box<dyn Error>> {
+    let tx = db.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    {
+        let mut insert = tx.prepare("INSERT OR IGNORE INTO images (input) VALUES (?1)")?;
+        for input in inputs(dir, options)? {
+            // names, not paths: workers may mount the folder elsewhere
+            let Some(name) = input.file_name().and_then(|name| name.to_str()) else {
+                eprintln!("warning: skipped {}, not a UTF-8 name", input.display());
+                continue;
+            };
+            insert.execute([name])?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/*
+ * Marks the next pending image, or one whose claim lapsed, as processing by
+ * `worker`; none once the batch is through
+ */
+fn claim(db: &mut Connection, worker: &str) -> rusqlite::Result<Option<String>> {
+    // immediate: two workers must not both read the same row as pending
+    let tx = db.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let now = now();
+    let name: Option<String> = tx
+        .query_row(
+            "SELECT input FROM images
+             WHERE state = 'pending' OR (state = 'processing' AND claimed_at < ?1)
+             ORDER BY input LIMIT 1",
+            [now - CLAIM_LEASE.as_secs_f64()],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(name) = &name {
+        tx.execute(
+            "UPDATE images SET state = 'processing', worker = ?2, claimed_at = ?3, updated_at = ?3 WHERE input = ?1",
+            params![name, worker, now],
+        )?;
+    }
+    tx.commit()?;
+    Ok(name)
+}
+
+fn counts(db: &Connection) -> Result<BatchCounts, Box<dyn Error>> {
+    let mut counts = BatchCounts::default();
+    let mut query = db.prepare("SELECT state, COUNT(*) FROM images GROUP BY state")?;
+    let mut rows = query.query([])?;
+    while let Some(row) = rows.next()? {
+        let count = row.get::<_, i64>(1)? as usize;
+        match row.get::<_, String>(0)?.as_str() {
+            "pending" => counts.pending = count,
+            "processing" => counts.processing = count,
+            "done" => counts.done = count,
+            _ => counts.error += count,
+        }
+    }
+    Ok(counts)
+}
+
+fn now() -> f64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64()
+}
@@ -0,0 +1,279 @@
+use std::error::Error;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use eframe::emath::Numeric;
+use eframe::egui::{self, Color32, ColorImage, Pos2, Rect, Sense, Stroke, TextureHandle, TextureOptions};
+use opencv::core::{Size, Vector};
+use opencv::imgcodecs::{imdecode, IMREAD_COLOR};
+use opencv::imgproc::{resize, INTER_AREA};
+use opencv::prelude::*;
+
+use nftimg::{convert_buffer, convert_image, output_path, ConvertOptions, Iterations};
+
+/*
+ * nftimg-gui (feature "gui")
+ *   open an image, tune the main parameters with live sliders and compare
+ *   the result with the input in a split view; Export converts the full
+ *   resolution image like the command line does
+ */
+
+/// Longer side of the image the sliders are previewed on, in pixels.
+const PREVIEW_SIDE: i32 = 1024;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let native = eframe::NativeOptions::default();
+    eframe::run_native("nftimg", native, Box::new(|_| Ok(Box::<Gui>::default())))?;
+    Ok(())
+}
+
+/// The opened image.
+struct Opened {
+    path: PathBuf,
+    bytes: Vec<u8>,
+    /// Decoded and reduced to the preview size.
+    preview: Mat,
+    before: TextureHandle,
+    after: Option<TextureHandle>,
+}
+
+struct Gui {
+    options: ConvertOptions,
+    opened: Option<Opened>,
+    /// Conversion of the preview in flight.
+    pending: Option<Receiver<Result<Mat, String>>>,
+    /// Options changed since the conversion in flight started.
+    stale: bool,
+    /// Fraction of the width showing the input.
+    split: f32,
+    status: String,
+}
+
+impl Default for Gui {
+    fn default() -> Self {
+        Gui {
+            options: ConvertOptions::default(),
+            opened: None,
+            pending: None,
+            stale: false,
+            split: 0.5,
+            status: "open an image to start".to_string(),
+        }
+    }
+}
+
+impl eframe::App for Gui {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.receive(ctx);
+        egui::SidePanel::left("parameters").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Open…").clicked() {
+                    self.open(ctx);
+                }
+                if ui.add_enabled(self.opened.is_some(), egui::Button::new("Export…")).clicked() {
+                    self.export();
+                }
+            });
+            ui.separator();
+            if self.sliders(ui) {
+                self.stale = true;
+            }
+            ui.separator();
+            ui.label(&self.status);
+        });
+        egui::CentralPanel::default().show(ctx, |ui| self.compare(ui));
+        if self.stale && self.pending.is_none() {
+            self.start_preview();
+        }
+        if self.pending.is_some() {
+            ctx.request_repaint();
+        }
+    }
+}
+
+impl Gui {
+    /*
+     * Slider per main parameter; true if one moved
+     */
+    fn sliders(&mut self, ui: &mut egui::Ui) -> bool {
+        let options = &mut self.options;
+        let mut changed = false;
+        ui.heading("Base");
+        changed |= typed_slider(ui, &mut options.spatial_radius, 1.0..=50.0, 0.0, "spatial radius");
+        changed |= typed_slider(ui, &mut options.color_radius, 1.0..=100.0, 0.0, "color radius");
+        changed |= ui.add(egui::Slider::new(&mut options.max_pyramid_level, 0..=4).text("pyramid levels")).changed();
+        ui.heading("Outlines");
+        changed |= typed_slider(ui, &mut options.diffusion_iterations, 1..=50, 0.0, "diffusion");
+        changed |= typed_slider(ui, &mut options.threshold_block_size, 3..=51, 2.0, "block size");
+        changed |= ui.add(egui::Slider::new(&mut options.threshold_c, 0.0..=30.0).text("threshold C")).changed();
+        changed |= ui.add(egui::Slider::new(&mut options.dilate_kernel_size, 1..=9).text("line width")).changed();
+        let mut passes = options.dilate_iterations.map_or(0, Iterations::get);
+        if ui.add(egui::Slider::new(&mut passes, 0..=4).text("line passes")).changed() {
+            if let Ok(iterations) = Iterations::optional(passes.into()) {
+                options.dilate_iterations = iterations;
+                changed = true;
+            }
+        }
+        changed |= ui.add(egui::Slider::new(&mut options.min_edge_area, 0.0..=50.0).text("min edge area")).changed();
+        if ui.button("Reset").clicked() {
+            *options = ConvertOptions::default();
+            changed = true;
+        }
+        changed
+    }
+
+    /*
+     * Input and output in one rectangle, split where the divider is dragged
+     */
+    fn compare(&mut self, ui: &mut egui::Ui) {
+        let Some(opened) = &self.opened else {
+            ui.centered_and_justified(|ui| ui.label("no image"));
+            return;
+        };
+        let [width, height] = opened.before.size().map(|side| side as f32);
+        let scale = (ui.available_width() / width).min(ui.available_height() / height);
+        let (rect, response) = ui.allocate_exact_size(egui::vec2(width * scale, height * scale), Sense::drag());
+        if let Some(pointer) = response.interact_pointer_pos() {
+            self.split = ((pointer.x - rect.left()) / rect.width()).clamp(0.0, 1.0);
+        }
+        let divider = rect.left() + rect.width() * self.split;
+        let full = Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0));
+        let before = Rect::from_min_max(rect.min, Pos2::new(divider, rect.bottom()));
+        ui.painter().with_clip_rect(before).image(opened.before.id(), rect, full, Color32::WHITE);
+        if let Some(after) = &opened.after {
+            let right = Rect::from_min_max(Pos2::new(divider, rect.top()), rect.max);
+            ui.painter().with_clip_rect(right).image(after.id(), rect, full, Color32::WHITE);
+        }
+        let line = [Pos2::new(divider, rect.top()), Pos2::new(divider, rect.bottom())];
+        ui.painter().line_segment(line, Stroke::new(2.0, Color32::WHITE));
+    }
+
+    fn open(&mut self, ctx: &egui::Context) {
+        let picked = rfd::FileDialog::new()
+            .add_filter("images", &["jpg", "jpeg", "png", "bmp", "webp", "tif", "tiff"])
+            .pick_file();
+        let Some(path) = picked else { return };
+        match load_preview(&path) {
+            Ok((bytes, preview)) => match texture(ctx, "before", &preview) {
+                Ok(before) => {
+                    self.status = path.display().to_string();
+                    self.opened = Some(Opened { path, bytes, preview, before, after: None });
+                    self.stale = true;
+                }
+                Err(e) => self.status = format!("error: {}", e),
+            },
+            Err(e) => self.status = format!("error: {}", e),
+        }
+    }
+
+    /*
+     * Converts the preview on a background thread, the window stays live
+     */
+    fn start_preview(&mut self) {
+        self.stale = false;
+        let Some(opened) = &self.opened else { return };
+        let (preview, options) = match opened.preview.try_clone() {
+            Ok(preview) => (preview, self.options.clone()),
+            Err(e) => {
+                self.status = format!("error: {}", e);
+                return;
+            }
+        };
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(convert_image(&preview, &options).map_err(|e| e.to_string()));
+        });
+        self.pending = Some(receiver);
+        self.status = "converting…".to_string();
+    }
+
+    fn receive(&mut self, ctx: &egui::Context) {
+        let Some(result) = self.pending.as_ref().and_then(|pending| pending.try_recv().ok()) else { return };
+        self.pending = None;
+        let after = result.map_err(|e| e.into()).and_then(|output| texture(ctx, "after", &output));
+        match (after, &mut self.opened) {
+            (Ok(after), Some(opened)) => {
+                opened.after = Some(after);
+                self.status = opened.path.display().to_string();
+            }
+            (Err(e), _) => self.status = format!("error: {}", e),
+            (Ok(_), None) => {}
+        }
+    }
+
+    /*
+     * Full resolution conversion, in the format of the input
+     */
+    fn export(&mut self) {
+        let Some(opened) = &self.opened else { return };
+        let mut dialog = rfd::FileDialog::new();
+        // no suggestion that would overwrite the input
+        if let Ok(suggested) = output_path(&opened.path, &self.options.output_suffix) {
+            if let Some(name) = suggested.file_name() {
+                dialog = dialog.set_file_name(name.to_string_lossy());
+            }
+            if let Some(dir) = suggested.parent() {
+                dialog = dialog.set_directory(dir);
+            }
+        }
+        let Some(path) = dialog.save_file() else { return };
+        let written = convert_buffer(&opened.bytes, &self.options).and_then(|output| Ok(fs::write(&path, output)?));
+        self.status = match written {
+            Ok(()) => format!("exported {}", path.display()),
+            Err(e) => format!("error: {}", e),
+        };
+    }
+}
+
+/*
+ * Slider over the number a parameter type holds; true if it moved to a
+ * value the type accepts, others leave the parameter as it was
+ */
+fn typed_slider<T, P>(ui: &mut egui::Ui, value: &mut P, range: RangeInclusive<T>, step: f64, text: &str) -> bool
+where
+    T: Numeric,
+    P: Copy + Into<T> + TryFrom<T>,
+{
+    let mut number: T = (*value).into();
+    if !ui.add(egui::Slider::new(&mut number, range).step_by(step).text(text)).changed() {
+        return false;
+    }
+    match P::try_from(number) {
+        Ok(changed) => {
+            *value = changed;
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/*
+ * image file -> (its bytes, decoded BGR image no larger than the preview)
+ */
+fn load_preview(path: &Path) -> Result<(Vec<u8>, Mat), Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    let image = imdecode(&Vector::from_slice(&bytes), IMREAD_COLOR)?;
+    if image.empty() {
+        return Err(format!("failed to decode image {}", path.display()).into());
+    }
+    let longer = image.cols().max(image.rows());
+    if longer <= PREVIEW_SIDE {
+        return Ok((bytes, image));
+    }
+    let scale = PREVIEW_SIDE as f64 / longer as f64;
+    let mut preview = Mat::default();
+    resize(&image, &mut preview, Size::default(), scale, scale, INTER_AREA)?;
+    Ok((bytes, preview))
+}
+
+/*
+ * BGR image -> texture
+ */
+fn texture(ctx: &egui::Context, name: &str, image: &Mat) -> Result<TextureHandle, Box<dyn Error>> {
+    let rgb: Vec<u8> = image.data_bytes()?.chunks_exact(3).flat_map(|px| [px[2], px[1], px[0]]).collect();
+    let size = [image.cols() as usize, image.rows() as usize];
+    Ok(ctx.load_texture(name, ColorImage::from_rgb(size, &rgb), TextureOptions::LINEAR))
+}
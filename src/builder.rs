@@ -0,0 +1,177 @@
+use std::path::PathBuf;
+
+use crate::edges::EdgeDetector;
+use crate::error::NftImgError;
+use crate::params::{BlockSize, Iterations, Radius};
+use crate::smoothing::Smoothing;
+use crate::ConvertOptions;
+
+/// Builds [`ConvertOptions`] from the defaults and checks them once, in
+/// [`build`](ConvertOptionsBuilder::build), so an even block size or a zero
+/// radius is reported by name instead of asserting inside OpenCV:
+///
+/// ```ignore
+/// let options = ConvertOptions::builder().spatial_radius(15.0).threshold_block_size(11).build()?;
+/// ```
+///
+/// Options without a setter are changed with [`with`](ConvertOptionsBuilder::with).
+#[derive(Debug, Default)]
+pub struct ConvertOptionsBuilder {
+    options: ConvertOptions,
+    /// First setter given an out of range value.
+    error: Option<NftImgError>,
+}
+
+impl ConvertOptions {
+    pub fn builder() -> ConvertOptionsBuilder {
+        ConvertOptionsBuilder::default()
+    }
+}
+
+impl From<ConvertOptions> for ConvertOptionsBuilder {
+    /// Starts from `options` instead of the defaults, e.g. a parsed recipe.
+    fn from(options: ConvertOptions) -> Self {
+        ConvertOptionsBuilder { options, error: None }
+    }
+}
+
+impl ConvertOptionsBuilder {
+    pub fn smoothing(mut self, smoothing: Smoothing) -> Self {
+        self.options.smoothing = smoothing;
+        self
+    }
+
+    /// Mean-shift spatial window radius, see [`Radius`].
+    pub fn spatial_radius(mut self, radius: f64) -> Self {
+        if let Some(radius) = self.checked("spatial_radius", Radius::new(radius)) {
+            self.options.spatial_radius = radius;
+        }
+        self
+    }
+
+    /// Mean-shift color window radius, see [`Radius`].
+    pub fn color_radius(mut self, radius: f64) -> Self {
+        if let Some(radius) = self.checked("color_radius", Radius::new(radius)) {
+            self.options.color_radius = radius;
+        }
+        self
+    }
+
+    /// Mean-shift pyramid depth, 0 to 8.
+    pub fn max_pyramid_level(mut self, level: i32) -> Self {
+        self.options.max_pyramid_level = level;
+        self
+    }
+
+    /// Anisotropic diffusion time step and conductance, both positive.
+    pub fn diffusion(mut self, alpha: f32, k: f32) -> Self {
+        self.options.diffusion_alpha = alpha;
+        self.options.diffusion_k = k;
+        self
+    }
+
+    /// Anisotropic diffusion passes, 1 to 1000.
+    pub fn diffusion_iterations(mut self, iterations: u32) -> Self {
+        if let Some(iterations) = self.checked("diffusion_iterations", Iterations::new(iterations)) {
+            self.options.diffusion_iterations = iterations;
+        }
+        self
+    }
+
+    pub fn edge_detector(mut self, detector: EdgeDetector) -> Self {
+        self.options.edge_detector = detector;
+        self
+    }
+
+    /// Adaptive threshold neighbourhood, see [`BlockSize`].
+    pub fn threshold_block_size(mut self, size: u16) -> Self {
+        if let Some(size) = self.checked("threshold_block_size", BlockSize::new(size)) {
+            self.options.threshold_block_size = size;
+        }
+        self
+    }
+
+    pub fn threshold_c(mut self, c: f64) -> Self {
+        self.options.threshold_c = c;
+        self
+    }
+
+    /// Pyramid levels the outlines are detected on, 1 to 8.
+    pub fn edge_scales(mut self, scales: i32) -> Self {
+        self.options.edge_scales = scales;
+        self
+    }
+
+    /// Side of the dilation kernel, at least 1.
+    pub fn dilate_kernel_size(mut self, size: i32) -> Self {
+        self.options.dilate_kernel_size = size;
+        self
+    }
+
+    /// Dilation passes, 0 to 1000; 0 leaves the outlines as detected.
+    pub fn dilate_iterations(mut self, iterations: u32) -> Self {
+        if let Some(iterations) = self.checked("dilate_iterations", Iterations::optional(iterations.into())) {
+            self.options.dilate_iterations = iterations;
+        }
+        self
+    }
+
+    /// Smallest outline component kept, in pixels; not negative.
+    pub fn min_edge_area(mut self, area: f64) -> Self {
+        self.options.min_edge_area = area;
+        self
+    }
+
+    pub fn output_suffix(mut self, suffix: impl Into<String>) -> Self {
+        self.options.output_suffix = suffix.into();
+        self
+    }
+
+    pub fn output_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.output_dir = Some(dir.into());
+        self
+    }
+
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.options.deterministic = deterministic;
+        self
+    }
+
+    /// Adapts the parameters to the measured noise, sharpness, range and
+    /// colors of each input.
+    pub fn auto(mut self, auto: bool) -> Self {
+        self.options.auto = auto;
+        self
+    }
+
+    /// Changes any other option, e.g.
+    /// `.with(|options| options.posterize = Some(PosterizeOptions::default()))`;
+    /// validated by `build` like the rest.
+    pub fn with(mut self, change: impl FnOnce(&mut ConvertOptions)) -> Self {
+        change(&mut self.options);
+        self
+    }
+
+    /// The options, or the first out of range, see [`ConvertOptions::validate`].
+    pub fn build(self) -> Result<ConvertOptions, NftImgError> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        self.options.validate()?;
+        Ok(self.options)
+    }
+
+    /*
+     * value or none, keeping the first failure under the option's name for
+     * build
+     */
+    fn checked<T>(&mut self, name: &'static str, value: Result<T, NftImgError>) -> Option<T> {
+        let e = match value {
+            Ok(value) => return Some(value),
+            Err(NftImgError::InvalidOption { value, expected, .. }) => NftImgError::InvalidOption { name, value, expected },
+            Err(e) => e,
+        };
+        self.error.get_or_insert(e);
+        None
+    }
+}
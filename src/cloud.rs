@@ -0,0 +1,210 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use object_store::aws::AmazonS3Builder;
+use object_store::gcp::GoogleCloudStorageBuilder;
+use object_store::path::Path as ObjectPath;
+use object_store::ObjectStore;
+use tokio::runtime::Runtime;
+
+use crate::metadata::sniff_format;
+use crate::naming::{self, is_output, output_path};
+use crate::{convert_buffer, ConvertOptions, NftImgError};
+
+/// Bytes of an object fetched to tell whether it is an image, as many as
+/// folders read of a file.
+const SNIFF_BYTES: usize = 16;
+
+/// Whether `input` is an `s3://` or `gs://` object URI rather than a path.
+pub fn is_object_uri(input: &str) -> bool {
+    input.starts_with("s3://") || input.starts_with("gs://")
+}
+
+/// An object, or a prefix when the key is empty or ends in `/`.
+#[derive(Clone, Copy)]
+struct ObjectUri<'a> {
+    scheme: &'a str,
+    bucket: &'a str,
+    key: &'a str,
+}
+
+impl<'a> ObjectUri<'a> {
+    fn parse(uri: &'a str) -> Result<ObjectUri<'a>, Box<dyn Error>> {
+        let (scheme, rest) = uri
+            .split_once("://")
+            .filter(|_| is_object_uri(uri))
+            .ok_or_else(|| format!("not an s3:// or gs:// URI: {}", uri))?;
+        let (bucket, key) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Err(format!("no bucket in {}", uri).into());
+        }
+        Ok(ObjectUri { scheme, bucket, key })
+    }
+
+    fn is_prefix(&self) -> bool {
+        self.key.is_empty() || self.key.ends_with('/')
+    }
+
+    /*
+     * Credentials and region come from the standard environment:
+     * AWS_ACCESS_KEY_ID, AWS_REGION, ... or GOOGLE_SERVICE_ACCOUNT and friends
+     */
+    fn store(&self) -> Result<Box<dyn ObjectStore>, Box<dyn Error>> {
+        Ok(match self.scheme {
+            "s3" => Box::new(AmazonS3Builder::from_env().with_bucket_name(self.bucket).build()?),
+            _ => Box::new(GoogleCloudStorageBuilder::from_env().with_bucket_name(self.bucket).build()?),
+        })
+    }
+
+    fn name(&self) -> &'a str {
+        self.key.rsplit('/').next().unwrap_or_default()
+    }
+
+    /*
+     * Key of `name` inside this prefix, or inside the object's "directory"
+     */
+    fn child(&self, name: &str) -> String {
+        format!("{}{}{}", self.key, if self.is_prefix() { "" } else { "/" }, name)
+    }
+
+    fn with_key(&self, key: &str) -> String {
+        format!("{}://{}/{}", self.scheme, self.bucket, key)
+    }
+}
+
+/*
+ * Output name for an input named `name`, as for local files; inputs without
+ * an extension keep their format, which `convert_buffer` encodes in
+ */
+fn output_name(name: &str, format: &str, suffix: &str) -> Result<String, NftImgError> {
+    let name = match Path::new(name).extension() {
+        Some(_) => name.to_string(),
+        None => format!("{}.{}", name, if format == "jpeg" { "jpg" } else { format }),
+    };
+    Ok(output_path(Path::new(&name), suffix)?.to_string_lossy().into_owned())
+}
+
+/*
+ * Writes `bytes` as `name` into the `output` prefix, the output directory of
+ * `options`, or else next to the input, returning where it went
+ */
+async fn write_output(
+    input: &ObjectUri<'_>,
+    name: &str,
+    bytes: Vec<u8>,
+    output: Option<&str>,
+    options: &ConvertOptions,
+) -> Result<String, Box<dyn Error>> {
+    let target = match (output, &options.output_dir) {
+        (Some(prefix), _) => ObjectUri::parse(prefix)?,
+        (None, Some(dir)) => {
+            let path = dir.join(name);
+            naming::write_atomic(&path, &bytes)?;
+            return Ok(path.display().to_string());
+        }
+        (None, None) => ObjectUri { key: &input.key[..input.key.len() - input.name().len()], ..*input },
+    };
+    let key = target.child(name);
+    target.store()?.put(&ObjectPath::from(key.as_str()), bytes.into()).await?;
+    Ok(target.with_key(&key))
+}
+
+/// Converts the image object at an `s3://` or `gs://` URI, or every image
+/// directly under it when it ends in `/`, and returns where the outputs
+/// went.
+///
+/// Outputs are written under the `output` URI prefix when given, into
+/// `options.output_dir`, or else next to their inputs, named as local
+/// outputs are. Credentials come from the standard AWS or Google Cloud
+/// environment variables. Prefixes skip earlier outputs like folders do, but
+/// produce no collection report; an object that fails does not stop the
+/// others, and the error names how many failed.
+pub fn convert_objects(input: &str, output: Option<&str>, options: &ConvertOptions) -> Result<Vec<String>, Box<dyn Error>> {
+    let uri = ObjectUri::parse(input)?;
+    if let Some(dir) = &options.output_dir {
+        naming::prepare_output_dir(dir)?;
+    }
+    Runtime::new()?.block_on(async {
+        let store = uri.store()?;
+        // sizes from the listing let headers be sniffed before downloading
+        let objects = if uri.is_prefix() {
+            let prefix = ObjectPath::from(uri.key);
+            let listing = store.list_with_delimiter(Some(&prefix)).await?;
+            let mut objects: Vec<(String, Option<usize>)> =
+                listing.objects.into_iter().map(|object| (object.location.to_string(), Some(object.size))).collect();
+            objects.retain(|(key, _)| options.restyle_outputs || !is_output(Path::new(key), &options.output_suffix));
+            objects.sort();
+            objects
+        } else {
+            vec![(uri.key.to_string(), None)]
+        };
+
+        let mut outputs = Vec::with_capacity(objects.len());
+        let mut failed = Vec::new();
+        for (key, size) in &objects {
+            let object = ObjectUri { key: key.as_str(), ..uri };
+            match convert_object(store.as_ref(), &object, *size, output, options).await {
+                Ok(Some(written)) => outputs.push(written),
+                // by content, as in folders: sidecars and mislabeled objects are skipped
+                Ok(None) if uri.is_prefix() => {}
+                Ok(None) => return Err(format!("{} is not a JPEG, PNG, BMP, WebP or TIFF image", input).into()),
+                // one unreadable or corrupt object does not stop the prefix
+                Err(e) if uri.is_prefix() => {
+                    eprintln!("warning: failed to convert {}: {}", uri.with_key(key), e);
+                    failed.push(uri.with_key(key));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        if let Some(first) = failed.first() {
+            let total = failed.len() + outputs.len();
+            return Err(format!("{} of {} objects failed, first {}", failed.len(), total, first).into());
+        }
+        Ok(outputs)
+    })
+}
+
+/*
+ * Converts one object and writes its output, none when it is no image;
+ * with its `size` known, a header is fetched to tell before the whole
+ * object is
+ */
+async fn convert_object(
+    store: &dyn ObjectStore,
+    object: &ObjectUri<'_>,
+    size: Option<usize>,
+    output: Option<&str>,
+    options: &ConvertOptions,
+) -> Result<Option<String>, Box<dyn Error>> {
+    let path = ObjectPath::from(object.key);
+    if let Some(size) = size {
+        if size == 0 || sniff_format(&store.get_range(&path, 0..size.min(SNIFF_BYTES)).await?).is_none() {
+            return Ok(None);
+        }
+    }
+    let bytes = store.get(&path).await?.bytes().await?;
+    let Some(format) = sniff_format(&bytes) else {
+        return Ok(None);
+    };
+    let converted = convert_buffer(&bytes, options)?;
+    let name = output_name(object.name(), format, &options.output_suffix)?;
+    Ok(Some(write_output(object, &name, converted, output, options).await?))
+}
+
+/// Converts a local image and uploads the output under the `output` URI
+/// prefix, returning its URI.
+pub fn convert_to_object(input: &Path, output: &str, options: &ConvertOptions) -> Result<String, Box<dyn Error>> {
+    let target = ObjectUri::parse(output)?;
+    let bytes = fs::read(input)?;
+    let format = sniff_format(&bytes)
+        .ok_or_else(|| format!("{} is not a JPEG, PNG, BMP, WebP or TIFF image", input.display()))?;
+    let converted = convert_buffer(&bytes, options)?;
+    let name = input
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| format!("no UTF-8 file name in {}", input.display()))?;
+    let key = target.child(&output_name(name, format, &options.output_suffix)?);
+    Runtime::new()?.block_on(target.store()?.put(&ObjectPath::from(key.as_str()), converted.into()))?;
+    Ok(target.with_key(&key))
+}
@@ -0,0 +1,605 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use opencv::core::{count_non_zero, Size};
+use opencv::imgproc::{cvt_color, resize, COLOR_BGR2GRAY, INTER_AREA};
+use opencv::prelude::*;
+
+use crate::metadata::sniff_format;
+use crate::metrics::{stage_times, StageTimes};
+use crate::naming::{is_abandoned, is_output, is_partial, write_atomic};
+#[cfg(feature = "quality")]
+use crate::quality::score_quality;
+#[cfg(feature = "webhook")]
+use crate::webhook::{self, Output};
+use crate::{convert_file, ConvertOptions, Converted};
+
+const REPORT_NAME: &str = "nftimg-report";
+
+/// Per-image entry of a [`CollectionReport`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ImageReport {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub width: i32,
+    pub height: i32,
+    /// Coarse palette of the output as `(#rrggbb, share)`, largest share first.
+    /// Outline pixels are not counted.
+    pub palette: Vec<(String, f64)>,
+    /// Share of the output covered by outlines.
+    pub edge_density: f64,
+    /// `(trait, value)` pairs derived from the output.
+    pub traits: Vec<(&'static str, String)>,
+    /// Sum over traits of `images / images sharing that value`.
+    pub rarity: f64,
+    /// 64-bit average hash of the output.
+    pub hash: u64,
+    /// Seconds spent in each stage, in the order they ran.
+    pub stages: Vec<(&'static str, f64)>,
+    /// BRISQUE score of the output, lower is better; only with quality
+    /// options.
+    pub quality: Option<f64>,
+    /// Why the output could not be scored; the image is reported regardless.
+    pub quality_error: Option<String>,
+}
+
+/// Summary of a collection run, see [`convert_collection`].
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CollectionReport {
+    pub images: Vec<ImageReport>,
+    /// For each trait, how many images carry each value.
+    pub trait_counts: BTreeMap<&'static str, BTreeMap<String, usize>>,
+    /// Palette shares averaged over the whole collection.
+    pub palette: Vec<(String, f64)>,
+    /// Groups of inputs whose outputs share the same average hash.
+    pub duplicates: Vec<Vec<PathBuf>>,
+    /// Inputs whose outputs scored worse than the quality threshold, for
+    /// manual review.
+    pub flagged: Vec<PathBuf>,
+    /// Inputs that could not be converted and why; the others are converted
+    /// regardless, see [`CollectionReport::error`].
+    pub failed: Vec<(PathBuf, String)>,
+    /// Inputs given up on after the timeout of the options, with that timeout.
+    pub timed_out: Vec<(PathBuf, Duration)>,
+}
+
+/*
+ * Converts every image in `dir` and summarizes the results, images that fail
+ * listed in the report; with a webhook, the folder is the job reported to it.
+ */
+pub fn convert_collection(dir: &Path, options: &ConvertOptions) -> Result<CollectionReport, Box<dyn Error>> {
+    #[cfg(feature = "webhook")]
+    if let Some(url) = &options.webhook {
+        let (result, timings) = webhook::timed(|| convert_images(dir, options));
+        let outputs: Vec<Output> = match &result {
+            Ok(report) => {
+                report.images.iter().map(|image| Output::written(image.output.display().to_string())).collect()
+            }
+            Err(_) => Vec::new(),
+        };
+        let error = match &result {
+            Ok(report) => report.error(),
+            Err(e) => Some(e.to_string()),
+        };
+        webhook::notify(url, &dir.display().to_string(), &outputs, error, &timings);
+        return result;
+    }
+    convert_images(dir, options)
+}
+
+fn convert_images(dir: &Path, options: &ConvertOptions) -> Result<CollectionReport, Box<dyn Error>> {
+    let mut images = Vec::new();
+    let mut flagged = Vec::new();
+    let mut failed = Vec::new();
+    let mut timed_out = Vec::new();
+    for input in inputs(dir, options)? {
+        // one unreadable or corrupt image does not stop the folder
+        let (converted, times) = match convert_timed(&input, options) {
+            Ok(Some(converted)) => converted,
+            Ok(None) => {
+                let timeout = options.timeout.unwrap_or_default();
+                eprintln!("warning: skipped {} after {:?}", input.display(), timeout);
+                timed_out.push((input, timeout));
+                continue;
+            }
+            Err(e) => {
+                eprintln!("warning: failed to convert {}: {}", input.display(), e);
+                failed.push((input, e.to_string()));
+                continue;
+            }
+        };
+        // an unscored output is still converted, only its quality is unknown
+        let (quality, quality_error) = match score(&converted, options) {
+            Ok(quality) => (quality, None),
+            Err(e) => {
+                eprintln!("warning: failed to score {}: {}", converted.output_path.display(), e);
+                (None, Some(e.to_string()))
+            }
+        };
+        let image = match summarize(input.clone(), &converted, &times) {
+            Ok(image) => image,
+            Err(e) => {
+                eprintln!("warning: failed to summarize {}: {}", converted.output_path.display(), e);
+                failed.push((input, e.to_string()));
+                continue;
+            }
+        };
+        if let Some((score, true)) = quality {
+            eprintln!("warning: {} scored {:.1}, flagged for review", converted.output_path.display(), score);
+            flagged.push(input);
+        }
+        images.push(ImageReport { quality: quality.map(|(score, _)| score), quality_error, ..image });
+    }
+    Ok(CollectionReport { flagged, failed, timed_out, ..CollectionReport::new(images) })
+}
+
+/*
+ * BRISQUE score of an output when `options` ask for one, and whether it is
+ * worse than their threshold
+ */
+#[cfg(feature = "quality")]
+fn score(converted: &Converted, options: &ConvertOptions) -> Result<Option<(f64, bool)>, Box<dyn Error>> {
+    let Some(quality) = &options.quality else {
+        return Ok(None);
+    };
+    let score = score_quality(&converted.output, quality)?;
+    Ok(Some((score, quality.flags(score))))
+}
+
+#[cfg(not(feature = "quality"))]
+fn score(_: &Converted, _: &ConvertOptions) -> Result<Option<(f64, bool)>, Box<dyn Error>> {
+    Ok(None)
+}
+
+/*
+ * The images of `dir` a collection run converts, sorted
+ */
+pub(crate) fn inputs(dir: &Path, options: &ConvertOptions) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut inputs = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        // never a valid input; another process may still be writing it
+        if is_partial(&path) {
+            if is_abandoned(&path) {
+                // a concurrent run may have removed it first
+                let _ = fs::remove_file(&path);
+            }
+            continue;
+        }
+        // by content: sidecars, .DS_Store and mislabeled files are skipped
+        if !path.is_file() || !is_image(&path)? {
+            continue;
+        }
+        // earlier results would come out as photo.nft.nft.jpg
+        if options.restyle_outputs || !is_output(&path, &options.output_suffix) {
+            inputs.push(path);
+        }
+    }
+    inputs.sort();
+    Ok(inputs)
+}
+
+/*
+ * convert_file with the latency of each stage; none if it outlasted the
+ * timeout of `options`
+ */
+pub(crate) fn convert_timed(
+    input: &Path,
+    options: &ConvertOptions,
+) -> Result<Option<(Converted, StageTimes)>, Box<dyn Error>> {
+    // intermediates of each image go into a directory named after it
+    let image_options;
+    let options = match (&options.debug_dir, input.file_stem()) {
+        (Some(dir), Some(stem)) => {
+            image_options = ConvertOptions { debug_dir: Some(dir.join(stem)), ..options.clone() };
+            &image_options
+        }
+        _ => options,
+    };
+    match options.timeout {
+        Some(timeout) => convert_file_within(input, options, timeout),
+        None => {
+            let (converted, times) = stage_times(|| convert_file(input, options));
+            Ok(Some((converted?, times)))
+        }
+    }
+}
+
+/*
+ * convert_file on a worker thread, given up on after `timeout`. OpenCV calls
+ * cannot be interrupted, so an abandoned conversion runs on in the background
+ * and may still write its output.
+ */
+fn convert_file_within(
+    input: &Path,
+    options: &ConvertOptions,
+    timeout: Duration,
+) -> Result<Option<(Converted, StageTimes)>, Box<dyn Error>> {
+    let (sender, receiver) = mpsc::channel();
+    let (input, options) = (input.to_path_buf(), options.clone());
+    thread::spawn(move || {
+        let (converted, times) = stage_times(|| convert_file(&input, &options));
+        // boxed errors are not Send, pass them on as text
+        let _ = sender.send(converted.map(|converted| (converted, times)).map_err(|e| e.to_string()));
+    });
+    match receiver.recv_timeout(timeout) {
+        Ok(converted) => Ok(Some(converted?)),
+        Err(RecvTimeoutError::Timeout) => Ok(None),
+        Err(RecvTimeoutError::Disconnected) => Err("conversion thread panicked".into()),
+    }
+}
+
+fn is_image(path: &Path) -> Result<bool, Box<dyn Error>> {
+    let mut header = Vec::with_capacity(16);
+    File::open(path)?.take(16).read_to_end(&mut header)?;
+    Ok(sniff_format(&header).is_some())
+}
+
+fn summarize(input: PathBuf, converted: &Converted, times: &StageTimes) -> Result<ImageReport, Box<dyn Error>> {
+    let pixels = converted.output.data_bytes()?;
+    let mask = converted.edge.data_bytes()?;
+
+    // 4 levels per channel -> 64 bins
+    let mut bins = [0usize; 64];
+    let mut luma = 0.0;
+    let mut painted = 0usize;
+    for (px, &m) in pixels.chunks_exact(3).zip(mask) {
+        if m == 0 {
+            continue;
+        }
+        let (b, g, r) = (px[0], px[1], px[2]);
+        bins[(r >> 6) as usize * 16 + (g >> 6) as usize * 4 + (b >> 6) as usize] += 1;
+        luma += 0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64;
+        painted += 1;
+    }
+    let mut palette: Vec<(String, f64)> = bins
+        .iter()
+        .enumerate()
+        .filter(|(_, n)| **n > 0)
+        .map(|(i, &n)| {
+            let center = |level: usize| (level * 64 + 32) as u8;
+            let hex = format!("#{:02x}{:02x}{:02x}", center(i / 16), center(i / 4 % 4), center(i % 4));
+            (hex, n as f64 / painted as f64)
+        })
+        .collect();
+    palette.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let total = converted.edge.total();
+    let edge_density = (total - count_non_zero(&converted.edge)? as usize) as f64 / total as f64;
+    let luma = if painted > 0 { luma / painted as f64 } else { 0.0 };
+
+    let dominant = palette.first().map_or_else(|| "none".to_string(), |(hex, _)| hex.clone());
+    let tone = match luma {
+        l if l < 85.0 => "dark",
+        l if l < 170.0 => "balanced",
+        _ => "bright",
+    };
+    let linework = match edge_density {
+        d if d < 0.05 => "sparse",
+        d if d < 0.15 => "medium",
+        _ => "dense",
+    };
+
+    Ok(ImageReport {
+        input,
+        output: converted.output_path.clone(),
+        width: converted.output.cols(),
+        height: converted.output.rows(),
+        palette,
+        edge_density,
+        traits: vec![
+            ("dominant_color", dominant),
+            ("tone", tone.to_string()),
+            ("linework", linework.to_string()),
+        ],
+        rarity: 0.0,
+        hash: average_hash(&converted.output)?,
+        stages: times.iter().map(|&(stage, elapsed)| (stage, elapsed.as_secs_f64())).collect(),
+        quality: None,
+        quality_error: None,
+    })
+}
+
+/// 8x8 average hash, robust to re-encoding and small resizes.
+fn average_hash(image: &Mat) -> Result<u64, Box<dyn Error>> {
+    let mut small = Mat::default();
+    resize(image, &mut small, Size::new(8, 8), 0.0, 0.0, INTER_AREA)?;
+    let mut gray = Mat::default();
+    cvt_color(&small, &mut gray, COLOR_BGR2GRAY, 0)?;
+    let data = gray.data_bytes()?;
+    let mean = data.iter().map(|&v| v as u32).sum::<u32>() / data.len() as u32;
+    Ok(data
+        .iter()
+        .enumerate()
+        .fold(0u64, |hash, (i, &v)| if v as u32 > mean { hash | 1 << i } else { hash }))
+}
+
+impl CollectionReport {
+    fn new(mut images: Vec<ImageReport>) -> CollectionReport {
+        let mut trait_counts: BTreeMap<&'static str, BTreeMap<String, usize>> = BTreeMap::new();
+        let mut palette: BTreeMap<String, f64> = BTreeMap::new();
+        let mut hashes: BTreeMap<u64, Vec<PathBuf>> = BTreeMap::new();
+        for image in &images {
+            for (name, value) in &image.traits {
+                *trait_counts.entry(*name).or_default().entry(value.clone()).or_default() += 1;
+            }
+            for (hex, share) in &image.palette {
+                *palette.entry(hex.clone()).or_default() += share / images.len() as f64;
+            }
+            hashes.entry(image.hash).or_default().push(image.input.clone());
+        }
+
+        let n = images.len() as f64;
+        for image in &mut images {
+            image.rarity = image
+                .traits
+                .iter()
+                .map(|(name, value)| n / trait_counts[name][value] as f64)
+                .sum();
+        }
+
+        let mut palette: Vec<(String, f64)> = palette.into_iter().collect();
+        palette.sort_by(|a, b| b.1.total_cmp(&a.1));
+        CollectionReport {
+            images,
+            trait_counts,
+            palette,
+            duplicates: hashes.into_values().filter(|group| group.len() > 1).collect(),
+            flagged: Vec::new(),
+            failed: Vec::new(),
+            timed_out: Vec::new(),
+        }
+    }
+
+    /// A summary of the failed and timed out inputs, none if every input was
+    /// converted.
+    pub fn error(&self) -> Option<String> {
+        let total = self.images.len() + self.failed.len() + self.timed_out.len();
+        if let Some((input, error)) = self.failed.first() {
+            return Some(format!(
+                "{} of {} images failed, first {}: {}",
+                self.failed.len() + self.timed_out.len(),
+                total,
+                input.display(),
+                error
+            ));
+        }
+        let (input, timeout) = self.timed_out.first()?;
+        Some(format!(
+            "{} of {} images timed out, first {} after {:?}",
+            self.timed_out.len(),
+            total,
+            input.display(),
+            timeout
+        ))
+    }
+
+    /// Writes `nftimg-report.json` and `nftimg-report.html` into `dir`.
+    pub fn write(&self, dir: &Path) -> Result<(), Box<dyn Error>> {
+        write_atomic(&dir.join(format!("{}.json", REPORT_NAME)), self.to_json().as_bytes())?;
+        write_atomic(&dir.join(format!("{}.html", REPORT_NAME)), self.to_html().as_bytes())?;
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        let palette = |entries: &[(String, f64)]| {
+            entries
+                .iter()
+                .map(|(hex, share)| format!("{{\"color\":\"{}\",\"share\":{:.4}}}", hex, share))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let images = self
+            .images
+            .iter()
+            .map(|image| {
+                let traits = image
+                    .traits
+                    .iter()
+                    .map(|(name, value)| format!("\"{}\":{}", name, json_string(value)))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                let quality = image.quality.map_or_else(|| "null".to_string(), |score| format!("{:.2}", score));
+                let quality_error = image.quality_error.as_deref().map_or_else(|| "null".to_string(), json_string);
+                format!(
+                    "{{\"input\":{},\"output\":{},\"rarity\":{:.4},\"hash\":\"{:016x}\",\"quality\":{},\"quality_error\":{},\"traits\":{{{}}},\"palette\":[{}]}}",
+                    json_string(&image.input.to_string_lossy()),
+                    json_string(&image.output.to_string_lossy()),
+                    image.rarity,
+                    image.hash,
+                    quality,
+                    quality_error,
+                    traits,
+                    palette(&image.palette),
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let traits = self
+            .trait_counts
+            .iter()
+            .map(|(name, counts)| {
+                let counts = counts
+                    .iter()
+                    .map(|(value, n)| format!("{}:{}", json_string(value), n))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                format!("\"{}\":{{{}}}", name, counts)
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let duplicates = self
+            .duplicates
+            .iter()
+            .map(|group| {
+                let paths = group
+                    .iter()
+                    .map(|p| json_string(&p.to_string_lossy()))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                format!("[{}]", paths)
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let flagged = self
+            .flagged
+            .iter()
+            .map(|p| json_string(&p.to_string_lossy()))
+            .collect::<Vec<_>>()
+            .join(",");
+        let failed = self
+            .failed
+            .iter()
+            .map(|(input, error)| {
+                format!("{{\"input\":{},\"error\":{}}}", json_string(&input.to_string_lossy()), json_string(error))
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let timed_out = self
+            .timed_out
+            .iter()
+            .map(|(input, timeout)| {
+                let input = json_string(&input.to_string_lossy());
+                format!("{{\"input\":{},\"timeout\":{:.3}}}", input, timeout.as_secs_f64())
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        format!(
+            "{{\"images\":[{}],\"traits\":{{{}}},\"palette\":[{}],\"duplicates\":[{}],\"flagged\":[{}],\"failed\":[{}],\"timed_out\":[{}]}}\n",
+            images,
+            traits,
+            palette(&self.palette),
+            duplicates,
+            flagged,
+            failed,
+            timed_out,
+        )
+    }
+
+    pub fn to_html(&self) -> String {
+        let swatches = |entries: &[(String, f64)], limit: usize| {
+            entries
+                .iter()
+                .take(limit)
+                .map(|(hex, share)| {
+                    format!(
+                        "<span class=\"swatch\" style=\"background:{}\" title=\"{} {:.1}%\"></span>",
+                        hex,
+                        hex,
+                        share * 100.0
+                    )
+                })
+                .collect::<String>()
+        };
+
+        let mut html = String::from(concat!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>nftimg collection report</title>",
+            "<style>body{font-family:sans-serif}table{border-collapse:collapse}",
+            "td,th{border:1px solid #ccc;padding:4px 8px;text-align:left}",
+            ".swatch{display:inline-block;width:16px;height:16px;margin-right:2px}</style></head><body>\n",
+        ));
+        html.push_str(&format!("<h1>Collection report ({} images)</h1>\n", self.images.len()));
+
+        html.push_str("<h2>Palette</h2>\n<p>");
+        html.push_str(&swatches(&self.palette, 32));
+        html.push_str("</p>\n<h2>Traits</h2>\n<table><tr><th>trait</th><th>value</th><th>images</th></tr>\n");
+        for (name, counts) in &self.trait_counts {
+            for (value, n) in counts {
+                html.push_str(&format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                    name,
+                    escape_html(value),
+                    n
+                ));
+            }
+        }
+        html.push_str("</table>\n<h2>Images</h2>\n<table><tr><th>image</th><th>rarity</th><th>quality</th><th>traits</th><th>palette</th></tr>\n");
+        let mut ranked: Vec<&ImageReport> = self.images.iter().collect();
+        ranked.sort_by(|a, b| b.rarity.total_cmp(&a.rarity));
+        for image in ranked {
+            let traits = image
+                .traits
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, escape_html(value)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let quality = match (image.quality, &image.quality_error) {
+                (Some(score), _) => format!("{:.1}", score),
+                (None, Some(e)) => format!("<span title=\"{}\">error</span>", escape_html(e)),
+                (None, None) => "-".to_string(),
+            };
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{:.2}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                escape_html(&image.input.to_string_lossy()),
+                image.rarity,
+                quality,
+                traits,
+                swatches(&image.palette, 8)
+            ));
+        }
+        html.push_str("</table>\n<h2>Duplicates</h2>\n");
+        if self.duplicates.is_empty() {
+            html.push_str("<p>none</p>\n");
+        }
+        for group in &self.duplicates {
+            let names = group
+                .iter()
+                .map(|p| escape_html(&p.to_string_lossy()))
+                .collect::<Vec<_>>()
+                .join(", ");
+            html.push_str(&format!("<p>{}</p>\n", names));
+        }
+        if !self.flagged.is_empty() {
+            html.push_str("<h2>Flagged for review</h2>\n");
+            for input in &self.flagged {
+                html.push_str(&format!("<p>{}</p>\n", escape_html(&input.to_string_lossy())));
+            }
+        }
+        if !self.failed.is_empty() {
+            html.push_str("<h2>Failed</h2>\n");
+            for (input, error) in &self.failed {
+                html.push_str(&format!(
+                    "<p>{}: {}</p>\n",
+                    escape_html(&input.to_string_lossy()),
+                    escape_html(error)
+                ));
+            }
+        }
+        if !self.timed_out.is_empty() {
+            html.push_str("<h2>Timed out</h2>\n");
+            for (input, timeout) in &self.timed_out {
+                html.push_str(&format!("<p>{} after {:?}</p>\n", escape_html(&input.to_string_lossy()), timeout));
+            }
+        }
+        html.push_str("</body></html>\n");
+        html
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
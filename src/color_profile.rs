@@ -0,0 +1,333 @@
+use std::error::Error;
+
+use opencv::core::{Scalar, Size, CV_32FC3, CV_8UC3};
+use opencv::imgproc::resize;
+use opencv::prelude::*;
+
+use crate::metadata::{jpeg_segment, jpeg_segments, png_chunk, png_chunks, JPEG_SOI, PNG_SIGNATURE};
+
+const ICC_HEADER: &[u8] = b"ICC_PROFILE\0";
+
+/// RGB color spaces recognised on input. The Lab conversion assumes sRGB, so
+/// anything else is converted to sRGB before processing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "kebab-case"))]
+pub enum ColorProfile {
+    Srgb,
+    AdobeRgb,
+    DisplayP3,
+}
+
+impl ColorProfile {
+    /// Parses `srgb`, `adobe-rgb` or `display-p3`.
+    pub fn from_name(name: &str) -> Option<ColorProfile> {
+        match name.to_lowercase().as_str() {
+            "srgb" => Some(ColorProfile::Srgb),
+            "adobe-rgb" | "adobergb" => Some(ColorProfile::AdobeRgb),
+            "display-p3" | "p3" => Some(ColorProfile::DisplayP3),
+            _ => None,
+        }
+    }
+
+    /// The name [`ColorProfile::from_name`] parses, as written in recipes.
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorProfile::Srgb => "srgb",
+            ColorProfile::AdobeRgb => "adobe-rgb",
+            ColorProfile::DisplayP3 => "display-p3",
+        }
+    }
+
+    /// Guesses the color space from an ICC profile description or name.
+    fn from_description(description: &str) -> Option<ColorProfile> {
+        let description = description.to_lowercase();
+        if description.contains("adobe rgb") || description.contains("adobergb") {
+            Some(ColorProfile::AdobeRgb)
+        } else if description.contains("p3") {
+            Some(ColorProfile::DisplayP3)
+        } else if description.contains("srgb") {
+            Some(ColorProfile::Srgb)
+        } else {
+            None
+        }
+    }
+
+    /// Linear RGB of this space -> linear sRGB, both D65.
+    fn to_srgb_matrix(self) -> [[f64; 3]; 3] {
+        match self {
+            ColorProfile::Srgb => [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            ColorProfile::AdobeRgb => [
+                [1.398283, -0.398283, 0.0],
+                [0.0, 1.0, 0.0],
+                [0.0, -0.042938, 1.042938],
+            ],
+            ColorProfile::DisplayP3 => [
+                [1.224745, -0.224904, 0.0],
+                [-0.042058, 1.042081, 0.0],
+                [-0.019642, -0.078655, 1.098537],
+            ],
+        }
+    }
+
+    /// Transfer function, encoded [0, 1] -> linear [0, 1].
+    fn decode(self, v: f64) -> f64 {
+        match self {
+            ColorProfile::AdobeRgb => v.powf(563.0 / 256.0),
+            // Display P3 shares the sRGB curve
+            ColorProfile::Srgb | ColorProfile::DisplayP3 => srgb_decode(v),
+        }
+    }
+}
+
+fn srgb_decode(v: f64) -> f64 {
+    if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) }
+}
+
+fn srgb_encode(v: f64) -> f64 {
+    if v <= 0.0031308 { v * 12.92 } else { 1.055 * v.powf(1.0 / 2.4) - 0.055 }
+}
+
+/// 8-bit sRGB value -> linear light in [0, 1], for mixing colors the way
+/// light adds up instead of in gamma space.
+pub(crate) fn linear_table() -> [f64; 256] {
+    std::array::from_fn(|v| srgb_decode(v as f64 / 255.0))
+}
+
+/// Linear light in [0, 1] -> 8-bit sRGB value.
+pub(crate) fn encode_linear(v: f64) -> u8 {
+    (srgb_encode(v.clamp(0.0, 1.0)) * 255.0).round() as u8
+}
+
+/// Mixes 8-bit channel values in gamma space, as stored, or in linear
+/// light. Values stay on the 0-255 scale either way.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Mixing {
+    pub(crate) linear: bool,
+}
+
+impl Mixing {
+    /// Stored value -> value to mix.
+    pub(crate) fn decode(self, v: f64) -> f64 {
+        if self.linear { srgb_decode((v / 255.0).clamp(0.0, 1.0)) * 255.0 } else { v }
+    }
+
+    /// Mixed value -> stored value.
+    pub(crate) fn encode(self, v: f64) -> u8 {
+        if self.linear { encode_linear(v / 255.0) } else { v.round().clamp(0.0, 255.0) as u8 }
+    }
+}
+
+/*
+ * BGR image -> BGR image resized with the interpolation averaging linear
+ * light; downsampling in gamma space darkens fine bright detail
+ */
+pub(crate) fn resize_linear(input: &Mat, size: Size, interpolation: i32) -> Result<Mat, Box<dyn Error>> {
+    let table = linear_table();
+    let mut linear = Mat::new_rows_cols_with_default(input.rows(), input.cols(), CV_32FC3, Scalar::all(0.0))?;
+    for (l, &v) in linear.data_typed_mut::<f32>()?.iter_mut().zip(input.data_bytes()?) {
+        *l = table[v as usize] as f32;
+    }
+    let mut resized = Mat::default();
+    resize(&linear, &mut resized, size, 0.0, 0.0, interpolation)?;
+    let mut output = Mat::new_rows_cols_with_default(resized.rows(), resized.cols(), CV_8UC3, Scalar::all(0.0))?;
+    for (v, &l) in output.data_bytes_mut()?.iter_mut().zip(resized.data_typed::<f32>()?) {
+        *v = encode_linear(l as f64);
+    }
+    Ok(output)
+}
+
+/*
+ * Detects the color space of an encoded JPEG or PNG from its embedded ICC
+ * profile. Returns None when there is no profile or it is not recognised.
+ */
+pub(crate) fn detect(bytes: &[u8]) -> Option<ColorProfile> {
+    if bytes.starts_with(&JPEG_SOI) {
+        // profiles above 64 KiB are split over several numbered APP2 segments
+        let mut parts: Vec<(u8, &[u8])> = jpeg_segments(bytes)
+            .filter(|(marker, body)| *marker == 0xE2 && body.starts_with(ICC_HEADER))
+            .filter_map(|(_, body)| {
+                let body = &body[ICC_HEADER.len()..];
+                Some((*body.first()?, body.get(2..)?))
+            })
+            .collect();
+        if parts.is_empty() {
+            return None;
+        }
+        parts.sort_by_key(|(seq, _)| *seq);
+        let profile: Vec<u8> = parts.into_iter().flat_map(|(_, data)| data.iter().copied()).collect();
+        icc_description(&profile).and_then(|d| ColorProfile::from_description(&d))
+    } else if bytes.starts_with(&PNG_SIGNATURE) {
+        for (kind, data) in png_chunks(bytes) {
+            match kind {
+                b"sRGB" => return Some(ColorProfile::Srgb),
+                // the profile itself is compressed, but its name is not
+                b"iCCP" => {
+                    let name = data.split(|&b| b == 0).next()?;
+                    return ColorProfile::from_description(&String::from_utf8_lossy(name));
+                }
+                b"IDAT" => break,
+                _ => {}
+            }
+        }
+        None
+    } else {
+        None
+    }
+}
+
+/// Reads the `desc` tag of an ICC profile, v2 (`desc`) or v4 (`mluc`) typed.
+fn icc_description(profile: &[u8]) -> Option<String> {
+    let u32_at = |pos: usize| -> Option<usize> {
+        Some(u32::from_be_bytes(profile.get(pos..pos + 4)?.try_into().ok()?) as usize)
+    };
+    let count = u32_at(128)?;
+    for i in 0..count {
+        let entry = 132 + i * 12;
+        if profile.get(entry..entry + 4)? != b"desc" {
+            continue;
+        }
+        let offset = u32_at(entry + 4)?;
+        return match profile.get(offset..offset + 4)? {
+            b"desc" => {
+                let len = u32_at(offset + 8)?;
+                let text = profile.get(offset + 12..offset + 12 + len)?;
+                Some(String::from_utf8_lossy(text).trim_end_matches('\0').to_string())
+            }
+            b"mluc" => {
+                // first record: language, country, length, offset from tag start
+                let len = u32_at(offset + 20)?;
+                let start = offset + u32_at(offset + 24)?;
+                let utf16: Vec<u16> = profile
+                    .get(start..start + len)?
+                    .chunks_exact(2)
+                    .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                    .collect();
+                Some(String::from_utf16_lossy(&utf16))
+            }
+            _ => None,
+        };
+    }
+    None
+}
+
+/*
+ * BGR image in `profile` -> BGR image in sRGB
+ */
+pub(crate) fn to_srgb(input: &Mat, profile: ColorProfile) -> Result<Mat, Box<dyn Error>> {
+    let mut output = input.try_clone()?;
+    if profile == ColorProfile::Srgb {
+        return Ok(output);
+    }
+
+    let linear: Vec<f64> = (0..256).map(|v| profile.decode(v as f64 / 255.0)).collect();
+    let m = profile.to_srgb_matrix();
+    for px in output.data_bytes_mut()?.chunks_exact_mut(3) {
+        let (b, g, r) = (linear[px[0] as usize], linear[px[1] as usize], linear[px[2] as usize]);
+        let encode = |row: [f64; 3]| encode_linear(row[0] * r + row[1] * g + row[2] * b);
+        px[2] = encode(m[0]);
+        px[1] = encode(m[1]);
+        px[0] = encode(m[2]);
+    }
+    Ok(output)
+}
+
+/// APP2 segment carrying an sRGB ICC profile.
+pub(crate) fn jpeg_srgb_segment() -> Result<Vec<u8>, Box<dyn Error>> {
+    // single segment: sequence number 1 of 1
+    jpeg_segment(0xE2, &[ICC_HEADER, &[1, 1], &srgb_icc()])
+}
+
+/// `sRGB` chunk with perceptual rendering intent.
+pub(crate) fn png_srgb_chunk() -> Vec<u8> {
+    png_chunk(b"sRGB", &[0])
+}
+
+/*
+ * Builds a minimal ICC v2 display profile for sRGB (IEC 61966-2.1):
+ * D50-adapted colorants and a sampled sRGB tone curve.
+ */
+fn srgb_icc() -> Vec<u8> {
+    fn s15_fixed16(v: f64) -> [u8; 4] {
+        ((v * 65536.0).round() as i32).to_be_bytes()
+    }
+    fn xyz(x: f64, y: f64, z: f64) -> Vec<u8> {
+        let mut tag = b"XYZ \0\0\0\0".to_vec();
+        for v in [x, y, z] {
+            tag.extend_from_slice(&s15_fixed16(v));
+        }
+        tag
+    }
+
+    let name = b"sRGB IEC61966-2.1\0";
+    let mut desc = b"desc\0\0\0\0".to_vec();
+    desc.extend_from_slice(&(name.len() as u32).to_be_bytes());
+    desc.extend_from_slice(name);
+    // empty unicode and scriptcode descriptions
+    desc.extend_from_slice(&[0; 4 + 4 + 2 + 1 + 67]);
+
+    let mut cprt = b"text\0\0\0\0".to_vec();
+    cprt.extend_from_slice(b"No copyright, use freely\0");
+
+    let mut trc = b"curv\0\0\0\0".to_vec();
+    trc.extend_from_slice(&1024u32.to_be_bytes());
+    for i in 0..1024 {
+        let v = srgb_decode(i as f64 / 1023.0);
+        trc.extend_from_slice(&((v * 65535.0).round() as u16).to_be_bytes());
+    }
+
+    let data = [
+        desc,
+        cprt,
+        xyz(0.9505, 1.0, 1.0891),
+        xyz(0.4361, 0.2225, 0.0139),
+        xyz(0.3851, 0.7169, 0.0971),
+        xyz(0.1431, 0.0606, 0.7141),
+        trc,
+    ];
+    // the three tone curves share one data block
+    let tags: [(&[u8; 4], usize); 9] = [
+        (b"desc", 0),
+        (b"cprt", 1),
+        (b"wtpt", 2),
+        (b"rXYZ", 3),
+        (b"gXYZ", 4),
+        (b"bXYZ", 5),
+        (b"rTRC", 6),
+        (b"gTRC", 6),
+        (b"bTRC", 6),
+    ];
+
+    let mut offsets = Vec::with_capacity(data.len());
+    let mut body = Vec::new();
+    let data_start = 128 + 4 + tags.len() * 12;
+    for block in &data {
+        offsets.push(data_start + body.len());
+        body.extend_from_slice(block);
+        body.resize(body.len().next_multiple_of(4), 0);
+    }
+    let size = data_start + body.len();
+
+    let mut profile = Vec::with_capacity(size);
+    profile.extend_from_slice(&(size as u32).to_be_bytes());
+    profile.extend_from_slice(&[0; 4]); // preferred CMM
+    profile.extend_from_slice(&0x0210_0000u32.to_be_bytes());
+    profile.extend_from_slice(b"mntrRGB XYZ ");
+    for v in [2024u16, 1, 1, 0, 0, 0] {
+        profile.extend_from_slice(&v.to_be_bytes());
+    }
+    profile.extend_from_slice(b"acsp");
+    profile.extend_from_slice(&[0; 4 + 4 + 4 + 4 + 8 + 4]); // platform .. rendering intent
+    for v in [0.9642, 1.0, 0.8249] {
+        profile.extend_from_slice(&s15_fixed16(v));
+    }
+    profile.resize(128, 0); // creator, profile id, reserved
+
+    profile.extend_from_slice(&(tags.len() as u32).to_be_bytes());
+    for (signature, index) in tags {
+        profile.extend_from_slice(signature);
+        profile.extend_from_slice(&(offsets[index] as u32).to_be_bytes());
+        profile.extend_from_slice(&(data[index].len() as u32).to_be_bytes());
+    }
+    profile.extend_from_slice(&body);
+    profile
+}
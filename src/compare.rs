@@ -0,0 +1,147 @@
+use std::error::Error;
+use std::path::Path;
+
+use opencv::core::{absdiff, multiply, no_array, norm, psnr, Scalar, Size, BORDER_DEFAULT, CV_32F, CV_8U, CV_8UC1, NORM_INF};
+use opencv::imgcodecs::IMREAD_COLOR;
+use opencv::imgproc::{apply_color_map, gaussian_blur, COLORMAP_JET};
+use opencv::prelude::*;
+
+use crate::{read_image, write_image, NftImgError};
+
+/// Gaussian window of the SSIM statistics, as in Wang et al. 2004.
+const SSIM_WINDOW: i32 = 11;
+const SSIM_SIGMA: f64 = 1.5;
+/// SSIM stabilizers for 8-bit values, (0.01 * 255)² and (0.03 * 255)².
+const SSIM_C1: f32 = 6.5025;
+const SSIM_C2: f32 = 58.5225;
+/// Heatmap gain: a channel difference of 64 or more is drawn at full heat.
+const HEATMAP_GAIN: f64 = 4.0;
+
+/// How much two renderings of the same image differ, e.g. the outputs of
+/// two parameter sets, see [`compare`].
+#[derive(Debug)]
+pub struct CompareReport {
+    /// Peak signal-to-noise ratio in dB; OpenCV reports 361 for identical
+    /// images.
+    pub psnr: f64,
+    /// Mean structural similarity over pixels and channels, 1 for identical
+    /// images.
+    pub ssim: f64,
+    /// Largest per-channel absolute difference, 0 to 255.
+    pub max_abs: f64,
+    /// Share of pixels differing in any channel, 0 to 1.
+    pub changed: f64,
+    /// BGR heatmap of the largest channel difference per pixel, blue where
+    /// the images agree and red where they differ by 64 or more.
+    pub heatmap: Mat,
+}
+
+impl CompareReport {
+    /// Writes the heatmap, in the format of the extension of `path`.
+    pub fn write_heatmap(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        write_image(path, &self.heatmap)
+    }
+}
+
+/// Compares two 8-bit images of the same size and channel count: PSNR,
+/// SSIM, the largest difference, the share of changed pixels and a heatmap
+/// of where they differ. Quantifies how much a parameter change affects the
+/// output:
+///
+/// ```ignore
+/// let report = nftimg::compare(&before, &after)?;
+/// println!("PSNR {:.1} dB, SSIM {:.4}", report.psnr, report.ssim);
+/// ```
+pub fn compare(a: &Mat, b: &Mat) -> Result<CompareReport, Box<dyn Error>> {
+    if a.size()? != b.size()? || a.typ() != b.typ() {
+        return Err(format!(
+            "cannot compare a {}x{} image with {} channels with a {}x{} image with {} channels",
+            a.cols(),
+            a.rows(),
+            a.channels(),
+            b.cols(),
+            b.rows(),
+            b.channels()
+        )
+        .into());
+    }
+    if a.depth() != CV_8U {
+        return Err("expected 8-bit images".into());
+    }
+    let mut diff = Mat::default();
+    absdiff(a, b, &mut diff)?;
+
+    // largest channel difference per pixel
+    let channels = diff.channels() as usize;
+    let mut largest = Mat::new_rows_cols_with_default(diff.rows(), diff.cols(), CV_8UC1, Scalar::all(0.0))?;
+    let mut changed = 0usize;
+    for (out, px) in largest.data_bytes_mut()?.iter_mut().zip(diff.data_bytes()?.chunks_exact(channels)) {
+        *out = px.iter().copied().max().unwrap_or(0);
+        changed += usize::from(*out > 0);
+    }
+    let pixels = (diff.rows() as usize * diff.cols() as usize).max(1);
+
+    let mut scaled = Mat::default();
+    largest.convert_to(&mut scaled, CV_8U, HEATMAP_GAIN, 0.0)?;
+    let mut heatmap = Mat::default();
+    apply_color_map(&scaled, &mut heatmap, COLORMAP_JET)?;
+
+    Ok(CompareReport {
+        psnr: psnr(a, b, 255.0)?,
+        ssim: ssim(a, b)?,
+        max_abs: norm(&diff, NORM_INF, &no_array())?,
+        changed: changed as f64 / pixels as f64,
+        heatmap,
+    })
+}
+
+/// [`compare`] on two image files, decoded as BGR.
+pub fn compare_files(a: &Path, b: &Path) -> Result<CompareReport, Box<dyn Error>> {
+    let decode = |path: &Path| -> Result<Mat, Box<dyn Error>> {
+        let image = read_image(path, IMREAD_COLOR)?;
+        if image.empty() {
+            return Err(NftImgError::DecodeFailed { path: path.to_path_buf() }.into());
+        }
+        Ok(image)
+    };
+    compare(&decode(a)?, &decode(b)?)
+}
+
+/*
+ * Mean SSIM of two 8-bit images of the same size and type, over every
+ * channel
+ */
+fn ssim(a: &Mat, b: &Mat) -> Result<f64, Box<dyn Error>> {
+    let (mut x, mut y) = (Mat::default(), Mat::default());
+    a.convert_to(&mut x, CV_32F, 1.0, 0.0)?;
+    b.convert_to(&mut y, CV_32F, 1.0, 0.0)?;
+    let product = |p: &Mat, q: &Mat| -> Result<Mat, Box<dyn Error>> {
+        let mut out = Mat::default();
+        multiply(p, q, &mut out, 1.0, -1)?;
+        Ok(out)
+    };
+    let window = |image: &Mat| -> Result<Mat, Box<dyn Error>> {
+        let mut out = Mat::default();
+        gaussian_blur(image, &mut out, Size::new(SSIM_WINDOW, SSIM_WINDOW), SSIM_SIGMA, 0.0, BORDER_DEFAULT)?;
+        Ok(out)
+    };
+    let mu_x = window(&x)?;
+    let mu_y = window(&y)?;
+    let xx = window(&product(&x, &x)?)?;
+    let yy = window(&product(&y, &y)?)?;
+    let xy = window(&product(&x, &y)?)?;
+
+    // one f32 per channel value, the statistics line up element by element
+    let values = |m: &Mat| -> Result<Vec<f32>, Box<dyn Error>> { Ok(m.reshape(1, 0)?.data_typed::<f32>()?.to_vec()) };
+    let (mu_x, mu_y, xx, yy, xy) = (values(&mu_x)?, values(&mu_y)?, values(&xx)?, values(&yy)?, values(&xy)?);
+    let mut sum = 0.0f64;
+    for (i, (&mx, &my)) in mu_x.iter().zip(&mu_y).enumerate() {
+        let var_x = xx[i] - mx * mx;
+        let var_y = yy[i] - my * my;
+        let cov = xy[i] - mx * my;
+        let index = ((2.0 * mx * my + SSIM_C1) * (2.0 * cov + SSIM_C2))
+            / ((mx * mx + my * my + SSIM_C1) * (var_x + var_y + SSIM_C2));
+        sum += f64::from(index);
+    }
+    Ok(sum / mu_x.len().max(1) as f64)
+}
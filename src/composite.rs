@@ -0,0 +1,59 @@
+use std::error::Error;
+
+use opencv::core::{Size, BORDER_DEFAULT, CV_32F};
+use opencv::imgproc::gaussian_blur;
+use opencv::prelude::*;
+
+use crate::color_profile::Mixing;
+
+/// How outlines are drawn when base and edge are merged. The default draws
+/// opaque black outlines, i.e. the masked-out pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct EdgeStyle {
+    /// Outline color, BGR.
+    pub color: [u8; 3],
+    /// Outline opacity; below 1 the outlines are a semi-transparent overlay.
+    pub opacity: f64,
+    /// Color revealed where the mask removes base pixels, BGR. `None` keeps
+    /// the base under semi-transparent outlines.
+    pub background: Option<[u8; 3]>,
+    /// Gaussian sigma the edge mask is feathered with before alpha blending,
+    /// for anti-aliased outlines; 0 keeps the hard mask.
+    pub feather: f64,
+}
+
+impl Default for EdgeStyle {
+    fn default() -> Self {
+        EdgeStyle { color: [0, 0, 0], opacity: 1.0, background: None, feather: 0.0 }
+    }
+}
+
+/*
+ * BGR base + edge mask -> BGR image with styled outlines where the mask is 0,
+ * blended by the feathered mask coverage
+ */
+pub(crate) fn composite(base: &Mat, edge: &Mat, style: &EdgeStyle, mixing: Mixing) -> Result<Mat, Box<dyn Error>> {
+    let mut mask = Mat::default();
+    edge.convert_to(&mut mask, CV_32F, 1.0 / 255.0, 0.0)?;
+    if style.feather > 0.0 {
+        let mut feathered = Mat::default();
+        gaussian_blur(&mask, &mut feathered, Size::new(0, 0), style.feather, 0.0, BORDER_DEFAULT)?;
+        mask = feathered;
+    }
+
+    let mut output = base.try_clone()?;
+    for (px, &kept) in output.data_bytes_mut()?.chunks_exact_mut(3).zip(mask.data_typed::<f32>()?) {
+        let alpha = (1.0 - kept as f64).clamp(0.0, 1.0);
+        if alpha == 0.0 {
+            continue;
+        }
+        let revealed = style.background.unwrap_or([px[0], px[1], px[2]]);
+        for c in 0..3 {
+            let (color, revealed) = (mixing.decode(style.color[c] as f64), mixing.decode(revealed[c] as f64));
+            let line = color * style.opacity + revealed * (1.0 - style.opacity);
+            px[c] = mixing.encode(line * alpha + mixing.decode(px[c] as f64) * (1.0 - alpha));
+        }
+    }
+    Ok(output)
+}
@@ -0,0 +1,181 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error::Error;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+use opencv::prelude::*;
+
+use crate::ConvertOptions;
+
+/// What a cached resource is, e.g. `style`, and the file it was loaded from.
+type Key = (&'static str, PathBuf);
+
+thread_local! {
+    /// Cache of the converter running on this thread, see [`Converter`].
+    static CACHE: RefCell<Option<Arc<Cache>>> = const { RefCell::new(None) };
+}
+
+/// Resources loaded by the conversions of a converter and kept for the next.
+#[derive(Default)]
+struct Cache {
+    /// Read-only once loaded, shared by every conversion, e.g. parsed LUTs.
+    shared: Mutex<HashMap<Key, Arc<dyn Any + Send + Sync>>>,
+    /// Idle models, each used by one conversion at a time since OpenCV runs
+    /// them through `&mut`, e.g. networks and cascades. Concurrent
+    /// conversions load as many as they need at once.
+    pooled: Mutex<HashMap<Key, Vec<Box<dyn Any + Send>>>>,
+}
+
+/// Conversions with fixed options that keep what they load: LUTs are parsed
+/// once, and the networks of style transfer, segmentation, depth and
+/// upscaling and face cascades are loaded on first use and reused by later
+/// conversions instead of being read from disk for each image. Methods take
+/// `&self` and a converter is `Send + Sync`, so a server shares one, e.g. in
+/// an `Arc`, across its worker threads:
+///
+/// ```ignore
+/// let converter = Arc::new(nftimg::Converter::new(options));
+/// for request in requests {
+///     let converter = converter.clone();
+///     thread::spawn(move || converter.convert_buffer(&request.body));
+/// }
+/// ```
+///
+/// To be told as stages end, wrap one in a [`crate::Pipeline`], which
+/// converts through it.
+#[derive(Clone)]
+pub struct Converter {
+    options: ConvertOptions,
+    cache: Arc<Cache>,
+}
+
+impl Converter {
+    pub fn new(options: ConvertOptions) -> Converter {
+        Converter { options, cache: Arc::default() }
+    }
+
+    pub fn options(&self) -> &ConvertOptions {
+        &self.options
+    }
+
+    /// A converter with other options sharing what this one loaded, e.g.
+    /// for requests overriding some options of a server's defaults.
+    pub fn with_options(&self, options: ConvertOptions) -> Converter {
+        Converter { options, cache: self.cache.clone() }
+    }
+
+    /// Converts the file like [`crate::convert_with_options`] and returns
+    /// the path of the output.
+    pub fn convert_file(&self, path: impl AsRef<Path>) -> Result<PathBuf, Box<dyn Error>> {
+        Ok(self.cached(|| crate::convert_file(path.as_ref(), &self.options))?.output_path)
+    }
+
+    /// See [`crate::convert_buffer`].
+    pub fn convert_buffer(&self, bytes: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.cached(|| crate::convert_buffer(bytes, &self.options))
+    }
+
+    /// See [`crate::convert_image`].
+    pub fn convert_image(&self, image: &Mat) -> Result<Mat, Box<dyn Error>> {
+        self.cached(|| crate::convert_image(image, &self.options))
+    }
+
+    /*
+     * Runs `run` on this thread with the conversions in it keeping what they
+     * load in this converter, whatever options they are given
+     */
+    pub(crate) fn cached<T>(&self, run: impl FnOnce() -> T) -> T {
+        // restored on unwinding too, and for converters nested in a stage
+        struct Restore(Option<Arc<Cache>>);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CACHE.set(self.0.take());
+            }
+        }
+        let _restore = Restore(CACHE.replace(Some(self.cache.clone())));
+        run()
+    }
+}
+
+/*
+ * The resource of `kind` loaded from `path`, loaded now unless a converter
+ * on this thread already has it
+ */
+pub(crate) fn shared<T: Any + Send + Sync>(
+    kind: &'static str,
+    path: &Path,
+    load: impl FnOnce() -> Result<T, Box<dyn Error>>,
+) -> Result<Arc<T>, Box<dyn Error>> {
+    let Some(cache) = CACHE.with_borrow(Clone::clone) else {
+        return Ok(Arc::new(load()?));
+    };
+    let key = (kind, path.to_path_buf());
+    if let Some(resource) = lock(&cache.shared).get(&key).cloned() {
+        if let Ok(resource) = resource.downcast::<T>() {
+            return Ok(resource);
+        }
+    }
+    // loaded unlocked; conversions racing for it each load a copy, one kept
+    let resource = Arc::new(load()?);
+    lock(&cache.shared).insert(key, resource.clone());
+    Ok(resource)
+}
+
+/*
+ * A model of `kind` loaded from `path`, an idle one of the converter on this
+ * thread if it has one; given back to it when dropped
+ */
+pub(crate) fn pooled<M: Any + Send>(
+    kind: &'static str,
+    path: &Path,
+    load: impl FnOnce() -> Result<M, Box<dyn Error>>,
+) -> Result<Pooled<M>, Box<dyn Error>> {
+    let cache = CACHE.with_borrow(Clone::clone);
+    let key = (kind, path.to_path_buf());
+    let idle = cache.as_ref().and_then(|cache| lock(&cache.pooled).get_mut(&key)?.pop());
+    let model = match idle.map(|model| model.downcast::<M>()) {
+        Some(Ok(model)) => *model,
+        _ => load()?,
+    };
+    Ok(Pooled { model: Some(model), home: cache.map(|cache| (cache, key)) })
+}
+
+/// A model checked out by [`pooled`].
+pub(crate) struct Pooled<M: Any + Send> {
+    model: Option<M>,
+    home: Option<(Arc<Cache>, Key)>,
+}
+
+impl<M: Any + Send> Deref for Pooled<M> {
+    type Target = M;
+
+    fn deref(&self) -> &M {
+        self.model.as_ref().expect("model taken")
+    }
+}
+
+impl<M: Any + Send> DerefMut for Pooled<M> {
+    fn deref_mut(&mut self) -> &mut M {
+        self.model.as_mut().expect("model taken")
+    }
+}
+
+impl<M: Any + Send> Drop for Pooled<M> {
+    fn drop(&mut self) {
+        // a panic may have left it half-run
+        if thread::panicking() {
+            return;
+        }
+        if let (Some((cache, key)), Some(model)) = (self.home.take(), self.model.take()) {
+            lock(&cache.pooled).entry(key).or_default().push(Box::new(model));
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
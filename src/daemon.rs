@@ -0,0 +1,136 @@
+use std::error::Error;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::thread;
+
+use serde_json::{json, Value};
+
+use crate::{convert_file_to, ConvertOptions, Converter};
+
+/// JSON-RPC 2.0 error codes.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// The conversion itself failed.
+const CONVERSION_FAILED: i64 = -32000;
+
+/// A JSON-RPC error code and its message.
+type Failure = (i64, String);
+
+/// Answers JSON-RPC 2.0 requests on the Unix socket at `socket` until the
+/// process is stopped, one request per line and one response line each, so
+/// editors and scripts convert without starting a process per image.
+///
+/// `{"jsonrpc":"2.0","id":1,"method":"convert","params":{"input":"photo.jpg"}}`
+/// converts like the command line and answers `{"output":"/abs/photo.nft.jpg"}`;
+/// `params` may name the `output` file and carry `options`, recipe keys
+/// applied on top of `options`, e.g. `{"spatial_radius":15}`. `version`
+/// answers the crate version. A socket left behind by a stopped daemon is
+/// replaced.
+pub fn run_daemon(socket: &Path, options: &ConvertOptions) -> Result<(), Box<dyn Error>> {
+    if socket.exists() {
+        if UnixStream::connect(socket).is_ok() {
+            return Err(format!("a daemon already listens on {}", socket.display()).into());
+        }
+        fs::remove_file(socket)?;
+    }
+    let listener = UnixListener::bind(socket)?;
+    eprintln!("listening on {}", socket.display());
+    // requests reuse the models earlier ones loaded
+    let converter = Converter::new(options.clone());
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let converter = converter.clone();
+        // one client converting does not hold up the others
+        thread::spawn(move || {
+            if let Err(e) = serve_client(stream, &converter) {
+                eprintln!("warning: client connection failed: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn serve_client(stream: UnixStream, converter: &Converter) -> Result<(), Box<dyn Error>> {
+    let mut writer = &stream;
+    for line in BufReader::new(&stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = respond(&line, converter) {
+            writeln!(writer, "{}", response)?;
+        }
+    }
+    Ok(())
+}
+
+/*
+ * request line -> response, none for notifications
+ */
+fn respond(line: &str, converter: &Converter) -> Option<Value> {
+    let request: Value = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => return Some(response(Value::Null, Err((PARSE_ERROR, e.to_string())))),
+    };
+    let id = request.get("id").cloned();
+    let result = match request.get("method").and_then(Value::as_str) {
+        Some("convert") => convert(request.get("params"), converter),
+        Some("version") => Ok(json!({ "version": env!("CARGO_PKG_VERSION") })),
+        Some(method) => Err((METHOD_NOT_FOUND, format!("no method {}", method))),
+        None => Err((INVALID_REQUEST, "request without method".to_string())),
+    };
+    if let Err((CONVERSION_FAILED, message)) = &result {
+        eprintln!("warning: convert failed: {}", message);
+    }
+    Some(response(id?, result))
+}
+
+fn response(id: Value, result: Result<Value, Failure>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, message)) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } }),
+    }
+}
+
+fn convert(params: Option<&Value>, converter: &Converter) -> Result<Value, Failure> {
+    let invalid = |message: String| (INVALID_PARAMS, message);
+    let params = params.ok_or_else(|| invalid("convert without params".to_string()))?;
+    let input = params.get("input").and_then(Value::as_str);
+    let input = input.ok_or_else(|| invalid("input must be a path".to_string()))?;
+    let output = match params.get("output") {
+        None | Some(Value::Null) => None,
+        Some(output) => Some(output.as_str().ok_or_else(|| invalid("output must be a path".to_string()))?),
+    };
+    let options = request_options(params.get("options"), converter.options()).map_err(|e| invalid(e.to_string()))?;
+    let converted = converter
+        .cached(|| convert_file_to(Path::new(input), output.map(Path::new), &options))
+        .map_err(|e| (CONVERSION_FAILED, e.to_string()))?;
+    Ok(json!({ "output": converted.output_path.display().to_string() }))
+}
+
+/*
+ * daemon options + request recipe keys -> ConvertOptions; output naming,
+ * metadata and limits stay the daemon's, as for worker jobs
+ */
+fn request_options(keys: Option<&Value>, base: &ConvertOptions) -> Result<ConvertOptions, Box<dyn Error>> {
+    let mut recipe = String::new();
+    if let Some(keys) = keys {
+        let keys = keys.as_object().ok_or("options must be an object of recipe keys")?;
+        for (key, value) in keys {
+            let value = match value {
+                Value::String(value) => value.clone(),
+                other => other.to_string(),
+            };
+            // one key must not smuggle in further recipe lines
+            if key.contains(['\n', '\r', '=']) || value.contains(['\n', '\r']) {
+                return Err(format!("malformed option {}", key).into());
+            }
+            recipe.push_str(&format!("{}={}\n", key, value));
+        }
+    }
+    base.with_recipe(&recipe)
+}
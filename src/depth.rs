@@ -0,0 +1,178 @@
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use opencv::core::{in_range, no_array, normalize, Scalar, CV_32F, NORM_MINMAX};
+#[cfg(feature = "dnn")]
+use opencv::core::{Size, CV_32FC1};
+#[cfg(feature = "dnn")]
+use opencv::dnn::{blob_from_image, read_net_def};
+use opencv::imgcodecs::{IMREAD_ANYDEPTH, IMREAD_GRAYSCALE};
+use opencv::imgproc::{resize, INTER_LINEAR};
+use opencv::prelude::*;
+
+#[cfg(feature = "dnn")]
+use crate::converter;
+use crate::{blend_weighted, grayscaled_to_edged, read_image, segment_colors, ConvertOptions};
+
+/// Network input side of MiDaS v2.1 small.
+#[cfg(feature = "dnn")]
+const MIDAS_SIZE: i32 = 256;
+
+/// Where the depth map comes from.
+///
+/// Parses from and prints as `file:PATH` or `midas:MODEL`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "kebab-case"))]
+pub enum DepthSource {
+    /// Grayscale image, brighter is nearer (MiDaS' inverse depth convention).
+    File(PathBuf),
+    /// MiDaS monocular depth network, e.g. `midas_v21_small_256.onnx`.
+    #[cfg(feature = "dnn")]
+    Midas(PathBuf),
+}
+
+impl fmt::Display for DepthSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DepthSource::File(path) => write!(f, "file:{}", path.display()),
+            #[cfg(feature = "dnn")]
+            DepthSource::Midas(model) => write!(f, "midas:{}", model.display()),
+        }
+    }
+}
+
+impl FromStr for DepthSource {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("file", path)) if !path.is_empty() => Ok(DepthSource::File(path.into())),
+            #[cfg(feature = "dnn")]
+            Some(("midas", model)) if !model.is_empty() => Ok(DepthSource::Midas(model.into())),
+            _ => Err(format!("unknown depth source: {}", s).into()),
+        }
+    }
+}
+
+/// Depth-aware stylization: the background is smoothed harder and loses
+/// outlines, the subject keeps its detail.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DepthOptions {
+    pub source: DepthSource,
+    /// Factor applied to the mean-shift radii at the nearest depth.
+    pub near: f64,
+    /// Factor applied to the mean-shift radii at the farthest depth.
+    pub far: f64,
+    /// Added to the adaptive threshold constant in the far half.
+    pub edge_c: f64,
+}
+
+impl DepthOptions {
+    pub fn new(source: DepthSource) -> DepthOptions {
+        DepthOptions { source, near: 0.5, far: 1.5, edge_c: 6.0 }
+    }
+}
+
+/*
+ * BGR image -> CV_32FC1 nearness map of the same size in [0, 1], 1 nearest
+ */
+pub(crate) fn depth_map(input: &Mat, options: &DepthOptions) -> Result<Mat, Box<dyn Error>> {
+    let raw = match &options.source {
+        DepthSource::File(path) => {
+            let map = read_image(path, IMREAD_GRAYSCALE | IMREAD_ANYDEPTH)?;
+            if map.empty() {
+                return Err(format!("failed to read depth map {}", path.display()).into());
+            }
+            map
+        }
+        #[cfg(feature = "dnn")]
+        DepthSource::Midas(model) => midas(input, model)?,
+    };
+    let mut scaled = Mat::default();
+    normalize(&raw, &mut scaled, 0.0, 1.0, NORM_MINMAX, CV_32F, &no_array())?;
+    let mut map = Mat::default();
+    resize(&scaled, &mut map, input.size()?, 0.0, 0.0, INTER_LINEAR)?;
+    Ok(map)
+}
+
+/*
+ * BGR image -> CV_32FC1 relative inverse depth at the network resolution
+ */
+#[cfg(feature = "dnn")]
+fn midas(input: &Mat, model: &std::path::Path) -> Result<Mat, Box<dyn Error>> {
+    let path = model.to_str().ok_or("non UTF-8 model path")?;
+    let mut net = converter::pooled("depth", model, || {
+        let net = read_net_def(path)?;
+        if net.empty()? {
+            return Err(format!("failed to load depth model {}", path).into());
+        }
+        Ok(net)
+    })?;
+    let blob = blob_from_image(
+        input,
+        1.0 / 255.0,
+        Size::new(MIDAS_SIZE, MIDAS_SIZE),
+        Scalar::new(123.675, 116.28, 103.53, 0.0),
+        true,
+        false,
+        CV_32F,
+    )?;
+    net.set_input_def(&blob)?;
+    let prediction = net.forward_single_def()?;
+
+    // 1xHxW or 1x1xHxW
+    let dims = prediction.mat_size();
+    let (height, width) = match dims.len() {
+        3 | 4 => (dims[dims.len() - 2], dims[dims.len() - 1]),
+        _ => return Err("depth model must output a 1xHxW map".into()),
+    };
+    let data = prediction.data_typed::<f32>()?;
+    if data.len() != (height * width) as usize {
+        return Err("depth model must output a single-channel map".into());
+    }
+    let mut map = Mat::new_rows_cols_with_default(height, width, CV_32FC1, Scalar::all(0.0))?;
+    map.data_typed_mut::<f32>()?.copy_from_slice(data);
+    Ok(map)
+}
+
+/*
+ * Segments the Lab image twice, with near and far radii, and mixes the two
+ * by nearness.
+ */
+pub(crate) fn segment_colors_by_depth(
+    input: &Mat,
+    nearness: &Mat,
+    options: &ConvertOptions,
+    depth: &DepthOptions,
+) -> Result<Mat, Box<dyn Error>> {
+    let scaled = |factor: f64| ConvertOptions {
+        spatial_radius: options.spatial_radius.scaled(factor),
+        color_radius: options.color_radius.scaled(factor),
+        ..options.clone()
+    };
+    let detailed = segment_colors(input, &scaled(depth.near))?;
+    let flat = segment_colors(input, &scaled(depth.far))?;
+    blend_weighted(&detailed, &flat, nearness)
+}
+
+/*
+ * Re-thresholds the far half of the grayscaled image with a higher constant,
+ * so background texture does not turn into outlines.
+ */
+pub(crate) fn refine_edge(
+    edge: &mut Mat,
+    gray: &Mat,
+    nearness: &Mat,
+    options: &ConvertOptions,
+    depth: &DepthOptions,
+) -> Result<(), Box<dyn Error>> {
+    let far = ConvertOptions { threshold_c: options.threshold_c + depth.edge_c, ..options.clone() };
+    let lines = grayscaled_to_edged(gray, &far)?;
+    let mut background = Mat::default();
+    in_range(nearness, &Scalar::all(0.0), &Scalar::all(0.5), &mut background)?;
+    lines.copy_to_masked(edge, &background)?;
+    Ok(())
+}
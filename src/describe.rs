@@ -0,0 +1,381 @@
+use std::fmt::Write;
+
+use crate::palette::bgr_to_hex;
+use crate::{AsciiMode, ConvertOptions, EdgeDetector, Iterations, LutStage, Pipeline, Smoothing, StagePoint};
+
+/// What a pipeline does with its options: the stages that run, what each
+/// reads, and the steps inside them with their parameters. Documentation and
+/// UIs render it to show what a preset actually does, e.g. as a graph with
+/// [`PipelineDescription::to_dot`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PipelineDescription {
+    /// In the order they run, named as for [`Pipeline::on_stage`].
+    pub stages: Vec<StageDescription>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StageDescription {
+    pub name: &'static str,
+    /// Stages whose output this one reads; the base and edge branches both
+    /// read `analyze`, and `merge` reads both.
+    pub inputs: Vec<&'static str>,
+    /// Enabled steps, in order.
+    pub steps: Vec<StepDescription>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StepDescription {
+    pub name: &'static str,
+    /// Values as the recipe writes them, under its key names.
+    pub params: Vec<(&'static str, String)>,
+}
+
+impl Pipeline {
+    /// The stages and steps a conversion with these options runs, see
+    /// [`PipelineDescription`].
+    pub fn describe(&self) -> PipelineDescription {
+        describe(self.options())
+    }
+}
+
+impl PipelineDescription {
+    /// The stages as a Graphviz digraph, one node per stage listing its
+    /// steps and parameters, e.g. for `dot -Tsvg`.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph nftimg {\n    rankdir=LR;\n    node [shape=box, fontname=\"monospace\"];\n");
+        for stage in &self.stages {
+            let mut label = stage.name.to_string();
+            for step in &stage.steps {
+                label.push_str(&format!("\\n{}", step.name));
+                for (key, value) in &step.params {
+                    label.push_str(&format!("\\l  {}={}", key, value));
+                }
+            }
+            if !stage.steps.is_empty() {
+                label.push_str("\\l");
+            }
+            let _ = writeln!(dot, "    \"{}\" [label=\"{}\"];", stage.name, label.replace('"', "\\\""));
+            for input in &stage.inputs {
+                let _ = writeln!(dot, "    \"{}\" -> \"{}\";", input, stage.name);
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/*
+ * Mirrors `load`, `process` and `cartoonize`: a step is listed when its
+ * option enables it
+ */
+pub(crate) fn describe(options: &ConvertOptions) -> PipelineDescription {
+    let mut stages = Vec::new();
+
+    let mut decode = vec![step("decode", vec![("auto_orient", options.auto_orient.to_string())])];
+    if let Some(max_pixels) = options.max_pixels {
+        let limit = if options.downscale_oversized { "downscale" } else { "limit" };
+        decode.push(step(limit, vec![("max_pixels", max_pixels.to_string())]));
+    }
+    if let Some(profile) = options.input_profile {
+        decode.push(step("color_profile", vec![("input_profile", profile.name().to_string())]));
+    }
+    stages.push(stage("decode", &[], decode));
+
+    let mut prepare = Vec::new();
+    if options.auto {
+        prepare.push(step("analyze", vec![("auto", "true".to_string())]));
+    }
+    #[cfg(feature = "white-balance")]
+    if let Some(white_balance) = &options.white_balance {
+        prepare.push(step("white_balance", vec![("white_balance", white_balance.to_string())]));
+    }
+    if let Some(lut) = options.lut.as_ref().filter(|l| l.stage == LutStage::Pre) {
+        prepare.push(step("lut", vec![("lut", lut.path.display().to_string())]));
+    }
+    if let Some(denoise) = &options.denoise {
+        prepare.push(step("denoise", vec![("denoise", denoise.to_string())]));
+    }
+    prepare.extend(custom_stages(options, StagePoint::Input));
+    stages.push(stage("prepare", &["decode"], prepare));
+
+    #[cfg(feature = "dnn")]
+    let stylized = options
+        .style
+        .as_ref()
+        .map(|style| stage("style", &["prepare"], vec![step("style", vec![("style", style.to_string())])]));
+    #[cfg(not(feature = "dnn"))]
+    let stylized = None;
+    let last = match stylized {
+        Some(style) => {
+            stages.push(style);
+            "style"
+        }
+        None => {
+            stages.extend(cartoonize(options));
+            "merge"
+        }
+    };
+
+    #[cfg(feature = "superres")]
+    let last = match &options.upscale {
+        Some(upscale) => {
+            let params = vec![
+                ("upscale_algorithm", upscale.algorithm.to_string()),
+                ("upscale_scale", upscale.scale.to_string()),
+            ];
+            stages.push(stage("upscale", &[last], vec![step("upscale", params)]));
+            "upscale"
+        }
+        None => last,
+    };
+
+    stages.push(stage("finish", &[last], finish(options)));
+
+    let metadata = &options.metadata;
+    let mut encode = vec![step("encode", Vec::new())];
+    if metadata.embed_recipe || !metadata.exif_fields.is_empty() || metadata.xmp.is_some() {
+        let fields: Vec<String> = metadata.exif_fields.iter().map(|f| format!("{:?}", f)).collect();
+        let params = vec![
+            ("embed_recipe", metadata.embed_recipe.to_string()),
+            ("keep_exif", fields.join(",")),
+            ("xmp", metadata.xmp.is_some().to_string()),
+        ];
+        encode.push(step("metadata", params));
+    }
+    stages.push(stage("encode", &["finish"], encode));
+    PipelineDescription { stages }
+}
+
+fn cartoonize(options: &ConvertOptions) -> Vec<StageDescription> {
+    let mut analyze = vec![step("lab", Vec::new())];
+    if let Some(clahe) = &options.clahe {
+        let params = vec![
+            ("clahe_clip_limit", clahe.clip_limit.to_string()),
+            ("clahe_tile_grid", clahe.tile_grid.to_string()),
+        ];
+        analyze.push(step("clahe", params));
+    }
+    if let Some(depth) = &options.depth {
+        analyze.push(step("depth", vec![("depth", depth.source.to_string())]));
+    }
+    if options.portrait.is_some() {
+        analyze.push(step("skin", Vec::new()));
+    }
+    #[cfg(feature = "faces")]
+    if let Some(faces) = &options.faces {
+        analyze.push(step("faces", vec![("face_cascade", faces.cascade.display().to_string())]));
+    }
+    #[cfg(feature = "dnn")]
+    if let Some(segmentation) = &options.segmentation {
+        analyze.push(step("segmentation", vec![("segmentation_model", segmentation.model.display().to_string())]));
+    }
+
+    let smoothing = match &options.smoothing {
+        Smoothing::MeanShift => vec![
+            ("smoothing", options.smoothing.to_string()),
+            ("spatial_radius", options.spatial_radius.to_string()),
+            ("color_radius", options.color_radius.to_string()),
+            ("max_pyramid_level", options.max_pyramid_level.to_string()),
+        ],
+        smoothing => vec![("smoothing", smoothing.to_string())],
+    };
+    let mut base = vec![step("smoothing", smoothing)];
+    #[cfg(feature = "saliency")]
+    if let Some(saliency) = &options.saliency {
+        let params = vec![
+            ("saliency_detail", saliency.detail.to_string()),
+            ("saliency_flat", saliency.flat.to_string()),
+        ];
+        base.push(step("saliency", params));
+    }
+    if let Some(depth) = &options.depth {
+        base.push(step("depth_smoothing", vec![("depth_near", depth.near.to_string()), ("depth_far", depth.far.to_string())]));
+    }
+    if let Some(portrait) = &options.portrait {
+        base.push(step("skin_smoothing", vec![("portrait_smoothing", portrait.smoothing.to_string())]));
+    }
+    #[cfg(feature = "faces")]
+    if let Some(faces) = &options.faces {
+        base.push(step("face_detail", vec![("face_smoothing", faces.smoothing.to_string())]));
+    }
+    if let Some(posterize) = &options.posterize {
+        let params = vec![
+            ("posterize_colors", posterize.colors.to_string()),
+            ("posterize_dither", posterize.dither.to_string()),
+        ];
+        base.push(step("posterize", params));
+    }
+    if let Some(palette) = &options.palette {
+        base.push(step("palette", vec![("palette", palette.to_string())]));
+    }
+    if let Some(gradient) = &options.gradient_map {
+        base.push(step("gradient_map", vec![("gradient_map", gradient.to_string())]));
+    }
+    if let Some(halftone) = &options.halftone {
+        let params = vec![
+            ("halftone_dot_size", halftone.dot_size.to_string()),
+            ("halftone_angle", halftone.angle.to_string()),
+            ("halftone_mode", halftone.mode.to_string()),
+        ];
+        base.push(step("halftone", params));
+    }
+    if let Some(hatch) = &options.hatch {
+        let params = vec![
+            ("hatch_spacing", hatch.spacing.to_string()),
+            ("hatch_line_width", hatch.line_width.to_string()),
+            ("hatch_angle", hatch.angle.to_string()),
+        ];
+        base.push(step("hatch", params));
+    }
+    base.extend(custom_stages(options, StagePoint::Base));
+
+    let diffusion = if cfg!(feature = "ximgproc") {
+        step(
+            "anisotropic_diffusion",
+            vec![
+                ("diffusion_alpha", options.diffusion_alpha.to_string()),
+                ("diffusion_k", options.diffusion_k.to_string()),
+                ("diffusion_iterations", options.diffusion_iterations.to_string()),
+            ],
+        )
+    } else {
+        step("bilateral_filter", Vec::new())
+    };
+    let mut edges = vec![diffusion];
+    edges.push(match &options.edge_detector {
+        EdgeDetector::AdaptiveThreshold => step(
+            "adaptive_threshold",
+            vec![
+                ("threshold_block_size", options.threshold_block_size.to_string()),
+                ("threshold_c", options.threshold_c.to_string()),
+                ("edge_scales", options.edge_scales.to_string()),
+            ],
+        ),
+        EdgeDetector::Structured { .. } => step("structured_edges", vec![("edge_detector", options.edge_detector.to_string())]),
+    });
+    edges.push(match &options.stroke {
+        Some(stroke) => step(
+            "strokes",
+            vec![
+                ("stroke_min_width", stroke.min_width.to_string()),
+                ("stroke_max_width", stroke.max_width.to_string()),
+            ],
+        ),
+        None => step(
+            "dilate",
+            vec![
+                ("dilate_kernel_size", options.dilate_kernel_size.to_string()),
+                ("dilate_kernel_shape", options.dilate_kernel_shape.to_string()),
+                ("dilate_iterations", options.dilate_iterations.map_or(0, Iterations::get).to_string()),
+            ],
+        ),
+    });
+    if !options.morphology.is_empty() {
+        let steps: Vec<String> = options.morphology.iter().map(ToString::to_string).collect();
+        edges.push(step("morphology", vec![("morphology", steps.join(","))]));
+    }
+    if options.min_edge_area > 0.0 {
+        edges.push(step("remove_speckle", vec![("min_edge_area", options.min_edge_area.to_string())]));
+    }
+    edges.extend(custom_stages(options, StagePoint::Edges));
+
+    let mut merge = Vec::new();
+    if let Some(pixelart) = &options.pixelart {
+        let params = vec![
+            ("pixelart_cells", pixelart.cells.to_string()),
+            ("pixelart_colors", pixelart.colors.to_string()),
+            ("pixelart_outline", pixelart.outline.to_string()),
+        ];
+        merge.push(step("pixelart", params));
+    }
+    merge.push(match (&options.popart, &options.edge_style) {
+        (Some(popart), _) => step(
+            "popart",
+            vec![
+                ("popart_rows", popart.rows.to_string()),
+                ("popart_cols", popart.cols.to_string()),
+                ("popart_levels", popart.levels.to_string()),
+            ],
+        ),
+        (None, Some(style)) => step(
+            "composite",
+            vec![
+                ("edge_color", bgr_to_hex(style.color)),
+                ("edge_opacity", style.opacity.to_string()),
+                ("edge_feather", style.feather.to_string()),
+            ],
+        ),
+        (None, None) => step("combine", Vec::new()),
+    });
+
+    vec![
+        stage("analyze", &["prepare"], analyze),
+        stage("base", &["analyze"], base),
+        stage("edges", &["analyze"], edges),
+        stage("merge", &["base", "edges"], merge),
+    ]
+}
+
+fn finish(options: &ConvertOptions) -> Vec<StepDescription> {
+    let mut finish = Vec::new();
+    if let Some(lut) = options.lut.as_ref().filter(|l| l.stage == LutStage::Post) {
+        finish.push(step("lut", vec![("lut", lut.path.display().to_string())]));
+    }
+    if let Some(adjust) = &options.adjust {
+        let params = vec![
+            ("adjust_saturation", adjust.saturation.to_string()),
+            ("adjust_brightness", adjust.brightness.to_string()),
+            ("adjust_contrast", adjust.contrast.to_string()),
+            ("adjust_hue", adjust.hue.to_string()),
+        ];
+        finish.push(step("adjust", params));
+    }
+    if let Some(sharpen) = &options.sharpen {
+        let params = vec![
+            ("sharpen_amount", sharpen.amount.to_string()),
+            ("sharpen_radius", sharpen.radius.to_string()),
+            ("sharpen_threshold", sharpen.threshold.to_string()),
+        ];
+        finish.push(step("sharpen", params));
+    }
+    if let Some(vignette) = &options.vignette {
+        let params = vec![
+            ("vignette_strength", vignette.strength.to_string()),
+            ("vignette_radius", vignette.radius.to_string()),
+            ("vignette_feather", vignette.feather.to_string()),
+        ];
+        finish.push(step("vignette", params));
+    }
+    if let Some(texture) = &options.texture {
+        let params = vec![
+            ("texture", texture.texture.to_string()),
+            ("texture_opacity", texture.opacity.to_string()),
+            ("texture_blend", texture.blend.to_string()),
+        ];
+        finish.push(step("texture", params));
+    }
+    finish.extend(custom_stages(options, StagePoint::Output));
+    if let Some(ascii) = options.ascii.as_ref().filter(|a| a.mode == AsciiMode::Image) {
+        finish.push(step("ascii", vec![("ascii_columns", ascii.columns.to_string())]));
+    }
+    finish
+}
+
+fn custom_stages(options: &ConvertOptions, point: StagePoint) -> impl Iterator<Item = StepDescription> + '_ {
+    options
+        .stages
+        .iter()
+        .filter(move |stage| stage.point == point)
+        .map(|stage| step("custom", vec![("stages", stage.to_string())]))
+}
+
+fn stage(name: &'static str, inputs: &[&'static str], steps: Vec<StepDescription>) -> StageDescription {
+    StageDescription { name, inputs: inputs.to_vec(), steps }
+}
+
+fn step(name: &'static str, params: Vec<(&'static str, String)>) -> StepDescription {
+    StepDescription { name, params }
+}
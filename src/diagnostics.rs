@@ -0,0 +1,125 @@
+use std::error::Error;
+use std::fmt;
+
+use opencv::core::{
+    get_build_information, get_cuda_enabled_device_count, get_num_threads, get_version_string, have_opencl, Scalar,
+    Vector, CV_8UC3,
+};
+use opencv::imgcodecs::{have_image_writer, imdecode, imencode, IMREAD_COLOR};
+use opencv::prelude::*;
+
+/// OpenCV modules every build needs.
+const REQUIRED_MODULES: &[&str] = &["core", "imgproc", "imgcodecs", "photo"];
+
+/// Cargo features of this crate and the OpenCV module each one needs.
+const FEATURE_MODULES: &[(&str, &str, bool)] = &[
+    ("ximgproc", "ximgproc", cfg!(feature = "ximgproc")),
+    ("faces", "objdetect", cfg!(feature = "faces")),
+    ("saliency", "saliency", cfg!(feature = "saliency")),
+    ("white-balance", "xphoto", cfg!(feature = "white-balance")),
+    ("dnn", "dnn", cfg!(feature = "dnn")),
+    ("superres", "dnn_superres", cfg!(feature = "superres")),
+];
+
+/// Image formats inputs are accepted in, see `metadata::sniff_format`.
+const FORMATS: &[&str] = &["jpeg", "png", "bmp", "webp", "tiff"];
+
+/// What the linked OpenCV provides, for bug reports and `nftimg doctor`.
+/// Printed with `Display` as one `key: value` line per entry.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Diagnostics {
+    pub opencv_version: String,
+    /// OpenCV modules the library was built with, from its build information.
+    pub modules: Vec<String>,
+    /// Cargo features of this crate compiled in.
+    pub features: Vec<&'static str>,
+    /// Whether OpenCL devices are available to OpenCV's transparent API.
+    pub opencl: bool,
+    /// CUDA devices usable by OpenCV, 0 for builds without CUDA.
+    pub cuda_devices: i32,
+    pub threads: i32,
+    /// Formats that survive an encode and decode round trip.
+    pub codecs: Vec<&'static str>,
+}
+
+impl Diagnostics {
+    /// Modules needed by the required parts or the compiled-in features
+    /// that the library lacks; conversions using them will fail.
+    pub fn missing_modules(&self) -> Vec<&'static str> {
+        let needed = FEATURE_MODULES.iter().filter(|(_, _, enabled)| *enabled).map(|(_, module, _)| module);
+        REQUIRED_MODULES
+            .iter()
+            .chain(needed)
+            .filter(|module| !self.modules.iter().any(|m| m == *module))
+            .copied()
+            .collect()
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "nftimg: {}", env!("CARGO_PKG_VERSION"))?;
+        writeln!(f, "opencv: {}", self.opencv_version)?;
+        writeln!(f, "modules: {}", self.modules.join(" "))?;
+        writeln!(f, "features: {}", self.features.join(" "))?;
+        writeln!(f, "opencl: {}", if self.opencl { "available" } else { "unavailable" })?;
+        writeln!(f, "cuda devices: {}", self.cuda_devices)?;
+        writeln!(f, "threads: {}", self.threads)?;
+        writeln!(f, "codecs: {}", self.codecs.join(" "))?;
+        let missing = self.missing_modules();
+        if !missing.is_empty() {
+            writeln!(f, "missing modules: {}", missing.join(" "))?;
+        }
+        let unsupported: Vec<&str> = FORMATS.iter().filter(|format| !self.codecs.contains(format)).copied().collect();
+        if !unsupported.is_empty() {
+            writeln!(f, "unsupported formats: {}", unsupported.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Inspects the OpenCV library linked at runtime.
+pub fn diagnostics() -> Result<Diagnostics, Box<dyn Error>> {
+    let build = get_build_information()?;
+    // "    To be built:                 calib3d core dnn ..."
+    let modules = build
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("To be built:"))
+        .map(|list| list.split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default();
+    let features = FEATURE_MODULES.iter().filter(|(_, _, enabled)| *enabled).map(|(name, _, _)| *name).collect();
+
+    let mut codecs = Vec::new();
+    for format in FORMATS {
+        if round_trips(format)? {
+            codecs.push(*format);
+        }
+    }
+    Ok(Diagnostics {
+        opencv_version: get_version_string()?,
+        modules,
+        features,
+        opencl: have_opencl()?,
+        cuda_devices: get_cuda_enabled_device_count()?,
+        threads: get_num_threads()?,
+        codecs,
+    })
+}
+
+/*
+ * Encodes and decodes a small image; haveImageReader needs an existing file.
+ */
+fn round_trips(format: &str) -> Result<bool, Box<dyn Error>> {
+    let extension = format!(".{}", format);
+    if !have_image_writer(&extension)? {
+        return Ok(false);
+    }
+    let image = Mat::new_rows_cols_with_default(8, 8, CV_8UC3, Scalar::all(128.0))?;
+    let mut encoded = Vector::<u8>::new();
+    // codecs built without support fail with an OpenCV error
+    if !imencode(&extension, &image, &mut encoded, &Vector::default()).unwrap_or(false) {
+        return Ok(false);
+    }
+    Ok(imdecode(&encoded, IMREAD_COLOR).is_ok_and(|decoded| !decoded.empty()))
+}
@@ -0,0 +1,74 @@
+use std::cell::RefCell;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use opencv::prelude::*;
+
+use crate::naming::write_atomic;
+use crate::{encode_image, lab_to_bgr};
+
+thread_local! {
+    /// Directory intermediates are dumped into and how many were, see
+    /// [`with_dump_dir`].
+    static DUMP: RefCell<Option<(PathBuf, u32)>> = const { RefCell::new(None) };
+}
+
+/*
+ * Runs `run` with every intermediate it dumps written into `dir`, created if
+ * missing, numbered from 01 in the order they were produced
+ */
+pub(crate) fn with_dump_dir<T>(
+    dir: &Path,
+    run: impl FnOnce() -> Result<T, Box<dyn Error>>,
+) -> Result<T, Box<dyn Error>> {
+    // restored on unwinding too, and for conversions nested in a stage
+    struct Restore(Option<(PathBuf, u32)>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            DUMP.set(self.0.take());
+        }
+    }
+    fs::create_dir_all(dir)?;
+    let _restore = Restore(DUMP.replace(Some((dir.to_path_buf(), 0))));
+    run()
+}
+
+/*
+ * Writes `image`, a BGR image or mask, as `NN_<step>_<params>.png` when a
+ * dump directory is set; `params` is only formatted then
+ */
+pub(crate) fn dump(step: &str, params: impl FnOnce() -> String, image: &Mat) -> Result<(), Box<dyn Error>> {
+    let Some(path) = next_path(step, params) else {
+        return Ok(());
+    };
+    write_atomic(&path, &encode_image("png", image)?)
+}
+
+/*
+ * dump for Lab images, converted to BGR first
+ */
+pub(crate) fn dump_lab(step: &str, params: impl FnOnce() -> String, image: &Mat) -> Result<(), Box<dyn Error>> {
+    let Some(path) = next_path(step, params) else {
+        return Ok(());
+    };
+    write_atomic(&path, &encode_image("png", &lab_to_bgr(image)?)?)
+}
+
+fn next_path(step: &str, params: impl FnOnce() -> String) -> Option<PathBuf> {
+    let (dir, count) = DUMP.with_borrow_mut(|dump| {
+        let (dir, count) = dump.as_mut()?;
+        *count += 1;
+        Some((dir.clone(), *count))
+    })?;
+    // recipe values hold `:` and `/`, which file names cannot
+    let params: String = params()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') { c } else { '-' })
+        .collect();
+    if params.is_empty() {
+        Some(dir.join(format!("{:02}_{}.png", count, step)))
+    } else {
+        Some(dir.join(format!("{:02}_{}_{}.png", count, step, params)))
+    }
+}
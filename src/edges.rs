@@ -0,0 +1,288 @@
+use std::error::Error;
+use std::fmt;
+#[cfg(feature = "ximgproc")]
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use opencv::core::{bitwise_and, bitwise_not_def, no_array, Point, Scalar, Size, Vector, BORDER_DEFAULT, BORDER_REFLECT};
+#[cfg(feature = "ximgproc")]
+use opencv::core::{CV_32F, CV_8U};
+use opencv::imgproc::{
+    arc_length, contour_area, draw_contours, find_contours, get_structuring_element, morphology_ex, pyr_down, resize,
+    CHAIN_APPROX_NONE, INTER_NEAREST, FILLED, LINE_8, MORPH_CLOSE, MORPH_CROSS, MORPH_DILATE, MORPH_ELLIPSE,
+    MORPH_ERODE, MORPH_OPEN, MORPH_RECT, RETR_EXTERNAL,
+};
+#[cfg(feature = "ximgproc")]
+use opencv::imgproc::{cvt_color, threshold, COLOR_BGR2RGB, THRESH_BINARY_INV};
+use opencv::prelude::*;
+#[cfg(feature = "ximgproc")]
+use opencv::ximgproc::create_structured_edge_detection_def;
+
+use crate::progress;
+#[cfg(feature = "ximgproc")]
+use crate::thicken_edges;
+use crate::{grayscaled_to_edged, ConvertOptions};
+
+/// How outlines are found on the border branch.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "kebab-case"))]
+pub enum EdgeDetector {
+    /// Adaptive mean threshold of the blurred lightness.
+    #[default]
+    AdaptiveThreshold,
+    /// ximgproc structured forests, with a trained model such as OpenCV's
+    /// `model.yml.gz`. Cleaner, perceptually meaningful outlines; edges
+    /// stronger than `threshold` (in [0, 1]) are drawn.
+    Structured { model: PathBuf, threshold: f64 },
+}
+
+impl fmt::Display for EdgeDetector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EdgeDetector::AdaptiveThreshold => write!(f, "adaptive"),
+            EdgeDetector::Structured { model, threshold } => write!(f, "sed:{}:{}", model.display(), threshold),
+        }
+    }
+}
+
+impl FromStr for EdgeDetector {
+    type Err = Box<dyn Error>;
+
+    /// Parses `adaptive` or `sed:MODEL[:THRESHOLD]`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "adaptive" {
+            return Ok(EdgeDetector::AdaptiveThreshold);
+        }
+        let model = s.strip_prefix("sed:").ok_or_else(|| format!("unknown edge detector: {}", s))?;
+        // the model path may itself contain colons
+        Ok(match model.rsplit_once(':').map(|(path, t)| (path, t.parse::<f64>())) {
+            Some((path, Ok(threshold))) => EdgeDetector::Structured { model: path.into(), threshold },
+            _ => EdgeDetector::Structured { model: model.into(), threshold: 0.1 },
+        })
+    }
+}
+
+/// Structuring element shape.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "kebab-case"))]
+pub enum KernelShape {
+    #[default]
+    Rect,
+    Ellipse,
+    Cross,
+}
+
+impl KernelShape {
+    fn morph_shape(self) -> i32 {
+        match self {
+            KernelShape::Rect => MORPH_RECT,
+            KernelShape::Ellipse => MORPH_ELLIPSE,
+            KernelShape::Cross => MORPH_CROSS,
+        }
+    }
+
+    pub(crate) fn kernel(self, size: i32) -> Result<Mat, Box<dyn Error>> {
+        Ok(get_structuring_element(self.morph_shape(), Size::new(size, size), Point::new(-1, -1))?)
+    }
+}
+
+impl fmt::Display for KernelShape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KernelShape::Rect => write!(f, "rect"),
+            KernelShape::Ellipse => write!(f, "ellipse"),
+            KernelShape::Cross => write!(f, "cross"),
+        }
+    }
+}
+
+impl FromStr for KernelShape {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rect" => Ok(KernelShape::Rect),
+            "ellipse" => Ok(KernelShape::Ellipse),
+            "cross" => Ok(KernelShape::Cross),
+            _ => Err(format!("unknown kernel shape: {}", s).into()),
+        }
+    }
+}
+
+/// Morphological operation on the outlines (the black pixels of the edge
+/// mask), applied after the dilation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "kebab-case"))]
+pub enum MorphOp {
+    /// Thicken the outlines.
+    Dilate,
+    /// Thin the outlines.
+    Erode,
+    /// Remove outline bits smaller than the kernel.
+    Open,
+    /// Connect broken outlines.
+    Close,
+}
+
+/// One step of [`crate::ConvertOptions::morphology`], written
+/// `op:shape:size[:iterations]`, e.g. `close:ellipse:3`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Morphology {
+    pub op: MorphOp,
+    pub shape: KernelShape,
+    pub size: i32,
+    pub iterations: i32,
+}
+
+impl fmt::Display for Morphology {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match self.op {
+            MorphOp::Dilate => "dilate",
+            MorphOp::Erode => "erode",
+            MorphOp::Open => "open",
+            MorphOp::Close => "close",
+        };
+        write!(f, "{}:{}:{}:{}", op, self.shape, self.size, self.iterations)
+    }
+}
+
+impl FromStr for Morphology {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+        if parts.len() < 3 || parts.len() > 4 {
+            return Err(format!("expected op:shape:size[:iterations], got {}", s).into());
+        }
+        let op = match parts[0] {
+            "dilate" => MorphOp::Dilate,
+            "erode" => MorphOp::Erode,
+            "open" => MorphOp::Open,
+            "close" => MorphOp::Close,
+            _ => return Err(format!("unknown morphological operation: {}", parts[0]).into()),
+        };
+        Ok(Morphology {
+            op,
+            shape: parts[1].parse()?,
+            size: parts[2].parse()?,
+            iterations: parts.get(3).map_or(Ok(1), |i| i.parse())?,
+        })
+    }
+}
+
+/*
+ * Edge mask -> edge mask with the operations applied in order. Outlines are
+ * black, so each operation runs as its dual on the mask.
+ */
+pub(crate) fn apply_morphology(edge: &Mat, steps: &[Morphology]) -> Result<Mat, Box<dyn Error>> {
+    let mut output = edge.try_clone()?;
+    for step in steps {
+        let op = match step.op {
+            MorphOp::Dilate => MORPH_ERODE,
+            MorphOp::Erode => MORPH_DILATE,
+            MorphOp::Open => MORPH_CLOSE,
+            MorphOp::Close => MORPH_OPEN,
+        };
+        let kernel = step.shape.kernel(step.size)?;
+        let mut next = Mat::default();
+        let anchor = Point::new(-1, -1);
+        morphology_ex(&output, &mut next, op, &kernel, anchor, step.iterations, BORDER_REFLECT, Scalar::default())?;
+        output = next;
+    }
+    Ok(output)
+}
+
+/*
+ * Edge mask (0 on lines) -> the same mask without the line components
+ * smaller than `min_area` pixels, i.e. the pepper noise adaptive
+ * thresholding leaves in flat regions.
+ */
+pub(crate) fn remove_speckle(edge: &Mat, min_area: f64) -> Result<Mat, Box<dyn Error>> {
+    let mut lines = Mat::default();
+    bitwise_not_def(edge, &mut lines)?;
+    let mut contours = Vector::<Vector<Point>>::new();
+    find_contours(&lines, &mut contours, RETR_EXTERNAL, CHAIN_APPROX_NONE, Point::new(0, 0))?;
+
+    let mut speckles = Vector::<Vector<Point>>::new();
+    for contour in contours {
+        // thin lines enclose almost no area, half the perimeter is their length
+        let size = contour_area(&contour, false)?.max(arc_length(&contour, true)? / 2.0);
+        if size < min_area {
+            speckles.push(contour);
+        }
+    }
+    let mut output = edge.try_clone()?;
+    if !speckles.is_empty() {
+        let white = Scalar::all(255.0);
+        draw_contours(&mut output, &speckles, -1, white, FILLED, LINE_8, &no_array(), i32::MAX, Point::new(0, 0))?;
+    }
+    Ok(output)
+}
+
+/*
+ * Grayscaled image -> edge mask combining the outlines found on each level of
+ * a Gaussian pyramid: fine texture from the full resolution, structure from
+ * the coarse levels.
+ */
+pub(crate) fn multi_scale(input: &Mat, options: &ConvertOptions) -> Result<Mat, Box<dyn Error>> {
+    let mut output = grayscaled_to_edged(input, options)?;
+    let mut level = input.try_clone()?;
+    let levels = options.edge_scales as u32;
+    progress::advance("edge_scales", 1, levels);
+    for scale in 2..=levels {
+        let block_size = i32::from(options.threshold_block_size);
+        if level.cols() < 2 * block_size || level.rows() < 2 * block_size {
+            progress::advance("edge_scales", levels, levels);
+            break;
+        }
+        let mut smaller = Mat::default();
+        pyr_down(&level, &mut smaller, Size::default(), BORDER_DEFAULT)?;
+        level = smaller;
+
+        let coarse = grayscaled_to_edged(&level, options)?;
+        let mut upscaled = Mat::default();
+        resize(&coarse, &mut upscaled, input.size()?, 0.0, 0.0, INTER_NEAREST)?;
+        // outlines are 0, so and-ing the masks unites them
+        let mut combined = Mat::default();
+        bitwise_and(&output, &upscaled, &mut combined, &no_array())?;
+        output = combined;
+        progress::advance("edge_scales", scale, levels);
+    }
+    Ok(output)
+}
+
+/*
+ * BGR image -> edge mask from structured edge detection with non-maximum
+ * suppression, thickened like the adaptive-threshold outlines.
+ */
+#[cfg(feature = "ximgproc")]
+pub(crate) fn structured(
+    input: &Mat,
+    gray: &Mat,
+    model: &Path,
+    strength: f64,
+    options: &ConvertOptions,
+) -> Result<Mat, Box<dyn Error>> {
+    let model = model.to_str().ok_or("non UTF-8 model path")?;
+    let detector = create_structured_edge_detection_def(model)?;
+
+    let mut rgb = Mat::default();
+    cvt_color(input, &mut rgb, COLOR_BGR2RGB, 0)?;
+    let mut image = Mat::default();
+    rgb.convert_to(&mut image, CV_32F, 1.0 / 255.0, 0.0)?;
+    let mut edges = Mat::default();
+    detector.detect_edges(&image, &mut edges)?;
+    let mut orientation = Mat::default();
+    detector.compute_orientation(&edges, &mut orientation)?;
+    let mut thin = Mat::default();
+    detector.edges_nms(&edges, &orientation, &mut thin, 2, 0, 1.0, true)?;
+
+    // strong edges become the black outlines of the mask
+    let mut mask = Mat::default();
+    threshold(&thin, &mut mask, strength, 1.0, THRESH_BINARY_INV)?;
+    let mut binary = Mat::default();
+    mask.convert_to(&mut binary, CV_8U, 255.0, 0.0)?;
+    thicken_edges(&binary, gray, options)
+}
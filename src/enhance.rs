@@ -0,0 +1,101 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use opencv::core::{merge, split, Size, Vector};
+use opencv::imgproc::{create_clahe, median_blur};
+use opencv::photo::fast_nl_means_denoising_colored;
+use opencv::prelude::*;
+
+/// Contrast-limited adaptive histogram equalization of the lightness, to
+/// recover detail in flat, low-contrast photos before segmentation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct ClaheOptions {
+    /// Histogram clip limit; higher values give more contrast and more noise.
+    pub clip_limit: f64,
+    /// Tiles per side of the equalization grid.
+    pub tile_grid: i32,
+}
+
+impl Default for ClaheOptions {
+    fn default() -> Self {
+        ClaheOptions { clip_limit: 2.0, tile_grid: 8 }
+    }
+}
+
+/*
+ * Lab image -> Lab image with CLAHE applied to L
+ */
+pub(crate) fn clahe(input: &Mat, options: &ClaheOptions) -> Result<Mat, Box<dyn Error>> {
+    let mut channels = Vector::<Mat>::new();
+    split(input, &mut channels)?;
+    let mut equalizer = create_clahe(options.clip_limit, Size::new(options.tile_grid, options.tile_grid))?;
+    let mut lightness = Mat::default();
+    equalizer.apply(&channels.get(0)?, &mut lightness)?;
+    channels.set(0, lightness)?;
+    let mut output = Mat::default();
+    merge(&channels, &mut output)?;
+    Ok(output)
+}
+
+/// Denoising before segmentation and edge detection: sensor noise otherwise
+/// turns into adaptive-threshold speckles that dilation makes worse.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "kebab-case"))]
+pub enum Denoise {
+    /// Median filter with an odd aperture, fast.
+    Median { size: i32 },
+    /// Non-local means, slower and better on high-ISO photos. `h` filters
+    /// lightness, `h_color` the color components.
+    NlMeans { h: f32, h_color: f32 },
+}
+
+impl Default for Denoise {
+    fn default() -> Self {
+        Denoise::NlMeans { h: 6.0, h_color: 6.0 }
+    }
+}
+
+impl fmt::Display for Denoise {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Denoise::Median { size } => write!(f, "median:{}", size),
+            Denoise::NlMeans { h, h_color } => write!(f, "nlmeans:{}:{}", h, h_color),
+        }
+    }
+}
+
+impl FromStr for Denoise {
+    type Err = Box<dyn Error>;
+
+    /// Parses `median[:SIZE]` or `nlmeans[:H:H_COLOR]`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        match parts.next() {
+            Some("median") => Ok(Denoise::Median { size: parts.next().map_or(Ok(5), str::parse)? }),
+            Some("nlmeans") => Ok(Denoise::NlMeans {
+                h: parts.next().map_or(Ok(6.0), str::parse)?,
+                h_color: parts.next().map_or(Ok(6.0), str::parse)?,
+            }),
+            _ => Err(format!("unknown denoise method: {}", s).into()),
+        }
+    }
+}
+
+/*
+ * BGR image -> denoised BGR image
+ */
+pub(crate) fn denoise(input: &Mat, method: &Denoise) -> Result<Mat, Box<dyn Error>> {
+    let mut output = Mat::default();
+    match *method {
+        Denoise::Median { size } => {
+            if size < 3 || size % 2 == 0 {
+                return Err("median denoise size must be odd and at least 3".into());
+            }
+            median_blur(input, &mut output, size)?
+        }
+        Denoise::NlMeans { h, h_color } => fast_nl_means_denoising_colored(input, &mut output, h, h_color, 7, 21)?,
+    }
+    Ok(output)
+}
@@ -0,0 +1,38 @@
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+
+/// Failures callers may want to tell apart; everything else is reported as
+/// a plain message. Returned boxed, recover it with `downcast_ref`.
+#[derive(Debug)]
+pub enum NftImgError {
+    /// The input is missing, unreadable or not an image OpenCV can decode.
+    DecodeFailed { path: PathBuf },
+    /// The content is not JPEG, PNG, BMP, WebP or TIFF, whatever the
+    /// extension says.
+    UnsupportedFormat { path: PathBuf },
+    /// A stage got an image with a channel count it cannot handle.
+    UnexpectedChannels { expected: i32, found: i32 },
+    /// An option is out of the range its stage accepts, see
+    /// [`ConvertOptions::validate`](crate::ConvertOptions::validate).
+    InvalidOption { name: &'static str, value: String, expected: &'static str },
+}
+
+impl fmt::Display for NftImgError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NftImgError::DecodeFailed { path } => write!(f, "failed to decode image {}", path.display()),
+            NftImgError::UnsupportedFormat { path } => {
+                write!(f, "{} is not a JPEG, PNG, BMP, WebP or TIFF image", path.display())
+            }
+            NftImgError::UnexpectedChannels { expected, found } => {
+                write!(f, "expected a {}-channel image, got {} channels", expected, found)
+            }
+            NftImgError::InvalidOption { name, value, expected } => {
+                write!(f, "invalid {} {}, expected {}", name, value, expected)
+            }
+        }
+    }
+}
+
+impl Error for NftImgError {}
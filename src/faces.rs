@@ -0,0 +1,128 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+use opencv::core::{Point, Rect, Scalar, Size, Vector, CV_8UC1};
+use opencv::imgproc::{cvt_color, ellipse, equalize_hist, COLOR_BGR2GRAY, FILLED, LINE_8};
+use opencv::objdetect::CascadeClassifier;
+use opencv::prelude::*;
+
+use crate::converter;
+use crate::{grayscaled_to_edged, segment_colors, ConvertOptions};
+
+/// Face-aware processing: mean-shift erases facial features on portraits, so
+/// detected faces get gentler smoothing and undilated outlines.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FaceOptions {
+    /// Haar/LBP cascade, e.g. OpenCV's `haarcascade_frontalface_default.xml`.
+    pub cascade: PathBuf,
+    /// Factor applied to the mean-shift radii inside faces.
+    pub smoothing: f64,
+    /// Growth of each detection box, as a fraction of its size, so that hair
+    /// line and chin are covered.
+    pub margin: f64,
+}
+
+impl FaceOptions {
+    pub fn new(cascade: PathBuf) -> FaceOptions {
+        FaceOptions { cascade, smoothing: 0.5, margin: 0.2 }
+    }
+}
+
+/*
+ * Detects faces on the BGR input, returning their boxes grown by the margin
+ * and clamped to the image.
+ */
+pub(crate) fn detect(input: &Mat, options: &FaceOptions) -> Result<Vector<Rect>, Box<dyn Error>> {
+    let cascade_path = options.cascade.to_str().ok_or("non UTF-8 cascade path")?;
+    let mut classifier = converter::pooled("faces", &options.cascade, || {
+        let classifier = CascadeClassifier::new(cascade_path)?;
+        if classifier.empty()? {
+            return Err(format!("failed to load face cascade {}", cascade_path).into());
+        }
+        Ok(classifier)
+    })?;
+
+    let mut gray = Mat::default();
+    cvt_color(input, &mut gray, COLOR_BGR2GRAY, 0)?;
+    let mut equalized = Mat::default();
+    equalize_hist(&gray, &mut equalized)?;
+
+    let mut detections = Vector::<Rect>::new();
+    classifier.detect_multi_scale(&equalized, &mut detections, 1.1, 3, 0, Size::new(30, 30), Size::default())?;
+
+    let mut faces = Vector::<Rect>::new();
+    for face in detections {
+        let dx = (face.width as f64 * options.margin) as i32;
+        let dy = (face.height as f64 * options.margin) as i32;
+        let x = (face.x - dx).max(0);
+        let y = (face.y - dy).max(0);
+        let width = (face.x + face.width + dx).min(input.cols()) - x;
+        let height = (face.y + face.height + dy).min(input.rows()) - y;
+        faces.push(Rect::new(x, y, width, height));
+    }
+    Ok(faces)
+}
+
+/// Filled ellipse inscribed in a face box, in the box's coordinates.
+fn face_mask(face: Rect) -> Result<Mat, Box<dyn Error>> {
+    let mut mask = Mat::new_rows_cols_with_default(face.height, face.width, CV_8UC1, Scalar::all(0.0))?;
+    ellipse(
+        &mut mask,
+        Point::new(face.width / 2, face.height / 2),
+        Size::new(face.width / 2, face.height / 2),
+        0.0,
+        0.0,
+        360.0,
+        Scalar::all(255.0),
+        FILLED,
+        LINE_8,
+        0,
+    )?;
+    Ok(mask)
+}
+
+/*
+ * Re-segments each face region of the Lab image with reduced radii and pastes
+ * it into the segmented base.
+ */
+pub(crate) fn refine_base(
+    base: &mut Mat,
+    lab: &Mat,
+    faces: &Vector<Rect>,
+    options: &ConvertOptions,
+    face_options: &FaceOptions,
+) -> Result<(), Box<dyn Error>> {
+    let gentle = ConvertOptions {
+        spatial_radius: options.spatial_radius.scaled(face_options.smoothing),
+        color_radius: options.color_radius.scaled(face_options.smoothing),
+        ..options.clone()
+    };
+    for face in faces {
+        let roi = Mat::roi(lab, face)?.try_clone()?;
+        let detail = segment_colors(&roi, &gentle)?;
+        let mut target = Mat::roi_mut(base, face)?;
+        detail.copy_to_masked(&mut target, &face_mask(face)?)?;
+    }
+    Ok(())
+}
+
+/*
+ * Re-thresholds each face region of the grayscaled image without dilation,
+ * keeping thin lines such as eyelids and lips.
+ */
+pub(crate) fn refine_edge(
+    edge: &mut Mat,
+    gray: &Mat,
+    faces: &Vector<Rect>,
+    options: &ConvertOptions,
+) -> Result<(), Box<dyn Error>> {
+    let fine = ConvertOptions { dilate_iterations: None, stroke: None, ..options.clone() };
+    for face in faces {
+        let roi = Mat::roi(gray, face)?.try_clone()?;
+        let detail = grayscaled_to_edged(&roi, &fine)?;
+        let mut target = Mat::roi_mut(edge, face)?;
+        detail.copy_to_masked(&mut target, &face_mask(face)?)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use ureq::AgentBuilder;
+
+use crate::metadata::sniff_format;
+use crate::{convert_file, ConvertOptions};
+
+/// Largest download accepted, in bytes.
+const MAX_DOWNLOAD_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Whole-request timeout, including the body.
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// File names given to URLs by this process and the URL each went to.
+static NAMED: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+/// Whether `input` is an `http://` or `https://` URL rather than a path.
+pub fn is_url(input: &str) -> bool {
+    input.starts_with("https://") || input.starts_with("http://")
+}
+
+/*
+ * Fetches `url` into memory, refusing other content than images and bodies
+ * over the size limit.
+ */
+fn download(url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let agent = AgentBuilder::new().timeout(DOWNLOAD_TIMEOUT).redirects(5).build();
+    let response = agent.get(url).call()?;
+    // some servers label everything binary; the content is sniffed anyway
+    let content_type = response.content_type();
+    if !content_type.starts_with("image/") && content_type != "application/octet-stream" {
+        return Err(format!("{} is {}, not an image", url, content_type).into());
+    }
+    let declared = response.header("Content-Length").and_then(|length| length.parse::<u64>().ok());
+    if declared.is_some_and(|length| length > MAX_DOWNLOAD_BYTES) {
+        return Err(format!("{} is larger than {} bytes", url, MAX_DOWNLOAD_BYTES).into());
+    }
+    let mut bytes = Vec::new();
+    response.into_reader().take(MAX_DOWNLOAD_BYTES + 1).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 > MAX_DOWNLOAD_BYTES {
+        return Err(format!("{} is larger than {} bytes", url, MAX_DOWNLOAD_BYTES).into());
+    }
+    Ok(bytes)
+}
+
+/*
+ * Local file name for `url`: the last path segment with anything but
+ * letters, digits, `.`, `-` and `_` replaced, and an extension for `format`
+ * when it has none.
+ */
+fn file_name(url: &str, format: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let segment = path.split("://").nth(1).and_then(|rest| rest.split_once('/')).map_or("", |(_, p)| p);
+    let last = segment.rsplit('/').next().unwrap_or_default();
+    let mut name: String = last
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "._-".contains(c) { c } else { '_' })
+        .collect();
+    if name.trim_matches('.').is_empty() {
+        name = "download".to_string();
+    }
+    if !name.contains('.') || name.starts_with('.') {
+        name.push('.');
+        name.push_str(if format == "jpeg" { "jpg" } else { format });
+    }
+    name
+}
+
+/*
+ * file_name of `url`, with a hash of the URL before the extension when an
+ * earlier URL of this process had the same name, so that URLs ending alike,
+ * e.g. `.../a/photo.jpg` and `.../b/photo.jpg`, do not overwrite each
+ * other's output
+ */
+fn unique_file_name(url: &str, format: &str) -> String {
+    let name = file_name(url, format);
+    let mut named = NAMED.lock().unwrap_or_else(PoisonError::into_inner);
+    let name = match named.get(&name) {
+        Some(owner) if owner != url => {
+            let (stem, extension) = name.rsplit_once('.').unwrap_or((&name, ""));
+            format!("{}-{:08x}.{}", stem, fnv1a(url), extension)
+        }
+        _ => name,
+    };
+    named.insert(name.clone(), url.to_string());
+    name
+}
+
+/*
+ * 32-bit FNV-1a, the same for a URL on every run and platform
+ */
+fn fnv1a(text: &str) -> u32 {
+    text.bytes().fold(0x811c_9dc5, |hash, byte| (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193))
+}
+
+/// Downloads an image from an `http://` or `https://` URL and converts it
+/// like a local file, returning the output path.
+///
+/// The output is named after the last segment of the URL's path, with a
+/// hash of the URL added when an earlier URL converted by the process ended
+/// the same, and written to `options.output_dir`, or the working directory. Downloads
+/// above 64 MiB, and responses whose content type or content is not an
+/// image, are refused.
+pub fn convert_url(url: &str, options: &ConvertOptions) -> Result<PathBuf, Box<dyn Error>> {
+    let bytes = download(url)?;
+    let format = sniff_format(&bytes)
+        .ok_or_else(|| format!("{} is not a JPEG, PNG, BMP, WebP or TIFF image", url))?;
+
+    let dir = env::temp_dir().join(format!("nftimg-{}", process::id()));
+    fs::create_dir_all(&dir)?;
+    let input = dir.join(unique_file_name(url, format));
+    fs::write(&input, &bytes)?;
+    let output_dir = match &options.output_dir {
+        Some(dir) => dir.clone(),
+        None => env::current_dir()?,
+    };
+    let options = ConvertOptions { output_dir: Some(output_dir), ..options.clone() };
+    let converted = convert_file(&input, &options);
+    let _ = fs::remove_file(&input);
+    let _ = fs::remove_dir(&dir);
+    Ok(converted?.output_path)
+}
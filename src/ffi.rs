@@ -0,0 +1,210 @@
+//! C API, behind the `capi` feature; `include/nftimg.h` declares it. Build
+//! the library with `cargo rustc --lib --release --features capi --crate-type cdylib`.
+//!
+//! Every function returns an [`NftImgStatus`]; on failure the message is
+//! kept per thread and read with [`nftimg_last_error`]. Panics are caught at
+//! the boundary and reported as [`NftImgStatus::Panic`].
+
+use std::cell::RefCell;
+use std::error::Error;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+use std::slice;
+
+use crate::{convert_buffer, convert_with_options, ConvertOptions, Iterations, NftImgError};
+
+/// Version of [`NftImgOptions`] this library understands.
+pub const NFTIMG_OPTIONS_VERSION: u32 = 1;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NftImgStatus {
+    Ok = 0,
+    InvalidArgument = 1,
+    DecodeFailed = 2,
+    UnsupportedFormat = 3,
+    Failed = 4,
+    Panic = 5,
+}
+
+/// The most used parameters, plus an optional recipe for everything else.
+/// Fill with [`nftimg_options_default`] and change what is needed.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct NftImgOptions {
+    /// Must be [`NFTIMG_OPTIONS_VERSION`]; fields are only ever appended.
+    pub version: u32,
+    pub spatial_radius: f64,
+    pub color_radius: f64,
+    pub max_pyramid_level: i32,
+    pub threshold_block_size: i32,
+    pub threshold_c: f64,
+    pub dilate_kernel_size: i32,
+    pub dilate_iterations: i32,
+    /// NUL-terminated `key=value` lines as written by
+    /// `ConvertOptions::recipe`, applied instead of the fields above; NULL
+    /// for none.
+    pub recipe: *const c_char,
+}
+
+impl NftImgOptions {
+    /*
+     * C options -> ConvertOptions
+     */
+    unsafe fn to_options(self) -> Result<ConvertOptions, Box<dyn Error>> {
+        if self.version != NFTIMG_OPTIONS_VERSION {
+            return Err(format!("unsupported options version {}", self.version).into());
+        }
+        if !self.recipe.is_null() {
+            return ConvertOptions::from_recipe(CStr::from_ptr(self.recipe).to_str()?);
+        }
+        let options = ConvertOptions {
+            spatial_radius: self.spatial_radius.try_into()?,
+            color_radius: self.color_radius.try_into()?,
+            max_pyramid_level: self.max_pyramid_level,
+            threshold_block_size: self.threshold_block_size.try_into()?,
+            threshold_c: self.threshold_c,
+            dilate_kernel_size: self.dilate_kernel_size,
+            dilate_iterations: Iterations::optional(self.dilate_iterations.into())?,
+            ..ConvertOptions::default()
+        };
+        options.validate()?;
+        Ok(options)
+    }
+}
+
+/*
+ * Runs `f`, turning errors and panics into a status and the thread's last
+ * error message.
+ */
+fn guard(f: impl FnOnce() -> Result<(), Box<dyn Error>>) -> NftImgStatus {
+    let (status, message) = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => (NftImgStatus::Ok, None),
+        Ok(Err(e)) => {
+            let status = match e.downcast_ref::<NftImgError>() {
+                Some(NftImgError::DecodeFailed { .. }) => NftImgStatus::DecodeFailed,
+                Some(NftImgError::UnsupportedFormat { .. }) => NftImgStatus::UnsupportedFormat,
+                Some(NftImgError::InvalidOption { .. }) => NftImgStatus::InvalidArgument,
+                _ => NftImgStatus::Failed,
+            };
+            (status, Some(e.to_string()))
+        }
+        Err(_) => (NftImgStatus::Panic, Some("panic in nftimg".to_string())),
+    };
+    set_last_error(message);
+    status
+}
+
+fn set_last_error(message: Option<String>) {
+    let message = message.map(|m| CString::new(m.replace('\0', " ")).unwrap_or_default());
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+
+/// Fills `options` with the library defaults.
+///
+/// # Safety
+///
+/// `options` must be NULL or point to writable memory for an
+/// `NftImgOptions`.
+#[no_mangle]
+pub unsafe extern "C" fn nftimg_options_default(options: *mut NftImgOptions) -> NftImgStatus {
+    if options.is_null() {
+        set_last_error(Some("options is NULL".to_string()));
+        return NftImgStatus::InvalidArgument;
+    }
+    let defaults = ConvertOptions::default();
+    options.write(NftImgOptions {
+        version: NFTIMG_OPTIONS_VERSION,
+        spatial_radius: defaults.spatial_radius.into(),
+        color_radius: defaults.color_radius.into(),
+        max_pyramid_level: defaults.max_pyramid_level,
+        threshold_block_size: defaults.threshold_block_size.into(),
+        threshold_c: defaults.threshold_c,
+        dilate_kernel_size: defaults.dilate_kernel_size,
+        dilate_iterations: defaults.dilate_iterations.map_or(0, i32::from),
+        recipe: ptr::null(),
+    });
+    set_last_error(None);
+    NftImgStatus::Ok
+}
+
+/// Converts the image at `path` (UTF-8) and writes the output next to it,
+/// like the command line tool.
+///
+/// # Safety
+///
+/// `path` must be a NUL-terminated string; `options` NULL for the defaults
+/// or a valid `NftImgOptions`.
+#[no_mangle]
+pub unsafe extern "C" fn nftimg_convert_file(path: *const c_char, options: *const NftImgOptions) -> NftImgStatus {
+    if path.is_null() {
+        set_last_error(Some("path is NULL".to_string()));
+        return NftImgStatus::InvalidArgument;
+    }
+    guard(|| {
+        let options = match options.as_ref() {
+            Some(options) => options.to_options()?,
+            None => ConvertOptions::default(),
+        };
+        convert_with_options(Path::new(CStr::from_ptr(path).to_str()?), &options)
+    })
+}
+
+/// Converts the encoded image in `data` and returns the output, encoded in
+/// the input's format, in `*output` / `*output_len`. Release it with
+/// [`nftimg_buffer_free`].
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes; `options` NULL for the
+/// defaults or a valid `NftImgOptions`; `output` and `output_len` must be
+/// writable.
+#[no_mangle]
+pub unsafe extern "C" fn nftimg_convert_buffer(
+    data: *const u8,
+    len: usize,
+    options: *const NftImgOptions,
+    output: *mut *mut u8,
+    output_len: *mut usize,
+) -> NftImgStatus {
+    if data.is_null() || output.is_null() || output_len.is_null() {
+        set_last_error(Some("data, output and output_len must not be NULL".to_string()));
+        return NftImgStatus::InvalidArgument;
+    }
+    guard(|| {
+        let options = match options.as_ref() {
+            Some(options) => options.to_options()?,
+            None => ConvertOptions::default(),
+        };
+        let encoded = convert_buffer(slice::from_raw_parts(data, len), &options)?.into_boxed_slice();
+        output_len.write(encoded.len());
+        output.write(Box::into_raw(encoded).cast());
+        Ok(())
+    })
+}
+
+/// Releases a buffer returned by [`nftimg_convert_buffer`].
+///
+/// # Safety
+///
+/// `data` and `len` must be exactly as returned, and the buffer not freed
+/// before; NULL is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn nftimg_buffer_free(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)));
+    }
+}
+
+/// Message of the last failed call on this thread, or NULL. Valid until
+/// the next call on the same thread.
+#[no_mangle]
+pub extern "C" fn nftimg_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
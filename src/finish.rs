@@ -0,0 +1,228 @@
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use opencv::core::{Size, BORDER_DEFAULT};
+use opencv::imgcodecs::IMREAD_COLOR;
+use opencv::imgproc::{gaussian_blur, resize, INTER_LINEAR};
+use opencv::prelude::*;
+
+use crate::color_profile::Mixing;
+use crate::read_image;
+
+/// Unsharp mask applied after the merge, for results shown as thumbnails.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct SharpenOptions {
+    /// How much of the detail layer is added back.
+    pub amount: f64,
+    /// Gaussian sigma of the blur the detail is measured against.
+    pub radius: f64,
+    /// Differences up to this many levels are left alone, so flat areas do
+    /// not get noisy.
+    pub threshold: u8,
+}
+
+impl Default for SharpenOptions {
+    fn default() -> Self {
+        SharpenOptions { amount: 0.8, radius: 1.5, threshold: 4 }
+    }
+}
+
+/*
+ * BGR image -> sharpened BGR image
+ */
+pub(crate) fn sharpen(input: &Mat, options: &SharpenOptions) -> Result<Mat, Box<dyn Error>> {
+    let mut blurred = Mat::default();
+    gaussian_blur(input, &mut blurred, Size::new(0, 0), options.radius, 0.0, BORDER_DEFAULT)?;
+    let mut output = input.try_clone()?;
+    for (v, &b) in output.data_bytes_mut()?.iter_mut().zip(blurred.data_bytes()?) {
+        let detail = *v as f64 - b as f64;
+        if detail.abs() > options.threshold as f64 {
+            *v = (*v as f64 + options.amount * detail).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    Ok(output)
+}
+
+/// Vignette: darkens the output towards its borders.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct VignetteOptions {
+    /// Darkening at the corners, 0 none to 1 black.
+    pub strength: f64,
+    /// Distance from the center where darkening starts, as a fraction of
+    /// the half diagonal.
+    pub radius: f64,
+    /// Width of the transition, same unit as the radius.
+    pub feather: f64,
+}
+
+impl Default for VignetteOptions {
+    fn default() -> Self {
+        VignetteOptions { strength: 0.5, radius: 0.6, feather: 0.4 }
+    }
+}
+
+/*
+ * BGR image -> BGR image with a vignette
+ */
+pub(crate) fn vignette(input: &Mat, options: &VignetteOptions, mixing: Mixing) -> Result<Mat, Box<dyn Error>> {
+    let mut output = input.try_clone()?;
+    let (width, height) = (input.cols() as f64, input.rows() as f64);
+    let (cx, cy) = (width / 2.0, height / 2.0);
+    let half_diagonal = (cx * cx + cy * cy).sqrt().max(1.0);
+    let feather = options.feather.max(1e-3);
+    for (i, px) in output.data_bytes_mut()?.chunks_exact_mut(3).enumerate() {
+        let x = (i % input.cols() as usize) as f64 + 0.5 - cx;
+        let y = (i / input.cols() as usize) as f64 + 0.5 - cy;
+        let d = (x * x + y * y).sqrt() / half_diagonal;
+        let t = ((d - options.radius) / feather).clamp(0.0, 1.0);
+        let factor = 1.0 - options.strength * t * t * (3.0 - 2.0 * t);
+        for v in px {
+            *v = mixing.encode(mixing.decode(*v as f64) * factor);
+        }
+    }
+    Ok(output)
+}
+
+/// What the texture stage lays over the output.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "kebab-case"))]
+pub enum Texture {
+    /// Procedural film grain.
+    #[default]
+    Grain,
+    /// An image such as paper or canvas, stretched to the output.
+    Image(PathBuf),
+}
+
+impl fmt::Display for Texture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Texture::Grain => write!(f, "grain"),
+            Texture::Image(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+impl FromStr for Texture {
+    type Err = Box<dyn Error>;
+
+    /// `grain`, or the path of a texture image.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" => Err("empty texture".into()),
+            "grain" => Ok(Texture::Grain),
+            path => Ok(Texture::Image(path.into())),
+        }
+    }
+}
+
+/// How texture and output combine, per channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "kebab-case"))]
+pub enum BlendMode {
+    Normal,
+    Multiply,
+    Screen,
+    #[default]
+    Overlay,
+}
+
+impl fmt::Display for BlendMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlendMode::Normal => write!(f, "normal"),
+            BlendMode::Multiply => write!(f, "multiply"),
+            BlendMode::Screen => write!(f, "screen"),
+            BlendMode::Overlay => write!(f, "overlay"),
+        }
+    }
+}
+
+impl FromStr for BlendMode {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "normal" => Ok(BlendMode::Normal),
+            "multiply" => Ok(BlendMode::Multiply),
+            "screen" => Ok(BlendMode::Screen),
+            "overlay" => Ok(BlendMode::Overlay),
+            _ => Err(format!("unknown blend mode: {}", s).into()),
+        }
+    }
+}
+
+impl BlendMode {
+    fn blend(self, base: f64, layer: f64) -> f64 {
+        match self {
+            BlendMode::Normal => layer,
+            BlendMode::Multiply => base * layer / 255.0,
+            BlendMode::Screen => 255.0 - (255.0 - base) * (255.0 - layer) / 255.0,
+            BlendMode::Overlay if base < 128.0 => 2.0 * base * layer / 255.0,
+            BlendMode::Overlay => 255.0 - 2.0 * (255.0 - base) * (255.0 - layer) / 255.0,
+        }
+    }
+}
+
+/// Grain or texture overlay on the output.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct TextureOptions {
+    pub texture: Texture,
+    /// Share of the blended result, 0 to 1.
+    pub opacity: f64,
+    pub blend: BlendMode,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        TextureOptions { texture: Texture::Grain, opacity: 0.25, blend: BlendMode::Overlay }
+    }
+}
+
+/*
+ * Gray grain around mid-gray, one value per pixel. Seeded, so that the same
+ * input gives the same output.
+ */
+fn grain(pixels: usize) -> Vec<u8> {
+    let mut state = 0x9e37_79b9_7f4a_7c15u64;
+    let mut next = || {
+        // xorshift64*
+        state ^= state >> 12;
+        state ^= state << 25;
+        state ^= state >> 27;
+        (state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 56) as f64
+    };
+    // sum of four uniforms, close enough to gaussian
+    (0..pixels).map(|_| ((next() + next() + next() + next()) / 4.0).round() as u8).collect()
+}
+
+/*
+ * BGR image -> BGR image with the texture blended over it
+ */
+pub(crate) fn texture(input: &Mat, options: &TextureOptions, mixing: Mixing) -> Result<Mat, Box<dyn Error>> {
+    let mut output = input.try_clone()?;
+    let layer: Vec<u8> = match &options.texture {
+        Texture::Grain => grain(input.total()).into_iter().flat_map(|v| [v; 3]).collect(),
+        Texture::Image(path) => {
+            let image = read_image(path, IMREAD_COLOR)?;
+            if image.empty() {
+                return Err(format!("failed to read texture {}", path.display()).into());
+            }
+            let mut stretched = Mat::default();
+            resize(&image, &mut stretched, input.size()?, 0.0, 0.0, INTER_LINEAR)?;
+            stretched.data_bytes()?.to_vec()
+        }
+    };
+    // blend modes are defined on stored values, only the opacity mixes light
+    for (v, &l) in output.data_bytes_mut()?.iter_mut().zip(&layer) {
+        let blended = mixing.decode(options.blend.blend(*v as f64, l as f64));
+        let base = mixing.decode(*v as f64);
+        *v = mixing.encode(base + options.opacity * (blended - base));
+    }
+    Ok(output)
+}
@@ -0,0 +1,264 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use opencv::core::{lut, Scalar, CV_8UC3};
+use opencv::imgproc::{cvt_color, COLOR_BGR2HSV, COLOR_HSV2BGR};
+use opencv::prelude::*;
+
+use crate::converter;
+use crate::palette::{bgr_to_hex, hex_to_bgr, luma};
+
+/// When the LUT is applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "kebab-case"))]
+pub enum LutStage {
+    /// On the input, so segmentation sees the graded colors.
+    Pre,
+    /// On the merged output.
+    #[default]
+    Post,
+}
+
+impl fmt::Display for LutStage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LutStage::Pre => write!(f, "pre"),
+            LutStage::Post => write!(f, "post"),
+        }
+    }
+}
+
+impl FromStr for LutStage {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pre" => Ok(LutStage::Pre),
+            "post" => Ok(LutStage::Post),
+            _ => Err(format!("unknown LUT stage: {}", s).into()),
+        }
+    }
+}
+
+/// Color grading with a `.cube` LUT, 1D curves or a 3D table.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LutOptions {
+    pub path: PathBuf,
+    pub stage: LutStage,
+}
+
+impl LutOptions {
+    pub fn new(path: PathBuf) -> LutOptions {
+        LutOptions { path, stage: LutStage::Post }
+    }
+}
+
+/// Parsed `.cube` file, RGB entries in [0, 1] after domain scaling.
+enum Cube {
+    /// One curve per channel.
+    OneD(Vec<[f32; 3]>),
+    /// Red varies fastest, then green, then blue.
+    ThreeD { size: usize, table: Vec<[f32; 3]> },
+}
+
+/*
+ * Parses the Adobe/Resolve `.cube` format: keyword lines, then one `r g b`
+ * line per entry.
+ */
+fn parse_cube(text: &str) -> Result<Cube, Box<dyn Error>> {
+    let mut size_1d = None;
+    let mut size_3d = None;
+    let mut domain = ([0.0f32; 3], [1.0f32; 3]);
+    let mut entries = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let keyword = fields.next().unwrap_or_default();
+        let mut triple = || -> Result<[f32; 3], Box<dyn Error>> {
+            let v = fields.by_ref().take(3).map(str::parse).collect::<Result<Vec<f32>, _>>()?;
+            v.try_into().map_err(|_| format!("malformed .cube line: {}", line).into())
+        };
+        match keyword {
+            "TITLE" => {}
+            "LUT_1D_SIZE" => size_1d = Some(line[keyword.len()..].trim().parse::<usize>()?),
+            "LUT_3D_SIZE" => size_3d = Some(line[keyword.len()..].trim().parse::<usize>()?),
+            "DOMAIN_MIN" => domain.0 = triple()?,
+            "DOMAIN_MAX" => domain.1 = triple()?,
+            _ => {
+                let v = line.split_whitespace().map(str::parse).collect::<Result<Vec<f32>, _>>()?;
+                let rgb: [f32; 3] = v.try_into().map_err(|_| format!("malformed .cube line: {}", line))?;
+                let mut scaled = [0.0; 3];
+                for c in 0..3 {
+                    scaled[c] = (rgb[c] - domain.0[c]) / (domain.1[c] - domain.0[c]);
+                }
+                entries.push(scaled);
+            }
+        }
+    }
+    match (size_1d, size_3d) {
+        (Some(size), None) if size >= 2 && entries.len() == size => Ok(Cube::OneD(entries)),
+        (None, Some(size)) if size >= 2 && entries.len() == size * size * size => {
+            Ok(Cube::ThreeD { size, table: entries })
+        }
+        _ => Err("invalid .cube: size missing or not matching the entries".into()),
+    }
+}
+
+/*
+ * BGR image -> BGR image graded with the LUT file
+ */
+pub(crate) fn apply_lut(input: &Mat, options: &LutOptions) -> Result<Mat, Box<dyn Error>> {
+    let cube = converter::shared("lut", &options.path, || parse_cube(&fs::read_to_string(&options.path)?))?;
+    match &*cube {
+        Cube::OneD(curves) => {
+            // resample the curves to OpenCV's 256-entry per-channel LUT
+            let mut table = Mat::new_rows_cols_with_default(1, 256, CV_8UC3, Scalar::all(0.0))?;
+            for (v, entry) in table.data_bytes_mut()?.chunks_exact_mut(3).enumerate() {
+                let pos = v as f32 / 255.0 * (curves.len() - 1) as f32;
+                let (i, t) = (pos.floor() as usize, pos.fract());
+                let next = (i + 1).min(curves.len() - 1);
+                for (channel, out) in [2, 1, 0].into_iter().zip(entry.iter_mut()) {
+                    let value = curves[i][channel] * (1.0 - t) + curves[next][channel] * t;
+                    *out = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+                }
+            }
+            let mut output = Mat::default();
+            lut(input, &table, &mut output)?;
+            Ok(output)
+        }
+        Cube::ThreeD { size, table } => {
+            let size = *size;
+            let mut output = input.try_clone()?;
+            let max = (size - 1) as f32;
+            let at = |r: usize, g: usize, b: usize| table[r + size * (g + size * b)];
+            for px in output.data_bytes_mut()?.chunks_exact_mut(3) {
+                let pos = [px[2] as f32 / 255.0 * max, px[1] as f32 / 255.0 * max, px[0] as f32 / 255.0 * max];
+                let lo = pos.map(|p| (p.floor() as usize).min(size - 2));
+                let t: Vec<f32> = (0..3).map(|c| pos[c] - lo[c] as f32).collect();
+                // trilinear interpolation over the enclosing cell
+                let mut rgb = [0.0f32; 3];
+                for corner in 0..8 {
+                    let d = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+                    let weight: f32 = (0..3).map(|c| if d[c] == 1 { t[c] } else { 1.0 - t[c] }).product();
+                    let entry = at(lo[0] + d[0], lo[1] + d[1], lo[2] + d[2]);
+                    for c in 0..3 {
+                        rgb[c] += weight * entry[c];
+                    }
+                }
+                let to_u8 = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+                px.copy_from_slice(&[to_u8(rgb[2]), to_u8(rgb[1]), to_u8(rgb[0])]);
+            }
+            Ok(output)
+        }
+    }
+}
+
+/// Global adjustments of the merged output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct AdjustOptions {
+    /// Saturation factor, 1 keeps it.
+    pub saturation: f64,
+    /// Brightness offset in [-1, 1].
+    pub brightness: f64,
+    /// Contrast factor around mid-gray, 1 keeps it.
+    pub contrast: f64,
+    /// Hue rotation in degrees.
+    pub hue: f64,
+}
+
+impl Default for AdjustOptions {
+    fn default() -> Self {
+        AdjustOptions { saturation: 1.0, brightness: 0.0, contrast: 1.0, hue: 0.0 }
+    }
+}
+
+/*
+ * BGR image -> BGR image with hue and saturation adjusted in HSV, then
+ * brightness and contrast per channel
+ */
+pub(crate) fn adjust(input: &Mat, options: &AdjustOptions) -> Result<Mat, Box<dyn Error>> {
+    let mut hsv = Mat::default();
+    cvt_color(input, &mut hsv, COLOR_BGR2HSV, 0)?;
+    // 8-bit hue is in [0, 180)
+    let shift = (options.hue / 2.0).rem_euclid(180.0);
+    for px in hsv.data_bytes_mut()?.chunks_exact_mut(3) {
+        px[0] = ((px[0] as f64 + shift) % 180.0) as u8;
+        px[1] = (px[1] as f64 * options.saturation).round().clamp(0.0, 255.0) as u8;
+    }
+    let mut output = Mat::default();
+    cvt_color(&hsv, &mut output, COLOR_HSV2BGR, 0)?;
+
+    let curve: Vec<u8> = (0..256)
+        .map(|v| {
+            let v = (v as f64 / 255.0 - 0.5) * options.contrast + 0.5 + options.brightness;
+            (v.clamp(0.0, 1.0) * 255.0).round() as u8
+        })
+        .collect();
+    for v in output.data_bytes_mut()? {
+        *v = curve[*v as usize];
+    }
+    Ok(output)
+}
+
+/// Gradient map: luminance mapped through two or more color stops, evenly
+/// spaced from shadows to highlights (duotone, tritone, ...). BGR, like the
+/// images.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GradientMap {
+    pub stops: Vec<[u8; 3]>,
+}
+
+impl fmt::Display for GradientMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hex: Vec<String> = self.stops.iter().map(|&c| bgr_to_hex(c)).collect();
+        write!(f, "{}", hex.join(","))
+    }
+}
+
+impl FromStr for GradientMap {
+    type Err = Box<dyn Error>;
+
+    /// Parses comma-separated hex colors, darkest stop first.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let stops = s.split(',').map(hex_to_bgr).collect::<Result<Vec<_>, _>>()?;
+        if stops.len() < 2 {
+            return Err("a gradient map needs at least two stops".into());
+        }
+        Ok(GradientMap { stops })
+    }
+}
+
+/*
+ * BGR image -> BGR image recolored through the gradient by luminance
+ */
+pub(crate) fn gradient_map(input: &Mat, gradient: &GradientMap) -> Result<Mat, Box<dyn Error>> {
+    let stops = &gradient.stops;
+    // the field is public and deserialized, not only parsed
+    if stops.len() < 2 {
+        return Err("a gradient map needs at least two stops".into());
+    }
+    let segments = (stops.len() - 1) as f64;
+    let table: Vec<[u8; 3]> = (0..256)
+        .map(|v| {
+            let pos = v as f64 / 255.0 * segments;
+            let i = (pos.floor() as usize).min(stops.len() - 2);
+            let t = pos - i as f64;
+            let mix = |c: usize| (stops[i][c] as f64 * (1.0 - t) + stops[i + 1][c] as f64 * t).round() as u8;
+            [mix(0), mix(1), mix(2)]
+        })
+        .collect();
+    let mut output = input.try_clone()?;
+    for px in output.data_bytes_mut()?.chunks_exact_mut(3) {
+        let l = luma([px[0], px[1], px[2]]).round() as usize;
+        px.copy_from_slice(&table[l.min(255)]);
+    }
+    Ok(output)
+}
@@ -0,0 +1,130 @@
+use std::error::Error;
+use std::net::SocketAddr;
+
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc;
+use tokio::task;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status, Streaming};
+
+use crate::metrics;
+use crate::remote::client_options;
+use crate::{ConvertOptions, Converter, NftImgError};
+
+/// Messages generated from `proto/nftimg.proto`.
+pub mod proto {
+    tonic::include_proto!("nftimg.v1");
+}
+
+use proto::stylizer_server::{Stylizer, StylizerServer};
+use proto::{StylizeRequest, StylizeResponse};
+
+/// Largest message accepted or sent, in bytes, as for HTTP bodies.
+const MAX_MESSAGE_BYTES: usize = 64 * 1024 * 1024;
+
+/// Frames converted ahead of a client that reads responses slowly.
+const FRAMES_IN_FLIGHT: usize = 4;
+
+struct Service {
+    /// Recipe of the options the server was started with.
+    base: String,
+    /// Keeps the models loaded by requests for the next ones.
+    converter: Converter,
+}
+
+impl Service {
+    fn options(&self, recipe: &str) -> Result<ConvertOptions, Status> {
+        let mut params = Vec::new();
+        for line in recipe.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let pair = line.split_once('=');
+            params.push(pair.ok_or_else(|| Status::invalid_argument(format!("malformed recipe line: {}", line)))?);
+        }
+        client_options(&self.base, params).map_err(|e| Status::invalid_argument(e.to_string()))
+    }
+}
+
+#[tonic::async_trait]
+impl Stylizer for Service {
+    async fn stylize(&self, request: Request<StylizeRequest>) -> Result<Response<StylizeResponse>, Status> {
+        let request = request.into_inner();
+        let converter = self.converter.with_options(self.options(&request.recipe)?);
+        let image = convert(request.image, converter).await?;
+        Ok(Response::new(StylizeResponse { image }))
+    }
+
+    type StylizeFramesStream = ReceiverStream<Result<StylizeResponse, Status>>;
+
+    async fn stylize_frames(
+        &self,
+        request: Request<Streaming<StylizeRequest>>,
+    ) -> Result<Response<Self::StylizeFramesStream>, Status> {
+        let mut frames = request.into_inner();
+        let mut converter = self.converter.with_options(self.options("")?);
+        let service = Service { base: self.base.clone(), converter: self.converter.clone() };
+        let (responses, stream) = mpsc::channel(FRAMES_IN_FLIGHT);
+        tokio::spawn(async move {
+            loop {
+                let frame = match frames.message().await {
+                    Ok(Some(frame)) => frame,
+                    Ok(None) => break,
+                    Err(status) => {
+                        let _ = responses.send(Err(status)).await;
+                        break;
+                    }
+                };
+                if !frame.recipe.is_empty() {
+                    match service.options(&frame.recipe) {
+                        Ok(frame_options) => converter = service.converter.with_options(frame_options),
+                        Err(status) => {
+                            let _ = responses.send(Err(status)).await;
+                            break;
+                        }
+                    }
+                }
+                let result = convert(frame.image, converter.clone()).await;
+                let failed = result.is_err();
+                // a gone client drops the receiver
+                if responses.send(result.map(|image| StylizeResponse { image })).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(stream)))
+    }
+}
+
+/*
+ * Runs a conversion off the async workers; bad inputs are invalid
+ * arguments, other failures internal errors
+ */
+async fn convert(image: Vec<u8>, converter: Converter) -> Result<Vec<u8>, Status> {
+    let result = task::spawn_blocking(move || {
+        converter.convert_buffer(&image).map_err(|e| match e.downcast_ref::<NftImgError>() {
+            Some(
+                NftImgError::DecodeFailed { .. }
+                | NftImgError::UnsupportedFormat { .. }
+                | NftImgError::UnexpectedChannels { .. },
+            ) => Status::invalid_argument(e.to_string()),
+            _ => Status::internal(e.to_string()),
+        })
+    })
+    .await
+    .map_err(|_| Status::internal("conversion panicked"))?;
+    metrics::count_request(result.is_err());
+    result
+}
+
+/// Serves the `nftimg.v1.Stylizer` gRPC service of `proto/nftimg.proto` on
+/// `addr` until the process is stopped; request recipes are applied on top
+/// of `options`.
+pub fn serve_grpc(addr: SocketAddr, options: &ConvertOptions) -> Result<(), Box<dyn Error>> {
+    let service = StylizerServer::new(Service { base: options.recipe(), converter: Converter::new(options.clone()) })
+        .max_decoding_message_size(MAX_MESSAGE_BYTES)
+        .max_encoding_message_size(MAX_MESSAGE_BYTES);
+    Runtime::new()?.block_on(async {
+        eprintln!("listening on {} (gRPC)", addr);
+        Server::builder().add_service(service).serve(addr).await?;
+        Ok(())
+    })
+}
@@ -0,0 +1,116 @@
+use std::error::Error;
+
+use image::{DynamicImage, RgbImage, RgbaImage};
+use opencv::core::{Scalar, CV_8U, CV_8UC3, CV_8UC4};
+use opencv::imgproc::{
+    cvt_color, COLOR_BGR2RGB, COLOR_BGR2RGBA, COLOR_BGRA2RGB, COLOR_BGRA2RGBA, COLOR_GRAY2RGB, COLOR_GRAY2RGBA,
+    COLOR_RGB2BGR, COLOR_RGBA2BGR,
+};
+use opencv::prelude::*;
+
+use crate::{BgrImage, NftImgError};
+
+/*
+ * Packed RGB or RGBA pixels -> BGR Mat
+ */
+fn from_pixels(pixels: &[u8], width: u32, height: u32, typ: i32, code: i32) -> Result<BgrImage, Box<dyn Error>> {
+    let mut packed = Mat::new_rows_cols_with_default(height as i32, width as i32, typ, Scalar::all(0.0))?;
+    packed.data_bytes_mut()?.copy_from_slice(pixels);
+    let mut bgr = Mat::default();
+    cvt_color(&packed, &mut bgr, code, 0)?;
+    Ok(BgrImage(bgr))
+}
+
+/*
+ * BGR, BGRA or gray Mat -> (width, height, packed RGB or RGBA pixels)
+ */
+fn to_pixels(image: &BgrImage, alpha: bool) -> Result<(u32, u32, Vec<u8>), Box<dyn Error>> {
+    if image.0.depth() != CV_8U {
+        return Err("expected an 8-bit image".into());
+    }
+    let code = match (image.0.channels(), alpha) {
+        (1, false) => COLOR_GRAY2RGB,
+        (1, true) => COLOR_GRAY2RGBA,
+        (3, false) => COLOR_BGR2RGB,
+        (3, true) => COLOR_BGR2RGBA,
+        (4, false) => COLOR_BGRA2RGB,
+        (4, true) => COLOR_BGRA2RGBA,
+        (found, _) => return Err(NftImgError::UnexpectedChannels { expected: 3, found }.into()),
+    };
+    let mut rgb = Mat::default();
+    cvt_color(&image.0, &mut rgb, code, 0)?;
+    Ok((rgb.cols() as u32, rgb.rows() as u32, rgb.data_bytes()?.to_vec()))
+}
+
+impl TryFrom<&RgbImage> for BgrImage {
+    type Error = Box<dyn Error>;
+
+    fn try_from(image: &RgbImage) -> Result<BgrImage, Box<dyn Error>> {
+        from_pixels(image.as_raw(), image.width(), image.height(), CV_8UC3, COLOR_RGB2BGR)
+    }
+}
+
+/// The alpha channel is dropped; the pipeline works on opaque images.
+impl TryFrom<&RgbaImage> for BgrImage {
+    type Error = Box<dyn Error>;
+
+    fn try_from(image: &RgbaImage) -> Result<BgrImage, Box<dyn Error>> {
+        from_pixels(image.as_raw(), image.width(), image.height(), CV_8UC4, COLOR_RGBA2BGR)
+    }
+}
+
+/// Images other than 8-bit RGB or RGBA are converted to 8-bit RGB first.
+impl TryFrom<&DynamicImage> for BgrImage {
+    type Error = Box<dyn Error>;
+
+    fn try_from(image: &DynamicImage) -> Result<BgrImage, Box<dyn Error>> {
+        match image {
+            DynamicImage::ImageRgb8(rgb) => rgb.try_into(),
+            DynamicImage::ImageRgba8(rgba) => rgba.try_into(),
+            other => (&other.to_rgb8()).try_into(),
+        }
+    }
+}
+
+impl TryFrom<DynamicImage> for BgrImage {
+    type Error = Box<dyn Error>;
+
+    fn try_from(image: DynamicImage) -> Result<BgrImage, Box<dyn Error>> {
+        (&image).try_into()
+    }
+}
+
+impl TryFrom<&BgrImage> for RgbImage {
+    type Error = Box<dyn Error>;
+
+    fn try_from(image: &BgrImage) -> Result<RgbImage, Box<dyn Error>> {
+        let (width, height, pixels) = to_pixels(image, false)?;
+        Ok(RgbImage::from_raw(width, height, pixels).ok_or("pixel buffer does not match the image size")?)
+    }
+}
+
+/// Opaque: alpha is 255 everywhere.
+impl TryFrom<&BgrImage> for RgbaImage {
+    type Error = Box<dyn Error>;
+
+    fn try_from(image: &BgrImage) -> Result<RgbaImage, Box<dyn Error>> {
+        let (width, height, pixels) = to_pixels(image, true)?;
+        Ok(RgbaImage::from_raw(width, height, pixels).ok_or("pixel buffer does not match the image size")?)
+    }
+}
+
+impl TryFrom<&BgrImage> for DynamicImage {
+    type Error = Box<dyn Error>;
+
+    fn try_from(image: &BgrImage) -> Result<DynamicImage, Box<dyn Error>> {
+        Ok(DynamicImage::ImageRgb8(image.try_into()?))
+    }
+}
+
+impl TryFrom<BgrImage> for DynamicImage {
+    type Error = Box<dyn Error>;
+
+    fn try_from(image: BgrImage) -> Result<DynamicImage, Box<dyn Error>> {
+        (&image).try_into()
+    }
+}
@@ -1,46 +1,803 @@
 use std::error::Error;
-use std::path::Path;
-use opencv::core::{bitwise_and, split, Point, Scalar, Size, TermCriteria, Vector, BORDER_REFLECT};
-use opencv::imgcodecs::{imread, imwrite, IMREAD_COLOR};
+use std::fs;
+use std::path::{self, Path, PathBuf};
+use opencv::core::{
+    bitwise_and, flip, get_num_threads, rotate, set_num_threads, set_rng_seed, split, transpose, Point, Scalar, Size,
+    TermCriteria, Vector, BORDER_REFLECT, CV_8U, CV_8UC1, ROTATE_180, ROTATE_90_CLOCKWISE, ROTATE_90_COUNTERCLOCKWISE,
+};
+use opencv::imgcodecs::{
+    imdecode, imencode, IMREAD_COLOR, IMREAD_IGNORE_ORIENTATION, IMREAD_REDUCED_COLOR_2, IMREAD_REDUCED_COLOR_4,
+    IMREAD_REDUCED_COLOR_8,
+};
+#[cfg(not(feature = "ximgproc"))]
+use opencv::imgproc::bilateral_filter;
 use opencv::imgproc::{
-    adaptive_threshold, cvt_color, dilate, get_structuring_element, pyr_mean_shift_filtering,
-    COLOR_BGR2Lab, COLOR_Lab2BGR, ADAPTIVE_THRESH_MEAN_C, MORPH_RECT, THRESH_BINARY,
+    adaptive_threshold, cvt_color, dilate, pyr_mean_shift_filtering, resize, COLOR_BGR2Lab, COLOR_Lab2BGR,
+    ADAPTIVE_THRESH_MEAN_C, INTER_AREA, INTER_NEAREST, THRESH_BINARY,
 };
-use opencv::prelude::Mat;
+use opencv::prelude::*;
+#[cfg(feature = "ximgproc")]
 use opencv::ximgproc::anisotropic_diffusion;
 
-pub fn convert(file_path: &str) -> Result<(), Box<dyn Error>> {
+mod analysis;
+mod ascii;
+mod batch;
+mod builder;
+#[cfg(feature = "sqlite")]
+mod batch_state;
+#[cfg(feature = "cloud")]
+mod cloud;
+mod collection;
+mod color_profile;
+mod compare;
+mod composite;
+mod converter;
+#[cfg(all(unix, feature = "daemon"))]
+mod daemon;
+mod depth;
+mod describe;
+mod diagnostics;
+mod dump;
+#[cfg(feature = "faces")]
+mod faces;
+mod edges;
+mod error;
+mod enhance;
+#[cfg(feature = "capi")]
+pub mod ffi;
+#[cfg(feature = "http")]
+mod fetch;
+mod finish;
+mod foreground;
+mod grading;
+#[cfg(feature = "image")]
+mod image_interop;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod metadata;
+mod metrics;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod naming;
+#[cfg(feature = "ndarray")]
+mod ndarray_interop;
+#[cfg(feature = "node")]
+mod node;
+mod options;
+#[cfg(feature = "otel")]
+mod otel;
+mod palette;
+mod params;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod pipeline;
+mod pixelart;
+mod plugin;
+mod popart;
+mod portrait;
+#[cfg(feature = "toml")]
+mod profile;
+mod progress;
+#[cfg(feature = "quality")]
+mod quality;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(any(feature = "serve", feature = "grpc"))]
+mod remote;
+#[cfg(feature = "saliency")]
+mod saliency;
+#[cfg(feature = "script")]
+mod script;
+#[cfg(feature = "dnn")]
+mod segmentation;
+#[cfg(feature = "serve")]
+mod server;
+mod shading;
+mod smoothing;
+mod stats;
+mod strokes;
+#[cfg(feature = "dnn")]
+mod style;
+mod svg;
+#[cfg(feature = "tauri")]
+mod tauri_commands;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+mod tiles;
+mod tune;
+#[cfg(feature = "superres")]
+mod upscale;
+#[cfg(feature = "webhook")]
+mod webhook;
+#[cfg(feature = "white-balance")]
+mod white_balance;
+#[cfg(feature = "worker")]
+mod worker;
+
+pub use analysis::{analyze, ImageStats};
+pub use ascii::{AsciiMode, AsciiOptions};
+pub use batch::{convert_iter, ConvertOutcome};
+pub use builder::ConvertOptionsBuilder;
+#[cfg(feature = "sqlite")]
+pub use batch_state::{convert_batch, BatchCounts};
+#[cfg(feature = "cloud")]
+pub use cloud::{convert_objects, convert_to_object, is_object_uri};
+pub use collection::{convert_collection, CollectionReport, ImageReport};
+pub use color_profile::ColorProfile;
+pub use compare::{compare, compare_files, CompareReport};
+pub use composite::EdgeStyle;
+pub use converter::Converter;
+#[cfg(all(unix, feature = "daemon"))]
+pub use daemon::run_daemon;
+pub use depth::{DepthOptions, DepthSource};
+pub use describe::{PipelineDescription, StageDescription, StepDescription};
+pub use diagnostics::{diagnostics, Diagnostics};
+#[cfg(feature = "faces")]
+pub use faces::FaceOptions;
+pub use edges::{EdgeDetector, KernelShape, MorphOp, Morphology};
+pub use error::NftImgError;
+pub use enhance::{ClaheOptions, Denoise};
+#[cfg(feature = "http")]
+pub use fetch::{convert_url, is_url};
+pub use finish::{BlendMode, SharpenOptions, Texture, TextureOptions, VignetteOptions};
+pub use foreground::segment_foreground;
+pub use grading::{AdjustOptions, GradientMap, LutOptions, LutStage};
+#[cfg(feature = "grpc")]
+pub use grpc::{proto, serve_grpc};
+pub use metadata::{read_recipe, ExifField, MetadataOptions, Xmp};
+pub use metrics::render_metrics;
+#[cfg(feature = "mqtt")]
+pub use mqtt::run_mqtt;
+#[cfg(any(feature = "grpc", feature = "worker"))]
+pub use metrics::serve_metrics;
+pub use naming::output_path;
+#[cfg(feature = "ndarray")]
+pub use ndarray_interop::convert_array;
+pub use options::ConvertOptions;
+pub use palette::{hex_to_bgr, Palette, PosterizeOptions};
+pub use params::{BlockSize, Iterations, Radius};
+#[cfg(feature = "rayon")]
+pub use parallel::par_convert_all;
+pub use pipeline::Pipeline;
+pub use pixelart::PixelArtOptions;
+pub use plugin::{register_stage, CustomStage, Stage, StagePoint};
+pub use popart::PopArtOptions;
+pub use portrait::PortraitOptions;
+pub use progress::{convert_with_progress, Progress};
+#[cfg(feature = "quality")]
+pub use quality::{score_quality, QualityOptions};
+#[cfg(feature = "saliency")]
+pub use saliency::SaliencyOptions;
+#[cfg(feature = "script")]
+pub use script::run_script;
+#[cfg(feature = "dnn")]
+pub use segmentation::{ClassParams, SegmentationOptions};
+#[cfg(feature = "serve")]
+pub use server::serve;
+pub use shading::{HalftoneMode, HalftoneOptions, HatchOptions};
+pub use smoothing::Smoothing;
+pub use strokes::StrokeOptions;
+#[cfg(feature = "dnn")]
+pub use style::Style;
+pub use svg::SvgOptions;
+#[cfg(feature = "tauri")]
+pub use tauri_commands::{tauri_commands, Preset, PRESETS};
+pub use tiles::convert_tiles;
+pub use tune::{tune, tune_files, TuneOptions, TuneReport};
+#[cfg(feature = "superres")]
+pub use upscale::UpscaleOptions;
+#[cfg(feature = "white-balance")]
+pub use white_balance::WhiteBalance;
+#[cfg(feature = "worker")]
+pub use worker::run_worker;
+
+use metrics::StageClock;
+
+pub fn convert(file_path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+    convert_with_options(file_path, &ConvertOptions::default())
+}
+
+pub fn convert_with_options(file_path: impl AsRef<Path>, options: &ConvertOptions) -> Result<(), Box<dyn Error>> {
+    convert_file(file_path.as_ref(), options)?;
+    Ok(())
+}
+
+/// Converts an encoded image held in memory and returns the output, encoded
+/// in the format of the input. SVG and text outputs are not produced.
+pub fn convert_buffer(bytes: &[u8], options: &ConvertOptions) -> Result<Vec<u8>, Box<dyn Error>> {
+    #[cfg(feature = "otel")]
+    return otel::conversion("buffer", options, || encode_buffer(bytes, options));
+    #[cfg(not(feature = "otel"))]
+    encode_buffer(bytes, options)
+}
+
+fn encode_buffer(bytes: &[u8], options: &ConvertOptions) -> Result<Vec<u8>, Box<dyn Error>> {
+    let source = Path::new("<buffer>");
+    let format = metadata::sniff_format(bytes).ok_or_else(|| NftImgError::UnsupportedFormat { path: source.into() })?;
+    let (_, _, output) = stylize(bytes, source, options)?;
+    let mut clock = StageClock::start();
+    clock.begin("encode");
+    let encoded = encode_image(format, &output)?;
+    let encoded = metadata::apply(bytes, encoded, &options.metadata, &options.recipe())?;
+    clock.lap("encode", &output);
+    Ok(encoded)
+}
 
-    let path = Path::new(file_path);
-    let folder = path.parent().unwrap().to_str().unwrap();
-    let filename = path.file_name().unwrap().to_str().unwrap();
+/// Converts a decoded 8-bit, 3-channel BGR image and returns the output
+/// image. The input is taken as sRGB and not auto-oriented; SVG and text
+/// outputs are not produced.
+pub fn convert_image(image: &Mat, options: &ConvertOptions) -> Result<Mat, Box<dyn Error>> {
+    #[cfg(feature = "otel")]
+    return otel::conversion("image", options, || process_image(image, options));
+    #[cfg(not(feature = "otel"))]
+    process_image(image, options)
+}
 
+fn process_image(image: &Mat, options: &ConvertOptions) -> Result<Mat, Box<dyn Error>> {
+    if image.channels() != 3 {
+        return Err(NftImgError::UnexpectedChannels { expected: 3, found: image.channels() }.into());
+    }
+    if image.depth() != CV_8U {
+        return Err("expected an 8-bit image".into());
+    }
+    let (_, _, output) = process(image.try_clone()?, options)?;
+    Ok(output)
+}
+
+/// An 8-bit BGR image as OpenCV holds it, for conversions from and to the
+/// image types of other crates (features `image` and `ndarray`).
+#[derive(Debug)]
+pub struct BgrImage(pub Mat);
+
+impl BgrImage {
+    /// Runs [`convert_image`] on the image.
+    pub fn convert(&self, options: &ConvertOptions) -> Result<BgrImage, Box<dyn Error>> {
+        Ok(BgrImage(convert_image(&self.0, options)?))
+    }
+}
+
+/// What a conversion produced, kept for collection reports.
+pub(crate) struct Converted {
+    pub output_path: PathBuf,
+    pub output: Mat,
+    /// Edge mask, 0 where an outline was drawn.
+    pub edge: Mat,
+}
+
+pub(crate) fn convert_file(path: &Path, options: &ConvertOptions) -> Result<Converted, Box<dyn Error>> {
+    convert_file_to(path, None, options)
+}
+
+/*
+ * convert_file writing to `output`, in the format of its extension, instead
+ * of the name derived from the input
+ */
+pub(crate) fn convert_file_to(
+    path: &Path,
+    output: Option<&Path>,
+    options: &ConvertOptions,
+) -> Result<Converted, Box<dyn Error>> {
+    #[cfg(feature = "otel")]
+    return otel::conversion("file", options, || write_file(path, output, options));
+    #[cfg(not(feature = "otel"))]
+    write_file(path, output, options)
+}
+
+fn write_file(path: &Path, output: Option<&Path>, options: &ConvertOptions) -> Result<Converted, Box<dyn Error>> {
+
+    // bare names such as `photo.jpg` have an empty parent; anchor relative
+    // inputs to the working directory so the output lands next to them
+    if path.file_name().is_none() {
+        return Err(format!("no file name in {}", path.display()).into());
+    }
+    let path = &path::absolute(path)?;
+    let path_write = match output {
+        Some(output) => path::absolute(output)?,
+        None => {
+            if let Some(dir) = &options.output_dir {
+                naming::prepare_output_dir(dir)?;
+            }
+            naming::output_location(path, &options.output_suffix, options.output_dir.as_deref())?
+        }
+    };
+
+    let bytes = fs::read(path).map_err(|_| NftImgError::DecodeFailed { path: path.to_path_buf() })?;
+    let (mat_0, mat_1, output) = stylize(&bytes, path, options)?;
+    // opencv::highgui::imshow("output", &output)?;
+    // metadata is injected before writing, the output appears complete or not at all
+    let extension = path_write.extension().and_then(|e| e.to_str()).ok_or("output has no image extension")?;
+    let mut clock = StageClock::start();
+    clock.begin("encode");
+    let encoded = encode_image(extension, &output)?;
+    let encoded = metadata::apply(&bytes, encoded, &options.metadata, &options.recipe())?;
+    naming::write_atomic(&path_write, &encoded)?;
+    clock.lap("encode", &output);
+    if let Some(svg_options) = &options.svg {
+        let svg = svg::trace(&mat_0, &mat_1, svg_options)?;
+        naming::write_atomic(&path_write.with_extension("svg"), svg.as_bytes())?;
+    }
+    if let Some(ascii_options) = &options.ascii {
+        if let Some(extension) = ascii_options.mode.extension() {
+            let text = ascii::to_text(&output, ascii_options)?;
+            naming::write_atomic(&path_write.with_extension(extension), text.as_bytes())?;
+        }
+    }
+
+    // opencv::highgui::wait_key(0)?;
+    let edge = if mat_1.size()? == output.size()? {
+        mat_1
+    } else {
+        let mut resized = Mat::default();
+        resize(&mat_1, &mut resized, output.size()?, 0.0, 0.0, INTER_NEAREST)?;
+        resized
+    };
+    Ok(Converted { output_path: path_write, output, edge })
+}
+
+/*
+ * Encoded input -> (base, edge mask, output) after every processing stage;
+ * `path` only names the input in errors
+ */
+fn stylize(bytes: &[u8], path: &Path, options: &ConvertOptions) -> Result<(Mat, Mat, Mat), Box<dyn Error>> {
+    process(load(bytes, path, options)?, options)
+}
+
+/*
+ * Encoded input -> oriented sRGB BGR image
+ */
+pub(crate) fn load(bytes: &[u8], path: &Path, options: &ConvertOptions) -> Result<Mat, Box<dyn Error>> {
+    let mut clock = StageClock::start();
+    clock.begin("decode");
     /* load img */
-    let mat_bgr = imread(path.to_str().unwrap(), IMREAD_COLOR)?;
+    // decoded from memory: imread takes UTF-8 only and misreads some Unicode
+    // paths on Windows
+    let decode_failed = || NftImgError::DecodeFailed { path: path.to_path_buf() };
+    if metadata::sniff_format(bytes).is_none() {
+        return Err(NftImgError::UnsupportedFormat { path: path.to_path_buf() }.into());
+    }
+    let mat_bgr = decode_limited(bytes, path, options)?;
+    if mat_bgr.empty() {
+        return Err(decode_failed().into());
+    }
+    let mat_bgr = match metadata::exif_orientation(bytes) {
+        Some(orientation) if options.auto_orient => orient(&mat_bgr, orientation)?,
+        _ => mat_bgr,
+    };
+    let profile = match options.input_profile {
+        Some(profile) => profile,
+        None => color_profile::detect(bytes).unwrap_or(ColorProfile::Srgb),
+    };
+    let mat_bgr = color_profile::to_srgb(&mat_bgr, profile)?;
+    clock.lap("decode", &mat_bgr);
+    Ok(mat_bgr)
+}
+
+/*
+ * sRGB BGR image -> (base, edge mask, output) after every processing stage,
+ * dumping the intermediates if a debug directory is set
+ */
+fn process(mat_bgr: Mat, options: &ConvertOptions) -> Result<(Mat, Mat, Mat), Box<dyn Error>> {
+    match &options.debug_dir {
+        Some(dir) => dump::with_dump_dir(dir, || process_stages(mat_bgr, options)),
+        None => process_stages(mat_bgr, options),
+    }
+}
+
+/// Gives OpenCV back the thread count it had when dropped, on unwinding too.
+struct RestoreThreads(i32);
+
+impl Drop for RestoreThreads {
+    fn drop(&mut self) {
+        let _ = set_num_threads(self.0);
+    }
+}
+
+/*
+ * Runs OpenCV single-threaded until the returned guard drops
+ */
+fn single_threaded() -> Result<RestoreThreads, Box<dyn Error>> {
+    let restore = RestoreThreads(get_num_threads()?);
+    set_num_threads(1)?;
+    Ok(restore)
+}
+
+fn process_stages(mut mat_bgr: Mat, options: &ConvertOptions) -> Result<(Mat, Mat, Mat), Box<dyn Error>> {
+    // parallel reductions may sum in any order, and k-means and the
+    // superpixel backends draw from OpenCV's RNG; reseeding per image keeps
+    // results independent of the batch order
+    let _threads = options.deterministic.then(single_threaded).transpose()?;
+    if options.deterministic {
+        set_rng_seed(0)?;
+    }
+    let mut clock = StageClock::start();
+    clock.begin("prepare");
+    // measured on the input as decoded, before anything adapted changes it
+    let adapted;
+    let options = if options.auto {
+        let analysis = analysis::analyze_input(&mat_bgr)?;
+        dump::dump("analyzed", || analysis.params(), &mat_bgr)?;
+        adapted = analysis.adapt(options);
+        &adapted
+    } else {
+        options
+    };
+    #[cfg(feature = "white-balance")]
+    if let Some(algorithm) = &options.white_balance {
+        mat_bgr = white_balance::balance(&mat_bgr, algorithm)?;
+    }
+    if let Some(lut) = options.lut.as_ref().filter(|l| l.stage == LutStage::Pre) {
+        mat_bgr = grading::apply_lut(&mat_bgr, lut)?;
+    }
     
-    let mat_lab = bgr_to_lab(&mat_bgr)?;
+    if let Some(method) = &options.denoise {
+        mat_bgr = enhance::denoise(&mat_bgr, method)?;
+    }
+    mat_bgr = plugin::apply(&options.stages, StagePoint::Input, mat_bgr)?;
+    clock.lap("prepare", &mat_bgr);
+    dump::dump("prepared", String::new, &mat_bgr)?;
+    #[cfg(feature = "dnn")]
+    let stage = if options.style.is_some() { "style" } else { "cartoonize" };
+    #[cfg(not(feature = "dnn"))]
+    let stage = "cartoonize";
+    clock.begin(stage);
+    #[cfg(feature = "dnn")]
+    let stylized = match &options.style {
+        Some(style_options) => Some(style::transfer(&mat_bgr, style_options)?),
+        None => None,
+    };
+    #[cfg(not(feature = "dnn"))]
+    let stylized: Option<Mat> = None;
+    let (mat_0, mat_1, output) = match stylized {
+        // no outlines: the network draws its own
+        Some(output) => {
+            let edge = Mat::new_rows_cols_with_default(output.rows(), output.cols(), CV_8UC1, Scalar::all(255.0))?;
+            (output.try_clone()?, edge, output)
+        }
+        None => cartoonize(&mat_bgr, options)?,
+    };
+    clock.lap(stage, &output);
+    if stage == "style" {
+        dump::dump("stylized", String::new, &output)?;
+    }
+    #[cfg(feature = "superres")]
+    let output = match &options.upscale {
+        Some(upscale_options) => {
+            clock.begin("upscale");
+            let upscaled = upscale::upscale(&output, upscale_options)?;
+            clock.lap("upscale", &upscaled);
+            dump::dump("upscaled", String::new, &upscaled)?;
+            upscaled
+        }
+        None => output,
+    };
+    clock.begin("finish");
+    let mixing = color_profile::Mixing { linear: options.linear_light };
+    let output = match options.lut.as_ref().filter(|l| l.stage == LutStage::Post) {
+        Some(lut) => grading::apply_lut(&output, lut)?,
+        None => output,
+    };
+    let output = match &options.adjust {
+        Some(adjust) => grading::adjust(&output, adjust)?,
+        None => output,
+    };
+    let output = match &options.sharpen {
+        Some(sharpen) => finish::sharpen(&output, sharpen)?,
+        None => output,
+    };
+    let output = match &options.vignette {
+        Some(vignette) => finish::vignette(&output, vignette, mixing)?,
+        None => output,
+    };
+    let output = match &options.texture {
+        Some(texture) => finish::texture(&output, texture, mixing)?,
+        None => output,
+    };
+    let output = plugin::apply(&options.stages, StagePoint::Output, output)?;
+    let output = match &options.ascii {
+        Some(ascii_options) if ascii_options.mode == AsciiMode::Image => ascii::to_image(&output, ascii_options)?,
+        _ => output,
+    };
+    clock.lap("finish", &output);
+    dump::dump("output", String::new, &output)?;
+    Ok((mat_0, mat_1, output))
+}
+
+/*
+ * Stages a conversion with `options` reports to a stage listener, see
+ * `load`, `process` and `cartoonize`
+ */
+#[cfg(feature = "serve")]
+pub(crate) fn stage_count(options: &ConvertOptions) -> usize {
+    #[cfg(feature = "dnn")]
+    let stylized = options.style.is_some();
+    #[cfg(not(feature = "dnn"))]
+    let stylized = false;
+    // decode, prepare, finish and encode, around the style stage or the
+    // cartoonize stage and its analyze, base, edges and merge
+    let mut count = if stylized { 5 } else { 9 };
+    #[cfg(feature = "superres")]
+    if options.upscale.is_some() {
+        count += 1;
+    }
+    count
+}
+
+/*
+ * BGR image -> (flattened base, edge mask, merged output)
+ */
+fn cartoonize(mat_bgr: &Mat, options: &ConvertOptions) -> Result<(Mat, Mat, Mat), Box<dyn Error>> {
+    let mut clock = StageClock::start();
+    clock.begin("analyze");
+    let mut mat_lab = bgr_to_lab(mat_bgr)?;
+    if let Some(clahe) = &options.clahe {
+        mat_lab = enhance::clahe(&mat_lab, clahe)?;
+    }
+
+    let depth = match &options.depth {
+        Some(depth_options) => Some(depth::depth_map(mat_bgr, depth_options)?),
+        None => None,
+    };
+    let skin = match &options.portrait {
+        Some(_) => Some(portrait::skin_mask(mat_bgr)?),
+        None => None,
+    };
+    #[cfg(feature = "faces")]
+    let faces = match &options.faces {
+        Some(face_options) => faces::detect(mat_bgr, face_options)?,
+        None => Vector::new(),
+    };
+    #[cfg(feature = "dnn")]
+    let labels = match &options.segmentation {
+        Some(segmentation_options) => Some(segmentation::classify(mat_bgr, segmentation_options)?),
+        None => None,
+    };
+    clock.lap("analyze", &mat_lab);
+    if let Some(clahe) = &options.clahe {
+        dump::dump_lab("equalized", || format!("clip{}_grid{}", clahe.clip_limit, clahe.tile_grid), &mat_lab)?;
+    }
+    clock.begin("base");
 
     /* base */
-    let mut mat_0 = segment_colors(&mat_lab)?;
+    let flatten = || match (&options.depth, &depth) {
+        (Some(depth_options), Some(nearness)) => {
+            depth::segment_colors_by_depth(&mat_lab, nearness, options, depth_options)
+        }
+        _ => smooth_base(&mat_lab, options),
+    };
+    #[cfg(feature = "saliency")]
+    let mut mat_0 = match &options.saliency {
+        Some(saliency_options) => {
+            let importance = saliency::importance_map(mat_bgr)?;
+            saliency::segment_colors_guided(&mat_lab, &importance, options, saliency_options)?
+        }
+        None => flatten()?,
+    };
+    #[cfg(not(feature = "saliency"))]
+    let mut mat_0 = flatten()?;
+    if let (Some(portrait_options), Some(skin)) = (&options.portrait, &skin) {
+        portrait::refine_base(&mut mat_0, &mat_lab, skin, options, portrait_options)?;
+    }
+    #[cfg(feature = "faces")]
+    if let Some(face_options) = &options.faces {
+        faces::refine_base(&mut mat_0, &mat_lab, &faces, options, face_options)?;
+    }
+    #[cfg(feature = "dnn")]
+    if let (Some(segmentation_options), Some(labels)) = (&options.segmentation, &labels) {
+        segmentation::refine_base(&mut mat_0, &mat_lab, labels, options, segmentation_options)?;
+    }
+    dump::dump_lab("segmented", || segmentation_params(options), &mat_0)?;
+    if let Some(posterize) = &options.posterize {
+        let colors = palette::kmeans_palette(&mat_0, posterize.colors)?;
+        mat_0 = palette::map_to_palette(&mat_0, &colors, posterize.dither)?;
+    }
+    if let Some(fixed) = &options.palette {
+        let dither = options.posterize.as_ref().is_some_and(|p| p.dither);
+        mat_0 = palette::map_to_palette(&mat_0, &palette::palette_to_lab(fixed)?, dither)?;
+    }
     // opencv::highgui::imshow("segmented", &mat_0)?;
     mat_0 = lab_to_bgr(&mat_0)?;
-    
+    if let Some(gradient) = &options.gradient_map {
+        mat_0 = grading::gradient_map(&mat_0, gradient)?;
+    }
+    if let Some(halftone) = &options.halftone {
+        mat_0 = shading::halftone(&mat_0, halftone)?;
+    }
+    if let Some(hatch) = &options.hatch {
+        mat_0 = shading::hatch(&mat_0, hatch)?;
+    }
+    mat_0 = plugin::apply(&options.stages, StagePoint::Base, mat_0)?;
+    clock.lap("base", &mat_0);
+    dump::dump("base", String::new, &mat_0)?;
+    clock.begin("edges");
+
     /* border */
-    let mut mat_1 = anisotropic_blur(&mat_lab)?;
+    let mut mat_1 = anisotropic_blur(&mat_lab, options)?;
+    dump::dump_lab("diffused", || diffusion_params(options), &mat_1)?;
     // opencv::highgui::imshow("blurred", &mat_1)?;
-    mat_1 = gray_from_lab(&mat_1)?;
-    // opencv::highgui::imshow("grayscaled", &mat_1)?;
-    mat_1 = grayscaled_to_edged(&mat_1)?;
+    let mat_gray = gray_from_lab(&mat_1)?;
+    // opencv::highgui::imshow("grayscaled", &mat_gray)?;
+    mat_1 = match &options.edge_detector {
+        #[cfg(feature = "ximgproc")]
+        EdgeDetector::Structured { model, threshold } => {
+            edges::structured(mat_bgr, &mat_gray, model, *threshold, options)?
+        }
+        #[cfg(not(feature = "ximgproc"))]
+        EdgeDetector::Structured { .. } => return Err("structured edges need the ximgproc feature".into()),
+        EdgeDetector::AdaptiveThreshold if options.edge_scales > 1 => edges::multi_scale(&mat_gray, options)?,
+        EdgeDetector::AdaptiveThreshold => grayscaled_to_edged(&mat_gray, options)?,
+    };
+    dump::dump("edges", || edge_params(options), &mat_1)?;
+    if let (Some(depth_options), Some(nearness)) = (&options.depth, &depth) {
+        depth::refine_edge(&mut mat_1, &mat_gray, nearness, options, depth_options)?;
+    }
+    if let (Some(portrait_options), Some(skin)) = (&options.portrait, &skin) {
+        portrait::refine_edge(&mut mat_1, &mat_gray, skin, options, portrait_options)?;
+    }
+    #[cfg(feature = "faces")]
+    faces::refine_edge(&mut mat_1, &mat_gray, &faces, options)?;
+    #[cfg(feature = "dnn")]
+    if let (Some(segmentation_options), Some(labels)) = (&options.segmentation, &labels) {
+        segmentation::refine_edge(&mut mat_1, &mat_gray, labels, options, segmentation_options)?;
+    }
+    if !options.morphology.is_empty() {
+        mat_1 = edges::apply_morphology(&mat_1, &options.morphology)?;
+    }
+    if options.min_edge_area > 0.0 {
+        mat_1 = edges::remove_speckle(&mat_1, options.min_edge_area)?;
+    }
+    mat_1 = plugin::apply(&options.stages, StagePoint::Edges, mat_1)?;
+    clock.lap("edges", &mat_1);
+    dump::dump("edges_final", || format!("min-area{}", options.min_edge_area), &mat_1)?;
+    clock.begin("merge");
     // opencv::highgui::imshow("edged", &mat_1)?;
     
+    if let Some(pixelart) = &options.pixelart {
+        (mat_0, mat_1) = pixelart::pixelate(&mat_0, &mat_1, pixelart, options.linear_light)?;
+    }
+
     /* merge */
-    let output = combine_base_and_edge(&mat_0, &mat_1)?;
-    // opencv::highgui::imshow("output", &output)?;
-    let path_write = format!("{}/{}", folder, filename.replace(".", ".nft."));
-    imwrite(&path_write, &output, &Vector::default())?;
+    let output = match &options.popart {
+        Some(popart) => popart::compose(&mat_0, &mat_1, options, popart)?,
+        None => combine_base_and_edge(&mat_0, &mat_1, options)?,
+    };
+    clock.lap("merge", &output);
+    dump::dump("merged", String::new, &output)?;
+    Ok((mat_0, mat_1, output))
+}
 
-    // opencv::highgui::wait_key(0)?;
-    Ok(())
+/*
+ * Parameters of the smoothing backend, for dumped file names
+ */
+fn segmentation_params(options: &ConvertOptions) -> String {
+    match &options.smoothing {
+        Smoothing::MeanShift => format!(
+            "mean-shift_sr{}_cr{}_l{}",
+            options.spatial_radius, options.color_radius, options.max_pyramid_level
+        ),
+        smoothing => smoothing.to_string(),
+    }
+}
+
+fn diffusion_params(options: &ConvertOptions) -> String {
+    if cfg!(feature = "ximgproc") {
+        format!("a{}_k{}_n{}", options.diffusion_alpha, options.diffusion_k, options.diffusion_iterations)
+    } else {
+        "bilateral".to_string()
+    }
+}
+
+fn edge_params(options: &ConvertOptions) -> String {
+    match &options.edge_detector {
+        EdgeDetector::AdaptiveThreshold => format!(
+            "bs{}_c{}_scales{}_d{}x{}",
+            options.threshold_block_size,
+            options.threshold_c,
+            options.edge_scales,
+            options.dilate_kernel_size,
+            options.dilate_iterations.map_or(0, Iterations::get)
+        ),
+        EdgeDetector::Structured { threshold, .. } => format!("sed_t{}", threshold),
+    }
+}
+
+/*
+ * Encoded image -> BGR image of at most `options.max_pixels` pixels. The
+ * header is checked before decoding, so oversized inputs are refused or
+ * reduced without allocating them in full where the codec allows.
+ */
+fn decode_limited(bytes: &[u8], path: &Path, options: &ConvertOptions) -> Result<Mat, Box<dyn Error>> {
+    // orientation is applied by convert_file, not by OpenCV, so
+    // --no-auto-orient works the same for every format
+    let mut flags = IMREAD_COLOR | IMREAD_IGNORE_ORIENTATION;
+    let max_pixels = options.max_pixels.unwrap_or(u64::MAX);
+    let too_large = |pixels: u64| -> Box<dyn Error> {
+        format!("{} has {} pixels, more than the limit of {}", path.display(), pixels, max_pixels).into()
+    };
+
+    let declared = metadata::dimensions(bytes).map(|(width, height)| width as u64 * height as u64);
+    if let Some(pixels) = declared.filter(|&p| p > max_pixels) {
+        if !options.downscale_oversized {
+            return Err(too_large(pixels));
+        }
+        // JPEG decodes directly at 1/2, 1/4 or 1/8
+        flags |= match pixels / max_pixels {
+            64.. => IMREAD_REDUCED_COLOR_8,
+            16.. => IMREAD_REDUCED_COLOR_4,
+            4.. => IMREAD_REDUCED_COLOR_2,
+            _ => 0,
+        };
+    }
+    let image = imdecode(&Vector::from_slice(bytes), flags)?;
+    let pixels = image.total() as u64;
+    if pixels <= max_pixels {
+        if declared.is_some_and(|p| p > max_pixels) {
+            progress::report(Progress::Downscaled { width: image.cols(), height: image.rows() });
+        }
+        return Ok(image);
+    }
+    if !options.downscale_oversized {
+        return Err(too_large(pixels));
+    }
+
+    let scale = (max_pixels as f64 / pixels as f64).sqrt();
+    let size = Size::new(
+        ((image.cols() as f64 * scale) as i32).max(1),
+        ((image.rows() as f64 * scale) as i32).max(1),
+    );
+    let mut output = Mat::default();
+    resize(&image, &mut output, size, 0.0, 0.0, INTER_AREA)?;
+    progress::report(Progress::Downscaled { width: size.width, height: size.height });
+    Ok(output)
+}
+
+/*
+ * Image stored with EXIF `orientation` -> upright image
+ */
+fn orient(input: &Mat, orientation: u16) -> Result<Mat, Box<dyn Error>> {
+    let mut output = Mat::default();
+    match orientation {
+        2 => flip(input, &mut output, 1)?,
+        3 => rotate(input, &mut output, ROTATE_180)?,
+        4 => flip(input, &mut output, 0)?,
+        5 => transpose(input, &mut output)?,
+        6 => rotate(input, &mut output, ROTATE_90_CLOCKWISE)?,
+        7 => {
+            let mut transposed = Mat::default();
+            transpose(input, &mut transposed)?;
+            flip(&transposed, &mut output, -1)?;
+        }
+        8 => rotate(input, &mut output, ROTATE_90_COUNTERCLOCKWISE)?,
+        _ => output = input.try_clone()?,
+    }
+    Ok(output)
+}
+
+/*
+ * Decodes in memory, so that any path works; empty when OpenCV cannot decode
+ * the file.
+ */
+pub(crate) fn read_image(path: &Path, flags: i32) -> Result<Mat, Box<dyn Error>> {
+    Ok(imdecode(&Vector::from_slice(&fs::read(path)?), flags)?)
+}
+
+/*
+ * Encodes and writes atomically, see `naming::write_atomic`.
+ */
+pub(crate) fn write_image(path: &Path, image: &Mat) -> Result<(), Box<dyn Error>> {
+    let extension = path.extension().and_then(|e| e.to_str()).ok_or("output has no image extension")?;
+    naming::write_atomic(path, &encode_image(extension, image)?)
+}
+
+/*
+ * Encodes in the format of a file `extension`, in memory so that any path
+ * works.
+ */
+fn encode_image(extension: &str, image: &Mat) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut encoded = Vector::<u8>::new();
+    if !imencode(&format!(".{}", extension), image, &mut encoded, &Vector::default())? {
+        return Err(format!("failed to encode .{} output", extension).into());
+    }
+    Ok(encoded.to_vec())
 }
 
 /*
@@ -63,6 +820,9 @@ fn lab_to_bgr(input: &Mat) -> Result<Mat, Box<dyn Error>> {
 
 /// Extracts the lightness channel from the Lab image.
 fn gray_from_lab(input: &Mat) -> Result<Mat, Box<dyn Error>> {
+    if input.channels() != 3 {
+        return Err(NftImgError::UnexpectedChannels { expected: 3, found: input.channels() }.into());
+    }
     let mut channels = Vector::<Mat>::new();
     split(input, &mut channels)?;
     // Extract the L channel (index 0) from the Lab image
@@ -73,8 +833,14 @@ fn gray_from_lab(input: &Mat) -> Result<Mat, Box<dyn Error>> {
 /*
  * grayscaled image -> edged image
  */ 
-fn grayscaled_to_edged(input: &Mat) -> Result<Mat, Box<dyn Error>> {
+fn grayscaled_to_edged(input: &Mat, options: &ConvertOptions) -> Result<Mat, Box<dyn Error>> {
     let max_binary_value = 255.0;
+    // the block may not exceed the image, and must stay odd
+    let smaller_side = input.rows().min(input.cols());
+    if smaller_side < 3 {
+        return Err(format!("image of {}x{} pixels is too small to outline", input.cols(), input.rows()).into());
+    }
+    let block_size = i32::from(options.threshold_block_size).min(smaller_side - (1 - smaller_side % 2));
     let mut edges = Mat::default();
     adaptive_threshold(
         input,
@@ -82,17 +848,31 @@ fn grayscaled_to_edged(input: &Mat) -> Result<Mat, Box<dyn Error>> {
         max_binary_value,
         ADAPTIVE_THRESH_MEAN_C,
         THRESH_BINARY,
-        9,
-        9.0,
+        block_size,
+        options.threshold_c,
     )?;
+    thicken_edges(&edges, input, options)
+}
+
+/*
+ * binary edge mask -> edge mask with the configured line width, uniform or
+ * modulated by the grayscaled image
+ */
+fn thicken_edges(edges: &Mat, gray: &Mat, options: &ConvertOptions) -> Result<Mat, Box<dyn Error>> {
+    if let Some(stroke) = &options.stroke {
+        return strokes::modulate(edges, gray, stroke);
+    }
 
     // Dilate the edges, i.e. make them less prominent.
+    let Some(iterations) = options.dilate_iterations else {
+        return Ok(edges.try_clone()?);
+    };
     let mut output = Mat::default();
-    let kernel = get_structuring_element(MORPH_RECT, Size::new(3, 3), Point::new(-1, -1))?;
+    let kernel = options.dilate_kernel_shape.kernel(options.dilate_kernel_size)?;
     let anchor = Point::new(-1, -1);
-    let iterations = 1;
+    let iterations = iterations.into();
     dilate(
-        &edges,
+        edges,
         &mut output,
         &kernel,
         anchor,
@@ -103,6 +883,35 @@ fn grayscaled_to_edged(input: &Mat) -> Result<Mat, Box<dyn Error>> {
     Ok(output)
 }
 
+/*
+ * Lab image -> flattened Lab image, using the configured smoothing backend
+ */
+fn smooth_base(input: &Mat, options: &ConvertOptions) -> Result<Mat, Box<dyn Error>> {
+    match options.smoothing {
+        Smoothing::MeanShift => segment_colors(input, options),
+        #[cfg(feature = "ximgproc")]
+        Smoothing::Slic { region_size, ruler, iterations } => smoothing::slic(input, region_size, ruler, iterations),
+        #[cfg(feature = "ximgproc")]
+        Smoothing::Seeds { superpixels, levels, iterations } => {
+            smoothing::seeds(input, superpixels, levels, iterations)
+        }
+        #[cfg(feature = "ximgproc")]
+        Smoothing::DomainTransform { sigma_spatial, sigma_color, iterations } => {
+            smoothing::domain_transform(input, sigma_spatial, sigma_color, iterations)
+        }
+        #[cfg(feature = "ximgproc")]
+        Smoothing::Guided { radius, eps, lightness } => smoothing::guided(input, radius, eps, lightness),
+        #[cfg(feature = "ximgproc")]
+        Smoothing::RollingGuidance { diameter, sigma_color, sigma_space, iterations } => {
+            smoothing::rolling_guidance(input, diameter, sigma_color, sigma_space, iterations)
+        }
+        #[cfg(feature = "ximgproc")]
+        Smoothing::L0 { lambda, kappa } => smoothing::l0(input, lambda, kappa),
+        #[cfg(not(feature = "ximgproc"))]
+        other => Err(format!("{} smoothing needs the ximgproc feature", other).into()),
+    }
+}
+
 /**
  * OpenCV provides an image processing function based on the Mean Shift algorithm. 
  * This function is primarily used for image smoothing and segmentation, especially 
@@ -110,10 +919,10 @@ fn grayscaled_to_edged(input: &Mat) -> Result<Mat, Box<dyn Error>> {
  * It is a non-parametric clustering method that performs smoothing and segmentation 
  * by finding high-density regions of data points in both the spatial and color spaces.
  */
-fn segment_colors(input: &Mat) -> Result<Mat, Box<dyn Error>> {
-    let spatial_radius = 10.0;
-    let color_radius = 20.0;
-    let max_pyramid_level = 1;
+fn segment_colors(input: &Mat, options: &ConvertOptions) -> Result<Mat, Box<dyn Error>> {
+    let spatial_radius = options.spatial_radius.get();
+    let color_radius = options.color_radius.get();
+    let max_pyramid_level = options.max_pyramid_level;
     let term_criteria = TermCriteria::default()?;
     let mut output = Mat::default();
     pyr_mean_shift_filtering(
@@ -132,11 +941,25 @@ fn segment_colors(input: &Mat) -> Result<Mat, Box<dyn Error>> {
  * This method adjusts the direction and degree of blur based on the local characteristics of the image, 
  * such as edge direction and intensity, in order to preserve edge details.
  */
-fn anisotropic_blur(input: &Mat) -> Result<Mat, Box<dyn Error>> {
+#[cfg(feature = "ximgproc")]
+fn anisotropic_blur(input: &Mat, options: &ConvertOptions) -> Result<Mat, Box<dyn Error>> {
     let mut output = Mat::default();
-    let conductance = 0.1;
-    let time_step = 0.05;
-    let num_iterations = 10;
+    let conductance = options.diffusion_k;
+    let time_step = options.diffusion_alpha;
+    let num_iterations = options.diffusion_iterations.into();
+    // iterations only read the previous 8-bit result, so one at a time
+    // gives the same image and lets progress be reported between them
+    let total = options.diffusion_iterations.get();
+    if progress::observed() && total > 1 {
+        let mut output = input.try_clone()?;
+        for done in 1..=total {
+            let mut next = Mat::default();
+            anisotropic_diffusion(&output, &mut next, time_step, conductance, 1)?;
+            output = next;
+            progress::advance("diffusion", done, total);
+        }
+        return Ok(output);
+    }
     anisotropic_diffusion(
         &input,
         &mut output,
@@ -147,10 +970,42 @@ fn anisotropic_blur(input: &Mat) -> Result<Mat, Box<dyn Error>> {
     Ok(output)
 }
 
+/*
+ * Stand-in for OpenCV builds without contrib: a bilateral filter, also edge
+ * preserving but blind to the diffusion parameters.
+ */
+#[cfg(not(feature = "ximgproc"))]
+fn anisotropic_blur(input: &Mat, _options: &ConvertOptions) -> Result<Mat, Box<dyn Error>> {
+    let mut output = Mat::default();
+    bilateral_filter(input, &mut output, 5, 25.0, 5.0, BORDER_REFLECT)?;
+    Ok(output)
+}
+
+/*
+ * Per-pixel mix of two 8-bit images of the same type: `weight` (CV_32FC1,
+ * in [0, 1]) of `a` plus the rest of `b`.
+ */
+fn blend_weighted(a: &Mat, b: &Mat, weight: &Mat) -> Result<Mat, Box<dyn Error>> {
+    let mut output = a.try_clone()?;
+    let channels = a.channels() as usize;
+    let weights = weight.data_typed::<f32>()?;
+    let pixels = output.data_bytes_mut()?.chunks_exact_mut(channels);
+    for ((px, other), &w) in pixels.zip(b.data_bytes()?.chunks_exact(channels)).zip(weights) {
+        for (v, &o) in px.iter_mut().zip(other) {
+            *v = (w * *v as f32 + (1.0 - w) * o as f32).round() as u8;
+        }
+    }
+    Ok(output)
+}
+
 fn combine_base_and_edge(
     base: &Mat,
     edge: &Mat,
+    options: &ConvertOptions,
 ) -> Result<Mat, Box<dyn Error>> {
+    if let Some(style) = &options.edge_style {
+        return composite::composite(base, edge, style, color_profile::Mixing { linear: options.linear_light });
+    }
     let mut output = Mat::default();
     bitwise_and(base, base, &mut output, edge)?;
     Ok(output)
@@ -1,16 +1,702 @@
 use std::error::Error;
 use std::env;
+use std::ffi::OsString;
+use std::fs;
+#[cfg(any(feature = "serve", feature = "grpc"))]
+use std::net::Ipv4Addr;
+#[cfg(any(feature = "serve", feature = "grpc", feature = "worker"))]
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
 
+use nftimg::{
+    AdjustOptions, AsciiOptions, ClaheOptions, ColorProfile, ConvertOptions, DepthOptions, EdgeStyle, ExifField,
+    HalftoneOptions, HatchOptions, LutOptions, Palette, PixelArtOptions, PopArtOptions, PortraitOptions,
+    PosterizeOptions, SharpenOptions, StrokeOptions, SvgOptions, TextureOptions, VignetteOptions, Xmp,
+};
+
+/*
+ * nftimg [options] <image|dir|url>
+ *   --recipe OUTPUT.png      reuse the parameters embedded in a previous output
+ *   --config FILE            read parameters from a recipe file, one key=value per line
+ *   --profile FILE.toml      read every option from a profile, see export-profile (feature "toml")
+ *   --white-balance ALGO     simple | grayworld | learning[:MODEL] (feature "white-balance")
+ *   --denoise METHOD         median[:SIZE] | nlmeans[:H:H_COLOR] before segmentation
+ *   --clahe CLIP             equalize lightness before segmentation, e.g. 2.0
+ *   --clahe-grid TILES       CLAHE tiles per side
+ *   --smoothing BACKEND      mean-shift | slic[:SIZE:RULER:ITER] | seeds[:N:LEVELS:ITER]
+ *                            | dt[:SIGMA_S:SIGMA_C:ITER] | guided[:RADIUS:EPS:self|l]
+ *                            | rolling[:D:SIGMA_C:SIGMA_S:ITER] | l0[:LAMBDA:KAPPA]
+ *   --edges DETECTOR         adaptive | sed:MODEL.yml.gz[:THRESHOLD] structured edge detection
+ *   --edge-scales LEVELS     detect outlines on LEVELS pyramid levels and combine them
+ *   --morph OP:SHAPE:SIZE[:N] dilate|erode|open|close the outlines, rect|ellipse|cross kernel; repeatable
+ *   --dilate-shape SHAPE     rect | ellipse | cross kernel of the default dilation
+ *   --min-edge-area PIXELS   drop outline specks smaller than PIXELS
+ *   --strokes MIN:MAX        outline width from MIN to MAX pixels by edge strength
+ *   --edge-color #HEX        outline color instead of black
+ *   --edge-opacity O         outline opacity, below 1 for a semi-transparent overlay
+ *   --background #HEX        color revealed under the outlines instead of the base
+ *   --antialias SIGMA        feather the outlines and alpha-blend them, e.g. 0.8
+ *   --posterize COLORS       reduce the base to COLORS colors
+ *   --dither                 Floyd–Steinberg dithering when posterizing
+ *   --palette COLORS|FILE    map to fixed colors: #hex list, .gpl, .aco or hex text file
+ *   --gradient-map STOPS     duotone/tritone: comma-separated #hex stops, darkest first
+ *   --halftone MODE          mono | cmyk comic-print dots under the outlines
+ *   --dot-size PIXELS        halftone cell size
+ *   --hatch SPACING          cross-hatch the mid-tones, lines SPACING pixels apart
+ *   --pixel-art CELLS        pixel art, CELLS cells along the longer side
+ *   --pixel-colors COLORS    pixel-art palette size
+ *   --no-pixel-outline       pixel art without the outline cells
+ *   --popart ROWSxCOLS       pop-art grid of differently colored panels
+ *   --ascii MODE             text | ansi | image ASCII art rendering
+ *   --ascii-columns COLUMNS  glyphs per line of the ASCII art
+ *   --svg COLORS             also trace COLORS color regions and the outlines into an SVG
+ *   --upscale MODEL.pb       super-resolution upscale, e.g. EDSR_x4.pb (feature "superres")
+ *   --lut FILE.cube          color grade with a 1D or 3D LUT
+ *   --lut-stage STAGE        pre (on the input) | post (on the output, default)
+ *   --saturation FACTOR      output saturation factor
+ *   --brightness OFFSET      output brightness offset, -1 to 1
+ *   --contrast FACTOR        output contrast factor
+ *   --hue DEGREES            rotate output hues
+ *   --sharpen AMOUNT         unsharp-mask the output
+ *   --sharpen-radius SIGMA   unsharp-mask blur radius
+ *   --sharpen-threshold N    leave differences of up to N levels unsharpened, 0 to 255
+ *   --vignette STRENGTH      darken the borders, 0 to 1
+ *   --vignette-radius R      where the vignette starts, fraction of the half diagonal
+ *   --vignette-feather F     width of the vignette transition
+ *   --texture grain|IMAGE    overlay film grain or a paper/canvas image
+ *   --texture-opacity O      texture share, 0 to 1
+ *   --texture-blend MODE     normal | multiply | screen | overlay
+ *   --max-pixels N           refuse inputs above N pixels, 0 for no limit (default 100000000)
+ *   --downscale              downscale inputs above --max-pixels instead of refusing them
+ *   --no-auto-orient         ignore the EXIF orientation of the input
+ *   --linear-light           blend outlines, finishes and pixel-art cells in linear light
+ *   --auto                   adapt denoising, CLAHE, saturation, outline threshold and dilation
+ *                            and the color radius to the measured noise, sharpness, exposure and
+ *                            colors of each input
+ *   --input-profile PROFILE  srgb | adobe-rgb | display-p3, instead of detecting it
+ *   --saliency               keep detail on the subject (feature "saliency")
+ *   --depth SOURCE           file:DEPTH.png | midas:MODEL (feature "dnn"), flatter backgrounds
+ *   --portrait               smooth skin harder
+ *   --faces CASCADE.xml      keep detail inside faces (feature "faces")
+ *   --segmentation MODEL     per-class parameters from a DeepLab-style network (feature "dnn")
+ *   --segment-class C:S:E    class name or index, radius factor, threshold offset; repeatable
+ *   --style model:MODEL      neural style transfer, .t7 or .onnx, instead of outlines (feature "dnn")
+ *                            | gan:MODEL.onnx[:TILE] CartoonGAN/AnimeGAN, tiled above TILE pixels
+ *   --out DIR                write outputs into DIR, created if missing; or an s3:// or gs:// prefix (feature "cloud")
+ *   --suffix NAME            output name NAME inserted before the extension, default nft
+ *   --debug-dir DIR          write every intermediate image into DIR, named after its step and
+ *                            parameters, e.g. 02_segmented_mean-shift_sr10_cr20_l1.png; one
+ *                            subdirectory per image for folders
+ *   --restyle-outputs        folders: also convert earlier outputs, skipped by default
+ *   --timeout SECONDS        folders: skip images taking longer than SECONDS
+ *   --webhook URL            POST a JSON summary to URL when a folder, server job or worker
+ *                            job ends (feature "webhook")
+ *   --quality DIR            folders: score outputs with BRISQUE, model and range files from DIR
+ *                            (feature "quality")
+ *   --quality-max SCORE      folders: flag outputs scoring above SCORE, 0-100, for review
+ *   --stats FILE             folders: also write dimensions, palette, edge density and stage
+ *                            timings per image to FILE, .csv or .parquet (feature "parquet")
+ *   --state-db FILE          folders: keep per-image state and timings in SQLite, resume and
+ *                            share the folder between concurrent runs (feature "sqlite")
+ *   --deterministic          single-threaded and seeded, byte-identical output on every run
+ *   --urls FILE              convert the URL on each line, or in each CSV row (feature "http")
+ *   --keep-exif FIELDS       comma-separated EXIF fields copied from the input
+ *   --artist NAME            XMP creator
+ *   --license TEXT           XMP usage terms
+ *
+ * nftimg doctor
+ *   report the OpenCV version, modules, OpenCL/CUDA and codecs, for bug reports
+ *
+ * nftimg diff A.png B.png [--heatmap FILE]
+ *   print PSNR, SSIM, the largest channel difference and the share of changed pixels
+ *   between two outputs; --heatmap writes where they differ, blue to red
+ *
+ * nftimg describe [OPTIONS]
+ *   print the stages and parameters OPTIONS run as a Graphviz graph, e.g. | dot -Tsvg
+ *
+ * nftimg tune [OPTIONS] --reference STYLED.png IMAGE
+ *   search the smoothing and outline parameters whose output of IMAGE comes closest to
+ *   STYLED.png and print them as a recipe for --config; OPTIONS are the starting point
+ *   --trials N               conversions tried (default 48); keep IMAGE small
+ *   --seed N                 seed of the sampling (default 1)
+ *
+ * nftimg export-profile [OPTIONS] [FILE.toml] (feature "toml")
+ *   write every option OPTIONS resolve to as a TOML profile to FILE, or print it;
+ *   `--recipe OUTPUT.png` recovers the parameters of an earlier output
+ *
+ * nftimg serve [OPTIONS] (feature "serve")
+ *   answer POST /convert with the converted body, OPTIONS as defaults, and GET /metrics;
+ *   POST /jobs converts in the background, streaming progress at /jobs/ID/events
+ *   --port N                 listen on port N (default 8080)
+ *   --bind ADDR              listen on ADDR instead of 127.0.0.1, e.g. 0.0.0.0
+ *
+ * nftimg grpc [OPTIONS] (feature "grpc")
+ *   serve proto/nftimg.proto on port 50051, OPTIONS as defaults; --port and --bind as above
+ *   --metrics ADDR:PORT      also serve Prometheus metrics at http://ADDR:PORT/metrics
+ *
+ * nftimg worker --redis URL [OPTIONS] (feature "worker")
+ *   convert jobs popped from a Redis list, OPTIONS as defaults
+ *   --queue NAME             list to pop jobs from (default nftimg:jobs)
+ *   --metrics ADDR:PORT      serve Prometheus metrics at http://ADDR:PORT/metrics
+ *
+ * nftimg mqtt --broker HOST[:PORT] --topic TOPIC [OPTIONS] (feature "mqtt")
+ *   convert the image, or the image path, in each message on TOPIC, OPTIONS as defaults
+ *   --reply-topic TOPIC      where the JSON status of each message is published
+ *                            (default nftimg/status)
+ *
+ * nftimg daemon --socket PATH [OPTIONS] (feature "daemon", Unix)
+ *   answer JSON-RPC convert requests on a Unix socket, one per line, OPTIONS as defaults
+ *
+ * nftimg run SCRIPT.rhai IMAGE [OPTIONS] (feature "script")
+ *   run the pipeline in a Rhai script on IMAGE, OPTIONS as the stages' defaults
+ */
 fn main() -> Result<(), Box<dyn Error>> {
 
-    let mut args = env::args();
-    if args.len() != 2 { return Ok(()); }
-    let img = args.nth(1).unwrap();
-    println!("image={}", &img);    
+    // an image named `doctor` is still reachable as `./doctor`
+    if env::args_os().nth(1).is_some_and(|arg| arg == "doctor") {
+        print!("{}", nftimg::diagnostics()?);
+        return Ok(());
+    }
+    if env::args_os().nth(1).is_some_and(|arg| arg == "diff") {
+        return diff(env::args_os().skip(2).collect());
+    }
+
+    let mut options = ConvertOptions::default();
+    let mut img = None;
+    #[cfg(feature = "http")]
+    let mut url_list = None;
+    #[cfg(feature = "cloud")]
+    let mut output_uri = None;
+    #[cfg(feature = "sqlite")]
+    let mut state_db: Option<String> = None;
+    let mut stats = None;
+    let (mut reference, mut tune_options) = (None, nftimg::TuneOptions::default());
+    // flags and values are matched as text, the image path is kept as given
+    // servers and workers take the conversion flags as defaults for every
+    // request or job
+    let mode = match env::args_os().nth(1) {
+        Some(arg) if arg == "describe" => Some(("describe", 0)),
+        Some(arg) if arg == "tune" => Some(("tune", 0)),
+        Some(arg) if cfg!(feature = "serve") && arg == "serve" => Some(("serve", 8080)),
+        Some(arg) if cfg!(feature = "grpc") && arg == "grpc" => Some(("grpc", 50051)),
+        Some(arg) if cfg!(feature = "worker") && arg == "worker" => Some(("worker", 0)),
+        Some(arg) if cfg!(feature = "script") && arg == "run" => Some(("run", 0)),
+        Some(arg) if cfg!(all(unix, feature = "daemon")) && arg == "daemon" => Some(("daemon", 0)),
+        Some(arg) if cfg!(feature = "mqtt") && arg == "mqtt" => Some(("mqtt", 0)),
+        Some(arg) if cfg!(feature = "toml") && arg == "export-profile" => Some(("export-profile", 0)),
+        _ => None,
+    };
+    #[cfg(any(feature = "serve", feature = "grpc"))]
+    let mut listen = SocketAddr::from((Ipv4Addr::LOCALHOST, mode.map_or(0, |(_, port)| port)));
+    #[cfg(feature = "worker")]
+    let (mut redis_url, mut queue) = (None, "nftimg:jobs".to_string());
+    #[cfg(feature = "script")]
+    let mut script = None;
+    #[cfg(all(unix, feature = "daemon"))]
+    let mut socket = None;
+    #[cfg(feature = "mqtt")]
+    let (mut broker, mut topic, mut reply_topic) = (None, None, "nftimg/status".to_string());
+    #[cfg(any(feature = "grpc", feature = "worker"))]
+    let mut metrics: Option<SocketAddr> = None;
+    let raw: Vec<OsString> = env::args_os().skip(1 + usize::from(mode.is_some())).collect();
+    let mut args = raw.iter().map(|arg| arg.to_string_lossy().into_owned());
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--recipe" => {
+                let output = args.next().ok_or("--recipe requires a value")?;
+                let recipe = nftimg::read_recipe(Path::new(&output))?
+                    .ok_or_else(|| format!("no recipe found in {}", output))?;
+                options = options.with_recipe(&recipe.recipe())?;
+            }
+            "--config" => {
+                let file = args.next().ok_or("--config requires a value")?;
+                options = options.with_recipe(&fs::read_to_string(&file)?)?;
+            }
+            #[cfg(feature = "white-balance")]
+            "--white-balance" => {
+                let algorithm = args.next().ok_or("--white-balance requires a value")?;
+                options.white_balance = Some(algorithm.parse()?);
+            }
+            "--denoise" => {
+                let method = args.next().ok_or("--denoise requires a value")?;
+                options.denoise = Some(method.parse()?);
+            }
+            "--clahe" => {
+                let clip_limit = args.next().ok_or("--clahe requires a value")?;
+                options.clahe.get_or_insert_with(ClaheOptions::default).clip_limit = clip_limit.parse()?;
+            }
+            "--clahe-grid" => {
+                let tiles = args.next().ok_or("--clahe-grid requires a value")?;
+                options.clahe.get_or_insert_with(ClaheOptions::default).tile_grid = tiles.parse()?;
+            }
+            "--smoothing" => {
+                let backend = args.next().ok_or("--smoothing requires a value")?;
+                options.smoothing = backend.parse()?;
+            }
+            "--edges" => {
+                let detector = args.next().ok_or("--edges requires a value")?;
+                options.edge_detector = detector.parse()?;
+            }
+            "--edge-scales" => {
+                let scales = args.next().ok_or("--edge-scales requires a value")?;
+                options.edge_scales = scales.parse()?;
+            }
+            "--morph" => {
+                let step = args.next().ok_or("--morph requires a value")?;
+                options.morphology.push(step.parse()?);
+            }
+            "--dilate-shape" => {
+                let shape = args.next().ok_or("--dilate-shape requires a value")?;
+                options.dilate_kernel_shape = shape.parse()?;
+            }
+            "--min-edge-area" => {
+                let area = args.next().ok_or("--min-edge-area requires a value")?;
+                options.min_edge_area = area.parse()?;
+            }
+            "--strokes" => {
+                let widths = args.next().ok_or("--strokes requires a value")?;
+                let (min, max) = widths.split_once(':').ok_or("--strokes expects MIN:MAX")?;
+                options.stroke = Some(StrokeOptions { min_width: min.parse()?, max_width: max.parse()? });
+            }
+            "--edge-color" => {
+                let color = args.next().ok_or("--edge-color requires a value")?;
+                options.edge_style.get_or_insert_with(EdgeStyle::default).color = nftimg::hex_to_bgr(&color)?;
+            }
+            "--edge-opacity" => {
+                let opacity = args.next().ok_or("--edge-opacity requires a value")?;
+                options.edge_style.get_or_insert_with(EdgeStyle::default).opacity = opacity.parse()?;
+            }
+            "--background" => {
+                let color = args.next().ok_or("--background requires a value")?;
+                let background = nftimg::hex_to_bgr(&color)?;
+                options.edge_style.get_or_insert_with(EdgeStyle::default).background = Some(background);
+            }
+            "--antialias" => {
+                let sigma = args.next().ok_or("--antialias requires a value")?;
+                options.edge_style.get_or_insert_with(EdgeStyle::default).feather = sigma.parse()?;
+            }
+            "--posterize" => {
+                let colors = args.next().ok_or("--posterize requires a value")?;
+                let posterize = options.posterize.get_or_insert_with(PosterizeOptions::default);
+                posterize.colors = colors.parse()?;
+            }
+            "--dither" => options.posterize.get_or_insert_with(PosterizeOptions::default).dither = true,
+            "--palette" => {
+                let palette = args.next().ok_or("--palette requires a value")?;
+                options.palette = Some(if Path::new(&palette).is_file() {
+                    Palette::load(Path::new(&palette))?
+                } else {
+                    palette.parse()?
+                });
+            }
+            "--gradient-map" => {
+                let stops = args.next().ok_or("--gradient-map requires a value")?;
+                options.gradient_map = Some(stops.parse()?);
+            }
+            "--halftone" => {
+                let mode = args.next().ok_or("--halftone requires a value")?;
+                options.halftone.get_or_insert_with(HalftoneOptions::default).mode = mode.parse()?;
+            }
+            "--dot-size" => {
+                let size = args.next().ok_or("--dot-size requires a value")?;
+                options.halftone.get_or_insert_with(HalftoneOptions::default).dot_size = size.parse()?;
+            }
+            "--hatch" => {
+                let spacing = args.next().ok_or("--hatch requires a value")?;
+                options.hatch.get_or_insert_with(HatchOptions::default).spacing = spacing.parse()?;
+            }
+            "--pixel-art" => {
+                let cells = args.next().ok_or("--pixel-art requires a value")?;
+                options.pixelart.get_or_insert_with(PixelArtOptions::default).cells = cells.parse()?;
+            }
+            "--pixel-colors" => {
+                let colors = args.next().ok_or("--pixel-colors requires a value")?;
+                options.pixelart.get_or_insert_with(PixelArtOptions::default).colors = colors.parse()?;
+            }
+            "--no-pixel-outline" => options.pixelart.get_or_insert_with(PixelArtOptions::default).outline = false,
+            "--popart" => {
+                let grid = args.next().ok_or("--popart requires a value")?;
+                let (rows, cols) = grid.split_once('x').ok_or("--popart expects ROWSxCOLS")?;
+                let popart = options.popart.get_or_insert_with(PopArtOptions::default);
+                popart.rows = rows.parse()?;
+                popart.cols = cols.parse()?;
+            }
+            "--ascii" => {
+                let mode = args.next().ok_or("--ascii requires a value")?;
+                options.ascii.get_or_insert_with(AsciiOptions::default).mode = mode.parse()?;
+            }
+            "--ascii-columns" => {
+                let columns = args.next().ok_or("--ascii-columns requires a value")?;
+                options.ascii.get_or_insert_with(AsciiOptions::default).columns = columns.parse()?;
+            }
+            "--svg" => {
+                let colors = args.next().ok_or("--svg requires a value")?;
+                options.svg.get_or_insert_with(SvgOptions::default).colors = colors.parse()?;
+            }
+            #[cfg(feature = "superres")]
+            "--upscale" => {
+                let model = args.next().ok_or("--upscale requires a value")?;
+                options.upscale = Some(nftimg::UpscaleOptions::from_model(model.into())?);
+            }
+            "--lut" => {
+                let path = args.next().ok_or("--lut requires a value")?;
+                options.lut = Some(LutOptions::new(path.into()));
+            }
+            "--lut-stage" => {
+                let stage = args.next().ok_or("--lut-stage requires a value")?;
+                options.lut.as_mut().ok_or("--lut-stage requires --lut first")?.stage = stage.parse()?;
+            }
+            "--saturation" => {
+                let value = args.next().ok_or("--saturation requires a value")?;
+                options.adjust.get_or_insert_with(AdjustOptions::default).saturation = value.parse()?;
+            }
+            "--brightness" => {
+                let value = args.next().ok_or("--brightness requires a value")?;
+                options.adjust.get_or_insert_with(AdjustOptions::default).brightness = value.parse()?;
+            }
+            "--contrast" => {
+                let value = args.next().ok_or("--contrast requires a value")?;
+                options.adjust.get_or_insert_with(AdjustOptions::default).contrast = value.parse()?;
+            }
+            "--hue" => {
+                let value = args.next().ok_or("--hue requires a value")?;
+                options.adjust.get_or_insert_with(AdjustOptions::default).hue = value.parse()?;
+            }
+            "--sharpen" => {
+                let amount = args.next().ok_or("--sharpen requires a value")?;
+                options.sharpen.get_or_insert_with(SharpenOptions::default).amount = amount.parse()?;
+            }
+            "--sharpen-radius" => {
+                let radius = args.next().ok_or("--sharpen-radius requires a value")?;
+                options.sharpen.get_or_insert_with(SharpenOptions::default).radius = radius.parse()?;
+            }
+            "--sharpen-threshold" => {
+                let threshold = args.next().ok_or("--sharpen-threshold requires a value")?;
+                options.sharpen.get_or_insert_with(SharpenOptions::default).threshold = threshold.parse()?;
+            }
+            "--vignette" => {
+                let strength = args.next().ok_or("--vignette requires a value")?;
+                options.vignette.get_or_insert_with(VignetteOptions::default).strength = strength.parse()?;
+            }
+            "--vignette-radius" => {
+                let radius = args.next().ok_or("--vignette-radius requires a value")?;
+                options.vignette.get_or_insert_with(VignetteOptions::default).radius = radius.parse()?;
+            }
+            "--vignette-feather" => {
+                let feather = args.next().ok_or("--vignette-feather requires a value")?;
+                options.vignette.get_or_insert_with(VignetteOptions::default).feather = feather.parse()?;
+            }
+            "--texture" => {
+                let texture = args.next().ok_or("--texture requires a value")?;
+                options.texture.get_or_insert_with(TextureOptions::default).texture = texture.parse()?;
+            }
+            "--texture-opacity" => {
+                let opacity = args.next().ok_or("--texture-opacity requires a value")?;
+                options.texture.get_or_insert_with(TextureOptions::default).opacity = opacity.parse()?;
+            }
+            "--texture-blend" => {
+                let blend = args.next().ok_or("--texture-blend requires a value")?;
+                options.texture.get_or_insert_with(TextureOptions::default).blend = blend.parse()?;
+            }
+            "--max-pixels" => {
+                let max_pixels: u64 = args.next().ok_or("--max-pixels requires a value")?.parse()?;
+                options.max_pixels = (max_pixels > 0).then_some(max_pixels);
+            }
+            "--downscale" => options.downscale_oversized = true,
+            "--no-auto-orient" => options.auto_orient = false,
+            "--linear-light" => options.linear_light = true,
+            "--auto" => options.auto = true,
+            "--input-profile" => {
+                let name = args.next().ok_or("--input-profile requires a value")?;
+                let profile = ColorProfile::from_name(&name)
+                    .ok_or_else(|| format!("unknown color profile: {}", name))?;
+                options.input_profile = Some(profile);
+            }
+            #[cfg(feature = "faces")]
+            "--faces" => {
+                let cascade = args.next().ok_or("--faces requires a value")?;
+                options.faces = Some(nftimg::FaceOptions::new(cascade.into()));
+            }
+            #[cfg(feature = "dnn")]
+            "--segmentation" => {
+                let model = args.next().ok_or("--segmentation requires a value")?;
+                options.segmentation = Some(nftimg::SegmentationOptions::new(model.into()));
+            }
+            #[cfg(feature = "dnn")]
+            "--segment-class" => {
+                let params = args.next().ok_or("--segment-class requires a value")?;
+                let segmentation = options.segmentation.as_mut().ok_or("--segment-class requires --segmentation")?;
+                segmentation.classes.push(params.parse()?);
+            }
+            #[cfg(feature = "dnn")]
+            "--style" => {
+                let style = args.next().ok_or("--style requires a value")?;
+                options.style = Some(style.parse()?);
+            }
+            #[cfg(feature = "saliency")]
+            "--saliency" => options.saliency = Some(nftimg::SaliencyOptions::default()),
+            "--depth" => {
+                let source = args.next().ok_or("--depth requires a value")?;
+                options.depth = Some(DepthOptions::new(source.parse()?));
+            }
+            "--portrait" => options.portrait = Some(PortraitOptions::default()),
+            "--out" => {
+                let dir = args.next().ok_or("--out requires a value")?;
+                #[cfg(feature = "cloud")]
+                if nftimg::is_object_uri(&dir) {
+                    output_uri = Some(dir);
+                    continue;
+                }
+                options.output_dir = Some(dir.into());
+            }
+            "--suffix" => {
+                let suffix = args.next().ok_or("--suffix requires a value")?;
+                if suffix.is_empty() || suffix.contains(['/', '\\']) {
+                    return Err("--suffix must be a non-empty file name part".into());
+                }
+                options.output_suffix = suffix;
+            }
+            "--debug-dir" => options.debug_dir = Some(args.next().ok_or("--debug-dir requires a value")?.into()),
+            // a profile holds every option, flags before it are replaced
+            #[cfg(feature = "toml")]
+            "--profile" => {
+                let file = args.next().ok_or("--profile requires a value")?;
+                options = ConvertOptions::from_toml(&fs::read_to_string(&file)?)?;
+            }
+            "--restyle-outputs" => options.restyle_outputs = true,
+            #[cfg(feature = "webhook")]
+            "--webhook" => options.webhook = Some(args.next().ok_or("--webhook requires a value")?),
+            #[cfg(feature = "quality")]
+            "--quality" => {
+                let dir = args.next().ok_or("--quality requires a value")?;
+                options.quality = Some(nftimg::QualityOptions::from_dir(Path::new(&dir)));
+            }
+            #[cfg(feature = "quality")]
+            "--quality-max" => {
+                let score: f64 = args.next().ok_or("--quality-max requires a value")?.parse()?;
+                options.quality.as_mut().ok_or("--quality-max requires --quality first")?.max_score = Some(score);
+            }
+            "--timeout" => {
+                let seconds: f64 = args.next().ok_or("--timeout requires a value")?.parse()?;
+                let timeout = Duration::try_from_secs_f64(seconds).ok().filter(|t| !t.is_zero());
+                options.timeout = Some(timeout.ok_or("--timeout must be a positive number of seconds")?);
+            }
+            "--stats" => stats = Some(args.next().ok_or("--stats requires a value")?),
+            "--reference" => reference = Some(args.next().ok_or("--reference requires a value")?),
+            "--trials" => tune_options.trials = args.next().ok_or("--trials requires a value")?.parse()?,
+            "--seed" => tune_options.seed = args.next().ok_or("--seed requires a value")?.parse()?,
+            #[cfg(feature = "sqlite")]
+            "--state-db" => state_db = Some(args.next().ok_or("--state-db requires a value")?),
+            "--deterministic" => options.deterministic = true,
+            #[cfg(feature = "http")]
+            "--urls" => url_list = Some(args.next().ok_or("--urls requires a value")?),
+            #[cfg(any(feature = "serve", feature = "grpc"))]
+            "--port" => listen.set_port(args.next().ok_or("--port requires a value")?.parse()?),
+            #[cfg(any(feature = "serve", feature = "grpc"))]
+            "--bind" => listen.set_ip(args.next().ok_or("--bind requires a value")?.parse()?),
+            #[cfg(feature = "worker")]
+            "--redis" => redis_url = Some(args.next().ok_or("--redis requires a value")?),
+            #[cfg(feature = "worker")]
+            "--queue" => queue = args.next().ok_or("--queue requires a value")?,
+            #[cfg(all(unix, feature = "daemon"))]
+            "--socket" => socket = Some(args.next().ok_or("--socket requires a value")?),
+            #[cfg(feature = "mqtt")]
+            "--broker" => broker = Some(args.next().ok_or("--broker requires a value")?),
+            #[cfg(feature = "mqtt")]
+            "--topic" => topic = Some(args.next().ok_or("--topic requires a value")?),
+            #[cfg(feature = "mqtt")]
+            "--reply-topic" => reply_topic = args.next().ok_or("--reply-topic requires a value")?,
+            #[cfg(any(feature = "grpc", feature = "worker"))]
+            "--metrics" => metrics = Some(args.next().ok_or("--metrics requires a value")?.parse()?),
+            "--keep-exif" => {
+                let fields = args.next().ok_or("--keep-exif requires a value")?;
+                for name in fields.split(',') {
+                    let field = ExifField::from_name(name)
+                        .ok_or_else(|| format!("unknown EXIF field: {}", name))?;
+                    options.metadata.exif_fields.push(field);
+                }
+            }
+            "--artist" => {
+                let artist = args.next().ok_or("--artist requires a value")?;
+                options.metadata.xmp.get_or_insert_with(Xmp::default).artist = Some(artist);
+            }
+            "--license" => {
+                let license = args.next().ok_or("--license requires a value")?;
+                options.metadata.xmp.get_or_insert_with(Xmp::default).license = Some(license);
+            }
+            // `run` takes the script before the image
+            #[cfg(feature = "script")]
+            _ if matches!(mode, Some(("run", _))) && script.is_none() => {
+                script = Some(&raw[raw.len() - args.len() - 1])
+            }
+            _ => img = Some(&raw[raw.len() - args.len() - 1]),
+        }
+    }
+    // a bad flag value is reported by name before anything is converted
+    options.validate()?;
+    if let Some(("describe", _)) = mode {
+        print!("{}", nftimg::Pipeline::new(options).describe().to_dot());
+        return Ok(());
+    }
+    if let Some(("tune", _)) = mode {
+        let (reference, img) = reference.zip(img).ok_or("tune requires --reference STYLED.png IMAGE")?;
+        let report = nftimg::tune_files(Path::new(img), Path::new(&reference), &options, &tune_options)?;
+        eprintln!("distance={:.4} start_distance={:.4}", report.distance, report.start_distance);
+        print!("{}", report.options.recipe());
+        return Ok(());
+    }
+    #[cfg(feature = "toml")]
+    if let Some(("export-profile", _)) = mode {
+        let profile = options.to_toml()?;
+        match img {
+            Some(file) => fs::write(file, profile)?,
+            None => print!("{}", profile),
+        }
+        return Ok(());
+    }
+    #[cfg(feature = "serve")]
+    if let Some(("serve", _)) = mode {
+        return nftimg::serve(listen, &options);
+    }
+    #[cfg(any(feature = "grpc", feature = "worker"))]
+    if let Some(addr) = metrics {
+        nftimg::serve_metrics(addr)?;
+    }
+    #[cfg(feature = "grpc")]
+    if let Some(("grpc", _)) = mode {
+        return nftimg::serve_grpc(listen, &options);
+    }
+    #[cfg(feature = "worker")]
+    if let Some(("worker", _)) = mode {
+        let redis_url = redis_url.ok_or("worker requires --redis URL")?;
+        return nftimg::run_worker(&redis_url, &queue, &options);
+    }
+    #[cfg(all(unix, feature = "daemon"))]
+    if let Some(("daemon", _)) = mode {
+        let socket = socket.ok_or("daemon requires --socket PATH")?;
+        return nftimg::run_daemon(Path::new(&socket), &options);
+    }
+    #[cfg(feature = "mqtt")]
+    if let Some(("mqtt", _)) = mode {
+        let broker = broker.ok_or("mqtt requires --broker HOST[:PORT]")?;
+        let topic = topic.ok_or("mqtt requires --topic TOPIC")?;
+        return nftimg::run_mqtt(&broker, &topic, &reply_topic, &options);
+    }
+    #[cfg(feature = "script")]
+    if let Some(("run", _)) = mode {
+        let (script, img) = script.zip(img).ok_or("run requires SCRIPT.rhai IMAGE")?;
+        println!("image={}", Path::new(img).display());
+        println!("output={}", nftimg::run_script(Path::new(script), Path::new(img), &options)?.display());
+        return Ok(());
+    }
+    #[cfg(feature = "http")]
+    if let Some(list) = url_list {
+        // a failed download is reported and the batch goes on
+        for line in fs::read_to_string(&list)?.lines() {
+            let Some(url) = line.split(',').map(|f| f.trim().trim_matches('"')).find(|f| nftimg::is_url(f)) else {
+                continue;
+            };
+            println!("url={}", url);
+            match nftimg::convert_url(url, &options) {
+                Ok(output) => println!("output={}", output.display()),
+                Err(e) => eprintln!("warning: skipped {}: {}", url, e),
+            }
+        }
+        return Ok(());
+    }
+    let Some(img) = img else { return Ok(()); };
+
+    #[cfg(feature = "http")]
+    if let Some(url) = img.to_str().filter(|arg| nftimg::is_url(arg)) {
+        println!("url={}", url);
+        nftimg::convert_url(url, &options)?;
+        return Ok(());
+    }
+
+    #[cfg(feature = "cloud")]
+    if let Some(uri) = img.to_str().filter(|arg| nftimg::is_object_uri(arg)) {
+        println!("object={}", uri);
+        for output in nftimg::convert_objects(uri, output_uri.as_deref(), &options)? {
+            println!("output={}", output);
+        }
+        return Ok(());
+    }
+    #[cfg(feature = "cloud")]
+    if let Some(prefix) = &output_uri {
+        if Path::new(&img).is_dir() {
+            return Err("folders cannot be written to object storage, convert into a local --out instead".into());
+        }
+        println!("image={}", Path::new(&img).display());
+        println!("output={}", nftimg::convert_to_object(Path::new(&img), prefix, &options)?);
+        return Ok(());
+    }
+
+    let dir = Path::new(&img);
+    if dir.is_dir() {
+        println!("collection={}", dir.display());
+        #[cfg(feature = "sqlite")]
+        if let Some(db) = &state_db {
+            let counts = nftimg::convert_batch(dir, Path::new(db), &options)?;
+            println!(
+                "done={} error={} pending={} processing={}",
+                counts.done, counts.error, counts.pending, counts.processing
+            );
+            return Ok(());
+        }
+        let report = nftimg::convert_collection(dir, &options)?;
+        report.write(dir)?;
+        if let Some(stats) = &stats {
+            report.write_stats(Path::new(stats))?;
+        }
+        println!(
+            "images={} duplicates={} failed={} timed_out={}",
+            report.images.len(),
+            report.duplicates.len(),
+            report.failed.len(),
+            report.timed_out.len()
+        );
+        if let Some(error) = report.error() {
+            return Err(error.into());
+        }
+        return Ok(());
+    }
+
+    println!("image={}", dir.display());
+    let path = dir.to_path_buf();
+    nftimg::convert_with_progress(dir, &options, move |progress| {
+        if let nftimg::Progress::Downscaled { width, height } = progress {
+            eprintln!("warning: downscaled {} to {}x{}", path.display(), width, height);
+        }
+    })?;
 
-    nftimg::convert(&img)?;
-    
     Ok(())
 }
 
-
+/*
+ * nftimg diff A B [--heatmap FILE]
+ */
+fn diff(args: Vec<OsString>) -> Result<(), Box<dyn Error>> {
+    let mut images = Vec::new();
+    let mut heatmap = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--heatmap" {
+            heatmap = Some(args.next().ok_or("--heatmap requires a value")?);
+        } else {
+            images.push(Path::new(arg));
+        }
+    }
+    let [a, b] = images[..] else {
+        return Err("diff requires two images".into());
+    };
+    let report = nftimg::compare_files(a, b)?;
+    println!(
+        "psnr={:.2} ssim={:.4} max_abs={} changed={:.4}",
+        report.psnr, report.ssim, report.max_abs, report.changed
+    );
+    if let Some(heatmap) = heatmap {
+        report.write_heatmap(Path::new(heatmap))?;
+    }
+    Ok(())
+}
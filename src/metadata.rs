@@ -0,0 +1,679 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::color_profile;
+use crate::options::ConvertOptions;
+
+pub(crate) const JPEG_SOI: [u8; 2] = [0xFF, 0xD8];
+pub(crate) const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const XMP_KEYWORD: &str = "XML:com.adobe.xmp";
+const RECIPE_KEYWORD: &str = "nftimg:recipe";
+
+/// EXIF (IFD0) fields that may be carried over from the input image.
+///
+/// Orientation is deliberately absent: the pixels are re-encoded, so the
+/// original rotation tag would no longer describe them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "kebab-case"))]
+pub enum ExifField {
+    ImageDescription,
+    Make,
+    Model,
+    Software,
+    DateTime,
+    Artist,
+    Copyright,
+}
+
+impl ExifField {
+    fn tag(self) -> u16 {
+        match self {
+            ExifField::ImageDescription => 0x010E,
+            ExifField::Make => 0x010F,
+            ExifField::Model => 0x0110,
+            ExifField::Software => 0x0131,
+            ExifField::DateTime => 0x0132,
+            ExifField::Artist => 0x013B,
+            ExifField::Copyright => 0x8298,
+        }
+    }
+
+    /// Parses a field name as spelled in the EXIF spec, e.g. `DateTime`.
+    pub fn from_name(name: &str) -> Option<ExifField> {
+        match name {
+            "ImageDescription" => Some(ExifField::ImageDescription),
+            "Make" => Some(ExifField::Make),
+            "Model" => Some(ExifField::Model),
+            "Software" => Some(ExifField::Software),
+            "DateTime" => Some(ExifField::DateTime),
+            "Artist" => Some(ExifField::Artist),
+            "Copyright" => Some(ExifField::Copyright),
+            _ => None,
+        }
+    }
+}
+
+/// Custom XMP properties injected into the output file.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct Xmp {
+    /// Written as `dc:creator`.
+    pub artist: Option<String>,
+    /// Written as `xmpRights:UsageTerms`.
+    pub license: Option<String>,
+    /// Free-form description of the processing parameters.
+    pub parameters: Option<String>,
+}
+
+impl Xmp {
+    fn is_empty(&self) -> bool {
+        self.artist.is_none() && self.license.is_none() && self.parameters.is_none()
+    }
+}
+
+/// What metadata ends up in the output file. `imwrite` drops everything,
+/// so apart from the recipe and the sRGB tag the output carries no metadata
+/// by default.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct MetadataOptions {
+    /// EXIF fields copied from the input, when present there.
+    pub exif_fields: Vec<ExifField>,
+    /// XMP packet written into the output.
+    pub xmp: Option<Xmp>,
+    /// Store the processing parameters in an `iTXt` chunk of PNG outputs,
+    /// see [`read_recipe`].
+    pub embed_recipe: bool,
+    /// Mark the output as sRGB, which is what the pipeline always produces.
+    pub tag_srgb: bool,
+}
+
+impl Default for MetadataOptions {
+    fn default() -> Self {
+        MetadataOptions {
+            exif_fields: Vec::new(),
+            xmp: None,
+            embed_recipe: true,
+            tag_srgb: true,
+        }
+    }
+}
+
+impl MetadataOptions {
+    pub fn is_empty(&self) -> bool {
+        self.exif_fields.is_empty()
+            && self.xmp.as_ref().is_none_or(Xmp::is_empty)
+            && !self.embed_recipe
+            && !self.tag_srgb
+    }
+}
+
+/// Reads back the options embedded in a PNG output, if it carries a recipe.
+pub fn read_recipe(path: &Path) -> Result<Option<ConvertOptions>, Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    if !bytes.starts_with(&PNG_SIGNATURE) {
+        return Ok(None);
+    }
+    for (kind, data) in png_chunks(&bytes) {
+        let Some(data) = data.strip_prefix(RECIPE_KEYWORD.as_bytes()).and_then(|d| d.strip_prefix(b"\0")) else {
+            continue;
+        };
+        // iTXt since paths in recipes may be any UTF-8; tEXt from earlier versions
+        let text = match kind {
+            b"tEXt" => data,
+            b"iTXt" => match itxt_text(data) {
+                Some(text) => text,
+                None => continue,
+            },
+            _ => continue,
+        };
+        let recipe = std::str::from_utf8(text)?;
+        return Ok(Some(ConvertOptions::from_recipe(recipe)?));
+    }
+    Ok(None)
+}
+
+/*
+ * Text of an uncompressed iTXt payload after its keyword and separator,
+ * none for compressed ones
+ */
+fn itxt_text(data: &[u8]) -> Option<&[u8]> {
+    let [0, _, rest @ ..] = data else {
+        return None;
+    };
+    // language tag and translated keyword, each null-terminated
+    let (_, rest) = rest.split_at(rest.iter().position(|&b| b == 0)? + 1);
+    let (_, text) = rest.split_at(rest.iter().position(|&b| b == 0)? + 1);
+    Some(text)
+}
+
+/*
+ * Injects the selected metadata from the input into the encoded output.
+ * Only JPEG and PNG outputs are supported; other formats are left untouched.
+ */
+pub(crate) fn apply(
+    input: &[u8],
+    bytes: Vec<u8>,
+    options: &MetadataOptions,
+    recipe: &str,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    if options.is_empty() {
+        return Ok(bytes);
+    }
+
+    let exif = if options.exif_fields.is_empty() {
+        None
+    } else {
+        read_exif(input).and_then(|tiff| filter_exif(tiff, &options.exif_fields))
+    };
+    let xmp = options.xmp.as_ref().filter(|xmp| !xmp.is_empty()).map(xmp_packet);
+
+    if bytes.starts_with(&JPEG_SOI) {
+        let mut segments = Vec::new();
+        if let Some(exif) = &exif {
+            segments.push(jpeg_segment(0xE1, &[EXIF_HEADER, exif])?);
+        }
+        if let Some(xmp) = &xmp {
+            segments.push(jpeg_segment(0xE1, &[XMP_HEADER, xmp])?);
+        }
+        if options.tag_srgb {
+            segments.push(color_profile::jpeg_srgb_segment()?);
+        }
+        if segments.is_empty() {
+            return Ok(bytes);
+        }
+        inject_jpeg(&bytes, &segments)
+    } else if bytes.starts_with(&PNG_SIGNATURE) {
+        let mut chunks = Vec::new();
+        if let Some(exif) = &exif {
+            chunks.push(png_chunk(b"eXIf", exif));
+        }
+        if let Some(xmp) = &xmp {
+            chunks.push(png_chunk(b"iTXt", &itxt(XMP_KEYWORD, xmp)));
+        }
+        if options.embed_recipe {
+            chunks.push(png_chunk(b"iTXt", &itxt(RECIPE_KEYWORD, recipe.as_bytes())));
+        }
+        if options.tag_srgb {
+            chunks.push(color_profile::png_srgb_chunk());
+        }
+        if chunks.is_empty() {
+            return Ok(bytes);
+        }
+        inject_png(&bytes, &chunks)
+    } else {
+        Ok(bytes)
+    }
+}
+
+/// Returns the raw TIFF block of the EXIF data in a JPEG or PNG file.
+fn read_exif(bytes: &[u8]) -> Option<&[u8]> {
+    if bytes.starts_with(&JPEG_SOI) {
+        jpeg_segments(bytes)
+            .find(|(marker, body)| *marker == 0xE1 && body.starts_with(EXIF_HEADER))
+            .map(|(_, body)| &body[EXIF_HEADER.len()..])
+    } else if bytes.starts_with(&PNG_SIGNATURE) {
+        png_chunks(bytes).find(|(kind, _)| kind == b"eXIf").map(|(_, data)| data)
+    } else {
+        None
+    }
+}
+
+/// Names the format of an encoded image from its magic bytes, for the
+/// formats nftimg accepts; `None` for anything else.
+pub(crate) fn sniff_format(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0xFF, 0xD8, 0xFF, ..] => Some("jpeg"),
+        _ if bytes.starts_with(&PNG_SIGNATURE) => Some("png"),
+        [b'B', b'M', ..] => Some("bmp"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("webp"),
+        [b'I', b'I', 42, 0, ..] | [b'M', b'M', 0, 42, ..] => Some("tiff"),
+        _ => None,
+    }
+}
+
+/// Reads `(width, height)` from a PNG, JPEG or BMP header, without decoding.
+pub(crate) fn dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    fn be(data: &[u8], pos: usize, len: usize) -> Option<u32> {
+        Some(data.get(pos..pos + len)?.iter().fold(0, |v, &b| v << 8 | b as u32))
+    }
+    if bytes.starts_with(&PNG_SIGNATURE) {
+        // IHDR is always the first chunk
+        Some((be(bytes, 16, 4)?, be(bytes, 20, 4)?))
+    } else if bytes.starts_with(&JPEG_SOI) {
+        // SOF0..SOF15, except DHT, JPG and DAC which share the range
+        let (_, sof) = jpeg_segments(bytes)
+            .find(|(marker, _)| (0xC0..=0xCF).contains(marker) && ![0xC4, 0xC8, 0xCC].contains(marker))?;
+        Some((be(sof, 3, 2)?, be(sof, 1, 2)?))
+    } else if bytes.starts_with(b"BM") {
+        let le = |pos: usize| -> Option<i32> { Some(i32::from_le_bytes(bytes.get(pos..pos + 4)?.try_into().ok()?)) };
+        // negative heights mark top-down bitmaps
+        Some((le(18)?.unsigned_abs(), le(22)?.unsigned_abs()))
+    } else {
+        None
+    }
+}
+
+/// Reads the EXIF orientation tag (1 to 8) of a JPEG or PNG file.
+pub(crate) fn exif_orientation(bytes: &[u8]) -> Option<u16> {
+    let tiff = read_exif(bytes)?;
+    let little = match tiff.get(0..2)? {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None,
+    };
+    let u16_at = |pos: usize| -> Option<u16> {
+        let b: [u8; 2] = tiff.get(pos..pos + 2)?.try_into().ok()?;
+        Some(if little { u16::from_le_bytes(b) } else { u16::from_be_bytes(b) })
+    };
+    let b: [u8; 4] = tiff.get(4..8)?.try_into().ok()?;
+    let ifd = if little { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) } as usize;
+    (0..u16_at(ifd)? as usize)
+        .map(|i| ifd + 2 + i * 12)
+        .find(|&entry| u16_at(entry) == Some(0x0112))
+        .and_then(|entry| u16_at(entry + 8))
+        .filter(|orientation| (1..=8).contains(orientation))
+}
+
+/// Iterates over `(marker, body)` of the JPEG segments preceding the image data.
+pub(crate) fn jpeg_segments(bytes: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    let mut pos = JPEG_SOI.len();
+    std::iter::from_fn(move || {
+        if bytes.get(pos) != Some(&0xFF) {
+            return None;
+        }
+        let marker = *bytes.get(pos + 1)?;
+        // SOS: entropy-coded data follows, no more metadata segments
+        if marker == 0xDA {
+            return None;
+        }
+        let len = u16::from_be_bytes(bytes.get(pos + 2..pos + 4)?.try_into().ok()?) as usize;
+        let body = bytes.get(pos + 4..pos + 2 + len)?;
+        pos += 2 + len;
+        Some((marker, body))
+    })
+}
+
+/// Iterates over `(type, data)` of the chunks of a PNG file, stopping at the
+/// first truncated chunk.
+pub(crate) fn png_chunks(bytes: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut pos = PNG_SIGNATURE.len();
+    std::iter::from_fn(move || {
+        let len = u32::from_be_bytes(bytes.get(pos..pos + 4)?.try_into().ok()?) as usize;
+        let kind = bytes.get(pos + 4..pos + 8)?;
+        let data = bytes.get(pos + 8..pos + 8 + len)?;
+        pos += 12 + len;
+        Some((kind, data))
+    })
+}
+
+/*
+ * Builds a new TIFF block holding only the selected IFD0 entries of `tiff`.
+ * Values are copied verbatim, so the original byte order is kept.
+ */
+fn filter_exif(tiff: &[u8], fields: &[ExifField]) -> Option<Vec<u8>> {
+    let little = match tiff.get(0..2)? {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None,
+    };
+    let u16_at = |pos: usize| -> Option<u16> {
+        let b: [u8; 2] = tiff.get(pos..pos + 2)?.try_into().ok()?;
+        Some(if little { u16::from_le_bytes(b) } else { u16::from_be_bytes(b) })
+    };
+    let u32_at = |pos: usize| -> Option<u32> {
+        let b: [u8; 4] = tiff.get(pos..pos + 4)?.try_into().ok()?;
+        Some(if little { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) })
+    };
+    let put_u16 = |out: &mut Vec<u8>, v: u16| {
+        out.extend_from_slice(&if little { v.to_le_bytes() } else { v.to_be_bytes() })
+    };
+    let put_u32 = |out: &mut Vec<u8>, v: u32| {
+        out.extend_from_slice(&if little { v.to_le_bytes() } else { v.to_be_bytes() })
+    };
+
+    let ifd = u32_at(4)? as usize;
+    let count = u16_at(ifd)? as usize;
+    let wanted: Vec<u16> = fields.iter().map(|f| f.tag()).collect();
+
+    // (tag, type, count, value bytes)
+    let mut entries = Vec::new();
+    for i in 0..count {
+        let entry = ifd + 2 + i * 12;
+        let tag = u16_at(entry)?;
+        if !wanted.contains(&tag) {
+            continue;
+        }
+        let kind = u16_at(entry + 2)?;
+        let n = u32_at(entry + 4)?;
+        let unit = match kind {
+            1 | 2 | 6 | 7 => 1,
+            3 | 8 => 2,
+            4 | 9 | 11 => 4,
+            5 | 10 | 12 => 8,
+            _ => continue,
+        };
+        let size = unit * n as usize;
+        let value = if size <= 4 {
+            tiff.get(entry + 8..entry + 8 + size)?
+        } else {
+            let offset = u32_at(entry + 8)? as usize;
+            tiff.get(offset..offset + size)?
+        };
+        entries.push((tag, kind, n, value));
+    }
+    if entries.is_empty() {
+        return None;
+    }
+    entries.sort_by_key(|e| e.0);
+
+    let mut out = Vec::new();
+    out.extend_from_slice(if little { b"II" } else { b"MM" });
+    put_u16(&mut out, 42);
+    put_u32(&mut out, 8);
+    put_u16(&mut out, entries.len() as u16);
+
+    let mut data_offset = 8 + 2 + entries.len() * 12 + 4;
+    let mut data = Vec::new();
+    for (tag, kind, n, value) in &entries {
+        put_u16(&mut out, *tag);
+        put_u16(&mut out, *kind);
+        put_u32(&mut out, *n);
+        if value.len() <= 4 {
+            out.extend_from_slice(value);
+            out.resize(out.len() + 4 - value.len(), 0);
+        } else {
+            put_u32(&mut out, data_offset as u32);
+            data.extend_from_slice(value);
+            // keep offsets word aligned as the spec requires
+            if value.len() % 2 == 1 {
+                data.push(0);
+            }
+            data_offset = 8 + 2 + entries.len() * 12 + 4 + data.len();
+        }
+    }
+    put_u32(&mut out, 0);
+    out.extend_from_slice(&data);
+    Some(out)
+}
+
+fn xmp_packet(xmp: &Xmp) -> Vec<u8> {
+    let mut props = String::new();
+    if let Some(artist) = &xmp.artist {
+        props.push_str(&format!(
+            "<dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>",
+            escape_xml(artist)
+        ));
+    }
+    if let Some(license) = &xmp.license {
+        props.push_str(&format!(
+            "<xmpRights:UsageTerms><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></xmpRights:UsageTerms>",
+            escape_xml(license)
+        ));
+    }
+    if let Some(parameters) = &xmp.parameters {
+        props.push_str(&format!("<nftimg:parameters>{}</nftimg:parameters>", escape_xml(parameters)));
+    }
+    format!(
+        concat!(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>",
+            "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">",
+            "<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">",
+            "<rdf:Description rdf:about=\"\"",
+            " xmlns:dc=\"http://purl.org/dc/elements/1.1/\"",
+            " xmlns:xmpRights=\"http://ns.adobe.com/xap/1.0/rights/\"",
+            " xmlns:nftimg=\"https://github.com/cybercoder2009/rs-opencv/ns/1.0/\">",
+            "{}",
+            "</rdf:Description></rdf:RDF></x:xmpmeta>",
+            "<?xpacket end=\"w\"?>"
+        ),
+        props
+    )
+    .into_bytes()
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/*
+ * Inserts the given encoded segments right after SOI, or after the JFIF APP0
+ * segment when one is present, since JFIF readers expect APP0 first.
+ */
+fn inject_jpeg(bytes: &[u8], segments: &[Vec<u8>]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut insert_at = 2;
+    if bytes.get(2..4) == Some(&[0xFF, 0xE0]) {
+        let len = bytes
+            .get(4..6)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]) as usize)
+            .ok_or("truncated JPEG output")?;
+        insert_at += 2 + len;
+        if insert_at > bytes.len() {
+            return Err("truncated JPEG output".into());
+        }
+    }
+
+    let mut out = Vec::with_capacity(bytes.len() + segments.iter().map(Vec::len).sum::<usize>());
+    out.extend_from_slice(&bytes[..insert_at]);
+    for segment in segments {
+        out.extend_from_slice(segment);
+    }
+    out.extend_from_slice(&bytes[insert_at..]);
+    Ok(out)
+}
+
+/// Encodes a JPEG marker segment whose body is the concatenation of `parts`.
+pub(crate) fn jpeg_segment(marker: u8, parts: &[&[u8]]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let len = 2 + parts.iter().map(|p| p.len()).sum::<usize>();
+    if len > u16::MAX as usize {
+        return Err("metadata segment exceeds 64 KiB".into());
+    }
+    let mut segment = Vec::with_capacity(2 + len);
+    segment.extend_from_slice(&[0xFF, marker]);
+    segment.extend_from_slice(&(len as u16).to_be_bytes());
+    for part in parts {
+        segment.extend_from_slice(part);
+    }
+    Ok(segment)
+}
+
+/// Inserts the given encoded chunks right after IHDR.
+pub(crate) fn inject_png(bytes: &[u8], chunks: &[Vec<u8>]) -> Result<Vec<u8>, Box<dyn Error>> {
+    // signature (8) + IHDR length/type (8) + IHDR data (13) + crc (4)
+    let insert_at = PNG_SIGNATURE.len() + 8 + 13 + 4;
+    if bytes.len() < insert_at || &bytes[12..16] != b"IHDR" {
+        return Err("malformed PNG output".into());
+    }
+    let mut out = Vec::with_capacity(bytes.len() + chunks.iter().map(Vec::len).sum::<usize>());
+    out.extend_from_slice(&bytes[..insert_at]);
+    for chunk in chunks {
+        out.extend_from_slice(chunk);
+    }
+    out.extend_from_slice(&bytes[insert_at..]);
+    Ok(out)
+}
+
+/// Encodes a PNG chunk: length, type, data and CRC.
+pub(crate) fn png_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = Vec::with_capacity(12 + data.len());
+    chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+    chunk.extend_from_slice(&crc32(&chunk[4..]).to_be_bytes());
+    chunk
+}
+
+/// Uncompressed `iTXt` payload with no language tag.
+fn itxt(keyword: &str, text: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(keyword.len() + 5 + text.len());
+    data.extend_from_slice(keyword.as_bytes());
+    // null separator, compression flag, compression method, empty language, empty translated keyword
+    data.extend_from_slice(&[0, 0, 0, 0, 0]);
+    data.extend_from_slice(text);
+    data
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* 640x480 PNG with no image data */
+    fn png() -> Vec<u8> {
+        let mut bytes = PNG_SIGNATURE.to_vec();
+        bytes.extend(png_chunk(b"IHDR", &[0, 0, 2, 128, 0, 0, 1, 224, 8, 2, 0, 0, 0]));
+        bytes.extend(png_chunk(b"IEND", &[]));
+        bytes
+    }
+
+    /* reads back the recipe of `bytes` through a file, as read_recipe does */
+    fn recipe_of(bytes: &[u8], name: &str) -> Result<Option<ConvertOptions>, Box<dyn Error>> {
+        let path = std::env::temp_dir().join(format!("nftimg-{}-{}.png", std::process::id(), name));
+        fs::write(&path, bytes)?;
+        let options = read_recipe(&path);
+        fs::remove_file(&path)?;
+        options
+    }
+
+    /* tags of the IFD0 entries of a little-endian TIFF block */
+    fn tags(tiff: &[u8]) -> Vec<u16> {
+        let u16_at = |pos: usize| u16::from_le_bytes([tiff[pos], tiff[pos + 1]]);
+        let ifd = u32::from_le_bytes(tiff[4..8].try_into().unwrap()) as usize;
+        (0..u16_at(ifd) as usize).map(|i| u16_at(ifd + 2 + i * 12)).collect()
+    }
+
+    #[test]
+    fn crc32_matches_known_values() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(png_chunk(b"IEND", &[]), [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]);
+    }
+
+    #[test]
+    fn recipe_round_trips_through_itxt_and_text() {
+        let options = ConvertOptions { max_pyramid_level: 2, threshold_c: 3.5, ..ConvertOptions::default() };
+        let recipe = options.recipe();
+        let metadata = MetadataOptions { tag_srgb: false, ..MetadataOptions::default() };
+        let itxt = apply(&[], png(), &metadata, &recipe).unwrap();
+        assert_eq!(recipe_of(&itxt, "itxt").unwrap().unwrap().recipe(), recipe);
+
+        let mut text = RECIPE_KEYWORD.as_bytes().to_vec();
+        text.push(0);
+        text.extend_from_slice(recipe.as_bytes());
+        let text = inject_png(&png(), &[png_chunk(b"tEXt", &text)]).unwrap();
+        assert_eq!(recipe_of(&text, "text").unwrap().unwrap().recipe(), recipe);
+
+        assert!(recipe_of(&png(), "none").unwrap().is_none());
+    }
+
+    #[test]
+    fn copied_exif_leaves_out_gps() {
+        let mut tiff = b"II".to_vec();
+        tiff.extend_from_slice(&42u16.to_le_bytes());
+        tiff.extend_from_slice(&8u32.to_le_bytes());
+        tiff.extend_from_slice(&2u16.to_le_bytes());
+        // Make, ASCII, 6 bytes stored after the IFD
+        for v in [0x010Fu16, 2] {
+            tiff.extend_from_slice(&v.to_le_bytes());
+        }
+        tiff.extend_from_slice(&6u32.to_le_bytes());
+        tiff.extend_from_slice(&38u32.to_le_bytes());
+        // GPSInfo, LONG, pointer to the GPS IFD
+        for v in [0x8825u16, 4] {
+            tiff.extend_from_slice(&v.to_le_bytes());
+        }
+        tiff.extend_from_slice(&1u32.to_le_bytes());
+        tiff.extend_from_slice(&44u32.to_le_bytes());
+        tiff.extend_from_slice(&0u32.to_le_bytes());
+        tiff.extend_from_slice(b"Canon\0");
+        assert_eq!(tags(&tiff), [0x010F, 0x8825]);
+
+        let mut input = JPEG_SOI.to_vec();
+        input.extend(jpeg_segment(0xE1, &[EXIF_HEADER, &tiff]).unwrap());
+        input.extend_from_slice(&[0xFF, 0xD9]);
+        let metadata = MetadataOptions {
+            exif_fields: vec![ExifField::Make],
+            embed_recipe: false,
+            tag_srgb: false,
+            ..MetadataOptions::default()
+        };
+        let output = apply(&input, vec![0xFF, 0xD8, 0xFF, 0xD9], &metadata, "").unwrap();
+        let copied = read_exif(&output).unwrap();
+        assert_eq!(tags(copied), [0x010F]);
+        assert_eq!(&copied[26..32], b"Canon\0");
+    }
+
+    #[test]
+    fn sniffs_formats_and_dimensions() {
+        assert_eq!(sniff_format(&[0xFF, 0xD8, 0xFF, 0xE0]), Some("jpeg"));
+        assert_eq!(sniff_format(&png()), Some("png"));
+        assert_eq!(sniff_format(b"BM\0\0"), Some("bmp"));
+        assert_eq!(sniff_format(b"RIFF\0\0\0\0WEBPVP8 "), Some("webp"));
+        assert_eq!(sniff_format(b"II*\0"), Some("tiff"));
+        assert_eq!(sniff_format(b"MM\0*"), Some("tiff"));
+        assert_eq!(sniff_format(b"GIF89a"), None);
+        assert_eq!(sniff_format(&[]), None);
+
+        assert_eq!(dimensions(&png()), Some((640, 480)));
+        let mut jpeg = JPEG_SOI.to_vec();
+        // a DHT segment shares the SOF marker range
+        jpeg.extend(jpeg_segment(0xC4, &[&[0; 4]]).unwrap());
+        jpeg.extend(jpeg_segment(0xC0, &[&[8, 0, 100, 0, 200, 1, 1, 0x11, 0]]).unwrap());
+        assert_eq!(dimensions(&jpeg), Some((200, 100)));
+        let mut bmp = vec![0; 26];
+        bmp[..2].copy_from_slice(b"BM");
+        bmp[18..22].copy_from_slice(&32i32.to_le_bytes());
+        bmp[22..26].copy_from_slice(&(-16i32).to_le_bytes());
+        assert_eq!(dimensions(&bmp), Some((32, 16)));
+    }
+
+    #[test]
+    fn truncated_and_malformed_input_is_refused() {
+        assert_eq!(dimensions(&PNG_SIGNATURE), None);
+        assert_eq!(dimensions(&[0xFF, 0xD8, 0xFF, 0xC0, 0, 20, 8]), None);
+        assert_eq!(dimensions(b"BM"), None);
+
+        // a chunk claiming more data than the file holds, and one claiming 4 GiB
+        let mut bytes = png();
+        bytes.extend_from_slice(&100u32.to_be_bytes());
+        bytes.extend_from_slice(b"tEXtshort");
+        assert_eq!(png_chunks(&bytes).count(), 2);
+        let mut bytes = PNG_SIGNATURE.to_vec();
+        bytes.extend_from_slice(&u32::MAX.to_be_bytes());
+        bytes.extend_from_slice(b"IDAT");
+        assert_eq!(png_chunks(&bytes).count(), 0);
+        // segment lengths below the two length bytes themselves
+        assert_eq!(jpeg_segments(&[0xFF, 0xD8, 0xFF, 0xE1, 0, 1]).count(), 0);
+        assert_eq!(jpeg_segments(&[0xFF, 0xD8, 0xFF, 0xE1, 0, 40, 1, 2]).count(), 0);
+        assert_eq!(exif_orientation(&[0xFF, 0xD8, 0xFF, 0xE1, 0, 8, b'E', b'x', b'i', b'f', 0, 0]), None);
+
+        assert!(inject_png(&PNG_SIGNATURE, &[]).is_err());
+        assert!(inject_jpeg(&[0xFF, 0xD8, 0xFF, 0xE0], &[]).is_err());
+        assert!(inject_jpeg(&[0xFF, 0xD8, 0xFF, 0xE0, 0, 16, 0], &[]).is_err());
+
+        // an iTXt recipe without its language separators is skipped
+        let mut broken = RECIPE_KEYWORD.as_bytes().to_vec();
+        broken.extend_from_slice(&[0, 0, 0]);
+        let bytes = inject_png(&png(), &[png_chunk(b"iTXt", &broken)]).unwrap();
+        assert!(recipe_of(&bytes, "broken").unwrap().is_none());
+        let bytes = inject_png(&png(), &[png_chunk(b"iTXt", &itxt(RECIPE_KEYWORD, b"no equals sign"))]).unwrap();
+        assert!(recipe_of(&bytes, "malformed").is_err());
+        let bytes = inject_png(&png(), &[png_chunk(b"iTXt", &itxt(RECIPE_KEYWORD, &[0xFF, 0xFE]))]).unwrap();
+        assert!(recipe_of(&bytes, "utf8").is_err());
+    }
+}
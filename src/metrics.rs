@@ -0,0 +1,192 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use opencv::prelude::*;
+
+use crate::progress::{self, Progress};
+
+/// Upper bounds of the stage latency buckets, in seconds.
+const BUCKETS: [f64; 12] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+#[derive(Default)]
+struct Histogram {
+    /// Observations per bucket, not cumulative; the last is +Inf.
+    counts: [u64; BUCKETS.len() + 1],
+    sum: f64,
+}
+
+static STAGE_SECONDS: Mutex<BTreeMap<&'static str, Histogram>> = Mutex::new(BTreeMap::new());
+static REQUESTS: AtomicU64 = AtomicU64::new(0);
+static FAILURES: AtomicU64 = AtomicU64::new(0);
+static QUEUE_DEPTH: AtomicU64 = AtomicU64::new(0);
+static HAS_QUEUE: AtomicBool = AtomicBool::new(false);
+
+/// Told about every stage that ends on this thread, with its result and
+/// latency, see [`with_stage_listener`].
+type Listener = Box<dyn FnMut(&'static str, &Mat, Duration)>;
+
+thread_local! {
+    /// Innermost last; all of them are told.
+    static LISTENERS: RefCell<Vec<Listener>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Times consecutive pipeline stages: each lap is recorded as the latency of
+/// the stage that just ended, and reported with the image it produced.
+pub(crate) struct StageClock(Instant);
+
+impl StageClock {
+    pub(crate) fn start() -> StageClock {
+        StageClock(Instant::now())
+    }
+
+    /*
+     * Tells progress callbacks that `stage` begins; it ends with the next lap
+     */
+    pub(crate) fn begin(&self, stage: &'static str) {
+        progress::report(Progress::Started { stage });
+    }
+
+    pub(crate) fn lap(&mut self, stage: &'static str, image: &Mat) {
+        let now = Instant::now();
+        observe(stage, now - self.0);
+        progress::report(Progress::Finished { stage, elapsed: now - self.0 });
+        LISTENERS.with_borrow_mut(|listeners| {
+            for listener in listeners.iter_mut().rev() {
+                listener(stage, image, now - self.0);
+            }
+        });
+        #[cfg(feature = "otel")]
+        crate::otel::stage(stage, image, now - self.0);
+        // time spent in the listener is not the next stage's
+        self.0 = Instant::now();
+    }
+}
+
+/*
+ * Runs `run` on this thread with `listener` called as each of its stages
+ * ends, e.g. to report the progress of a server job; listeners installed
+ * around it are still called
+ */
+pub(crate) fn with_stage_listener<T>(
+    listener: impl FnMut(&'static str, &Mat, Duration) + 'static,
+    run: impl FnOnce() -> T,
+) -> T {
+    // restored on unwinding too, blocking threads are reused
+    struct Restore;
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            LISTENERS.with_borrow_mut(Vec::pop);
+        }
+    }
+    LISTENERS.with_borrow_mut(|listeners| listeners.push(Box::new(listener)));
+    let _restore = Restore;
+    run()
+}
+
+/// Latency of each stage of a conversion, in the order they ran.
+pub(crate) type StageTimes = Vec<(&'static str, Duration)>;
+
+/*
+ * Runs `run` and records the stages it runs on this thread
+ */
+pub(crate) fn stage_times<T>(run: impl FnOnce() -> T) -> (T, StageTimes) {
+    let times = Rc::new(RefCell::new(Vec::new()));
+    let listener = times.clone();
+    let result = with_stage_listener(move |stage, _, elapsed| listener.borrow_mut().push((stage, elapsed)), run);
+    (result, times.take())
+}
+
+fn observe(stage: &'static str, elapsed: Duration) {
+    let seconds = elapsed.as_secs_f64();
+    let mut stages = STAGE_SECONDS.lock().unwrap_or_else(PoisonError::into_inner);
+    let histogram = stages.entry(stage).or_default();
+    let bucket = BUCKETS.iter().position(|&bound| seconds <= bound).unwrap_or(BUCKETS.len());
+    histogram.counts[bucket] += 1;
+    histogram.sum += seconds;
+}
+
+/*
+ * One served request or job, failed or not
+ */
+#[cfg(any(feature = "serve", feature = "grpc", feature = "worker"))]
+pub(crate) fn count_request(failed: bool) {
+    REQUESTS.fetch_add(1, Ordering::Relaxed);
+    if failed {
+        FAILURES.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(feature = "worker")]
+pub(crate) fn set_queue_depth(depth: u64) {
+    QUEUE_DEPTH.store(depth, Ordering::Relaxed);
+    HAS_QUEUE.store(true, Ordering::Relaxed);
+}
+
+/// The metrics of this process in the Prometheus text format.
+pub fn render_metrics() -> String {
+    let mut text = String::new();
+    let requests = REQUESTS.load(Ordering::Relaxed);
+    let failures = FAILURES.load(Ordering::Relaxed);
+    let _ = writeln!(text, "# HELP nftimg_requests_total Conversion requests and jobs handled.");
+    let _ = writeln!(text, "# TYPE nftimg_requests_total counter\nnftimg_requests_total {}", requests);
+    let _ = writeln!(text, "# HELP nftimg_failures_total Conversion requests and jobs that failed.");
+    let _ = writeln!(text, "# TYPE nftimg_failures_total counter\nnftimg_failures_total {}", failures);
+    if HAS_QUEUE.load(Ordering::Relaxed) {
+        let _ = writeln!(text, "# HELP nftimg_queue_depth Jobs waiting in the queue at the last pop.");
+        let _ = writeln!(text, "# TYPE nftimg_queue_depth gauge\nnftimg_queue_depth {}", QUEUE_DEPTH.load(Ordering::Relaxed));
+    }
+    let _ = writeln!(text, "# HELP nftimg_stage_seconds Latency of each pipeline stage.");
+    let _ = writeln!(text, "# TYPE nftimg_stage_seconds histogram");
+    for (stage, histogram) in STAGE_SECONDS.lock().unwrap_or_else(PoisonError::into_inner).iter() {
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(&histogram.counts) {
+            cumulative += count;
+            let _ = writeln!(text, "nftimg_stage_seconds_bucket{{stage=\"{}\",le=\"{}\"}} {}", stage, bound, cumulative);
+        }
+        cumulative += histogram.counts[BUCKETS.len()];
+        let _ = writeln!(text, "nftimg_stage_seconds_bucket{{stage=\"{}\",le=\"+Inf\"}} {}", stage, cumulative);
+        let _ = writeln!(text, "nftimg_stage_seconds_sum{{stage=\"{}\"}} {}", stage, histogram.sum);
+        let _ = writeln!(text, "nftimg_stage_seconds_count{{stage=\"{}\"}} {}", stage, cumulative);
+    }
+    text
+}
+
+/// Serves [`render_metrics`] as `GET /metrics` on `addr` from a background
+/// thread, for modes without an HTTP server of their own.
+#[cfg(any(feature = "grpc", feature = "worker"))]
+pub fn serve_metrics(addr: std::net::SocketAddr) -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::{BufRead, BufReader, Write as _};
+    use std::net::TcpListener;
+    use std::thread;
+
+    let listener = TcpListener::bind(addr)?;
+    eprintln!("metrics on http://{}/metrics", listener.local_addr()?);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // a client that never sends its request line does not stall the thread
+            let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+            let mut request_line = String::new();
+            if BufReader::new(&stream).read_line(&mut request_line).is_err() {
+                continue;
+            }
+            let response = if request_line.starts_with("GET /metrics ") {
+                let body = render_metrics();
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+            } else {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+            };
+            // a scraper that hung up needs no answer
+            let _ = (&stream).write_all(response.as_bytes());
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,112 @@
+use std::error::Error;
+use std::path::{self, Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rumqttc::{Client, Event, MqttOptions, Packet, Publish, QoS};
+use serde_json::{json, Value};
+
+use crate::metadata::sniff_format;
+use crate::naming::{output_location, prepare_output_dir, write_atomic};
+use crate::{convert_buffer, convert_file, ConvertOptions};
+
+const DEFAULT_PORT: u16 = 1883;
+
+/// Camera payloads are whole images; the client default is 10 KiB.
+const MAX_PACKET_SIZE: usize = 64 * 1024 * 1024;
+
+/// Pause before reconnecting after the broker went away.
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// Converts the images published on `topic` at the MQTT broker `broker`
+/// (`host[:port]`, optionally `mqtt://`) until the process is stopped;
+/// `options` are the settings of every conversion.
+///
+/// A message is either the path of an image readable here, converted like
+/// the command line does, or the image itself, written into `--out` (or the
+/// working directory) as `mqtt-<millis>.nft.<ext>`. For each message a JSON
+/// status is published on `reply_topic`: `{"topic":..,"input":..,
+/// "state":"processing"}`, then `"state":"done"` with `"output"`, the path
+/// written, or `"state":"failed"` with `"error"`. `topic` may hold
+/// wildcards, e.g. `booth/+/photos`.
+pub fn run_mqtt(broker: &str, topic: &str, reply_topic: &str, options: &ConvertOptions) -> Result<(), Box<dyn Error>> {
+    let broker = broker.strip_prefix("mqtt://").unwrap_or(broker);
+    let (host, port) = match broker.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().map_err(|_| format!("invalid port in {}", broker))?),
+        None => (broker, DEFAULT_PORT),
+    };
+    let mut mqtt_options = MqttOptions::new(format!("nftimg-{}", std::process::id()), host, port);
+    mqtt_options.set_keep_alive(Duration::from_secs(30));
+    mqtt_options.set_max_packet_size(MAX_PACKET_SIZE, MAX_PACKET_SIZE);
+    let (client, mut connection) = Client::new(mqtt_options, 16);
+
+    // the event loop keeps the connection alive while a conversion runs
+    let (sender, receiver) = mpsc::channel::<Publish>();
+    let subscriber = client.clone();
+    let topic = topic.to_string();
+    thread::spawn(move || {
+        for event in connection.iter() {
+            match event {
+                // subscriptions do not survive a reconnect with a clean session
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    eprintln!("subscribed to {}", topic);
+                    if subscriber.subscribe(topic.as_str(), QoS::AtLeastOnce).is_err() {
+                        return;
+                    }
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    if sender.send(publish).is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("warning: broker connection lost: {}", e);
+                    thread::sleep(RECONNECT_DELAY);
+                }
+            }
+        }
+    });
+
+    for message in receiver {
+        let input = match sniff_format(&message.payload) {
+            Some(_) => Value::Null,
+            None => json!(String::from_utf8_lossy(&message.payload).trim()),
+        };
+        println!("message={}", message.topic);
+        let status = json!({ "topic": message.topic, "input": input, "state": "processing" });
+        client.publish(reply_topic, QoS::AtLeastOnce, false, status.to_string())?;
+        let status = match convert_message(&message.payload, options) {
+            Ok(output) => {
+                println!("output={}", output.display());
+                json!({ "topic": message.topic, "input": input, "state": "done", "output": output.display().to_string() })
+            }
+            Err(e) => {
+                eprintln!("warning: message on {} failed: {}", message.topic, e);
+                json!({ "topic": message.topic, "input": input, "state": "failed", "error": e.to_string() })
+            }
+        };
+        client.publish(reply_topic, QoS::AtLeastOnce, false, status.to_string())?;
+    }
+    Err("broker connection closed".into())
+}
+
+/*
+ * image path or image bytes -> path of the written output
+ */
+fn convert_message(payload: &[u8], options: &ConvertOptions) -> Result<PathBuf, Box<dyn Error>> {
+    let Some(format) = sniff_format(payload) else {
+        let path = std::str::from_utf8(payload).map_err(|_| "payload is neither an image nor a path")?.trim();
+        return Ok(convert_file(Path::new(path), options)?.output_path);
+    };
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+    let name = format!("mqtt-{}.{}", millis, if format == "jpeg" { "jpg" } else { format });
+    if let Some(dir) = &options.output_dir {
+        prepare_output_dir(dir)?;
+    }
+    let output = output_location(Path::new(&name), &options.output_suffix, options.output_dir.as_deref())?;
+    let output = path::absolute(output)?;
+    write_atomic(&output, &convert_buffer(payload, options)?)?;
+    Ok(output)
+}
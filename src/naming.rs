@@ -0,0 +1,194 @@
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, SystemTime};
+
+use crate::NftImgError;
+
+/// Format of outputs whose input has no extension.
+const DEFAULT_EXTENSION: &str = "png";
+
+/// Appended to the name of an output while it is being written.
+const PARTIAL_EXTENSION: &str = "nftimg-part";
+
+/// Age after which a partial output is taken as left behind by a run that
+/// died; younger ones may still be written by another process.
+const ABANDONED_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Output path for `input`: `suffix` inserted before the extension, in the
+/// same directory, e.g. `archive.v2.png` -> `archive.v2.nft.png`. Inputs
+/// without an extension are written as PNG. Refused when the output would
+/// be the input itself, as with an empty suffix.
+pub fn output_path(input: &Path, suffix: &str) -> Result<PathBuf, NftImgError> {
+    let stem = input.file_stem().unwrap_or_default();
+    let extension = input.extension().unwrap_or(OsStr::new(DEFAULT_EXTENSION));
+    let mut name = stem.to_os_string();
+    if !suffix.is_empty() {
+        name.push(".");
+        name.push(suffix);
+    }
+    name.push(".");
+    name.push(extension);
+    not_input(input, input.with_file_name(name), suffix)
+}
+
+/// Where the output of `input` goes: [`output_path`], moved into `dir` when
+/// one is given.
+pub(crate) fn output_location(input: &Path, suffix: &str, dir: Option<&Path>) -> Result<PathBuf, NftImgError> {
+    let path = output_path(input, suffix)?;
+    match (dir, path.file_name()) {
+        (Some(dir), Some(name)) => not_input(input, dir.join(name), suffix),
+        _ => Ok(path),
+    }
+}
+
+/*
+ * `output` unless writing it would replace `input`
+ */
+fn not_input(input: &Path, output: PathBuf, suffix: &str) -> Result<PathBuf, NftImgError> {
+    if output == input {
+        return Err(NftImgError::InvalidOption {
+            name: "output_suffix",
+            value: format!("{:?}", suffix),
+            expected: "a suffix keeping the output from overwriting its input",
+        });
+    }
+    Ok(output)
+}
+
+/// Whether `path` looks like an output of [`output_path`] with `suffix`.
+pub(crate) fn is_output(path: &Path, suffix: &str) -> bool {
+    let stem = path.file_stem().map(Path::new);
+    !suffix.is_empty() && stem.and_then(Path::extension).is_some_and(|ext| ext == suffix)
+}
+
+/*
+ * Creates `dir` and its parents if missing and checks that files can be
+ * created in it, before any time is spent on processing.
+ */
+pub(crate) fn prepare_output_dir(dir: &Path) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(dir).map_err(|e| format!("cannot create output directory {}: {}", dir.display(), e))?;
+    let probe = dir.join(".nftimg-write-test");
+    fs::write(&probe, b"").map_err(|e| format!("output directory {} is not writable: {}", dir.display(), e))?;
+    fs::remove_file(&probe)?;
+    Ok(())
+}
+
+/*
+ * Writes `bytes` next to `path` and renames the file into place, so an
+ * interrupted run never leaves a truncated output under the final name.
+ */
+pub(crate) fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+    let mut name = path.file_name().ok_or_else(|| format!("no file name in {}", path.display()))?.to_os_string();
+    // processes writing the same output each get their own partial file
+    name.push(format!(".{}.{}", process::id(), PARTIAL_EXTENSION));
+    let partial = path.with_file_name(name);
+    let written = File::create(&partial).and_then(|mut file| {
+        file.write_all(bytes)?;
+        file.sync_all()
+    });
+    if let Err(e) = written.and_then(|_| fs::rename(&partial, path)) {
+        let _ = fs::remove_file(&partial);
+        return Err(format!("failed to write {}: {}", path.display(), e).into());
+    }
+    Ok(())
+}
+
+/// Whether `path` is a partial output of [`write_atomic`], being written or
+/// left behind by an interrupted run.
+pub(crate) fn is_partial(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == PARTIAL_EXTENSION)
+}
+
+/// Whether `path` is a partial output no run has written to for
+/// [`ABANDONED_AFTER`], safe to delete.
+pub(crate) fn is_abandoned(path: &Path) -> bool {
+    let modified = fs::metadata(path).and_then(|metadata| metadata.modified());
+    is_partial(path)
+        && modified.is_ok_and(|modified| {
+            SystemTime::now().duration_since(modified).is_ok_and(|age| age > ABANDONED_AFTER)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(input: &str, suffix: &str) -> PathBuf {
+        output_path(Path::new(input), suffix).unwrap()
+    }
+
+    #[test]
+    fn inserts_suffix_before_extension() {
+        assert_eq!(name("photo.jpg", "nft"), Path::new("photo.nft.jpg"));
+        assert_eq!(name("dir/photo.PNG", "nft"), Path::new("dir/photo.nft.PNG"));
+    }
+
+    #[test]
+    fn keeps_inner_dots() {
+        assert_eq!(name("archive.v2.final.png", "nft"), Path::new("archive.v2.final.nft.png"));
+        assert_eq!(name("my.dir/photo.jpg", "nft"), Path::new("my.dir/photo.nft.jpg"));
+    }
+
+    #[test]
+    fn dotless_names_become_png() {
+        assert_eq!(name("photo", "nft"), Path::new("photo.nft.png"));
+        assert_eq!(name("dir/.hidden", "nft"), Path::new("dir/.hidden.nft.png"));
+    }
+
+    #[test]
+    fn recognizes_outputs() {
+        assert!(is_output(&name("photo.jpg", "nft"), "nft"));
+        assert!(is_output(Path::new("archive.v2.nft.png"), "nft"));
+        assert!(!is_output(Path::new("photo.jpg"), "nft"));
+        assert!(!is_output(Path::new("nft.jpg"), "nft"));
+        assert!(!is_output(Path::new("photo.nft.jpg"), "toon"));
+    }
+
+    #[test]
+    fn custom_suffix() {
+        assert_eq!(name("photo.jpg", "toon"), Path::new("photo.toon.jpg"));
+    }
+
+    #[test]
+    fn refuses_to_overwrite_input() {
+        assert!(output_path(Path::new("photo.jpg"), "").is_err());
+        assert!(output_location(Path::new("dir/photo.jpg"), "", Some(Path::new("dir"))).is_err());
+        // written as PNG next to it
+        assert_eq!(name("photo", ""), Path::new("photo.png"));
+    }
+
+    #[test]
+    fn output_dir_replaces_parent() {
+        let input = Path::new("photos").join("2023").join("photo.jpg");
+        let dir = Path::new("results");
+        assert_eq!(output_location(&input, "nft", Some(dir)).unwrap(), dir.join("photo.nft.jpg"));
+        assert_eq!(output_location(&input, "nft", None).unwrap(), input.with_file_name("photo.nft.jpg"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_paths() {
+        assert_eq!(name(r"C:\photos\photo.jpg", "nft"), Path::new(r"C:\photos\photo.nft.jpg"));
+        // drive-relative, resolved against the drive's working directory later
+        assert_eq!(name(r"C:photo.jpg", "nft"), Path::new(r"C:photo.nft.jpg"));
+        assert_eq!(name(r"\\server\share\photo.jpg", "nft"), Path::new(r"\\server\share\photo.nft.jpg"));
+        assert_eq!(name(r"\\?\C:\photos\photo.jpg", "nft"), Path::new(r"\\?\C:\photos\photo.nft.jpg"));
+        // forward slashes are separators too, the output keeps the input's
+        assert_eq!(name("C:/photos/photo.jpg", "nft"), Path::new("C:/photos/photo.nft.jpg"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_output_dir() {
+        let dir = Path::new(r"\\server\share\results");
+        let expected = Path::new(r"\\server\share\results\photo.nft.jpg");
+        assert_eq!(output_location(Path::new(r"D:\photos\photo.jpg"), "nft", Some(dir)).unwrap(), expected);
+        let expected = Path::new(r"C:results\scan.nft.png");
+        assert_eq!(output_location(Path::new(r"D:\scan"), "nft", Some(Path::new("C:results"))).unwrap(), expected);
+        assert!(is_output(Path::new(r"\\server\share\photo.nft.jpg"), "nft"));
+    }
+}
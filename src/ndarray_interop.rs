@@ -0,0 +1,65 @@
+use std::error::Error;
+
+use ndarray::{Array3, ArrayView3};
+use opencv::prelude::*;
+
+use crate::{convert_image, BgrImage, ConvertOptions, NftImgError};
+
+/*
+ * Runs `f` on a Mat over the pixels of a height x width x 3 array, without
+ * a copy when the array is in standard (row-major, contiguous) layout
+ */
+fn with_mat<T>(input: ArrayView3<'_, u8>, f: impl FnOnce(&Mat) -> Result<T, Box<dyn Error>>) -> Result<T, Box<dyn Error>> {
+    let (height, width, channels) = input.dim();
+    if channels != 3 {
+        return Err(NftImgError::UnexpectedChannels { expected: 3, found: channels as i32 }.into());
+    }
+    if height == 0 || width == 0 {
+        return Err("array of an empty image".into());
+    }
+    let standard = input.as_standard_layout();
+    let pixels = standard.as_slice().ok_or("array is not contiguous")?;
+    let flat = Mat::from_slice(pixels)?;
+    let image = flat.reshape(3, height as i32)?;
+    f(&image)
+}
+
+impl BgrImage {
+    /// The pixels as a `height x width x channels` array view, without
+    /// copying; fails for images that are a region of a larger one.
+    pub fn view(&self) -> Result<ArrayView3<'_, u8>, Box<dyn Error>> {
+        if !self.0.is_continuous() {
+            return Err("image is not continuous, clone it first".into());
+        }
+        let shape = (self.0.rows() as usize, self.0.cols() as usize, self.0.channels() as usize);
+        Ok(ArrayView3::from_shape(shape, self.0.data_bytes()?)?)
+    }
+}
+
+/// Copies a `height x width x 3` BGR array.
+impl TryFrom<ArrayView3<'_, u8>> for BgrImage {
+    type Error = Box<dyn Error>;
+
+    fn try_from(input: ArrayView3<'_, u8>) -> Result<BgrImage, Box<dyn Error>> {
+        with_mat(input, |image| Ok(BgrImage(image.try_clone()?)))
+    }
+}
+
+impl TryFrom<&BgrImage> for Array3<u8> {
+    type Error = Box<dyn Error>;
+
+    fn try_from(image: &BgrImage) -> Result<Array3<u8>, Box<dyn Error>> {
+        if image.0.is_continuous() {
+            return Ok(image.view()?.to_owned());
+        }
+        Array3::try_from(&BgrImage(image.0.try_clone()?))
+    }
+}
+
+/// Converts a `height x width x 3` BGR array, e.g. a tensor from ML
+/// preprocessing, and returns the output as `height x width x channels`.
+/// Standard-layout arrays are read in place; others are copied first.
+pub fn convert_array(input: ArrayView3<'_, u8>, options: &ConvertOptions) -> Result<Array3<u8>, Box<dyn Error>> {
+    let output = with_mat(input, |image| convert_image(image, options))?;
+    Array3::try_from(&BgrImage(output))
+}
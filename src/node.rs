@@ -0,0 +1,89 @@
+//! Node.js addon, behind the `node` feature; `npm run build` builds it as a
+//! cdylib and copies it to `nftimg.node` (see `package.json`).
+//!
+//! ```js
+//! const { convert } = require('nftimg')
+//! const cartoon = await convert(fs.readFileSync('photo.jpg'), { spatialRadius: 15 })
+//! ```
+
+use napi::bindgen_prelude::{AsyncTask, Buffer};
+use napi::{Env, Task};
+use napi_derive::napi;
+
+use crate::{convert_buffer, BlockSize, ConvertOptions, Iterations, NftImgError, Radius};
+
+/// The most used parameters, camelCase in JavaScript, plus an optional
+/// recipe for everything else; missing fields keep their defaults.
+#[napi(object)]
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub spatial_radius: Option<f64>,
+    pub color_radius: Option<f64>,
+    pub max_pyramid_level: Option<i32>,
+    pub threshold_block_size: Option<i32>,
+    pub threshold_c: Option<f64>,
+    pub dilate_kernel_size: Option<i32>,
+    pub dilate_iterations: Option<i32>,
+    /// `key=value` lines as written by `ConvertOptions::recipe`, applied
+    /// instead of the fields above.
+    pub recipe: Option<String>,
+}
+
+impl Options {
+    /*
+     * JS options -> ConvertOptions
+     */
+    fn convert_options(self) -> napi::Result<ConvertOptions> {
+        if let Some(recipe) = &self.recipe {
+            return ConvertOptions::from_recipe(recipe).map_err(|e| napi::Error::from_reason(e.to_string()));
+        }
+        let defaults = ConvertOptions::default();
+        let invalid = |e: NftImgError| napi::Error::from_reason(e.to_string());
+        let options = ConvertOptions {
+            spatial_radius: self.spatial_radius.map_or(Ok(defaults.spatial_radius), Radius::try_from).map_err(invalid)?,
+            color_radius: self.color_radius.map_or(Ok(defaults.color_radius), Radius::try_from).map_err(invalid)?,
+            max_pyramid_level: self.max_pyramid_level.unwrap_or(defaults.max_pyramid_level),
+            threshold_block_size: self
+                .threshold_block_size
+                .map_or(Ok(defaults.threshold_block_size), BlockSize::try_from)
+                .map_err(invalid)?,
+            threshold_c: self.threshold_c.unwrap_or(defaults.threshold_c),
+            dilate_kernel_size: self.dilate_kernel_size.unwrap_or(defaults.dilate_kernel_size),
+            dilate_iterations: self
+                .dilate_iterations
+                .map_or(Ok(defaults.dilate_iterations), |passes| Iterations::optional(passes.into()))
+                .map_err(invalid)?,
+            ..defaults
+        };
+        options.validate().map_err(invalid)?;
+        Ok(options)
+    }
+}
+
+/// A conversion run on the libuv thread pool, off the JavaScript thread.
+pub struct ConvertTask {
+    input: Vec<u8>,
+    options: ConvertOptions,
+}
+
+impl Task for ConvertTask {
+    type Output = Vec<u8>;
+    type JsValue = Buffer;
+
+    fn compute(&mut self) -> napi::Result<Vec<u8>> {
+        convert_buffer(&self.input, &self.options).map_err(|e| napi::Error::from_reason(e.to_string()))
+    }
+
+    fn resolve(&mut self, _env: Env, output: Vec<u8>) -> napi::Result<Buffer> {
+        Ok(output.into())
+    }
+}
+
+/// Converts an encoded image and resolves to the output, encoded in the
+/// format of the input; rejects with the error message on failure.
+#[napi(ts_return_type = "Promise<Buffer>")]
+pub fn convert(input: Buffer, options: Option<Options>) -> napi::Result<AsyncTask<ConvertTask>> {
+    // copied so the task owns its input while JavaScript keeps running
+    let task = ConvertTask { input: input.to_vec(), options: options.unwrap_or_default().convert_options()? };
+    Ok(AsyncTask::new(task))
+}
@@ -0,0 +1,957 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::error::Error;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::ascii::AsciiOptions;
+use crate::color_profile::ColorProfile;
+use crate::composite::EdgeStyle;
+use crate::depth::DepthOptions;
+use crate::edges::{EdgeDetector, KernelShape, Morphology};
+use crate::enhance::{ClaheOptions, Denoise};
+use crate::error::NftImgError;
+#[cfg(feature = "faces")]
+use crate::faces::FaceOptions;
+use crate::finish::{SharpenOptions, TextureOptions, VignetteOptions};
+use crate::grading::{AdjustOptions, GradientMap, LutOptions};
+use crate::metadata::MetadataOptions;
+use crate::params::{BlockSize, Iterations, Radius};
+use crate::palette::{bgr_to_hex, hex_to_bgr, Palette, PosterizeOptions};
+use crate::pixelart::PixelArtOptions;
+use crate::plugin::CustomStage;
+use crate::popart::PopArtOptions;
+use crate::portrait::PortraitOptions;
+#[cfg(feature = "quality")]
+use crate::quality::QualityOptions;
+use crate::shading::{HalftoneOptions, HatchOptions};
+use crate::smoothing::Smoothing;
+use crate::strokes::StrokeOptions;
+#[cfg(feature = "dnn")]
+use crate::style::Style;
+use crate::svg::SvgOptions;
+#[cfg(feature = "superres")]
+use crate::upscale::UpscaleOptions;
+#[cfg(feature = "white-balance")]
+use crate::white_balance::WhiteBalance;
+#[cfg(feature = "saliency")]
+use crate::saliency::SaliencyOptions;
+#[cfg(feature = "dnn")]
+use crate::segmentation::SegmentationOptions;
+
+/// Options for a single conversion. `ConvertOptions::default()` reproduces
+/// the behaviour of [`crate::convert`].
+///
+/// With the `serde` feature, options and their parts (de)serialize with the
+/// recipe's key names; missing keys keep their defaults.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct ConvertOptions {
+    /// Remove color casts from the input first.
+    #[cfg(feature = "white-balance")]
+    pub white_balance: Option<WhiteBalance>,
+    /// Remove sensor noise before segmentation and edge detection.
+    pub denoise: Option<Denoise>,
+    /// Equalize the lightness before segmentation.
+    pub clahe: Option<ClaheOptions>,
+    /// Backend of the base (color flattening) branch.
+    pub smoothing: Smoothing,
+    /// Mean-shift spatial window radius.
+    pub spatial_radius: Radius,
+    /// Mean-shift color window radius.
+    pub color_radius: Radius,
+    /// Mean-shift pyramid depth.
+    pub max_pyramid_level: i32,
+    /// Anisotropic diffusion time step.
+    pub diffusion_alpha: f32,
+    /// Anisotropic diffusion conductance.
+    pub diffusion_k: f32,
+    pub diffusion_iterations: Iterations,
+    /// Backend of the border (outline) branch.
+    pub edge_detector: EdgeDetector,
+    /// Adaptive threshold neighbourhood.
+    pub threshold_block_size: BlockSize,
+    /// Constant subtracted from the neighbourhood mean.
+    pub threshold_c: f64,
+    /// Pyramid levels the outlines are detected on; 1 is the input resolution
+    /// only.
+    pub edge_scales: i32,
+    /// Side of the square dilation kernel.
+    pub dilate_kernel_size: i32,
+    pub dilate_kernel_shape: KernelShape,
+    /// Dilation passes; none leaves the outlines as detected.
+    pub dilate_iterations: Option<Iterations>,
+    /// Further operations on the outlines, in order.
+    pub morphology: Vec<Morphology>,
+    /// Outline components smaller than this many pixels are dropped; 0 keeps
+    /// them all.
+    pub min_edge_area: f64,
+    /// Widen outlines by edge strength instead of dilating them uniformly.
+    pub stroke: Option<StrokeOptions>,
+    /// Outline color and opacity, instead of opaque black.
+    pub edge_style: Option<EdgeStyle>,
+    /// Reduce the base to a few colors.
+    pub posterize: Option<PosterizeOptions>,
+    /// Map the base to these colors, nearest in Lab. Applied after
+    /// posterizing, whose dither setting it shares.
+    pub palette: Option<Palette>,
+    /// Recolor the base through a luminance gradient.
+    pub gradient_map: Option<GradientMap>,
+    /// Replace the tones of the base with a dot screen.
+    pub halftone: Option<HalftoneOptions>,
+    /// Replace the mid-tones of the base with cross-hatching.
+    pub hatch: Option<HatchOptions>,
+    /// Rebuild the result from a coarse grid of flat cells.
+    pub pixelart: Option<PixelArtOptions>,
+    /// Lay out remapped copies of the result as a pop-art grid.
+    pub popart: Option<PopArtOptions>,
+    /// Render the result as ASCII art as well, or instead of the image.
+    pub ascii: Option<AsciiOptions>,
+    /// Trace the result into an SVG as well.
+    pub svg: Option<SvgOptions>,
+    /// Upscale the result with a super-resolution network before the
+    /// finishing stages.
+    #[cfg(feature = "superres")]
+    pub upscale: Option<UpscaleOptions>,
+    /// Grade the input or the output with a `.cube` LUT.
+    pub lut: Option<LutOptions>,
+    /// Saturation, brightness, contrast and hue of the output.
+    pub adjust: Option<AdjustOptions>,
+    /// Unsharp-mask the output.
+    pub sharpen: Option<SharpenOptions>,
+    /// Darken the output towards its borders.
+    pub vignette: Option<VignetteOptions>,
+    /// Lay film grain or a paper texture over the output.
+    pub texture: Option<TextureOptions>,
+    /// Stages registered with [`crate::register_stage`], run at their points
+    /// in the given order.
+    pub stages: Vec<CustomStage>,
+    /// Largest input accepted, in pixels; `None` for no limit.
+    pub max_pixels: Option<u64>,
+    /// Downscale inputs above `max_pixels` to fit instead of refusing them.
+    pub downscale_oversized: bool,
+    /// Rotate and flip the input upright by its EXIF orientation.
+    pub auto_orient: bool,
+    /// Color space of the input; `None` detects it from the embedded ICC
+    /// profile and falls back to sRGB.
+    pub input_profile: Option<ColorProfile>,
+    /// Mix colors in linear light rather than gamma-encoded sRGB: outline
+    /// and finishing blends, pixel-art cell averages.
+    pub linear_light: bool,
+    /// Measure noise, sharpness, exposure and dominant colors of each input
+    /// and adapt denoising, CLAHE, saturation, outline threshold and dilation
+    /// and the color radius to them.
+    pub auto: bool,
+    /// Keep more detail in salient regions than in the background.
+    #[cfg(feature = "saliency")]
+    pub saliency: Option<SaliencyOptions>,
+    /// Smooth the background harder than the subject, by depth.
+    pub depth: Option<DepthOptions>,
+    /// Smooth skin harder than the rest of the image.
+    pub portrait: Option<PortraitOptions>,
+    /// Detect faces and keep more of their detail.
+    #[cfg(feature = "faces")]
+    pub faces: Option<FaceOptions>,
+    /// Label the image with a segmentation network and use per-class
+    /// smoothing and outline parameters.
+    #[cfg(feature = "dnn")]
+    pub segmentation: Option<SegmentationOptions>,
+    /// Stylize with a neural network instead of segmenting and outlining.
+    #[cfg(feature = "dnn")]
+    pub style: Option<Style>,
+    /// Metadata carried over from the input or injected into the output.
+    pub metadata: MetadataOptions,
+    /// Inserted before the extension of the output name, see
+    /// [`crate::output_path`].
+    pub output_suffix: String,
+    /// Write outputs into this directory, created if missing, instead of
+    /// next to their input.
+    pub output_dir: Option<PathBuf>,
+    /// Write the intermediate images of each conversion into this directory,
+    /// numbered in the order they are produced and named after their step
+    /// and its parameters, e.g. `02_segmented_mean-shift_sr10_cr20_l1.png`;
+    /// not part of the recipe.
+    pub debug_dir: Option<PathBuf>,
+    /// Convert earlier outputs found in a folder too, instead of skipping
+    /// names carrying `output_suffix`.
+    pub restyle_outputs: bool,
+    /// Run OpenCV single-threaded with a fixed random seed per image, so the
+    /// same input and options always give byte-identical output. OpenCV's
+    /// thread count is process-wide: it is restored after each conversion,
+    /// but conversions running meanwhile on other threads are single-threaded
+    /// too. The random generator of the converting thread stays reseeded.
+    pub deterministic: bool,
+    /// Wall-clock limit per image when converting a folder; slower images
+    /// are skipped and logged.
+    pub timeout: Option<Duration>,
+    /// URL a JSON summary is POSTed to when a folder, server job or worker
+    /// job ends; not part of the recipe.
+    #[cfg(feature = "webhook")]
+    pub webhook: Option<String>,
+    /// Score each output of a folder run with BRISQUE and flag poor ones for
+    /// review; not part of the recipe.
+    #[cfg(feature = "quality")]
+    pub quality: Option<QualityOptions>,
+}
+
+/// Deepest pyramid [`ConvertOptions::validate`] accepts, for mean shift and
+/// multi-scale outlines.
+const MAX_PYRAMID_LEVELS: i32 = 8;
+/// Most copies per side of a pop-art grid; the output grows with the grid.
+const MAX_POPART_GRID: i32 = 8;
+/// Most pixel-art cells and ASCII columns along a side.
+const MAX_CELLS: i32 = 4096;
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        ConvertOptions {
+            #[cfg(feature = "white-balance")]
+            white_balance: None,
+            denoise: None,
+            clahe: None,
+            smoothing: Smoothing::MeanShift,
+            spatial_radius: Radius(10.0),
+            color_radius: Radius(20.0),
+            max_pyramid_level: 1,
+            diffusion_alpha: 0.05,
+            diffusion_k: 0.1,
+            diffusion_iterations: Iterations(NonZeroU32::new(10).unwrap()),
+            edge_detector: EdgeDetector::AdaptiveThreshold,
+            threshold_block_size: BlockSize(9),
+            threshold_c: 9.0,
+            edge_scales: 1,
+            dilate_kernel_size: 3,
+            dilate_kernel_shape: KernelShape::Rect,
+            dilate_iterations: Some(Iterations::ONE),
+            morphology: Vec::new(),
+            min_edge_area: 0.0,
+            stroke: None,
+            edge_style: None,
+            posterize: None,
+            palette: None,
+            gradient_map: None,
+            halftone: None,
+            hatch: None,
+            pixelart: None,
+            popart: None,
+            ascii: None,
+            svg: None,
+            #[cfg(feature = "superres")]
+            upscale: None,
+            lut: None,
+            adjust: None,
+            sharpen: None,
+            vignette: None,
+            texture: None,
+            stages: Vec::new(),
+            max_pixels: Some(100_000_000),
+            downscale_oversized: false,
+            auto_orient: true,
+            input_profile: None,
+            linear_light: false,
+            auto: false,
+            #[cfg(feature = "saliency")]
+            saliency: None,
+            depth: None,
+            portrait: None,
+            #[cfg(feature = "faces")]
+            faces: None,
+            #[cfg(feature = "dnn")]
+            segmentation: None,
+            #[cfg(feature = "dnn")]
+            style: None,
+            metadata: MetadataOptions::default(),
+            output_suffix: "nft".to_string(),
+            output_dir: None,
+            debug_dir: None,
+            restyle_outputs: false,
+            deterministic: false,
+            timeout: None,
+            #[cfg(feature = "webhook")]
+            webhook: None,
+            #[cfg(feature = "quality")]
+            quality: None,
+        }
+    }
+}
+
+impl ConvertOptions {
+    /// Serializes the processing parameters as `key=value` lines, prefixed
+    /// with the crate version that produced them.
+    pub fn recipe(&self) -> String {
+        let mut recipe = format!(
+            "version={}\n\
+             smoothing={}\n\
+             spatial_radius={}\n\
+             color_radius={}\n\
+             max_pyramid_level={}\n\
+             diffusion_alpha={}\n\
+             diffusion_k={}\n\
+             diffusion_iterations={}\n\
+             edge_detector={}\n\
+             threshold_block_size={}\n\
+             threshold_c={}\n\
+             edge_scales={}\n\
+             dilate_kernel_size={}\n\
+             dilate_kernel_shape={}\n\
+             dilate_iterations={}\n\
+             min_edge_area={}\n",
+            env!("CARGO_PKG_VERSION"),
+            self.smoothing,
+            self.spatial_radius,
+            self.color_radius,
+            self.max_pyramid_level,
+            self.diffusion_alpha,
+            self.diffusion_k,
+            self.diffusion_iterations,
+            self.edge_detector,
+            self.threshold_block_size,
+            self.threshold_c,
+            self.edge_scales,
+            self.dilate_kernel_size,
+            self.dilate_kernel_shape,
+            self.dilate_iterations.map_or(0, Iterations::get),
+            self.min_edge_area,
+        );
+        // 0 lifts the limit, as --max-pixels 0 does
+        recipe.push_str(&format!("max_pixels={}\n", self.max_pixels.unwrap_or(0)));
+        recipe.push_str(&format!("downscale_oversized={}\n", self.downscale_oversized));
+        if !self.auto_orient {
+            recipe.push_str("auto_orient=false\n");
+        }
+        if let Some(profile) = self.input_profile {
+            recipe.push_str(&format!("input_profile={}\n", profile.name()));
+        }
+        if self.linear_light {
+            recipe.push_str("linear_light=true\n");
+        }
+        if self.auto {
+            recipe.push_str("auto=true\n");
+        }
+        #[cfg(feature = "white-balance")]
+        if let Some(white_balance) = &self.white_balance {
+            recipe.push_str(&format!("white_balance={}\n", white_balance));
+        }
+        if let Some(denoise) = &self.denoise {
+            recipe.push_str(&format!("denoise={}\n", denoise));
+        }
+        if !self.morphology.is_empty() {
+            let steps: Vec<String> = self.morphology.iter().map(Morphology::to_string).collect();
+            recipe.push_str(&format!("morphology={}\n", steps.join(",")));
+        }
+        if let Some(clahe) = &self.clahe {
+            recipe.push_str(&format!("clahe_clip_limit={}\nclahe_tile_grid={}\n", clahe.clip_limit, clahe.tile_grid));
+        }
+        if let Some(stroke) = &self.stroke {
+            recipe.push_str(&format!("stroke_min_width={}\nstroke_max_width={}\n", stroke.min_width, stroke.max_width));
+        }
+        if let Some(style) = &self.edge_style {
+            recipe.push_str(&format!("edge_color={}\nedge_opacity={}\n", bgr_to_hex(style.color), style.opacity));
+            recipe.push_str(&format!("edge_feather={}\n", style.feather));
+            if let Some(background) = style.background {
+                recipe.push_str(&format!("edge_background={}\n", bgr_to_hex(background)));
+            }
+        }
+        if let Some(posterize) = &self.posterize {
+            recipe.push_str(&format!(
+                "posterize_colors={}\nposterize_dither={}\n",
+                posterize.colors, posterize.dither,
+            ));
+        }
+        if let Some(palette) = &self.palette {
+            recipe.push_str(&format!("palette={}\n", palette));
+        }
+        if let Some(gradient) = &self.gradient_map {
+            recipe.push_str(&format!("gradient_map={}\n", gradient));
+        }
+        if let Some(halftone) = &self.halftone {
+            recipe.push_str(&format!(
+                "halftone_dot_size={}\nhalftone_angle={}\nhalftone_mode={}\n",
+                halftone.dot_size, halftone.angle, halftone.mode,
+            ));
+        }
+        if let Some(hatch) = &self.hatch {
+            recipe.push_str(&format!(
+                "hatch_spacing={}\nhatch_line_width={}\nhatch_angle={}\nhatch_low={}\nhatch_high={}\n",
+                hatch.spacing, hatch.line_width, hatch.angle, hatch.low, hatch.high,
+            ));
+        }
+        if let Some(pixelart) = &self.pixelart {
+            recipe.push_str(&format!(
+                "pixelart_cells={}\npixelart_colors={}\npixelart_outline={}\n",
+                pixelart.cells, pixelart.colors, pixelart.outline,
+            ));
+        }
+        if let Some(popart) = &self.popart {
+            recipe.push_str(&format!(
+                "popart_rows={}\npopart_cols={}\npopart_levels={}\n",
+                popart.rows, popart.cols, popart.levels,
+            ));
+        }
+        if let Some(ascii) = &self.ascii {
+            recipe.push_str(&format!("ascii_columns={}\nascii_mode={}\n", ascii.columns, ascii.mode));
+        }
+        if let Some(svg) = &self.svg {
+            recipe.push_str(&format!(
+                "svg_colors={}\nsvg_epsilon={}\nsvg_min_area={}\n",
+                svg.colors, svg.epsilon, svg.min_area,
+            ));
+        }
+        #[cfg(feature = "superres")]
+        if let Some(upscale) = &self.upscale {
+            recipe.push_str(&format!(
+                "upscale_model={}\nupscale_algorithm={}\nupscale_scale={}\n",
+                upscale.model.display(),
+                upscale.algorithm,
+                upscale.scale,
+            ));
+        }
+        if let Some(lut) = &self.lut {
+            recipe.push_str(&format!("lut={}\nlut_stage={}\n", lut.path.display(), lut.stage));
+        }
+        if let Some(adjust) = &self.adjust {
+            recipe.push_str(&format!(
+                "adjust_saturation={}\nadjust_brightness={}\nadjust_contrast={}\nadjust_hue={}\n",
+                adjust.saturation, adjust.brightness, adjust.contrast, adjust.hue,
+            ));
+        }
+        if let Some(sharpen) = &self.sharpen {
+            recipe.push_str(&format!(
+                "sharpen_amount={}\nsharpen_radius={}\nsharpen_threshold={}\n",
+                sharpen.amount, sharpen.radius, sharpen.threshold,
+            ));
+        }
+        if let Some(vignette) = &self.vignette {
+            recipe.push_str(&format!(
+                "vignette_strength={}\nvignette_radius={}\nvignette_feather={}\n",
+                vignette.strength, vignette.radius, vignette.feather,
+            ));
+        }
+        if let Some(texture) = &self.texture {
+            recipe.push_str(&format!(
+                "texture={}\ntexture_opacity={}\ntexture_blend={}\n",
+                texture.texture, texture.opacity, texture.blend,
+            ));
+        }
+        if !self.stages.is_empty() {
+            let stages: Vec<String> = self.stages.iter().map(CustomStage::to_string).collect();
+            recipe.push_str(&format!("stages={}\n", stages.join(",")));
+        }
+        #[cfg(feature = "saliency")]
+        if let Some(saliency) = &self.saliency {
+            recipe.push_str(&format!(
+                "saliency_detail={}\nsaliency_flat={}\n",
+                saliency.detail, saliency.flat,
+            ));
+        }
+        if let Some(depth) = &self.depth {
+            recipe.push_str(&format!(
+                "depth={}\ndepth_near={}\ndepth_far={}\ndepth_edge_c={}\n",
+                depth.source, depth.near, depth.far, depth.edge_c,
+            ));
+        }
+        if let Some(portrait) = &self.portrait {
+            recipe.push_str(&format!(
+                "portrait_smoothing={}\nportrait_edge_c={}\n",
+                portrait.smoothing, portrait.edge_c,
+            ));
+        }
+        #[cfg(feature = "faces")]
+        if let Some(faces) = &self.faces {
+            recipe.push_str(&format!(
+                "face_cascade={}\nface_smoothing={}\nface_margin={}\n",
+                faces.cascade.display(),
+                faces.smoothing,
+                faces.margin,
+            ));
+        }
+        #[cfg(feature = "dnn")]
+        if let Some(segmentation) = &self.segmentation {
+            recipe.push_str(&format!("segmentation_model={}\n", segmentation.model.display()));
+            for params in &segmentation.classes {
+                recipe.push_str(&format!("segmentation_class={}\n", params));
+            }
+        }
+        #[cfg(feature = "dnn")]
+        if let Some(style) = &self.style {
+            recipe.push_str(&format!("style={}\n", style));
+        }
+        recipe
+    }
+
+    /// Checks the parameters against the ranges their stages accept, so a bad
+    /// value is reported by name instead of failing an OpenCV assertion
+    /// mid-conversion. Errors are [`NftImgError::InvalidOption`]; radii,
+    /// block size and iterations are in range by their types.
+    pub fn validate(&self) -> Result<(), NftImgError> {
+        let invalid = |name, value: &dyn ToString, expected| {
+            Err(NftImgError::InvalidOption { name, value: value.to_string(), expected })
+        };
+        let positive = |value: f64| value.is_finite() && value > 0.0;
+        let non_negative = |value: f64| value.is_finite() && value >= 0.0;
+        let unit = |value: f64| (0.0..=1.0).contains(&value);
+        if !(0..=MAX_PYRAMID_LEVELS).contains(&self.max_pyramid_level) {
+            return invalid("max_pyramid_level", &self.max_pyramid_level, "0 to 8 levels");
+        }
+        if !positive(self.diffusion_alpha as f64) {
+            return invalid("diffusion_alpha", &self.diffusion_alpha, "a positive time step");
+        }
+        if !positive(self.diffusion_k as f64) {
+            return invalid("diffusion_k", &self.diffusion_k, "a positive conductance");
+        }
+        if !self.threshold_c.is_finite() {
+            return invalid("threshold_c", &self.threshold_c, "a finite constant");
+        }
+        if !(1..=MAX_PYRAMID_LEVELS).contains(&self.edge_scales) {
+            return invalid("edge_scales", &self.edge_scales, "1 to 8 levels");
+        }
+        if self.dilate_kernel_size < 1 {
+            return invalid("dilate_kernel_size", &self.dilate_kernel_size, "a kernel of at least 1 pixel");
+        }
+        if !(self.min_edge_area.is_finite() && self.min_edge_area >= 0.0) {
+            return invalid("min_edge_area", &self.min_edge_area, "a non-negative area");
+        }
+        if let Some(stroke) = &self.stroke {
+            if stroke.min_width < 1 || stroke.max_width < stroke.min_width {
+                let widths = format!("{}:{}", stroke.min_width, stroke.max_width);
+                return invalid("stroke widths", &widths, "1 <= min <= max");
+            }
+        }
+        if let Some(posterize) = &self.posterize {
+            if !(2..=256).contains(&posterize.colors) {
+                return invalid("posterize_colors", &posterize.colors, "2 to 256 colors");
+            }
+        }
+        if let Some(gradient) = &self.gradient_map {
+            if gradient.stops.len() < 2 {
+                return invalid("gradient_map", gradient, "at least two stops");
+            }
+        }
+        if let Some(pixelart) = &self.pixelart {
+            if !(1..=MAX_CELLS).contains(&pixelart.cells) {
+                return invalid("pixelart_cells", &pixelart.cells, "1 to 4096 cells");
+            }
+        }
+        if let Some(popart) = &self.popart {
+            if !(1..=MAX_POPART_GRID).contains(&popart.rows) {
+                return invalid("popart_rows", &popart.rows, "1 to 8 rows");
+            }
+            if !(1..=MAX_POPART_GRID).contains(&popart.cols) {
+                return invalid("popart_cols", &popart.cols, "1 to 8 columns");
+            }
+        }
+        if let Some(ascii) = &self.ascii {
+            if !(1..=MAX_CELLS).contains(&ascii.columns) {
+                return invalid("ascii_columns", &ascii.columns, "1 to 4096 columns");
+            }
+        }
+        #[cfg(feature = "superres")]
+        if let Some(upscale) = &self.upscale {
+            if !(2..=8).contains(&upscale.scale) {
+                return invalid("upscale_scale", &upscale.scale, "a factor of 2 to 8");
+            }
+        }
+        if let Some(sharpen) = &self.sharpen {
+            // gaussian_blur asserts on a zero sigma with a zero kernel size
+            if !positive(sharpen.radius) {
+                return invalid("sharpen_radius", &sharpen.radius, "a positive blur radius");
+            }
+        }
+        if let Some(style) = &self.edge_style {
+            if !unit(style.opacity) {
+                return invalid("edge_opacity", &style.opacity, "an opacity of 0 to 1");
+            }
+            if !non_negative(style.feather) {
+                return invalid("edge_feather", &style.feather, "a non-negative feather");
+            }
+        }
+        if let Some(texture) = &self.texture {
+            if !unit(texture.opacity) {
+                return invalid("texture_opacity", &texture.opacity, "an opacity of 0 to 1");
+            }
+        }
+        if let Some(vignette) = &self.vignette {
+            if !unit(vignette.strength) {
+                return invalid("vignette_strength", &vignette.strength, "a strength of 0 to 1");
+            }
+            if !non_negative(vignette.feather) {
+                return invalid("vignette_feather", &vignette.feather, "a non-negative feather");
+            }
+        }
+        if let Some(hatch) = &self.hatch {
+            if hatch.low.partial_cmp(&hatch.high) != Some(Ordering::Less) {
+                let levels = format!("{}:{}", hatch.low, hatch.high);
+                return invalid("hatch levels", &levels, "low < high");
+            }
+        }
+        // an empty suffix names the output after its input, overwriting it
+        if self.output_suffix.is_empty() || self.output_suffix.contains(['/', '\\']) {
+            return invalid("output_suffix", &format!("{:?}", self.output_suffix), "a non-empty file name part");
+        }
+        Ok(())
+    }
+
+    /// Parses a recipe written by [`ConvertOptions::recipe`]. Missing keys keep
+    /// their default value, unknown or repeated ones are refused; metadata
+    /// options and output naming are not part of a recipe. The options are
+    /// checked with [`ConvertOptions::validate`].
+    pub fn from_recipe(recipe: &str) -> Result<ConvertOptions, Box<dyn Error>> {
+        let mut options = ConvertOptions::default();
+        options.apply_recipe(recipe)?;
+        options.validate()?;
+        Ok(options)
+    }
+
+    /*
+     * Sets the keys of `recipe`, each at most once; segmentation classes are
+     * the one key listed repeatedly
+     */
+    fn apply_recipe(&mut self, recipe: &str) -> Result<(), Box<dyn Error>> {
+        let options = self;
+        let mut keys = HashSet::new();
+        for line in recipe.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("malformed recipe line: {}", line))?;
+            if key != "segmentation_class" && !keys.insert(key) {
+                return Err(format!("duplicate recipe key: {}", key).into());
+            }
+            match key {
+                "version" => {}
+                "smoothing" => options.smoothing = value.parse()?,
+                "spatial_radius" => options.spatial_radius = value.parse()?,
+                "color_radius" => options.color_radius = value.parse()?,
+                "max_pyramid_level" => options.max_pyramid_level = value.parse()?,
+                "diffusion_alpha" => options.diffusion_alpha = value.parse()?,
+                "diffusion_k" => options.diffusion_k = value.parse()?,
+                "diffusion_iterations" => options.diffusion_iterations = value.parse()?,
+                "edge_detector" => options.edge_detector = value.parse()?,
+                "threshold_block_size" => options.threshold_block_size = value.parse()?,
+                "threshold_c" => options.threshold_c = value.parse()?,
+                "edge_scales" => options.edge_scales = value.parse()?,
+                "dilate_kernel_size" => options.dilate_kernel_size = value.parse()?,
+                "dilate_kernel_shape" => options.dilate_kernel_shape = value.parse()?,
+                "dilate_iterations" => options.dilate_iterations = Iterations::optional(value.parse()?)?,
+                "morphology" => {
+                    options.morphology = value.split(',').map(str::parse).collect::<Result<_, _>>()?
+                }
+                "min_edge_area" => options.min_edge_area = value.parse()?,
+                "stages" => options.stages = value.split(',').map(str::parse).collect::<Result<_, _>>()?,
+                "max_pixels" => {
+                    let max_pixels: u64 = value.parse()?;
+                    options.max_pixels = (max_pixels > 0).then_some(max_pixels);
+                }
+                "downscale_oversized" => options.downscale_oversized = value.parse()?,
+                "auto_orient" => options.auto_orient = value.parse()?,
+                "input_profile" => {
+                    let profile = ColorProfile::from_name(value);
+                    options.input_profile = Some(profile.ok_or_else(|| format!("unknown color profile: {}", value))?);
+                }
+                "linear_light" => options.linear_light = value.parse()?,
+                "auto" => options.auto = value.parse()?,
+                #[cfg(feature = "white-balance")]
+                "white_balance" => options.white_balance = Some(value.parse()?),
+                "denoise" => options.denoise = Some(value.parse()?),
+                "clahe_clip_limit" => {
+                    options.clahe.get_or_insert_with(ClaheOptions::default).clip_limit = value.parse()?
+                }
+                "clahe_tile_grid" => options.clahe.get_or_insert_with(ClaheOptions::default).tile_grid = value.parse()?,
+                "stroke_min_width" => {
+                    options.stroke.get_or_insert_with(StrokeOptions::default).min_width = value.parse()?
+                }
+                "stroke_max_width" => {
+                    options.stroke.get_or_insert_with(StrokeOptions::default).max_width = value.parse()?
+                }
+                "edge_color" => options.edge_style.get_or_insert_with(EdgeStyle::default).color = hex_to_bgr(value)?,
+                "edge_opacity" => options.edge_style.get_or_insert_with(EdgeStyle::default).opacity = value.parse()?,
+                "edge_feather" => options.edge_style.get_or_insert_with(EdgeStyle::default).feather = value.parse()?,
+                "edge_background" => {
+                    options.edge_style.get_or_insert_with(EdgeStyle::default).background = Some(hex_to_bgr(value)?)
+                }
+                "posterize_colors" => {
+                    options.posterize.get_or_insert_with(PosterizeOptions::default).colors = value.parse()?
+                }
+                "posterize_dither" => {
+                    options.posterize.get_or_insert_with(PosterizeOptions::default).dither = value.parse()?
+                }
+                "palette" => options.palette = Some(value.parse()?),
+                "gradient_map" => options.gradient_map = Some(value.parse()?),
+                "halftone_dot_size" => {
+                    options.halftone.get_or_insert_with(HalftoneOptions::default).dot_size = value.parse()?
+                }
+                "halftone_angle" => {
+                    options.halftone.get_or_insert_with(HalftoneOptions::default).angle = value.parse()?
+                }
+                "halftone_mode" => {
+                    options.halftone.get_or_insert_with(HalftoneOptions::default).mode = value.parse()?
+                }
+                "hatch_spacing" => options.hatch.get_or_insert_with(HatchOptions::default).spacing = value.parse()?,
+                "hatch_line_width" => {
+                    options.hatch.get_or_insert_with(HatchOptions::default).line_width = value.parse()?
+                }
+                "hatch_angle" => options.hatch.get_or_insert_with(HatchOptions::default).angle = value.parse()?,
+                "hatch_low" => options.hatch.get_or_insert_with(HatchOptions::default).low = value.parse()?,
+                "hatch_high" => options.hatch.get_or_insert_with(HatchOptions::default).high = value.parse()?,
+                "pixelart_cells" => {
+                    options.pixelart.get_or_insert_with(PixelArtOptions::default).cells = value.parse()?
+                }
+                "pixelart_colors" => {
+                    options.pixelart.get_or_insert_with(PixelArtOptions::default).colors = value.parse()?
+                }
+                "pixelart_outline" => {
+                    options.pixelart.get_or_insert_with(PixelArtOptions::default).outline = value.parse()?
+                }
+                "popart_rows" => options.popart.get_or_insert_with(PopArtOptions::default).rows = value.parse()?,
+                "popart_cols" => options.popart.get_or_insert_with(PopArtOptions::default).cols = value.parse()?,
+                "popart_levels" => {
+                    options.popart.get_or_insert_with(PopArtOptions::default).levels = value.parse()?
+                }
+                "ascii_columns" => options.ascii.get_or_insert_with(AsciiOptions::default).columns = value.parse()?,
+                "ascii_mode" => options.ascii.get_or_insert_with(AsciiOptions::default).mode = value.parse()?,
+                "svg_colors" => options.svg.get_or_insert_with(SvgOptions::default).colors = value.parse()?,
+                "svg_epsilon" => options.svg.get_or_insert_with(SvgOptions::default).epsilon = value.parse()?,
+                "svg_min_area" => options.svg.get_or_insert_with(SvgOptions::default).min_area = value.parse()?,
+                #[cfg(feature = "superres")]
+                "upscale_model" => options.upscale = Some(UpscaleOptions::from_model(value.into())?),
+                #[cfg(feature = "superres")]
+                "upscale_algorithm" => {
+                    options.upscale.as_mut().ok_or("upscale_algorithm before upscale_model")?.algorithm = value.into()
+                }
+                #[cfg(feature = "superres")]
+                "upscale_scale" => options.upscale.as_mut().ok_or("upscale_scale before upscale_model")?.scale = value.parse()?,
+                "lut" => options.lut = Some(LutOptions::new(value.into())),
+                "lut_stage" => options.lut.as_mut().ok_or("lut_stage before lut")?.stage = value.parse()?,
+                "adjust_saturation" => {
+                    options.adjust.get_or_insert_with(AdjustOptions::default).saturation = value.parse()?
+                }
+                "adjust_brightness" => {
+                    options.adjust.get_or_insert_with(AdjustOptions::default).brightness = value.parse()?
+                }
+                "adjust_contrast" => {
+                    options.adjust.get_or_insert_with(AdjustOptions::default).contrast = value.parse()?
+                }
+                "adjust_hue" => options.adjust.get_or_insert_with(AdjustOptions::default).hue = value.parse()?,
+                "sharpen_amount" => {
+                    options.sharpen.get_or_insert_with(SharpenOptions::default).amount = value.parse()?
+                }
+                "sharpen_radius" => {
+                    options.sharpen.get_or_insert_with(SharpenOptions::default).radius = value.parse()?
+                }
+                "sharpen_threshold" => {
+                    options.sharpen.get_or_insert_with(SharpenOptions::default).threshold = value.parse()?
+                }
+                "vignette_strength" => {
+                    options.vignette.get_or_insert_with(VignetteOptions::default).strength = value.parse()?
+                }
+                "vignette_radius" => {
+                    options.vignette.get_or_insert_with(VignetteOptions::default).radius = value.parse()?
+                }
+                "vignette_feather" => {
+                    options.vignette.get_or_insert_with(VignetteOptions::default).feather = value.parse()?
+                }
+                "texture" => options.texture.get_or_insert_with(TextureOptions::default).texture = value.parse()?,
+                "texture_opacity" => {
+                    options.texture.get_or_insert_with(TextureOptions::default).opacity = value.parse()?
+                }
+                "texture_blend" => options.texture.get_or_insert_with(TextureOptions::default).blend = value.parse()?,
+                #[cfg(feature = "saliency")]
+                "saliency_detail" => {
+                    options.saliency.get_or_insert_with(SaliencyOptions::default).detail = value.parse()?
+                }
+                #[cfg(feature = "saliency")]
+                "saliency_flat" => {
+                    options.saliency.get_or_insert_with(SaliencyOptions::default).flat = value.parse()?
+                }
+                "depth" => options.depth = Some(DepthOptions::new(value.parse()?)),
+                "depth_near" => options.depth.as_mut().ok_or("depth_near before depth")?.near = value.parse()?,
+                "depth_far" => options.depth.as_mut().ok_or("depth_far before depth")?.far = value.parse()?,
+                "depth_edge_c" => options.depth.as_mut().ok_or("depth_edge_c before depth")?.edge_c = value.parse()?,
+                "portrait_smoothing" => {
+                    options.portrait.get_or_insert_with(PortraitOptions::default).smoothing = value.parse()?
+                }
+                "portrait_edge_c" => {
+                    options.portrait.get_or_insert_with(PortraitOptions::default).edge_c = value.parse()?
+                }
+                #[cfg(feature = "faces")]
+                "face_cascade" => options.faces = Some(FaceOptions::new(value.into())),
+                #[cfg(feature = "faces")]
+                "face_smoothing" => options.faces.as_mut().ok_or("face_smoothing before face_cascade")?.smoothing = value.parse()?,
+                #[cfg(feature = "faces")]
+                "face_margin" => options.faces.as_mut().ok_or("face_margin before face_cascade")?.margin = value.parse()?,
+                #[cfg(feature = "dnn")]
+                "segmentation_model" => options.segmentation = Some(SegmentationOptions::new(value.into())),
+                #[cfg(feature = "dnn")]
+                "segmentation_class" => options
+                    .segmentation
+                    .as_mut()
+                    .ok_or("segmentation_class before segmentation_model")?
+                    .classes
+                    .push(value.parse()?),
+                #[cfg(feature = "dnn")]
+                "style" => options.style = Some(value.parse()?),
+                _ => return Err(format!("unknown recipe key: {}", key).into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// These options with the keys of `recipe` applied on top. What a recipe
+    /// does not carry, metadata options, output naming, debug output and the
+    /// settings of the run, is kept.
+    pub fn with_recipe(&self, recipe: &str) -> Result<ConvertOptions, Box<dyn Error>> {
+        let mut options = self.clone();
+        options.apply_recipe(recipe)?;
+        options.validate()?;
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ascii::AsciiMode;
+    use crate::depth::DepthSource;
+    use crate::finish::{BlendMode, Texture};
+    use crate::grading::LutStage;
+    use crate::shading::HalftoneMode;
+
+    /* every option a recipe carries, none at its default */
+    fn populated() -> ConvertOptions {
+        ConvertOptions {
+            denoise: Some(Denoise::NlMeans { h: 4.5, h_color: 7.0 }),
+            clahe: Some(ClaheOptions { clip_limit: 3.0, tile_grid: 4 }),
+            smoothing: Smoothing::Slic { region_size: 24, ruler: 12.5, iterations: 6 },
+            spatial_radius: Radius::new(12.5).unwrap(),
+            color_radius: Radius::new(40.0).unwrap(),
+            max_pyramid_level: 2,
+            diffusion_alpha: 0.2,
+            diffusion_k: 15.5,
+            diffusion_iterations: Iterations::new(25).unwrap(),
+            threshold_block_size: BlockSize::new(11).unwrap(),
+            threshold_c: 3.5,
+            edge_scales: 2,
+            dilate_kernel_size: 3,
+            dilate_kernel_shape: KernelShape::Ellipse,
+            dilate_iterations: Some(Iterations::new(2).unwrap()),
+            morphology: vec!["close:ellipse:3:2".parse().unwrap(), "open:rect:2:1".parse().unwrap()],
+            min_edge_area: 12.0,
+            stroke: Some(StrokeOptions { min_width: 2, max_width: 6 }),
+            edge_style: Some(EdgeStyle {
+                color: [10, 20, 30],
+                opacity: 0.75,
+                background: Some([250, 240, 230]),
+                feather: 1.5,
+            }),
+            posterize: Some(PosterizeOptions { colors: 12, dither: true }),
+            palette: Some(Palette { colors: vec![[0, 0, 0], [255, 128, 0]] }),
+            gradient_map: Some(GradientMap { stops: vec![[0, 0, 64], [200, 255, 255]] }),
+            halftone: Some(HalftoneOptions { dot_size: 5.0, angle: 15.0, mode: HalftoneMode::Cmyk }),
+            hatch: Some(HatchOptions { spacing: 8.0, line_width: 2.0, angle: 30.0, low: 0.1, high: 0.7 }),
+            pixelart: Some(PixelArtOptions { cells: 48, colors: 16, outline: true }),
+            popart: Some(PopArtOptions { rows: 3, cols: 2, levels: 5 }),
+            ascii: Some(AsciiOptions { columns: 80, mode: AsciiMode::Ansi }),
+            svg: Some(SvgOptions { colors: 10, epsilon: 1.5, min_area: 20.0 }),
+            lut: Some(LutOptions { path: "grade.cube".into(), stage: LutStage::Pre }),
+            adjust: Some(AdjustOptions { saturation: 1.2, brightness: 0.1, contrast: 1.1, hue: 20.0 }),
+            sharpen: Some(SharpenOptions { amount: 0.5, radius: 2.0, threshold: 8 }),
+            vignette: Some(VignetteOptions { strength: 0.3, radius: 0.7, feather: 0.2 }),
+            texture: Some(TextureOptions { texture: Texture::Grain, opacity: 0.4, blend: BlendMode::Multiply }),
+            stages: vec!["edges:outline".parse().unwrap()],
+            max_pixels: Some(4_000_000),
+            downscale_oversized: true,
+            auto_orient: false,
+            input_profile: Some(ColorProfile::DisplayP3),
+            linear_light: true,
+            auto: true,
+            depth: Some(DepthOptions {
+                near: 0.2,
+                far: 2.5,
+                edge_c: 9.0,
+                ..DepthOptions::new(DepthSource::File("depth.png".into()))
+            }),
+            portrait: Some(PortraitOptions { smoothing: 2.0, edge_c: 4.0 }),
+            ..ConvertOptions::default()
+        }
+    }
+
+    #[test]
+    fn recipe_round_trips() {
+        let options = populated();
+        assert!(options.validate().is_ok());
+        let parsed = ConvertOptions::from_recipe(&options.recipe()).unwrap();
+        // not every option type compares, all of them print
+        assert_eq!(format!("{:?}", parsed), format!("{:?}", options));
+        assert_eq!(parsed.recipe(), options.recipe());
+    }
+
+    #[test]
+    fn rejects_malformed_recipes() {
+        let error = |recipe: &str| ConvertOptions::from_recipe(recipe).unwrap_err().to_string();
+        assert_eq!(error("spatial_radius=10\nsaturation=2\n"), "unknown recipe key: saturation");
+        assert_eq!(error("threshold_c=2\nthreshold_c=3\n"), "duplicate recipe key: threshold_c");
+        assert_eq!(error("threshold_c 2\n"), "malformed recipe line: threshold_c 2");
+        assert!(ConvertOptions::from_recipe("threshold_c=two\n").is_err());
+        assert!(ConvertOptions::from_recipe("lut_stage=pre\n").is_err());
+        // blank lines and surrounding whitespace are not malformed
+        assert!(ConvertOptions::from_recipe("\n  threshold_c=2  \n\n").is_ok());
+    }
+
+    #[test]
+    fn with_recipe_overrides_keys() {
+        let base = ConvertOptions { output_suffix: "cartoon".into(), ..populated() };
+        let options = base.with_recipe("threshold_c=7\n").unwrap();
+        assert_eq!(options.threshold_c, 7.0);
+        assert_eq!(options.output_suffix, "cartoon");
+        assert_eq!(options.recipe(), base.recipe().replace("threshold_c=3.5", "threshold_c=7"));
+    }
+
+    /* name of the option validate rejects after change */
+    fn rejected(change: impl FnOnce(&mut ConvertOptions)) -> &'static str {
+        let mut options = ConvertOptions::default();
+        change(&mut options);
+        match options.validate() {
+            Err(NftImgError::InvalidOption { name, .. }) => name,
+            other => panic!("expected an invalid option, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_out_of_range_finishing() {
+        assert!(ConvertOptions::default().validate().is_ok());
+        let sharpen = |radius| SharpenOptions { radius, ..SharpenOptions::default() };
+        assert_eq!(rejected(|o| o.sharpen = Some(sharpen(0.0))), "sharpen_radius");
+        assert_eq!(rejected(|o| o.sharpen = Some(sharpen(-1.0))), "sharpen_radius");
+        let edge = EdgeStyle { opacity: 1.5, ..EdgeStyle::default() };
+        assert_eq!(rejected(|o| o.edge_style = Some(edge)), "edge_opacity");
+        let edge = EdgeStyle { feather: -1.0, ..EdgeStyle::default() };
+        assert_eq!(rejected(|o| o.edge_style = Some(edge)), "edge_feather");
+        let texture = TextureOptions { opacity: -0.1, ..TextureOptions::default() };
+        assert_eq!(rejected(|o| o.texture = Some(texture)), "texture_opacity");
+        let vignette = VignetteOptions { strength: 2.0, ..VignetteOptions::default() };
+        assert_eq!(rejected(|o| o.vignette = Some(vignette)), "vignette_strength");
+        let vignette = VignetteOptions { feather: f64::NAN, ..VignetteOptions::default() };
+        assert_eq!(rejected(|o| o.vignette = Some(vignette)), "vignette_feather");
+    }
+
+    #[test]
+    fn rejects_bad_hatch_gradient_and_suffix() {
+        let hatch = HatchOptions { low: 0.8, high: 0.2, ..HatchOptions::default() };
+        assert_eq!(rejected(|o| o.hatch = Some(hatch)), "hatch levels");
+        let gradient = GradientMap { stops: vec![[0, 0, 0]] };
+        assert_eq!(rejected(|o| o.gradient_map = Some(gradient)), "gradient_map");
+        assert_eq!(rejected(|o| o.output_suffix.clear()), "output_suffix");
+        assert_eq!(rejected(|o| o.output_suffix = "_a/b".into()), "output_suffix");
+    }
+
+    #[test]
+    fn builder_and_recipe_refuse_invalid_options() {
+        let built = ConvertOptions::builder()
+            .with(|o| o.sharpen = Some(SharpenOptions { radius: 0.0, ..SharpenOptions::default() }))
+            .build();
+        assert!(matches!(built, Err(NftImgError::InvalidOption { name: "sharpen_radius", .. })));
+        assert!(ConvertOptions::from_recipe("sharpen_radius=0\n").is_err());
+        assert!(ConvertOptions::from_recipe("edge_opacity=2\n").is_err());
+        assert!(ConvertOptions::from_recipe("hatch_low=0.9\nhatch_high=0.1\n").is_err());
+    }
+}
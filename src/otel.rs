@@ -0,0 +1,71 @@
+use std::error::Error;
+use std::time::{Duration, SystemTime};
+
+use opencv::prelude::*;
+use opentelemetry::global::{self, BoxedTracer};
+use opentelemetry::trace::{Span, Status, TraceContextExt, Tracer};
+use opentelemetry::{Context, KeyValue};
+
+use crate::ConvertOptions;
+
+/*
+ * Spans go to the global tracer provider the embedding service installs;
+ * without one they are dropped
+ */
+fn tracer() -> BoxedTracer {
+    global::tracer("nftimg")
+}
+
+/*
+ * Runs a conversion of `input` ("file", "buffer" or "image") inside an
+ * `nftimg.convert` span, child of the caller's current span, carrying the
+ * options as `nftimg.option.<recipe key>` attributes; the stages' spans are
+ * its children
+ */
+pub(crate) fn conversion<T>(
+    input: &'static str,
+    options: &ConvertOptions,
+    run: impl FnOnce() -> Result<T, Box<dyn Error>>,
+) -> Result<T, Box<dyn Error>> {
+    let mut span = tracer().start("nftimg.convert");
+    span.set_attribute(KeyValue::new("nftimg.input", input));
+    for (key, value) in options.recipe().lines().filter_map(|line| line.split_once('=')) {
+        span.set_attribute(KeyValue::new(format!("nftimg.option.{}", key), value.to_string()));
+    }
+    let context = Context::current_with_span(span);
+    let result = {
+        let _attached = context.clone().attach();
+        run()
+    };
+    if let Err(e) = &result {
+        context.span().set_status(Status::error(e.to_string()));
+    }
+    context.span().end();
+    result
+}
+
+/*
+ * Records a stage that just ended after `elapsed` as an `nftimg.<stage>`
+ * span, with the size of the image it produced; the decoded size is also
+ * set on the conversion span
+ */
+pub(crate) fn stage(name: &'static str, image: &Mat, elapsed: Duration) {
+    let end = SystemTime::now();
+    let size = [
+        KeyValue::new("image.width", i64::from(image.cols())),
+        KeyValue::new("image.height", i64::from(image.rows())),
+    ];
+    if name == "decode" {
+        let context = Context::current();
+        for attribute in &size {
+            context.span().set_attribute(attribute.clone());
+        }
+    }
+    let tracer = tracer();
+    let mut span = tracer
+        .span_builder(format!("nftimg.{}", name))
+        .with_start_time(end.checked_sub(elapsed).unwrap_or(end))
+        .with_attributes(size)
+        .start(&tracer);
+    span.end_with_timestamp(end);
+}
@@ -0,0 +1,254 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use opencv::core::{kmeans, Scalar, TermCriteria, TermCriteria_COUNT, TermCriteria_EPS, CV_32FC1, CV_8UC3, KMEANS_PP_CENTERS};
+use opencv::prelude::*;
+
+/// Upper bound on the pixels fed to k-means; larger images are subsampled.
+const KMEANS_SAMPLES: usize = 20_000;
+
+/// Posterize stage: reduces the base to a few colors, optionally with
+/// Floyd–Steinberg dithering so gradients survive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct PosterizeOptions {
+    pub colors: usize,
+    pub dither: bool,
+}
+
+impl Default for PosterizeOptions {
+    fn default() -> Self {
+        PosterizeOptions { colors: 8, dither: false }
+    }
+}
+
+/// A fixed set of colors the base is mapped to, e.g. a brand or collection
+/// scheme. Colors are BGR, like the images.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct Palette {
+    pub colors: Vec<[u8; 3]>,
+}
+
+impl Palette {
+    /// Loads a GIMP `.gpl` or Photoshop `.aco` palette, or a text file of hex
+    /// colors separated by commas or whitespace.
+    pub fn load(path: &Path) -> Result<Palette, Box<dyn Error>> {
+        let extension = path.extension().and_then(|e| e.to_str()).map(str::to_lowercase);
+        match extension.as_deref() {
+            Some("aco") => Palette::from_aco(&fs::read(path)?),
+            Some("gpl") => Palette::from_gpl(&fs::read_to_string(path)?),
+            _ => fs::read_to_string(path)?.parse(),
+        }
+    }
+
+    fn from_gpl(text: &str) -> Result<Palette, Box<dyn Error>> {
+        let mut lines = text.lines();
+        if lines.next().map(str::trim) != Some("GIMP Palette") {
+            return Err("not a GIMP palette".into());
+        }
+        let mut colors = Vec::new();
+        for line in lines.map(str::trim) {
+            if line.is_empty() || line.starts_with('#') || (line.contains(':') && !line.starts_with(char::is_numeric)) {
+                continue;
+            }
+            // R G B, then an optional name
+            let rgb = line.split_whitespace().take(3).map(str::parse).collect::<Result<Vec<u8>, _>>()?;
+            if rgb.len() != 3 {
+                return Err(format!("malformed GIMP palette line: {}", line).into());
+            }
+            colors.push([rgb[2], rgb[1], rgb[0]]);
+        }
+        Palette::non_empty(colors)
+    }
+
+    /*
+     * Photoshop swatches: big-endian version and count, then 10 bytes per
+     * color. Version 2 files start with a version 1 section, which is all we
+     * read; only RGB swatches are supported.
+     */
+    fn from_aco(bytes: &[u8]) -> Result<Palette, Box<dyn Error>> {
+        let u16_at = |pos: usize| -> Result<u16, Box<dyn Error>> {
+            let b = bytes.get(pos..pos + 2).ok_or("truncated ACO palette")?;
+            Ok(u16::from_be_bytes([b[0], b[1]]))
+        };
+        let count = u16_at(2)? as usize;
+        let mut colors = Vec::with_capacity(count);
+        for i in 0..count {
+            let entry = 4 + i * 10;
+            if u16_at(entry)? != 0 {
+                return Err("only RGB swatches are supported in ACO palettes".into());
+            }
+            let channel = |n: usize| -> Result<u8, Box<dyn Error>> { Ok((u16_at(entry + 2 + n * 2)? >> 8) as u8) };
+            colors.push([channel(2)?, channel(1)?, channel(0)?]);
+        }
+        Palette::non_empty(colors)
+    }
+
+    fn non_empty(colors: Vec<[u8; 3]>) -> Result<Palette, Box<dyn Error>> {
+        if colors.is_empty() {
+            return Err("empty palette".into());
+        }
+        Ok(Palette { colors })
+    }
+}
+
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hex: Vec<String> = self.colors.iter().map(|&c| bgr_to_hex(c)).collect();
+        write!(f, "{}", hex.join(","))
+    }
+}
+
+impl FromStr for Palette {
+    type Err = Box<dyn Error>;
+
+    /// Parses hex colors separated by commas or whitespace.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let colors = s
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|c| !c.is_empty())
+            .map(hex_to_bgr)
+            .collect::<Result<Vec<_>, _>>()?;
+        Palette::non_empty(colors)
+    }
+}
+
+/*
+ * Palette BGR colors -> the same colors in 8-bit Lab, for mapping the base.
+ */
+pub(crate) fn palette_to_lab(palette: &Palette) -> Result<Vec<[u8; 3]>, Box<dyn Error>> {
+    let mut swatch = Mat::new_rows_cols_with_default(1, palette.colors.len() as i32, CV_8UC3, Scalar::all(0.0))?;
+    swatch.data_bytes_mut()?.copy_from_slice(palette.colors.concat().as_slice());
+    let lab = crate::bgr_to_lab(&swatch)?;
+    Ok(lab.data_bytes()?.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect())
+}
+
+/*
+ * Picks `colors` representative colors of a 3-channel image with k-means,
+ * in whatever color space the image is in.
+ */
+pub(crate) fn kmeans_palette(input: &Mat, colors: usize) -> Result<Vec<[u8; 3]>, Box<dyn Error>> {
+    let pixels = input.data_bytes()?;
+    let count = pixels.len() / 3;
+    if count == 0 || colors == 0 {
+        return Err("cannot build a palette from an empty image".into());
+    }
+    let step = count.div_ceil(KMEANS_SAMPLES);
+    let samples = count.div_ceil(step);
+    let k = colors.min(samples);
+
+    let mut data = Mat::new_rows_cols_with_default(samples as i32, 3, CV_32FC1, Scalar::all(0.0))?;
+    for (row, px) in data.data_typed_mut::<f32>()?.chunks_exact_mut(3).zip(pixels.chunks_exact(3).step_by(step)) {
+        for (v, &p) in row.iter_mut().zip(px) {
+            *v = p as f32;
+        }
+    }
+
+    let criteria = TermCriteria::new(TermCriteria_COUNT + TermCriteria_EPS, 20, 1.0)?;
+    let mut labels = Mat::default();
+    let mut centers = Mat::default();
+    kmeans(&data, k as i32, &mut labels, criteria, 3, KMEANS_PP_CENTERS, &mut centers)?;
+
+    Ok(centers
+        .data_typed::<f32>()?
+        .chunks_exact(3)
+        .map(|c| [c[0].round() as u8, c[1].round() as u8, c[2].round() as u8])
+        .collect())
+}
+
+/// Index of the palette entry closest to `px`.
+fn nearest_index(palette: &[[u8; 3]], px: [f32; 3]) -> usize {
+    (0..palette.len())
+        .min_by(|&a, &b| distance(px, &palette[a]).total_cmp(&distance(px, &palette[b])))
+        .unwrap_or(0)
+}
+
+/*
+ * For every pixel of a 3-channel image, the index of its nearest palette
+ * entry, in raster order.
+ */
+pub(crate) fn palette_indices(input: &Mat, palette: &[[u8; 3]]) -> Result<Vec<usize>, Box<dyn Error>> {
+    if palette.is_empty() {
+        return Err("empty palette".into());
+    }
+    Ok(input
+        .data_bytes()?
+        .chunks_exact(3)
+        .map(|px| nearest_index(palette, [px[0] as f32, px[1] as f32, px[2] as f32]))
+        .collect())
+}
+
+/*
+ * Maps every pixel of a 3-channel image to its nearest palette entry
+ * (Euclidean, in the image's color space), optionally diffusing the error
+ * Floyd–Steinberg style.
+ */
+pub(crate) fn map_to_palette(input: &Mat, palette: &[[u8; 3]], dither: bool) -> Result<Mat, Box<dyn Error>> {
+    if palette.is_empty() {
+        return Err("empty palette".into());
+    }
+    let nearest = |px: [f32; 3]| palette[nearest_index(palette, px)];
+
+    let mut output = input.try_clone()?;
+    let width = input.cols() as usize;
+    let out = output.data_bytes_mut()?;
+    if !dither {
+        for px in out.chunks_exact_mut(3) {
+            px.copy_from_slice(&nearest([px[0] as f32, px[1] as f32, px[2] as f32]));
+        }
+        return Ok(output);
+    }
+
+    let mut work: Vec<f32> = out.iter().map(|&v| v as f32).collect();
+    let height = work.len() / 3 / width.max(1);
+    for y in 0..height {
+        for x in 0..width {
+            let i = (y * width + x) * 3;
+            let old = [work[i], work[i + 1], work[i + 2]];
+            let new = nearest(old);
+            out[i..i + 3].copy_from_slice(&new);
+            for c in 0..3 {
+                let err = old[c] - new[c] as f32;
+                let mut spread = |dx: isize, dy: usize, weight: f32| {
+                    let nx = x as isize + dx;
+                    if nx >= 0 && (nx as usize) < width && y + dy < height {
+                        work[((y + dy) * width + nx as usize) * 3 + c] += err * weight;
+                    }
+                };
+                spread(1, 0, 7.0 / 16.0);
+                spread(-1, 1, 3.0 / 16.0);
+                spread(0, 1, 5.0 / 16.0);
+                spread(1, 1, 1.0 / 16.0);
+            }
+        }
+    }
+    Ok(output)
+}
+
+/// Parses `#rrggbb` (or `rrggbb`) into BGR.
+pub fn hex_to_bgr(hex: &str) -> Result<[u8; 3], Box<dyn Error>> {
+    let digits = hex.trim().trim_start_matches('#');
+    if digits.len() != 6 || !digits.is_ascii() {
+        return Err(format!("invalid color: {}", hex).into());
+    }
+    let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16);
+    Ok([channel(4)?, channel(2)?, channel(0)?])
+}
+
+/// Formats BGR as `#rrggbb`.
+pub(crate) fn bgr_to_hex(bgr: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", bgr[2], bgr[1], bgr[0])
+}
+
+/// Rec. 601 luma of a BGR color.
+pub(crate) fn luma(bgr: [u8; 3]) -> f64 {
+    0.114 * bgr[0] as f64 + 0.587 * bgr[1] as f64 + 0.299 * bgr[2] as f64
+}
+
+fn distance(px: [f32; 3], entry: &[u8; 3]) -> f32 {
+    px.iter().zip(entry).map(|(&a, &b)| (a - b as f32).powi(2)).sum()
+}
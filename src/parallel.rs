@@ -0,0 +1,37 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+use rayon::prelude::*;
+
+use crate::batch::{convert_outcome, ConvertOutcome};
+use crate::{ConvertOptions, NftImgError};
+
+/// Converts `paths` in parallel on the current rayon pool and returns one
+/// result per path, in the order of `paths`; a failed file does not stop the
+/// others. The global pool has a thread per core; run it inside your own
+/// pool to size it:
+///
+/// ```ignore
+/// let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build()?;
+/// let results = pool.install(|| nftimg::par_convert_all(&paths, &options));
+/// ```
+///
+/// OpenCV parallelizes inside each image as well; with many small images,
+/// `opencv::core::set_num_threads(1)` avoids oversubscribing the cores.
+pub fn par_convert_all(
+    paths: &[PathBuf],
+    options: &ConvertOptions,
+) -> Vec<Result<ConvertOutcome, Box<dyn Error + Send + Sync>>> {
+    paths.par_iter().map(|path| convert_outcome(path, options).map_err(sendable)).collect()
+}
+
+/*
+ * Boxed errors are not Send; NftImgError is kept for downcasting, anything
+ * else passed on as text
+ */
+fn sendable(e: Box<dyn Error>) -> Box<dyn Error + Send + Sync> {
+    match e.downcast::<NftImgError>() {
+        Ok(e) => e,
+        Err(e) => e.to_string().into(),
+    }
+}
@@ -0,0 +1,254 @@
+//! Parameters whose valid range is part of their type: a [`BlockSize`] is
+//! always odd, a [`Radius`] always positive, [`Iterations`] never zero, so
+//! options holding them cannot reach OpenCV out of range. Construct them with `new` or `try_from`; they
+//! parse and print like the numbers they hold, as recipes and flags write
+//! them.
+
+use std::error::Error;
+use std::fmt;
+use std::num::NonZeroU32;
+use std::str::FromStr;
+
+use crate::error::NftImgError;
+
+/// Adaptive threshold neighbourhood in pixels: odd, 3 to 255.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(try_from = "u16", into = "u16"))]
+pub struct BlockSize(pub(crate) u16);
+
+impl BlockSize {
+    pub fn new(size: u16) -> Result<BlockSize, NftImgError> {
+        if !(3..=255).contains(&size) || size % 2 == 0 {
+            return Err(invalid("block size", size, "an odd size from 3 to 255"));
+        }
+        Ok(BlockSize(size))
+    }
+
+    pub fn get(self) -> u16 {
+        self.0
+    }
+}
+
+/// Filter window radius, in pixels or color levels: above 0, at most 100.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(try_from = "f64", into = "f64"))]
+pub struct Radius(pub(crate) f64);
+
+impl Radius {
+    pub const MAX: f64 = 100.0;
+
+    pub fn new(radius: f64) -> Result<Radius, NftImgError> {
+        // NaN fails the comparison too
+        if !(radius > 0.0 && radius <= Radius::MAX) {
+            return Err(invalid("radius", radius, "a radius above 0 and at most 100"));
+        }
+        Ok(Radius(radius))
+    }
+
+    pub fn get(self) -> f64 {
+        self.0
+    }
+
+    /// The radius times `factor`, kept in range; stages smoothing some
+    /// regions harder than others scale the configured radius.
+    pub fn scaled(self, factor: f64) -> Radius {
+        let radius = self.0 * factor;
+        Radius(if radius > 0.0 { radius.min(Radius::MAX) } else { f64::MIN_POSITIVE })
+    }
+}
+
+/// Passes of an iterative stage, 1 to 1000. A stage that can be skipped
+/// takes `Option<Iterations>`, written as 0 the way `dilate_iterations=0`
+/// is for the `soft` preset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(try_from = "u32", into = "u32"))]
+pub struct Iterations(pub(crate) NonZeroU32);
+
+impl Iterations {
+    pub const ONE: Iterations = Iterations(NonZeroU32::MIN);
+    pub const MAX: u32 = 1000;
+
+    pub fn new(iterations: u32) -> Result<Iterations, NftImgError> {
+        match NonZeroU32::new(iterations) {
+            Some(iterations) if iterations.get() <= Iterations::MAX => Ok(Iterations(iterations)),
+            _ => Err(invalid("iterations", iterations, "1 to 1000 passes")),
+        }
+    }
+
+    /// Passes of a stage 0 skips: none for 0, else as [`Iterations::new`].
+    pub fn optional(passes: i64) -> Result<Option<Iterations>, NftImgError> {
+        if !(0..=i64::from(Iterations::MAX)).contains(&passes) {
+            return Err(invalid("iterations", passes, "0 to 1000 passes"));
+        }
+        Ok(NonZeroU32::new(passes as u32).map(Iterations))
+    }
+
+    pub fn get(self) -> u32 {
+        self.0.get()
+    }
+}
+
+fn invalid(name: &'static str, value: impl ToString, expected: &'static str) -> NftImgError {
+    NftImgError::InvalidOption { name, value: value.to_string(), expected }
+}
+
+impl TryFrom<u16> for BlockSize {
+    type Error = NftImgError;
+
+    fn try_from(size: u16) -> Result<Self, Self::Error> {
+        BlockSize::new(size)
+    }
+}
+
+impl TryFrom<i32> for BlockSize {
+    type Error = NftImgError;
+
+    fn try_from(size: i32) -> Result<Self, Self::Error> {
+        let size = u16::try_from(size).map_err(|_| invalid("block size", size, "an odd size from 3 to 255"))?;
+        BlockSize::new(size)
+    }
+}
+
+impl From<BlockSize> for u16 {
+    fn from(size: BlockSize) -> u16 {
+        size.0
+    }
+}
+
+impl From<BlockSize> for i32 {
+    fn from(size: BlockSize) -> i32 {
+        size.0.into()
+    }
+}
+
+impl TryFrom<f64> for Radius {
+    type Error = NftImgError;
+
+    fn try_from(radius: f64) -> Result<Self, Self::Error> {
+        Radius::new(radius)
+    }
+}
+
+impl From<Radius> for f64 {
+    fn from(radius: Radius) -> f64 {
+        radius.0
+    }
+}
+
+impl TryFrom<u32> for Iterations {
+    type Error = NftImgError;
+
+    fn try_from(iterations: u32) -> Result<Self, Self::Error> {
+        Iterations::new(iterations)
+    }
+}
+
+impl TryFrom<i32> for Iterations {
+    type Error = NftImgError;
+
+    fn try_from(iterations: i32) -> Result<Self, Self::Error> {
+        let iterations = u32::try_from(iterations).map_err(|_| invalid("iterations", iterations, "1 to 1000 passes"))?;
+        Iterations::new(iterations)
+    }
+}
+
+impl From<Iterations> for u32 {
+    fn from(iterations: Iterations) -> u32 {
+        iterations.get()
+    }
+}
+
+impl From<Iterations> for i32 {
+    /// Lossless, iterations stop at 1000.
+    fn from(iterations: Iterations) -> i32 {
+        iterations.get() as i32
+    }
+}
+
+impl fmt::Display for BlockSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Display for Radius {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Display for Iterations {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for BlockSize {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(BlockSize::new(s.trim().parse()?)?)
+    }
+}
+
+impl FromStr for Radius {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Radius::new(s.trim().parse()?)?)
+    }
+}
+
+impl FromStr for Iterations {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Iterations::new(s.trim().parse()?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_size_is_odd_and_bounded() {
+        assert_eq!(BlockSize::new(3).unwrap().get(), 3);
+        assert_eq!(BlockSize::new(255).unwrap().get(), 255);
+        for size in [0, 1, 2, 4, 254, 256, 257] {
+            assert!(BlockSize::new(size).is_err(), "block size {}", size);
+        }
+        assert!(BlockSize::try_from(-3i32).is_err());
+        assert!(BlockSize::try_from(70_000i32).is_err());
+        assert!("12".parse::<BlockSize>().is_err());
+        assert_eq!("11".parse::<BlockSize>().unwrap(), BlockSize::new(11).unwrap());
+    }
+
+    #[test]
+    fn radius_is_positive_and_bounded() {
+        assert_eq!(Radius::new(Radius::MAX).unwrap().get(), Radius::MAX);
+        assert!(Radius::new(0.5).is_ok());
+        for radius in [0.0, -1.0, 100.5, f64::NAN, f64::INFINITY] {
+            assert!(Radius::new(radius).is_err(), "radius {}", radius);
+        }
+        assert!("0".parse::<Radius>().is_err());
+        // scaling stays in range rather than failing
+        assert_eq!(Radius::new(60.0).unwrap().scaled(2.0).get(), Radius::MAX);
+        assert!(Radius::new(10.0).unwrap().scaled(0.0).get() > 0.0);
+    }
+
+    #[test]
+    fn iterations_are_nonzero_and_bounded() {
+        assert_eq!(Iterations::new(1).unwrap(), Iterations::ONE);
+        assert_eq!(Iterations::new(Iterations::MAX).unwrap().get(), Iterations::MAX);
+        for iterations in [0, 1001, u32::MAX] {
+            assert!(Iterations::new(iterations).is_err(), "iterations {}", iterations);
+        }
+        assert!(Iterations::try_from(-1i32).is_err());
+        assert!("0".parse::<Iterations>().is_err());
+        assert_eq!(Iterations::optional(0).unwrap(), None);
+        assert_eq!(Iterations::optional(3).unwrap(), Some(Iterations::new(3).unwrap()));
+        assert!(Iterations::optional(-1).is_err());
+        assert!(Iterations::optional(1001).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use opencv::prelude::*;
+
+use crate::metrics;
+use crate::{ConvertOptions, Converter};
+
+/// Called as a stage ends, see [`Pipeline::on_stage`].
+type Hook = Arc<dyn Fn(&str, &Mat, Duration) + Send + Sync>;
+
+/// Conversions with fixed options and callbacks on their stages, e.g. to
+/// log timings or keep the intermediates while tuning a preset:
+/// `Pipeline::new(options).on_stage(|name, image, elapsed| ...)`. They run
+/// through a [`Converter`], keeping the models they load.
+#[derive(Clone)]
+pub struct Pipeline {
+    converter: Converter,
+    hooks: Vec<Hook>,
+}
+
+impl Pipeline {
+    pub fn new(options: ConvertOptions) -> Pipeline {
+        Pipeline::with_converter(Converter::new(options))
+    }
+
+    /// A pipeline converting through `converter`, with its options and
+    /// sharing what it loaded.
+    pub fn with_converter(converter: Converter) -> Pipeline {
+        Pipeline { converter, hooks: Vec::new() }
+    }
+
+    pub fn options(&self) -> &ConvertOptions {
+        self.converter.options()
+    }
+
+    /// Adds `hook`, called on the converting thread as each stage ends with
+    /// its name, the image it produced and how long it took. The stages, in
+    /// order:
+    ///
+    /// * `decode`: the oriented sRGB input
+    /// * `prepare`: the input after white balance, pre-LUT, denoising and
+    ///   custom input stages
+    /// * `analyze`: the input in Lab, equalized if CLAHE is on
+    /// * `base`: the flattened, shaded base
+    /// * `edges`: the edge mask, black outlines on white
+    /// * `merge`: base and outlines merged
+    /// * `cartoonize`: the same image, timed over `analyze` to `merge`; or
+    ///   `style` when a network stylizes instead, replacing those five
+    /// * `upscale`: the super-resolved image, if enabled
+    /// * `finish`: the output after grading and finishing
+    /// * `encode`: the output again, once encoded (not for
+    ///   [`Pipeline::convert_image`])
+    pub fn on_stage(mut self, hook: impl Fn(&str, &Mat, Duration) + Send + Sync + 'static) -> Pipeline {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Converts the file like [`crate::convert_with_options`] and returns
+    /// the path of the output.
+    pub fn convert_file(&self, path: impl AsRef<Path>) -> Result<PathBuf, Box<dyn Error>> {
+        self.observed(|| self.converter.convert_file(path))
+    }
+
+    /// See [`crate::convert_buffer`].
+    pub fn convert_buffer(&self, bytes: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.observed(|| self.converter.convert_buffer(bytes))
+    }
+
+    /// See [`crate::convert_image`].
+    pub fn convert_image(&self, image: &Mat) -> Result<Mat, Box<dyn Error>> {
+        self.observed(|| self.converter.convert_image(image))
+    }
+
+    fn observed<T>(&self, run: impl FnOnce() -> T) -> T {
+        if self.hooks.is_empty() {
+            return run();
+        }
+        let hooks = self.hooks.clone();
+        let listener = move |stage: &'static str, image: &Mat, elapsed: Duration| {
+            for hook in &hooks {
+                hook(stage, image, elapsed);
+            }
+        };
+        metrics::with_stage_listener(listener, run)
+    }
+}
@@ -0,0 +1,72 @@
+use std::error::Error;
+
+use opencv::core::{Scalar, Size};
+use opencv::imgproc::{resize, threshold, INTER_AREA, INTER_NEAREST, THRESH_BINARY};
+use opencv::prelude::*;
+
+use crate::color_profile::resize_linear;
+use crate::palette::{kmeans_palette, map_to_palette};
+
+/// Pixel-art mode: the image is rebuilt from a coarse grid of flat cells in
+/// a small palette.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct PixelArtOptions {
+    /// Cells along the longer side; the other side keeps the aspect ratio.
+    pub cells: i32,
+    pub colors: usize,
+    /// Keep the outlines, drawn as whole cells.
+    pub outline: bool,
+}
+
+impl Default for PixelArtOptions {
+    fn default() -> Self {
+        PixelArtOptions { cells: 64, colors: 16, outline: true }
+    }
+}
+
+/*
+ * BGR base + edge mask -> the same, downsampled to the cell grid, quantized
+ * and upsampled back with nearest-neighbor so every cell stays sharp.
+ */
+pub(crate) fn pixelate(
+    base: &Mat,
+    edge: &Mat,
+    options: &PixelArtOptions,
+    linear: bool,
+) -> Result<(Mat, Mat), Box<dyn Error>> {
+    if options.cells < 1 {
+        return Err("pixel art needs at least one cell".into());
+    }
+    let (width, height) = (base.cols(), base.rows());
+    let longer = width.max(height);
+    let grid = Size::new(
+        (options.cells * width / longer).max(1),
+        (options.cells * height / longer).max(1),
+    );
+
+    let cells = if linear {
+        resize_linear(base, grid, INTER_AREA)?
+    } else {
+        let mut cells = Mat::default();
+        resize(base, &mut cells, grid, 0.0, 0.0, INTER_AREA)?;
+        cells
+    };
+    let colors = kmeans_palette(&cells, options.colors)?;
+    let cells = map_to_palette(&cells, &colors, false)?;
+    let mut output = Mat::default();
+    resize(&cells, &mut output, base.size()?, 0.0, 0.0, INTER_NEAREST)?;
+
+    if !options.outline {
+        let no_edge = Mat::new_size_with_default(edge.size()?, edge.typ(), Scalar::all(255.0))?;
+        return Ok((output, no_edge));
+    }
+    // a cell is outline when most of the pixels it covers are
+    let mut coverage = Mat::default();
+    resize(edge, &mut coverage, grid, 0.0, 0.0, INTER_AREA)?;
+    let mut edge_cells = Mat::default();
+    threshold(&coverage, &mut edge_cells, 127.0, 255.0, THRESH_BINARY)?;
+    let mut edge_output = Mat::default();
+    resize(&edge_cells, &mut edge_output, edge.size()?, 0.0, 0.0, INTER_NEAREST)?;
+    Ok((output, edge_output))
+}
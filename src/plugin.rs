@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, PoisonError, RwLock};
+
+use opencv::prelude::*;
+
+/// Stages registered with [`register_stage`], by name.
+static STAGES: RwLock<BTreeMap<String, Arc<dyn Stage>>> = RwLock::new(BTreeMap::new());
+
+/// A processing step supplied by the caller, e.g. a proprietary texture
+/// pass. Closures `Fn(&Mat) -> Result<Mat, Box<dyn Error>>` are stages.
+///
+/// The image is 8-bit BGR, except at [`StagePoint::Edges`] where it is the
+/// single-channel edge mask: white background, black outlines. Stages at
+/// `Base` and `Edges` must keep the size, as the branches are merged
+/// pixel by pixel.
+pub trait Stage: Send + Sync {
+    fn apply(&self, image: &Mat) -> Result<Mat, Box<dyn Error>>;
+}
+
+impl<F> Stage for F
+where
+    F: Fn(&Mat) -> Result<Mat, Box<dyn Error>> + Send + Sync,
+{
+    fn apply(&self, image: &Mat) -> Result<Mat, Box<dyn Error>> {
+        self(image)
+    }
+}
+
+/// Makes `stage` available to [`crate::ConvertOptions::stages`] as `name`,
+/// replacing any stage registered under that name before.
+pub fn register_stage(name: &str, stage: impl Stage + 'static) -> Result<(), Box<dyn Error>> {
+    if name.is_empty() || name.contains([',', ':', '\n', '\r']) {
+        return Err(format!("invalid stage name: {:?}", name).into());
+    }
+    STAGES.write().unwrap_or_else(PoisonError::into_inner).insert(name.to_string(), Arc::new(stage));
+    Ok(())
+}
+
+/// Where in the pipeline a custom stage runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "kebab-case"))]
+pub enum StagePoint {
+    /// On the input, after white balance, pre-LUT and denoising.
+    Input,
+    /// On the flattened base, after its shading and before the merge.
+    Base,
+    /// On the edge mask, after its clean-up and before the merge.
+    Edges,
+    /// On the output, after the finishing stages.
+    Output,
+}
+
+impl fmt::Display for StagePoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StagePoint::Input => write!(f, "input"),
+            StagePoint::Base => write!(f, "base"),
+            StagePoint::Edges => write!(f, "edges"),
+            StagePoint::Output => write!(f, "output"),
+        }
+    }
+}
+
+impl FromStr for StagePoint {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "input" => Ok(StagePoint::Input),
+            "base" => Ok(StagePoint::Base),
+            "edges" => Ok(StagePoint::Edges),
+            "output" => Ok(StagePoint::Output),
+            _ => Err(format!("unknown stage point: {}", s).into()),
+        }
+    }
+}
+
+/// One entry of [`crate::ConvertOptions::stages`], written `point:name`,
+/// e.g. `output:canvas`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CustomStage {
+    pub point: StagePoint,
+    /// Name the stage was registered under.
+    pub name: String,
+}
+
+impl fmt::Display for CustomStage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.point, self.name)
+    }
+}
+
+impl FromStr for CustomStage {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (point, name) = s.split_once(':').ok_or_else(|| format!("expected point:name, got {}", s))?;
+        Ok(CustomStage { point: point.parse()?, name: name.to_string() })
+    }
+}
+
+/*
+ * name -> the stage registered under it
+ */
+pub(crate) fn registered(name: &str) -> Result<Arc<dyn Stage>, Box<dyn Error>> {
+    let stages = STAGES.read().unwrap_or_else(PoisonError::into_inner);
+    Ok(stages.get(name).cloned().ok_or_else(|| format!("no stage registered as {}", name))?)
+}
+
+/*
+ * image -> image after the custom stages at `point`, in order
+ */
+pub(crate) fn apply(stages: &[CustomStage], point: StagePoint, mut image: Mat) -> Result<Mat, Box<dyn Error>> {
+    for custom in stages.iter().filter(|custom| custom.point == point) {
+        let output = registered(&custom.name)?.apply(&image)?;
+        if matches!(point, StagePoint::Base | StagePoint::Edges) && output.size()? != image.size()? {
+            return Err(format!("stage {} changed the image size", custom.name).into());
+        }
+        image = output;
+    }
+    Ok(image)
+}
@@ -0,0 +1,90 @@
+use std::error::Error;
+
+use opencv::core::{Rect, Scalar, Size, CV_8UC3};
+use opencv::imgproc::{resize, INTER_AREA};
+use opencv::prelude::*;
+
+use crate::{combine_base_and_edge, ConvertOptions};
+use crate::palette::{hex_to_bgr, kmeans_palette, luma, palette_indices};
+
+/// Panel inks from darkest to lightest tone, cycled over the panels.
+const POP_PALETTES: &[[&str; 4]] = &[
+    ["#2b0f54", "#ab1f65", "#ff4f69", "#fff7f8"],
+    ["#0b3d91", "#00a6d6", "#7fff00", "#ffff66"],
+    ["#1a1a1a", "#ff6600", "#ffcc00", "#ffffcc"],
+    ["#004d40", "#e91e63", "#ffeb3b", "#ffffff"],
+    ["#3d0066", "#00ccff", "#ff00cc", "#ffff00"],
+    ["#660000", "#ff0033", "#33ccff", "#ccff00"],
+];
+
+/// Pop-art mode: a grid of the same image, each panel with its own
+/// high-contrast palette.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct PopArtOptions {
+    pub rows: i32,
+    pub cols: i32,
+    /// Tones the base is quantized to before remapping.
+    pub levels: usize,
+}
+
+impl Default for PopArtOptions {
+    fn default() -> Self {
+        PopArtOptions { rows: 2, cols: 2, levels: 4 }
+    }
+}
+
+/*
+ * BGR base + edge mask -> canvas of rows x cols remapped panels, the size of
+ * the input.
+ */
+pub(crate) fn compose(
+    base: &Mat,
+    edge: &Mat,
+    convert_options: &ConvertOptions,
+    options: &PopArtOptions,
+) -> Result<Mat, Box<dyn Error>> {
+    if options.rows < 1 || options.cols < 1 {
+        return Err("pop-art grid needs at least one row and column".into());
+    }
+    let tones = kmeans_palette(base, options.levels)?;
+    let mut order: Vec<usize> = (0..tones.len()).collect();
+    order.sort_by(|&a, &b| luma(tones[a]).total_cmp(&luma(tones[b])));
+    let mut rank = vec![0; tones.len()];
+    for (r, &i) in order.iter().enumerate() {
+        rank[i] = r;
+    }
+    let indices = palette_indices(base, &tones)?;
+
+    let panel_size = Size::new((base.cols() / options.cols).max(1), (base.rows() / options.rows).max(1));
+    let mut canvas = Mat::new_rows_cols_with_default(
+        panel_size.height * options.rows,
+        panel_size.width * options.cols,
+        CV_8UC3,
+        Scalar::all(0.0),
+    )?;
+    for panel_index in 0..options.rows * options.cols {
+        let inks = POP_PALETTES[panel_index as usize % POP_PALETTES.len()]
+            .iter()
+            .map(|hex| hex_to_bgr(hex))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut panel = base.try_clone()?;
+        for (px, &i) in panel.data_bytes_mut()?.chunks_exact_mut(3).zip(&indices) {
+            // snap each tone to an ink, no blending: that is the pop-art look
+            let t = if tones.len() > 1 { rank[i] as f64 / (tones.len() - 1) as f64 } else { 0.0 };
+            px.copy_from_slice(&inks[(t * (inks.len() - 1) as f64).round() as usize]);
+        }
+
+        let merged = combine_base_and_edge(&panel, edge, convert_options)?;
+        let mut small = Mat::default();
+        resize(&merged, &mut small, panel_size, 0.0, 0.0, INTER_AREA)?;
+        let area = Rect::new(
+            panel_index % options.cols * panel_size.width,
+            panel_index / options.cols * panel_size.height,
+            panel_size.width,
+            panel_size.height,
+        );
+        small.copy_to(&mut Mat::roi_mut(&mut canvas, area)?)?;
+    }
+    Ok(canvas)
+}
@@ -0,0 +1,102 @@
+use std::error::Error;
+
+use opencv::core::{in_range, Point, Rect, Scalar, Size, BORDER_CONSTANT};
+use opencv::imgproc::{
+    bounding_rect, cvt_color, get_structuring_element, morphology_ex, morphology_default_border_value,
+    COLOR_BGR2YCrCb, MORPH_CLOSE, MORPH_ELLIPSE, MORPH_OPEN,
+};
+use opencv::prelude::*;
+
+use crate::{grayscaled_to_edged, segment_colors, ConvertOptions};
+
+/// Portrait mode: skin is smoothed harder and loses its texture lines, while
+/// hair and clothing keep the regular parameters.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct PortraitOptions {
+    /// Factor applied to the mean-shift radii on skin.
+    pub smoothing: f64,
+    /// Added to the adaptive threshold constant on skin; higher drops more lines.
+    pub edge_c: f64,
+}
+
+impl Default for PortraitOptions {
+    fn default() -> Self {
+        PortraitOptions { smoothing: 1.5, edge_c: 6.0 }
+    }
+}
+
+/*
+ * BGR image -> skin mask, using the usual YCrCb skin box and a small
+ * open/close to drop isolated pixels and fill pores.
+ */
+pub(crate) fn skin_mask(input: &Mat) -> Result<Mat, Box<dyn Error>> {
+    let mut ycrcb = Mat::default();
+    cvt_color(input, &mut ycrcb, COLOR_BGR2YCrCb, 0)?;
+    let mut raw = Mat::default();
+    in_range(
+        &ycrcb,
+        &Scalar::new(0.0, 133.0, 77.0, 0.0),
+        &Scalar::new(255.0, 173.0, 127.0, 0.0),
+        &mut raw,
+    )?;
+
+    let kernel = get_structuring_element(MORPH_ELLIPSE, Size::new(5, 5), Point::new(-1, -1))?;
+    let border_value = morphology_default_border_value()?;
+    let mut opened = Mat::default();
+    morphology_ex(&raw, &mut opened, MORPH_OPEN, &kernel, Point::new(-1, -1), 1, BORDER_CONSTANT, border_value)?;
+    let mut mask = Mat::default();
+    morphology_ex(&opened, &mut mask, MORPH_CLOSE, &kernel, Point::new(-1, -1), 1, BORDER_CONSTANT, border_value)?;
+    Ok(mask)
+}
+
+/*
+ * Re-segments the skin of the Lab image with larger radii and pastes it into
+ * the segmented base.
+ */
+pub(crate) fn refine_base(
+    base: &mut Mat,
+    lab: &Mat,
+    skin: &Mat,
+    options: &ConvertOptions,
+    portrait: &PortraitOptions,
+) -> Result<(), Box<dyn Error>> {
+    let area = bounding_rect(skin)?;
+    if area.area() == 0 {
+        return Ok(());
+    }
+    let strong = ConvertOptions {
+        spatial_radius: options.spatial_radius.scaled(portrait.smoothing),
+        color_radius: options.color_radius.scaled(portrait.smoothing),
+        ..options.clone()
+    };
+    let smooth = segment_colors(&Mat::roi(lab, area)?.try_clone()?, &strong)?;
+    paste(base, &smooth, skin, area)
+}
+
+/*
+ * Re-thresholds the skin of the grayscaled image with a higher constant, so
+ * skin texture does not turn into outlines.
+ */
+pub(crate) fn refine_edge(
+    edge: &mut Mat,
+    gray: &Mat,
+    skin: &Mat,
+    options: &ConvertOptions,
+    portrait: &PortraitOptions,
+) -> Result<(), Box<dyn Error>> {
+    let area = bounding_rect(skin)?;
+    if area.area() == 0 {
+        return Ok(());
+    }
+    let clean = ConvertOptions { threshold_c: options.threshold_c + portrait.edge_c, ..options.clone() };
+    let lines = grayscaled_to_edged(&Mat::roi(gray, area)?.try_clone()?, &clean)?;
+    paste(edge, &lines, skin, area)
+}
+
+pub(crate) fn paste(target: &mut Mat, patch: &Mat, mask: &Mat, area: Rect) -> Result<(), Box<dyn Error>> {
+    let mask = Mat::roi(mask, area)?;
+    let mut target = Mat::roi_mut(target, area)?;
+    patch.copy_to_masked(&mut target, &mask)?;
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use std::error::Error;
+
+use toml::{Table, Value};
+
+use crate::ConvertOptions;
+
+impl ConvertOptions {
+    /// Serializes every option as a TOML profile, the parameters exactly as
+    /// a run resolved them from flags, recipes and config files, so an
+    /// output can be reproduced later with [`ConvertOptions::from_toml`].
+    /// Unlike [`ConvertOptions::recipe`], output naming, metadata and limits
+    /// are included, after a `version` key naming the crate that wrote it.
+    pub fn to_toml(&self) -> Result<String, Box<dyn Error>> {
+        let mut profile = Table::new();
+        profile.insert("version".to_string(), Value::String(env!("CARGO_PKG_VERSION").to_string()));
+        let Value::Table(options) = Value::try_from(self)? else {
+            return Err("options did not serialize to a TOML table".into());
+        };
+        profile.extend(options);
+        // TOML has no null, and a missing key would read back as the default
+        // limit; 0 means no limit, as for --max-pixels
+        if self.max_pixels.is_none() {
+            profile.insert("max_pixels".to_string(), Value::Integer(0));
+        }
+        Ok(toml::to_string_pretty(&profile)?)
+    }
+
+    /// Parses a profile written by [`ConvertOptions::to_toml`]. Missing keys
+    /// keep their defaults, and the result is checked like
+    /// [`ConvertOptions::validate`].
+    pub fn from_toml(profile: &str) -> Result<ConvertOptions, Box<dyn Error>> {
+        let mut profile: Table = profile.parse()?;
+        profile.remove("version");
+        let no_limit = matches!(profile.get("max_pixels"), Some(Value::Integer(0)));
+        if no_limit {
+            profile.remove("max_pixels");
+        }
+        let mut options: ConvertOptions = Value::Table(profile).try_into()?;
+        if no_limit {
+            options.max_pixels = None;
+        }
+        options.validate()?;
+        Ok(options)
+    }
+}
@@ -0,0 +1,91 @@
+use std::cell::RefCell;
+use std::error::Error;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::{convert_file, ConvertOptions};
+
+/// How far a conversion is, reported to the callback of
+/// [`convert_with_progress`] on the converting thread.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Progress {
+    /// A stage began. Stages are named as for
+    /// [`Pipeline::on_stage`](crate::Pipeline::on_stage); `cartoonize`
+    /// encloses `analyze` to `merge`, which start and finish inside it.
+    Started { stage: &'static str },
+    /// Part of an iterative step of the running stage is done: `diffusion`
+    /// for the anisotropic diffusion iterations of `edges`, `edge_scales`
+    /// for the pyramid levels outlines are detected on. `fraction` grows
+    /// from above 0 to 1.
+    Advanced { step: &'static str, fraction: f64 },
+    /// A stage ended after `elapsed`.
+    Finished { stage: &'static str, elapsed: Duration },
+    /// The input had more pixels than
+    /// [`ConvertOptions::max_pixels`](crate::ConvertOptions::max_pixels) and
+    /// was downscaled to `width` x `height` on decoding, see
+    /// [`ConvertOptions::downscale_oversized`](crate::ConvertOptions::downscale_oversized).
+    Downscaled { width: i32, height: i32 },
+}
+
+type Callback = Box<dyn FnMut(Progress)>;
+
+thread_local! {
+    /// Innermost last; all of them are told.
+    static CALLBACKS: RefCell<Vec<Callback>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Converts the file like [`crate::convert_with_options`], calling
+/// `progress` as stages start and finish and as iterative steps advance,
+/// e.g. to drive a progress bar. The callback runs on the converting thread,
+/// between stages; send the events on from it rather than blocking it:
+///
+/// ```ignore
+/// let (tx, rx) = std::sync::mpsc::channel();
+/// std::thread::spawn(move || nftimg::convert_with_progress("photo.jpg", &options, move |p| drop(tx.send(p))));
+/// for progress in rx { /* update the bar */ }
+/// ```
+pub fn convert_with_progress(
+    file_path: impl AsRef<Path>,
+    options: &ConvertOptions,
+    progress: impl FnMut(Progress) + 'static,
+) -> Result<(), Box<dyn Error>> {
+    with_progress(progress, || convert_file(file_path.as_ref(), options))?;
+    Ok(())
+}
+
+/*
+ * Runs `run` on this thread with `callback` told the progress of its stages;
+ * callbacks installed around it are still told
+ */
+pub(crate) fn with_progress<T>(callback: impl FnMut(Progress) + 'static, run: impl FnOnce() -> T) -> T {
+    // restored on unwinding too, blocking threads are reused
+    struct Restore;
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            CALLBACKS.with_borrow_mut(Vec::pop);
+        }
+    }
+    CALLBACKS.with_borrow_mut(|callbacks| callbacks.push(Box::new(callback)));
+    let _restore = Restore;
+    run()
+}
+
+/*
+ * Whether anything on this thread listens, so stages can skip splitting
+ * their work into reportable steps
+ */
+pub(crate) fn observed() -> bool {
+    CALLBACKS.with_borrow(|callbacks| !callbacks.is_empty())
+}
+
+pub(crate) fn report(progress: Progress) {
+    CALLBACKS.with_borrow_mut(|callbacks| {
+        for callback in callbacks.iter_mut().rev() {
+            callback(progress);
+        }
+    });
+}
+
+pub(crate) fn advance(step: &'static str, done: u32, total: u32) {
+    report(Progress::Advanced { step, fraction: f64::from(done) / f64::from(total) });
+}
@@ -0,0 +1,217 @@
+//! Python module `nftimg`, behind the `python` feature; build it with
+//! `maturin develop --release` (see `pyproject.toml`).
+//!
+//! ```python
+//! import nftimg
+//! nftimg.convert("photo.jpg", nftimg.Options(spatial_radius=15))
+//! cartoon = nftimg.convert_array(cv2.imread("photo.jpg"))
+//! ```
+
+use std::error::Error;
+use std::path::PathBuf;
+
+use numpy::{PyArray1, PyArray3, PyArrayMethods, PyReadonlyArray3, PyUntypedArrayMethods};
+use opencv::core::{Scalar, CV_8UC3};
+use opencv::imgproc::{cvt_color, COLOR_BGR2RGB, COLOR_RGB2BGR};
+use opencv::prelude::*;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::{convert_buffer, convert_file, convert_image, ConvertOptions, Iterations, NftImgError};
+
+/// The most used parameters, plus an optional recipe for everything else;
+/// the fields are readable and writable from Python.
+#[pyclass(name = "Options", module = "nftimg", get_all, set_all)]
+#[derive(Debug, Clone)]
+pub struct PyOptions {
+    spatial_radius: f64,
+    color_radius: f64,
+    max_pyramid_level: i32,
+    threshold_block_size: i32,
+    threshold_c: f64,
+    dilate_kernel_size: i32,
+    dilate_iterations: i32,
+    /// `key=value` lines as written by `ConvertOptions::recipe`, applied
+    /// instead of the fields above.
+    recipe: Option<String>,
+}
+
+#[pymethods]
+impl PyOptions {
+    #[new]
+    #[pyo3(signature = (
+        *,
+        spatial_radius = None,
+        color_radius = None,
+        max_pyramid_level = None,
+        threshold_block_size = None,
+        threshold_c = None,
+        dilate_kernel_size = None,
+        dilate_iterations = None,
+        recipe = None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        spatial_radius: Option<f64>,
+        color_radius: Option<f64>,
+        max_pyramid_level: Option<i32>,
+        threshold_block_size: Option<i32>,
+        threshold_c: Option<f64>,
+        dilate_kernel_size: Option<i32>,
+        dilate_iterations: Option<i32>,
+        recipe: Option<String>,
+    ) -> Self {
+        let defaults = ConvertOptions::default();
+        PyOptions {
+            spatial_radius: spatial_radius.unwrap_or(defaults.spatial_radius.into()),
+            color_radius: color_radius.unwrap_or(defaults.color_radius.into()),
+            max_pyramid_level: max_pyramid_level.unwrap_or(defaults.max_pyramid_level),
+            threshold_block_size: threshold_block_size.unwrap_or(defaults.threshold_block_size.into()),
+            threshold_c: threshold_c.unwrap_or(defaults.threshold_c),
+            dilate_kernel_size: dilate_kernel_size.unwrap_or(defaults.dilate_kernel_size),
+            dilate_iterations: dilate_iterations.unwrap_or(defaults.dilate_iterations.map_or(0, i32::from)),
+            recipe,
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Options(spatial_radius={}, color_radius={}, max_pyramid_level={}, threshold_block_size={}, \
+             threshold_c={}, dilate_kernel_size={}, dilate_iterations={}, recipe={})",
+            self.spatial_radius,
+            self.color_radius,
+            self.max_pyramid_level,
+            self.threshold_block_size,
+            self.threshold_c,
+            self.dilate_kernel_size,
+            self.dilate_iterations,
+            self.recipe.as_ref().map_or("None".to_string(), |r| format!("{:?}", r))
+        )
+    }
+}
+
+/*
+ * Python options -> ConvertOptions, the defaults for None
+ */
+fn convert_options(options: Option<&PyOptions>) -> PyResult<ConvertOptions> {
+    let Some(options) = options else {
+        return Ok(ConvertOptions::default());
+    };
+    if let Some(recipe) = &options.recipe {
+        return ConvertOptions::from_recipe(recipe).map_err(to_py_err);
+    }
+    // ValueError naming the field rather than an OpenCV RuntimeError
+    let invalid = |e: NftImgError| to_py_err(e.into());
+    let options = ConvertOptions {
+        spatial_radius: options.spatial_radius.try_into().map_err(invalid)?,
+        color_radius: options.color_radius.try_into().map_err(invalid)?,
+        max_pyramid_level: options.max_pyramid_level,
+        threshold_block_size: options.threshold_block_size.try_into().map_err(invalid)?,
+        threshold_c: options.threshold_c,
+        dilate_kernel_size: options.dilate_kernel_size,
+        dilate_iterations: Iterations::optional(options.dilate_iterations.into()).map_err(invalid)?,
+        ..ConvertOptions::default()
+    };
+    options.validate().map_err(invalid)?;
+    Ok(options)
+}
+
+/*
+ * Bad inputs raise ValueError, everything else RuntimeError
+ */
+fn to_py_err(e: Box<dyn Error>) -> PyErr {
+    match e.downcast_ref::<NftImgError>() {
+        Some(
+            NftImgError::DecodeFailed { .. }
+            | NftImgError::UnsupportedFormat { .. }
+            | NftImgError::UnexpectedChannels { .. }
+            | NftImgError::InvalidOption { .. },
+        ) => PyValueError::new_err(e.to_string()),
+        _ => PyRuntimeError::new_err(e.to_string()),
+    }
+}
+
+/// Converts the image file at `path` like the command line tool and
+/// returns the output path.
+#[pyfunction]
+#[pyo3(signature = (path, options = None))]
+fn convert(py: Python<'_>, path: PathBuf, options: Option<&PyOptions>) -> PyResult<PathBuf> {
+    let options = convert_options(options)?;
+    py.allow_threads(|| convert_file(&path, &options).map(|converted| converted.output_path).map_err(to_py_err))
+}
+
+/// Converts an encoded image and returns the output as bytes, in the
+/// format of the input.
+#[pyfunction]
+#[pyo3(signature = (data, options = None))]
+fn convert_bytes<'py>(py: Python<'py>, data: &[u8], options: Option<&PyOptions>) -> PyResult<Bound<'py, PyBytes>> {
+    let options = convert_options(options)?;
+    let encoded = py.allow_threads(|| convert_buffer(data, &options).map_err(to_py_err))?;
+    Ok(PyBytes::new_bound(py, &encoded))
+}
+
+/// Converts a `height x width x 3` uint8 array and returns a new one. The
+/// channels are BGR as in OpenCV; pass `rgb=True` for arrays from PIL,
+/// imageio or matplotlib.
+#[pyfunction]
+#[pyo3(signature = (image, options = None, *, rgb = false))]
+fn convert_array<'py>(
+    py: Python<'py>,
+    image: PyReadonlyArray3<'py, u8>,
+    options: Option<&PyOptions>,
+    rgb: bool,
+) -> PyResult<Bound<'py, PyArray3<u8>>> {
+    let options = convert_options(options)?;
+    let (height, width) = match image.shape() {
+        [height, width, 3] => (*height, *width),
+        shape => return Err(PyValueError::new_err(format!("expected a height x width x 3 array, got shape {:?}", shape))),
+    };
+    // iterating in logical order also copies strided and transposed views
+    let pixels: Vec<u8> = image.as_array().iter().copied().collect();
+    let (pixels, [rows, cols, channels]) =
+        py.allow_threads(|| convert_pixels(&pixels, height, width, rgb, &options).map_err(to_py_err))?;
+    PyArray1::from_vec_bound(py, pixels).reshape([rows, cols, channels])
+}
+
+/*
+ * Packed pixels -> packed output pixels and their shape
+ */
+fn convert_pixels(
+    pixels: &[u8],
+    height: usize,
+    width: usize,
+    rgb: bool,
+    options: &ConvertOptions,
+) -> Result<(Vec<u8>, [usize; 3]), Box<dyn Error>> {
+    let mut input = Mat::new_rows_cols_with_default(height as i32, width as i32, CV_8UC3, Scalar::all(0.0))?;
+    input.data_bytes_mut()?.copy_from_slice(pixels);
+    if rgb {
+        input = swap_red_blue(&input, COLOR_RGB2BGR)?;
+    }
+    let mut output = convert_image(&input, options)?;
+    if rgb && output.channels() == 3 {
+        output = swap_red_blue(&output, COLOR_BGR2RGB)?;
+    }
+    if !output.is_continuous() {
+        output = output.try_clone()?;
+    }
+    let shape = [output.rows() as usize, output.cols() as usize, output.channels() as usize];
+    Ok((output.data_bytes()?.to_vec(), shape))
+}
+
+fn swap_red_blue(input: &Mat, code: i32) -> Result<Mat, Box<dyn Error>> {
+    let mut output = Mat::default();
+    cvt_color(input, &mut output, code, 0)?;
+    Ok(output)
+}
+
+#[pymodule]
+fn nftimg(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    m.add_class::<PyOptions>()?;
+    m.add_function(wrap_pyfunction!(convert, m)?)?;
+    m.add_function(wrap_pyfunction!(convert_bytes, m)?)?;
+    m.add_function(wrap_pyfunction!(convert_array, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,52 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use opencv::prelude::*;
+use opencv::quality::QualityBRISQUE;
+
+/// File names of the BRISQUE model trained on the LIVE database, as shipped
+/// in opencv_contrib's `modules/quality/samples`.
+const MODEL_NAME: &str = "brisque_model_live.yml";
+const RANGE_NAME: &str = "brisque_range_live.yml";
+
+/// No-reference quality scoring of outputs with BRISQUE, for folder runs:
+/// each output is scored, and those scoring worse than `max_score` are
+/// flagged for manual review. Scores run from about 0 (pristine) to 100
+/// (heavily distorted); flat cartoon outputs typically score 20 to 50.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QualityOptions {
+    /// Trained BRISQUE model, `brisque_model_live.yml`.
+    pub model: PathBuf,
+    /// Feature ranges of the model, `brisque_range_live.yml`.
+    pub range: PathBuf,
+    /// Outputs scoring above this are flagged; `None` only scores them.
+    pub max_score: Option<f64>,
+}
+
+impl QualityOptions {
+    /// Takes the model and range files from `dir`, under the names
+    /// opencv_contrib ships them with.
+    pub fn from_dir(dir: &Path) -> QualityOptions {
+        QualityOptions { model: dir.join(MODEL_NAME), range: dir.join(RANGE_NAME), max_score: None }
+    }
+
+    /// Whether `score` is worse than the threshold.
+    pub fn flags(&self, score: f64) -> bool {
+        self.max_score.is_some_and(|max| score > max)
+    }
+}
+
+/// BRISQUE score of a BGR or grayscale image, lower is better, see
+/// [`QualityOptions`].
+pub fn score_quality(image: &Mat, options: &QualityOptions) -> Result<f64, Box<dyn Error>> {
+    let model = options.model.to_str().ok_or("non UTF-8 model path")?;
+    let range = options.range.to_str().ok_or("non UTF-8 range path")?;
+    // OpenCV asserts on a missing file instead of saying which one
+    for path in [&options.model, &options.range] {
+        if !path.is_file() {
+            return Err(format!("BRISQUE file {} not found", path.display()).into());
+        }
+    }
+    Ok(QualityBRISQUE::compute(image, model, range)?[0])
+}
@@ -0,0 +1,107 @@
+use std::error::Error;
+
+use crate::ConvertOptions;
+
+/// Recipe keys clients may set per request: tuning of the stages the server
+/// runs. Keys naming files or models on the server (`lut`, `style`,
+/// `edge_detector=sed:…`, `white_balance=learning:…`, ...), resource limits
+/// (`max_pixels`, `downscale_oversized`), upscaling and custom stages are
+/// only those the server was started with.
+const CLIENT_KEYS: &[&str] = &[
+    "smoothing",
+    "spatial_radius",
+    "color_radius",
+    "max_pyramid_level",
+    "diffusion_alpha",
+    "diffusion_k",
+    "diffusion_iterations",
+    "threshold_block_size",
+    "threshold_c",
+    "edge_scales",
+    "dilate_kernel_size",
+    "dilate_kernel_shape",
+    "dilate_iterations",
+    "morphology",
+    "min_edge_area",
+    "auto_orient",
+    "input_profile",
+    "linear_light",
+    "auto",
+    "denoise",
+    "clahe_clip_limit",
+    "clahe_tile_grid",
+    "stroke_min_width",
+    "stroke_max_width",
+    "edge_color",
+    "edge_opacity",
+    "edge_feather",
+    "edge_background",
+    "posterize_colors",
+    "posterize_dither",
+    "palette",
+    "gradient_map",
+    "halftone_dot_size",
+    "halftone_angle",
+    "halftone_mode",
+    "hatch_spacing",
+    "hatch_line_width",
+    "hatch_angle",
+    "hatch_low",
+    "hatch_high",
+    "pixelart_cells",
+    "pixelart_colors",
+    "pixelart_outline",
+    "popart_rows",
+    "popart_cols",
+    "popart_levels",
+    "ascii_columns",
+    "ascii_mode",
+    "svg_colors",
+    "svg_epsilon",
+    "svg_min_area",
+    "lut_stage",
+    "adjust_saturation",
+    "adjust_brightness",
+    "adjust_contrast",
+    "adjust_hue",
+    "sharpen_amount",
+    "sharpen_radius",
+    "sharpen_threshold",
+    "vignette_strength",
+    "vignette_radius",
+    "vignette_feather",
+    "texture_opacity",
+    "texture_blend",
+    "saliency_detail",
+    "saliency_flat",
+    "depth_near",
+    "depth_far",
+    "depth_edge_c",
+    "portrait_smoothing",
+    "portrait_edge_c",
+    "face_smoothing",
+    "face_margin",
+    "segmentation_class",
+];
+
+/*
+ * server recipe + client key/value pairs -> ConvertOptions; later keys win
+ */
+pub(crate) fn client_options<'a>(
+    base: &str,
+    params: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> Result<ConvertOptions, Box<dyn Error>> {
+    let mut recipe = String::new();
+    for (key, value) in params {
+        if !CLIENT_KEYS.contains(&key) {
+            return Err(format!("{} cannot be set per request", key).into());
+        }
+        // one parameter must not smuggle in further recipe lines
+        if key.contains(['\n', '\r', '=']) || value.contains(['\n', '\r']) {
+            return Err(format!("malformed parameter {}", key).into());
+        }
+        recipe.push_str(&format!("{}={}\n", key, value));
+    }
+    // validated as it is parsed, bounding grids and sizes a request asks for
+    ConvertOptions::from_recipe(base)?.with_recipe(&recipe)
+}
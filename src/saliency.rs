@@ -0,0 +1,63 @@
+use std::error::Error;
+
+use opencv::core::{normalize, no_array, Size, BORDER_DEFAULT, CV_32F, NORM_MINMAX};
+use opencv::imgproc::gaussian_blur;
+use opencv::prelude::*;
+use opencv::saliency::StaticSaliencySpectralResidual;
+
+use crate::{blend_weighted, segment_colors, ConvertOptions};
+
+/// Saliency-guided smoothing: salient regions are segmented with smaller
+/// radii than the background, so the subject keeps detail.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct SaliencyOptions {
+    /// Factor applied to the mean-shift radii where saliency is highest.
+    pub detail: f64,
+    /// Factor applied to the mean-shift radii where saliency is lowest.
+    pub flat: f64,
+}
+
+impl Default for SaliencyOptions {
+    fn default() -> Self {
+        SaliencyOptions { detail: 0.5, flat: 1.5 }
+    }
+}
+
+/*
+ * BGR image -> CV_32FC1 importance map in [0, 1], blurred so that the
+ * transition between detailed and flat regions is not visible.
+ */
+pub(crate) fn importance_map(input: &Mat) -> Result<Mat, Box<dyn Error>> {
+    let mut detector = StaticSaliencySpectralResidual::create()?;
+    let mut raw = Mat::default();
+    if !detector.compute_saliency(input, &mut raw)? {
+        return Err("saliency computation failed".into());
+    }
+    let mut scaled = Mat::default();
+    normalize(&raw, &mut scaled, 0.0, 1.0, NORM_MINMAX, CV_32F, &no_array())?;
+    let sigma = input.cols().max(input.rows()) as f64 / 50.0;
+    let mut map = Mat::default();
+    gaussian_blur(&scaled, &mut map, Size::default(), sigma, sigma, BORDER_DEFAULT)?;
+    Ok(map)
+}
+
+/*
+ * Segments the Lab image twice, with detailed and flat radii, and mixes the
+ * two by importance.
+ */
+pub(crate) fn segment_colors_guided(
+    input: &Mat,
+    importance: &Mat,
+    options: &ConvertOptions,
+    saliency: &SaliencyOptions,
+) -> Result<Mat, Box<dyn Error>> {
+    let scaled = |factor: f64| ConvertOptions {
+        spatial_radius: options.spatial_radius.scaled(factor),
+        color_radius: options.color_radius.scaled(factor),
+        ..options.clone()
+    };
+    let detailed = segment_colors(input, &scaled(saliency.detail))?;
+    let flat = segment_colors(input, &scaled(saliency.flat))?;
+    blend_weighted(&detailed, &flat, importance)
+}
@@ -0,0 +1,120 @@
+//! Pipelines written as Rhai scripts, behind the `script` feature; run with
+//! `nftimg run SCRIPT.rhai IMAGE [OPTIONS]`.
+//!
+//! The script gets the decoded input as `image` and evaluates to the output.
+//! Every built-in stage takes the image and an optional map of recipe keys,
+//! applied on top of the command-line options:
+//!
+//! ```rhai
+//! let base = if image.width > 2000 { cartoon(image, #{ spatial_radius: 20 }) } else { cartoon(image) };
+//! let graded = adjust(base, #{ adjust_saturation: 1.3 });
+//! stage(vignette(graded), "canvas")
+//! ```
+
+use std::error::Error;
+use std::fs;
+use std::path::{self, Path, PathBuf};
+use std::sync::Arc;
+
+use opencv::prelude::*;
+use rhai::{Dynamic, Engine, EvalAltResult, Map, Scope};
+
+use crate::color_profile::Mixing;
+use crate::metadata::{self, MetadataOptions};
+use crate::{convert_image, encode_image, enhance, finish, grading, load, naming, plugin, shading, ConvertOptions};
+
+/// An image as scripts hold it, cheap to copy between variables.
+#[derive(Clone)]
+struct Image(Arc<Mat>);
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// A built-in stage, reading its parameters from the options.
+type BuiltIn = fn(&Mat, &ConvertOptions) -> Result<Mat, Box<dyn Error>>;
+
+/// The built-in stages by script name.
+const STAGES: [(&str, BuiltIn); 9] = [
+    ("cartoon", |image, options| convert_image(image, options)),
+    ("denoise", |image, options| enhance::denoise(image, &options.denoise.unwrap_or_default())),
+    ("lut", |image, options| grading::apply_lut(image, options.lut.as_ref().ok_or("lut() needs a lut key")?)),
+    ("adjust", |image, options| grading::adjust(image, &options.adjust.unwrap_or_default())),
+    ("sharpen", |image, options| finish::sharpen(image, &options.sharpen.unwrap_or_default())),
+    ("vignette", |image, options| {
+        finish::vignette(image, &options.vignette.unwrap_or_default(), Mixing { linear: options.linear_light })
+    }),
+    ("texture", |image, options| {
+        let texture = options.texture.clone().unwrap_or_default();
+        finish::texture(image, &texture, Mixing { linear: options.linear_light })
+    }),
+    ("halftone", |image, options| shading::halftone(image, &options.halftone.unwrap_or_default())),
+    ("hatch", |image, options| shading::hatch(image, &options.hatch.unwrap_or_default())),
+];
+
+/*
+ * base options + recipe keys of a script map -> stage options; run settings
+ * stay those of the command line
+ */
+fn with_params(base: &ConvertOptions, params: &Map) -> Result<ConvertOptions, Box<dyn Error>> {
+    let lines: String = params.iter().map(|(key, value)| format!("{}={}\n", key, value)).collect();
+    base.with_recipe(&lines)
+}
+
+fn script_error(e: Box<dyn Error>) -> Box<EvalAltResult> {
+    e.to_string().into()
+}
+
+fn engine(options: &Arc<ConvertOptions>) -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .register_type_with_name::<Image>("Image")
+        .register_get("width", |image: &mut Image| i64::from(image.0.cols()))
+        .register_get("height", |image: &mut Image| i64::from(image.0.rows()));
+    for (name, stage) in STAGES {
+        let base = Arc::clone(options);
+        engine.register_fn(name, move |image: Image| -> ScriptResult<Image> {
+            Ok(Image(Arc::new(stage(&image.0, &base).map_err(script_error)?)))
+        });
+        let base = Arc::clone(options);
+        engine.register_fn(name, move |image: Image, params: Map| -> ScriptResult<Image> {
+            let options = with_params(&base, &params).map_err(script_error)?;
+            Ok(Image(Arc::new(stage(&image.0, &options).map_err(script_error)?)))
+        });
+    }
+    // stages registered with register_stage, by name
+    engine.register_fn("stage", |image: Image, name: &str| -> ScriptResult<Image> {
+        let output = plugin::registered(name).and_then(|stage| stage.apply(&image.0)).map_err(script_error)?;
+        Ok(Image(Arc::new(output)))
+    });
+    engine
+}
+
+/// Runs the Rhai pipeline in `script` on the image at `input` and writes the
+/// output where [`crate::convert_with_options`] would, returning its path.
+/// `options` are the defaults of every stage the script calls.
+pub fn run_script(script: &Path, input: &Path, options: &ConvertOptions) -> Result<PathBuf, Box<dyn Error>> {
+    let source = fs::read_to_string(script).map_err(|e| format!("cannot read {}: {}", script.display(), e))?;
+    let input = &path::absolute(input)?;
+    let bytes = fs::read(input)?;
+    let image = load(&bytes, input, options)?;
+
+    let mut scope = Scope::new();
+    scope.push("image", Image(Arc::new(image)));
+    let result: Dynamic = engine(&Arc::new(options.clone()))
+        .eval_with_scope(&mut scope, &source)
+        .map_err(|e| format!("{}: {}", script.display(), e))?;
+    let type_name = result.type_name();
+    let output = result
+        .try_cast::<Image>()
+        .ok_or_else(|| format!("{} evaluates to {}, not an image", script.display(), type_name))?;
+
+    if let Some(dir) = &options.output_dir {
+        naming::prepare_output_dir(dir)?;
+    }
+    let path_write = naming::output_location(input, &options.output_suffix, options.output_dir.as_deref())?;
+    let extension = path_write.extension().and_then(|e| e.to_str()).ok_or("output has no image extension")?;
+    // a recipe would not reproduce what the script did
+    let metadata = MetadataOptions { embed_recipe: false, ..options.metadata.clone() };
+    let encoded = metadata::apply(&bytes, encode_image(extension, &output.0)?, &metadata, "")?;
+    naming::write_atomic(&path_write, &encoded)?;
+    Ok(path_write)
+}
@@ -0,0 +1,183 @@
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use opencv::core::{in_range, Scalar, Size, CV_32F, CV_8UC1};
+use opencv::dnn::{blob_from_image, read_net_def};
+use opencv::imgproc::{bounding_rect, resize, INTER_NEAREST};
+use opencv::prelude::*;
+
+use crate::converter;
+use crate::portrait::paste;
+use crate::{grayscaled_to_edged, segment_colors, ConvertOptions};
+
+/// Network input side; DeepLab exports are trained at 513x513.
+const INPUT_SIZE: i32 = 513;
+
+/// PASCAL VOC labels, in the order DeepLab's VOC models emit them.
+const VOC_CLASSES: &[&str] = &[
+    "background", "aeroplane", "bicycle", "bird", "boat", "bottle", "bus", "car", "cat", "chair", "cow",
+    "diningtable", "dog", "horse", "motorbike", "person", "pottedplant", "sheep", "sofa", "train", "tvmonitor",
+];
+
+/// Semantic segmentation: a DNN labels every pixel and each configured class
+/// is smoothed and outlined with its own parameters.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SegmentationOptions {
+    /// Segmentation network emitting 1xCxHxW class scores, e.g. DeepLab v3.
+    pub model: PathBuf,
+    /// Per-class parameters; classes not listed keep the global ones.
+    pub classes: Vec<ClassParams>,
+}
+
+impl SegmentationOptions {
+    pub fn new(model: PathBuf) -> SegmentationOptions {
+        SegmentationOptions { model, classes: Vec::new() }
+    }
+}
+
+/// Parameters of one class, parsed from and printed as
+/// `class:smoothing:edge_c`, e.g. `person:0.5:-2`. The class is a VOC name
+/// or a label index, for models trained on other datasets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClassParams {
+    pub class: usize,
+    /// Factor applied to the mean-shift radii.
+    pub smoothing: f64,
+    /// Added to the adaptive threshold constant; higher drops more lines.
+    pub edge_c: f64,
+}
+
+impl fmt::Display for ClassParams {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match VOC_CLASSES.get(self.class) {
+            Some(name) => write!(f, "{}:{}:{}", name, self.smoothing, self.edge_c),
+            None => write!(f, "{}:{}:{}", self.class, self.smoothing, self.edge_c),
+        }
+    }
+}
+
+impl FromStr for ClassParams {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+        let [class, smoothing, edge_c] = parts[..] else {
+            return Err(format!("expected class:smoothing:edge_c, got {}", s).into());
+        };
+        let class = match VOC_CLASSES.iter().position(|name| name.eq_ignore_ascii_case(class)) {
+            Some(index) => index,
+            None => class.parse().map_err(|_| format!("unknown class: {}", class))?,
+        };
+        Ok(ClassParams { class, smoothing: smoothing.parse()?, edge_c: edge_c.parse()? })
+    }
+}
+
+/*
+ * BGR image -> CV_8UC1 label map of the same size, the most likely class per
+ * pixel
+ */
+pub(crate) fn classify(input: &Mat, options: &SegmentationOptions) -> Result<Mat, Box<dyn Error>> {
+    let path = options.model.to_str().ok_or("non UTF-8 model path")?;
+    let mut net = converter::pooled("segmentation", &options.model, || {
+        let net = read_net_def(path)?;
+        if net.empty()? {
+            return Err(format!("failed to load segmentation model {}", path).into());
+        }
+        Ok(net)
+    })?;
+    // RGB scaled to [-1, 1]
+    let blob = blob_from_image(
+        input,
+        1.0 / 127.5,
+        Size::new(INPUT_SIZE, INPUT_SIZE),
+        Scalar::all(127.5),
+        true,
+        false,
+        CV_32F,
+    )?;
+    net.set_input_def(&blob)?;
+    let scores = net.forward_single_def()?;
+
+    let dims = scores.mat_size();
+    if dims.len() != 4 || dims[1] < 2 {
+        return Err("segmentation model must output 1xCxHxW class scores".into());
+    }
+    let (classes, height, width) = (dims[1] as usize, dims[2], dims[3]);
+    let plane = (height * width) as usize;
+    let data = scores.data_typed::<f32>()?;
+    if data.len() != classes * plane {
+        return Err("segmentation model must output 1xCxHxW class scores".into());
+    }
+    let mut labels = Mat::new_rows_cols_with_default(height, width, CV_8UC1, Scalar::all(0.0))?;
+    for (i, label) in labels.data_bytes_mut()?.iter_mut().enumerate() {
+        let best = (0..classes).max_by(|&a, &b| data[a * plane + i].total_cmp(&data[b * plane + i]));
+        *label = best.unwrap_or(0).min(u8::MAX as usize) as u8;
+    }
+
+    let mut output = Mat::default();
+    resize(&labels, &mut output, input.size()?, 0.0, 0.0, INTER_NEAREST)?;
+    Ok(output)
+}
+
+fn class_mask(labels: &Mat, class: usize) -> Result<Mat, Box<dyn Error>> {
+    let mut mask = Mat::default();
+    let value = Scalar::all(class as f64);
+    in_range(labels, &value, &value, &mut mask)?;
+    Ok(mask)
+}
+
+/*
+ * Re-segments each configured class of the Lab image with its own radii and
+ * pastes it into the segmented base.
+ */
+pub(crate) fn refine_base(
+    base: &mut Mat,
+    lab: &Mat,
+    labels: &Mat,
+    options: &ConvertOptions,
+    segmentation: &SegmentationOptions,
+) -> Result<(), Box<dyn Error>> {
+    for params in &segmentation.classes {
+        let mask = class_mask(labels, params.class)?;
+        let area = bounding_rect(&mask)?;
+        if area.area() == 0 {
+            continue;
+        }
+        let scaled = ConvertOptions {
+            spatial_radius: options.spatial_radius.scaled(params.smoothing),
+            color_radius: options.color_radius.scaled(params.smoothing),
+            ..options.clone()
+        };
+        let smooth = segment_colors(&Mat::roi(lab, area)?.try_clone()?, &scaled)?;
+        paste(base, &smooth, &mask, area)?;
+    }
+    Ok(())
+}
+
+/*
+ * Re-thresholds each configured class of the grayscaled image with its own
+ * constant and pastes it into the edge mask.
+ */
+pub(crate) fn refine_edge(
+    edge: &mut Mat,
+    gray: &Mat,
+    labels: &Mat,
+    options: &ConvertOptions,
+    segmentation: &SegmentationOptions,
+) -> Result<(), Box<dyn Error>> {
+    for params in &segmentation.classes {
+        let mask = class_mask(labels, params.class)?;
+        let area = bounding_rect(&mask)?;
+        if area.area() == 0 {
+            continue;
+        }
+        let shifted = ConvertOptions { threshold_c: options.threshold_c + params.edge_c, ..options.clone() };
+        let lines = grayscaled_to_edged(&Mat::roi(gray, area)?.try_clone()?, &shifted)?;
+        paste(edge, &lines, &mask, area)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,312 @@
+use std::collections::hash_map::RandomState;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::error::Error;
+use std::hash::BuildHasher;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use axum::body::Bytes;
+use axum::extract::{DefaultBodyLimit, FromRequest, Multipart, Path, Query, Request, State};
+use axum::http::{header, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::Router;
+use tokio::net::TcpListener;
+use tokio::runtime::Runtime;
+use tokio::sync::{mpsc, watch};
+use tokio::task;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::metadata::sniff_format;
+use crate::metrics::{self, render_metrics};
+use crate::remote::client_options;
+#[cfg(feature = "webhook")]
+use crate::webhook::{self, Output};
+use crate::{stage_count, ConvertOptions, Converter, NftImgError};
+
+/// Largest request body accepted, in bytes, as for downloads.
+const MAX_BODY_BYTES: usize = 64 * 1024 * 1024;
+
+/// Jobs kept for their events and output; older ones are forgotten.
+const JOBS_KEPT: usize = 64;
+
+/// An HTTP status and the plain-text message sent with it.
+type Failure = (StatusCode, String);
+
+struct Shared {
+    /// Recipe of the options the server was started with.
+    base: String,
+    /// Models and LUTs loaded by earlier requests, kept for the next.
+    converter: Converter,
+    /// Jobs started with `POST /jobs`, oldest first.
+    jobs: Mutex<VecDeque<Arc<Job>>>,
+    /// Told when a job ends.
+    #[cfg(feature = "webhook")]
+    webhook: Option<String>,
+}
+
+/// A conversion started with `POST /jobs`.
+struct Job {
+    id: String,
+    /// Stages the conversion reports, for the fraction sent with each.
+    total: usize,
+    /// Content type of the output, that of the input.
+    content_type: String,
+    progress: watch::Sender<Progress>,
+}
+
+#[derive(Default)]
+struct Progress {
+    /// Stages ended so far, in order.
+    stages: Vec<&'static str>,
+    /// The output once done, or the failure.
+    result: Option<Result<Bytes, Failure>>,
+}
+
+/// Serves `POST /convert` on `addr` until the process is stopped.
+///
+/// The body is the image, raw or as the `image` field (or first field) of a
+/// multipart form; query parameters are recipe keys applied on top of
+/// `options`, e.g. `/convert?spatial_radius=15&edge_color=navy`. The
+/// response is the output in the format of the input. `GET /metrics` reports
+/// requests, failures and stage latencies for Prometheus.
+///
+/// `POST /jobs` takes the same request and answers `202 Accepted` with a job
+/// id at once. `GET /jobs/{id}/events` streams its progress as server-sent
+/// events: a `stage` event as each stage ends, with data such as
+/// `{"stage":"edges","done":5,"total":9}`, then `done` or `failed` with the
+/// error. `GET /jobs/{id}/output` returns the output once done.
+pub fn serve(addr: SocketAddr, options: &ConvertOptions) -> Result<(), Box<dyn Error>> {
+    let shared = Shared {
+        base: options.recipe(),
+        converter: Converter::new(options.clone()),
+        jobs: Mutex::new(VecDeque::new()),
+        #[cfg(feature = "webhook")]
+        webhook: options.webhook.clone(),
+    };
+    let app = Router::new()
+        .route("/convert", post(convert))
+        .route("/jobs", post(start_job))
+        .route("/jobs/:id/events", get(job_events))
+        .route("/jobs/:id/output", get(job_output))
+        .route("/metrics", get(report_metrics))
+        .layer(DefaultBodyLimit::max(MAX_BODY_BYTES))
+        .with_state(Arc::new(shared));
+    Runtime::new()?.block_on(async {
+        let listener = TcpListener::bind(addr).await?;
+        eprintln!("listening on http://{}", listener.local_addr()?);
+        axum::serve(listener, app).await?;
+        Ok(())
+    })
+}
+
+async fn convert(
+    State(shared): State<Arc<Shared>>,
+    Query(params): Query<Vec<(String, String)>>,
+    request: Request,
+) -> Response {
+    let result = respond(&shared, &params, request).await;
+    metrics::count_request(result.is_err());
+    match result {
+        Ok(response) => response,
+        Err((status, message)) => {
+            if status == StatusCode::INTERNAL_SERVER_ERROR {
+                eprintln!("warning: POST /convert failed: {}", message);
+            }
+            (status, message).into_response()
+        }
+    }
+}
+
+async fn report_metrics() -> Response {
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], render_metrics()).into_response()
+}
+
+async fn respond(shared: &Shared, params: &[(String, String)], request: Request) -> Result<Response, Failure> {
+    let (options, input, format) = read_request(&shared.base, params, request).await?;
+    let converter = shared.converter.with_options(options);
+    // conversions are CPU-bound and would stall the async workers
+    let output = task::spawn_blocking(move || converter.convert_buffer(&input).map_err(failure))
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "conversion panicked".to_string()))??;
+    Ok(([(header::CONTENT_TYPE, format!("image/{}", format))], output).into_response())
+}
+
+/*
+ * request -> options, image and its format, or the reason it is refused
+ */
+async fn read_request(
+    base: &str,
+    params: &[(String, String)],
+    request: Request,
+) -> Result<(ConvertOptions, Bytes, &'static str), Failure> {
+    let params = params.iter().map(|(key, value)| (key.as_str(), value.as_str()));
+    let options = client_options(base, params).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let input = read_body(request).await?;
+    let format = sniff_format(&input).ok_or_else(|| {
+        (StatusCode::UNSUPPORTED_MEDIA_TYPE, "not a JPEG, PNG, BMP, WebP or TIFF image".to_string())
+    })?;
+    Ok((options, input, format))
+}
+
+async fn start_job(
+    State(shared): State<Arc<Shared>>,
+    Query(params): Query<Vec<(String, String)>>,
+    request: Request,
+) -> Result<Response, Failure> {
+    let (options, input, format) = read_request(&shared.base, &params, request).await?;
+    let job = Arc::new(Job {
+        id: job_id(),
+        total: stage_count(&options),
+        content_type: format!("image/{}", format),
+        progress: watch::Sender::new(Progress::default()),
+    });
+    {
+        let mut jobs = shared.jobs.lock().unwrap_or_else(PoisonError::into_inner);
+        if jobs.len() == JOBS_KEPT {
+            jobs.pop_front();
+        }
+        jobs.push_back(job.clone());
+    }
+    let id = job.id.clone();
+    let converter = shared.converter.with_options(options);
+    #[cfg(feature = "webhook")]
+    let webhook = shared.webhook.clone();
+    task::spawn_blocking(move || {
+        let listener = job.clone();
+        let run = || {
+            metrics::with_stage_listener(
+                move |stage, _, _| listener.progress.send_modify(|progress| progress.stages.push(stage)),
+                || converter.convert_buffer(&input),
+            )
+        };
+        #[cfg(feature = "webhook")]
+        let (result, timings) = webhook::timed(run);
+        #[cfg(not(feature = "webhook"))]
+        let result = run();
+        metrics::count_request(result.is_err());
+        if let Err(e) = &result {
+            eprintln!("warning: job {} failed: {}", job.id, e);
+        }
+        #[cfg(feature = "webhook")]
+        let notification = webhook.map(|url| {
+            let outputs = match &result {
+                Ok(output) => vec![Output::in_memory(format!("/jobs/{}/output", job.id), output)],
+                Err(_) => Vec::new(),
+            };
+            (url, outputs, result.as_ref().err().map(|e| e.to_string()))
+        });
+        let result = result.map(Bytes::from).map_err(failure);
+        job.progress.send_modify(|progress| progress.result = Some(result));
+        #[cfg(feature = "webhook")]
+        if let Some((url, outputs, error)) = notification {
+            webhook::notify(&url, &job.id, &outputs, error, &timings);
+        }
+    });
+    let location = format!("/jobs/{}/events", id);
+    Ok((StatusCode::ACCEPTED, [(header::LOCATION, location)], id).into_response())
+}
+
+async fn job_events(
+    State(shared): State<Arc<Shared>>,
+    Path(id): Path<String>,
+) -> Result<Sse<ReceiverStream<Result<Event, Infallible>>>, Failure> {
+    let job = find_job(&shared, &id)?;
+    let mut progress = job.progress.subscribe();
+    let (sender, receiver) = mpsc::channel(16);
+    tokio::spawn(async move {
+        let mut sent = 0;
+        loop {
+            let (events, finished) = {
+                let progress = progress.borrow_and_update();
+                let mut events = Vec::new();
+                for (done, stage) in progress.stages.iter().enumerate().skip(sent) {
+                    let data = format!(r#"{{"stage":"{}","done":{},"total":{}}}"#, stage, done + 1, job.total);
+                    events.push(Event::default().event("stage").data(data));
+                }
+                sent = progress.stages.len();
+                match &progress.result {
+                    Some(Ok(_)) => events.push(Event::default().event("done").data(format!("/jobs/{}/output", job.id))),
+                    Some(Err((_, message))) => events.push(Event::default().event("failed").data(message)),
+                    None => {}
+                }
+                (events, progress.result.is_some())
+            };
+            for event in events {
+                // the client went away
+                if sender.send(Ok(event)).await.is_err() {
+                    return;
+                }
+            }
+            if finished || progress.changed().await.is_err() {
+                return;
+            }
+        }
+    });
+    Ok(Sse::new(ReceiverStream::new(receiver)).keep_alive(KeepAlive::default()))
+}
+
+async fn job_output(State(shared): State<Arc<Shared>>, Path(id): Path<String>) -> Result<Response, Failure> {
+    let job = find_job(&shared, &id)?;
+    let progress = job.progress.borrow();
+    match &progress.result {
+        Some(Ok(output)) => Ok(([(header::CONTENT_TYPE, job.content_type.clone())], output.clone()).into_response()),
+        Some(Err(failure)) => Err(failure.clone()),
+        None => Err((StatusCode::CONFLICT, format!("job {} is still running", id))),
+    }
+}
+
+fn find_job(shared: &Shared, id: &str) -> Result<Arc<Job>, Failure> {
+    let jobs = shared.jobs.lock().unwrap_or_else(PoisonError::into_inner);
+    let job = jobs.iter().find(|job| job.id == id).cloned();
+    job.ok_or_else(|| (StatusCode::NOT_FOUND, format!("no job {}", id)))
+}
+
+/*
+ * Unique, hard to guess id: ids of other clients' jobs must not be
+ * enumerable
+ */
+fn job_id() -> String {
+    static STARTED: AtomicU64 = AtomicU64::new(0);
+    let sequence = STARTED.fetch_add(1, Ordering::Relaxed);
+    format!("{:016x}{:08x}", RandomState::new().hash_one(sequence), sequence as u32)
+}
+
+async fn read_body(request: Request) -> Result<Bytes, Failure> {
+    let multipart = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("multipart/form-data"));
+    if !multipart {
+        return Bytes::from_request(request, &()).await.map_err(|e| (e.status(), e.body_text()));
+    }
+    let mut form = Multipart::from_request(request, &()).await.map_err(|e| (e.status(), e.body_text()))?;
+    let mut first = None;
+    while let Some(field) = form.next_field().await.map_err(|e| (e.status(), e.body_text()))? {
+        let named = field.name() == Some("image");
+        let bytes = field.bytes().await.map_err(|e| (e.status(), e.body_text()))?;
+        if named {
+            return Ok(bytes);
+        }
+        first.get_or_insert(bytes);
+    }
+    first.ok_or_else(|| (StatusCode::BAD_REQUEST, "multipart form without fields".to_string()))
+}
+
+/*
+ * conversion error -> 422 for inputs that cannot be read, 500 otherwise
+ */
+fn failure(e: Box<dyn Error>) -> Failure {
+    let status = match e.downcast_ref::<NftImgError>() {
+        Some(NftImgError::DecodeFailed { .. } | NftImgError::UnexpectedChannels { .. }) => {
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+        Some(NftImgError::UnsupportedFormat { .. }) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (status, e.to_string())
+}
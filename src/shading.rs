@@ -0,0 +1,169 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use opencv::prelude::*;
+
+/// Ink separation of the halftone stage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "kebab-case"))]
+pub enum HalftoneMode {
+    /// Black dots on white paper, driven by luminance.
+    #[default]
+    Monochrome,
+    /// Cyan, magenta, yellow and black screens at the classic print angles.
+    Cmyk,
+}
+
+impl fmt::Display for HalftoneMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HalftoneMode::Monochrome => write!(f, "mono"),
+            HalftoneMode::Cmyk => write!(f, "cmyk"),
+        }
+    }
+}
+
+impl FromStr for HalftoneMode {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mono" => Ok(HalftoneMode::Monochrome),
+            "cmyk" => Ok(HalftoneMode::Cmyk),
+            _ => Err(format!("unknown halftone mode: {}", s).into()),
+        }
+    }
+}
+
+/// Halftone stage: replaces the tones of the base with comic-print dots.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct HalftoneOptions {
+    /// Screen cell size in pixels.
+    pub dot_size: f64,
+    /// Screen angle in degrees; CMYK screens are offset from it.
+    pub angle: f64,
+    pub mode: HalftoneMode,
+}
+
+impl Default for HalftoneOptions {
+    fn default() -> Self {
+        HalftoneOptions { dot_size: 8.0, angle: 45.0, mode: HalftoneMode::Monochrome }
+    }
+}
+
+/*
+ * Whether pixel (x, y) lies inside the dot of its screen cell, for a screen of
+ * the given cell size and angle and an ink coverage in [0, 1].
+ */
+fn in_dot(x: f64, y: f64, cell: f64, angle: f64, coverage: f64) -> bool {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let u = x * cos + y * sin;
+    let v = -x * sin + y * cos;
+    let du = u - (u / cell).round() * cell;
+    let dv = v - (v / cell).round() * cell;
+    // area of the dot relative to the cell equals the coverage
+    let radius = cell * (coverage / std::f64::consts::PI).sqrt();
+    du * du + dv * dv < radius * radius
+}
+
+/*
+ * BGR image -> BGR halftone print of it
+ */
+pub(crate) fn halftone(input: &Mat, options: &HalftoneOptions) -> Result<Mat, Box<dyn Error>> {
+    let mut output = input.try_clone()?;
+    let width = input.cols() as usize;
+    let cell = options.dot_size.max(2.0);
+    for (i, px) in output.data_bytes_mut()?.chunks_exact_mut(3).enumerate() {
+        let x = (i % width) as f64;
+        let y = (i / width) as f64;
+        let (b, g, r) = (px[0] as f64 / 255.0, px[1] as f64 / 255.0, px[2] as f64 / 255.0);
+        match options.mode {
+            HalftoneMode::Monochrome => {
+                let darkness = 1.0 - (0.299 * r + 0.587 * g + 0.114 * b);
+                let v = if in_dot(x, y, cell, options.angle, darkness) { 0 } else { 255 };
+                px.copy_from_slice(&[v, v, v]);
+            }
+            HalftoneMode::Cmyk => {
+                let k = 1.0 - r.max(g).max(b);
+                let ink = |channel: f64| if k < 1.0 { (1.0 - channel - k) / (1.0 - k) } else { 0.0 };
+                let (c, m, y_ink) = (ink(r), ink(g), ink(b));
+                // paper white, each ink absorbs its complementary channel
+                let mut rgb = [1.0f64; 3];
+                if in_dot(x, y, cell, options.angle - 30.0, c) {
+                    rgb[0] = 0.0;
+                }
+                if in_dot(x, y, cell, options.angle + 30.0, m) {
+                    rgb[1] = 0.0;
+                }
+                if in_dot(x, y, cell, options.angle - 45.0, y_ink) {
+                    rgb[2] = 0.0;
+                }
+                if in_dot(x, y, cell, options.angle, k) {
+                    rgb = [0.0; 3];
+                }
+                px.copy_from_slice(&[(rgb[2] * 255.0) as u8, (rgb[1] * 255.0) as u8, (rgb[0] * 255.0) as u8]);
+            }
+        }
+    }
+    Ok(output)
+}
+
+/// Cross-hatching stage: mid-tones become engraved lines, darker tones
+/// crossing more line directions. Highlights and shadows keep their color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct HatchOptions {
+    /// Distance between parallel lines in pixels.
+    pub spacing: f64,
+    pub line_width: f64,
+    /// Direction of the first line family in degrees.
+    pub angle: f64,
+    /// Luminance range in [0, 1] treated as mid-tones.
+    pub low: f64,
+    pub high: f64,
+}
+
+impl Default for HatchOptions {
+    fn default() -> Self {
+        HatchOptions { spacing: 6.0, line_width: 1.5, angle: 45.0, low: 0.2, high: 0.8 }
+    }
+}
+
+/// Whether pixel (x, y) lies on a line of the family at the given angle.
+fn on_line(x: f64, y: f64, spacing: f64, angle: f64, width: f64) -> bool {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let v = -x * sin + y * cos;
+    (v - (v / spacing).round() * spacing).abs() < width / 2.0
+}
+
+/*
+ * BGR image -> BGR image with its mid-tones replaced by black hatching on
+ * white paper
+ */
+pub(crate) fn hatch(input: &Mat, options: &HatchOptions) -> Result<Mat, Box<dyn Error>> {
+    if options.low >= options.high {
+        return Err("hatching needs low < high".into());
+    }
+    // one more line family per band, from the lightest mid-tones to the darkest
+    let families = [0.0, 90.0, 45.0, 135.0];
+    let mut output = input.try_clone()?;
+    let width = input.cols() as usize;
+    let spacing = options.spacing.max(2.0);
+    for (i, px) in output.data_bytes_mut()?.chunks_exact_mut(3).enumerate() {
+        let luma = (0.114 * px[0] as f64 + 0.587 * px[1] as f64 + 0.299 * px[2] as f64) / 255.0;
+        if luma < options.low || luma > options.high {
+            continue;
+        }
+        let darkness = (options.high - luma) / (options.high - options.low);
+        let layers = 1 + (darkness * (families.len() - 1) as f64).round() as usize;
+        let (x, y) = ((i % width) as f64, (i / width) as f64);
+        let inked = families[..layers]
+            .iter()
+            .any(|offset| on_line(x, y, spacing, options.angle + offset, options.line_width));
+        let v = if inked { 0 } else { 255 };
+        px.copy_from_slice(&[v, v, v]);
+    }
+    Ok(output)
+}
@@ -0,0 +1,247 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(feature = "ximgproc")]
+use opencv::prelude::*;
+#[cfg(feature = "ximgproc")]
+use opencv::core::{split, Vector};
+#[cfg(feature = "ximgproc")]
+use opencv::core::BORDER_DEFAULT;
+#[cfg(feature = "ximgproc")]
+use opencv::ximgproc::{
+    create_superpixel_seeds, create_superpixel_slic, dt_filter, guided_filter, l0_smooth, rolling_guidance_filter,
+    DTF_NC, SLICO,
+};
+
+/// Smallest superpixel kept by SLIC's connectivity pass, in percent of the
+/// nominal region area.
+#[cfg(feature = "ximgproc")]
+const SLIC_MIN_ELEMENT_PERCENT: i32 = 25;
+
+/// How the base branch flattens colors.
+///
+/// Parses from and prints as `name[:param:...]`, e.g. `slic:20:10:10`;
+/// omitted parameters take their defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "kebab-case"))]
+pub enum Smoothing {
+    /// Mean-shift filtering, tuned by `spatial_radius`, `color_radius` and
+    /// `max_pyramid_level`.
+    #[default]
+    MeanShift,
+    /// SLICO superpixels averaged to their mean color; usually several times
+    /// faster than mean-shift, with more uniform regions.
+    Slic { region_size: i32, ruler: f32, iterations: i32 },
+    /// SEEDS superpixels averaged to their mean color.
+    Seeds { superpixels: i32, levels: i32, iterations: i32 },
+    /// Self-guided domain transform filter (normalized convolution); an order
+    /// of magnitude faster than mean-shift, suited to video frames.
+    DomainTransform { sigma_spatial: f64, sigma_color: f64, iterations: i32 },
+    /// Guided filter over a `radius` window; `eps` (8-bit scale, squared)
+    /// sets how strong an edge must be to survive. Guided by the image itself
+    /// or, with `lightness`, by its L channel only, which also flattens
+    /// color-only texture.
+    Guided { radius: i32, eps: f64, lightness: bool },
+    /// Rolling guidance: removes texture smaller than `sigma_space` while
+    /// keeping large structures; often the best cartoon look.
+    RollingGuidance { diameter: i32, sigma_color: f64, sigma_space: f64, iterations: i32 },
+    /// L0 gradient minimization: very flat, piecewise-constant regions.
+    /// Larger `lambda` gives fewer regions, `kappa` sets the convergence rate.
+    L0 { lambda: f64, kappa: f64 },
+}
+
+impl fmt::Display for Smoothing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Smoothing::MeanShift => write!(f, "mean-shift"),
+            Smoothing::Slic { region_size, ruler, iterations } => {
+                write!(f, "slic:{}:{}:{}", region_size, ruler, iterations)
+            }
+            Smoothing::Seeds { superpixels, levels, iterations } => {
+                write!(f, "seeds:{}:{}:{}", superpixels, levels, iterations)
+            }
+            Smoothing::DomainTransform { sigma_spatial, sigma_color, iterations } => {
+                write!(f, "dt:{}:{}:{}", sigma_spatial, sigma_color, iterations)
+            }
+            Smoothing::Guided { radius, eps, lightness } => {
+                write!(f, "guided:{}:{}:{}", radius, eps, if *lightness { "l" } else { "self" })
+            }
+            Smoothing::RollingGuidance { diameter, sigma_color, sigma_space, iterations } => {
+                write!(f, "rolling:{}:{}:{}:{}", diameter, sigma_color, sigma_space, iterations)
+            }
+            Smoothing::L0 { lambda, kappa } => write!(f, "l0:{}:{}", lambda, kappa),
+        }
+    }
+}
+
+impl FromStr for Smoothing {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let name = parts.next().unwrap_or_default();
+        let params: Vec<&str> = parts.collect();
+        let param = |i: usize| params.get(i).copied().filter(|p| !p.is_empty());
+        let smoothing = match name {
+            "mean-shift" => Smoothing::MeanShift,
+            "slic" => Smoothing::Slic {
+                region_size: param(0).map_or(Ok(20), str::parse)?,
+                ruler: param(1).map_or(Ok(10.0), str::parse)?,
+                iterations: param(2).map_or(Ok(10), str::parse)?,
+            },
+            "seeds" => Smoothing::Seeds {
+                superpixels: param(0).map_or(Ok(400), str::parse)?,
+                levels: param(1).map_or(Ok(4), str::parse)?,
+                iterations: param(2).map_or(Ok(4), str::parse)?,
+            },
+            "dt" => Smoothing::DomainTransform {
+                sigma_spatial: param(0).map_or(Ok(40.0), str::parse)?,
+                sigma_color: param(1).map_or(Ok(30.0), str::parse)?,
+                iterations: param(2).map_or(Ok(3), str::parse)?,
+            },
+            "guided" => Smoothing::Guided {
+                radius: param(0).map_or(Ok(8), str::parse)?,
+                eps: param(1).map_or(Ok(500.0), str::parse)?,
+                lightness: match param(2).unwrap_or("self") {
+                    "self" => false,
+                    "l" => true,
+                    guide => return Err(format!("unknown guided filter guide: {}", guide).into()),
+                },
+            },
+            "rolling" => Smoothing::RollingGuidance {
+                diameter: param(0).map_or(Ok(-1), str::parse)?,
+                sigma_color: param(1).map_or(Ok(25.0), str::parse)?,
+                sigma_space: param(2).map_or(Ok(3.0), str::parse)?,
+                iterations: param(3).map_or(Ok(4), str::parse)?,
+            },
+            "l0" => Smoothing::L0 {
+                lambda: param(0).map_or(Ok(0.02), str::parse)?,
+                kappa: param(1).map_or(Ok(2.0), str::parse)?,
+            },
+            _ => return Err(format!("unknown smoothing backend: {}", name).into()),
+        };
+        Ok(smoothing)
+    }
+}
+
+/*
+ * Lab image -> Lab image flattened over SLICO superpixels
+ */
+#[cfg(feature = "ximgproc")]
+pub(crate) fn slic(input: &Mat, region_size: i32, ruler: f32, iterations: i32) -> Result<Mat, Box<dyn Error>> {
+    let mut superpixels = create_superpixel_slic(input, SLICO, region_size, ruler)?;
+    superpixels.iterate(iterations)?;
+    superpixels.enforce_label_connectivity(SLIC_MIN_ELEMENT_PERCENT)?;
+    let mut labels = Mat::default();
+    superpixels.get_labels(&mut labels)?;
+    let count = superpixels.get_number_of_superpixels()?;
+    average_labels(input, &labels, count)
+}
+
+/*
+ * Lab image -> Lab image flattened over SEEDS superpixels
+ */
+#[cfg(feature = "ximgproc")]
+pub(crate) fn seeds(input: &Mat, superpixels: i32, levels: i32, iterations: i32) -> Result<Mat, Box<dyn Error>> {
+    let mut seeds = create_superpixel_seeds(
+        input.cols(),
+        input.rows(),
+        input.channels(),
+        superpixels,
+        levels,
+        2,
+        5,
+        false,
+    )?;
+    seeds.iterate(input, iterations)?;
+    let mut labels = Mat::default();
+    seeds.get_labels(&mut labels)?;
+    let count = seeds.get_number_of_superpixels()?;
+    average_labels(input, &labels, count)
+}
+
+/*
+ * Lab image -> Lab image smoothed by the domain transform, guided by itself
+ */
+#[cfg(feature = "ximgproc")]
+pub(crate) fn domain_transform(
+    input: &Mat,
+    sigma_spatial: f64,
+    sigma_color: f64,
+    iterations: i32,
+) -> Result<Mat, Box<dyn Error>> {
+    let mut output = Mat::default();
+    dt_filter(input, input, &mut output, sigma_spatial, sigma_color, DTF_NC, iterations)?;
+    Ok(output)
+}
+
+/*
+ * Lab image -> Lab image smoothed by the guided filter
+ */
+#[cfg(feature = "ximgproc")]
+pub(crate) fn guided(input: &Mat, radius: i32, eps: f64, lightness: bool) -> Result<Mat, Box<dyn Error>> {
+    let mut output = Mat::default();
+    if lightness {
+        let mut channels = Vector::<Mat>::new();
+        split(input, &mut channels)?;
+        guided_filter(&channels.get(0)?, input, &mut output, radius, eps, -1)?;
+    } else {
+        guided_filter(input, input, &mut output, radius, eps, -1)?;
+    }
+    Ok(output)
+}
+
+/*
+ * Lab image -> Lab image with small-scale texture rolled away
+ */
+#[cfg(feature = "ximgproc")]
+pub(crate) fn rolling_guidance(
+    input: &Mat,
+    diameter: i32,
+    sigma_color: f64,
+    sigma_space: f64,
+    iterations: i32,
+) -> Result<Mat, Box<dyn Error>> {
+    let mut output = Mat::default();
+    rolling_guidance_filter(input, &mut output, diameter, sigma_color, sigma_space, iterations, BORDER_DEFAULT)?;
+    Ok(output)
+}
+
+/*
+ * Lab image -> piecewise-constant Lab image
+ */
+#[cfg(feature = "ximgproc")]
+pub(crate) fn l0(input: &Mat, lambda: f64, kappa: f64) -> Result<Mat, Box<dyn Error>> {
+    let mut output = Mat::default();
+    l0_smooth(input, &mut output, lambda, kappa)?;
+    Ok(output)
+}
+
+#[cfg(feature = "ximgproc")]
+/// Replaces every pixel by the mean color of its label (CV_32SC1 labels).
+fn average_labels(input: &Mat, labels: &Mat, count: i32) -> Result<Mat, Box<dyn Error>> {
+    let channels = input.channels() as usize;
+    let labels = labels.data_typed::<i32>()?;
+    let mut sums = vec![0u64; count as usize * channels];
+    let mut sizes = vec![0u64; count as usize];
+    for (px, &label) in input.data_bytes()?.chunks_exact(channels).zip(labels) {
+        let label = label as usize;
+        sizes[label] += 1;
+        for (sum, &v) in sums[label * channels..].iter_mut().zip(px) {
+            *sum += v as u64;
+        }
+    }
+
+    let means: Vec<u8> = sums
+        .chunks_exact(channels)
+        .zip(&sizes)
+        .flat_map(|(sum, &n)| sum.iter().map(move |&s| (s / n.max(1)) as u8))
+        .collect();
+    let mut output = input.try_clone()?;
+    for (px, &label) in output.data_bytes_mut()?.chunks_exact_mut(channels).zip(labels) {
+        let label = label as usize;
+        px.copy_from_slice(&means[label * channels..(label + 1) * channels]);
+    }
+    Ok(output)
+}
@@ -0,0 +1,144 @@
+use std::error::Error;
+use std::path::Path;
+#[cfg(feature = "parquet")]
+use std::sync::Arc;
+
+#[cfg(feature = "parquet")]
+use arrow_array::{ArrayRef, Float64Array, Int32Array, RecordBatch, StringArray};
+#[cfg(feature = "parquet")]
+use parquet::arrow::ArrowWriter;
+
+use crate::collection::{CollectionReport, ImageReport};
+use crate::naming::write_atomic;
+
+/// Columns before the per-stage ones.
+const COLUMNS: [&str; 10] = [
+    "input",
+    "output",
+    "width",
+    "height",
+    "edge_density",
+    "colors",
+    "dominant_color",
+    "dominant_share",
+    "palette",
+    "seconds",
+];
+
+impl CollectionReport {
+    /// Writes one row per image to `path` for analytics across runs: input,
+    /// output, width, height, edge density, palette, dominant color, total
+    /// seconds and a `<stage>_seconds` column per stage. `.parquet` files are
+    /// written as Parquet (feature "parquet"), anything else as CSV.
+    pub fn write_stats(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let parquet = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("parquet"));
+        let bytes = if parquet { self.to_parquet()? } else { self.to_csv().into_bytes() };
+        write_atomic(path, &bytes)
+    }
+
+    pub fn to_csv(&self) -> String {
+        let stages = self.stage_names();
+        let mut header: Vec<String> = COLUMNS.iter().map(|column| column.to_string()).collect();
+        header.extend(stages.iter().map(|stage| format!("{}_seconds", stage)));
+        let mut csv = header.join(",") + "\n";
+        for image in &self.images {
+            let (dominant, share) = dominant(image);
+            let mut row = vec![
+                csv_field(&image.input.to_string_lossy()),
+                csv_field(&image.output.to_string_lossy()),
+                image.width.to_string(),
+                image.height.to_string(),
+                format!("{:.6}", image.edge_density),
+                image.palette.len().to_string(),
+                dominant.to_string(),
+                format!("{:.6}", share),
+                palette_field(image),
+                format!("{:.6}", seconds(image)),
+            ];
+            // blank where the image ran no such stage, e.g. edges under a style
+            for stage in &stages {
+                row.push(stage_seconds(image, stage).map_or_else(String::new, |seconds| format!("{:.6}", seconds)));
+            }
+            csv += &(row.join(",") + "\n");
+        }
+        csv
+    }
+
+    #[cfg(feature = "parquet")]
+    fn to_parquet(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let images = &self.images;
+        let text = |value: fn(&ImageReport) -> String| -> ArrayRef {
+            Arc::new(StringArray::from_iter_values(images.iter().map(value)))
+        };
+        let number = |value: &dyn Fn(&ImageReport) -> Option<f64>| -> ArrayRef {
+            Arc::new(images.iter().map(value).collect::<Float64Array>())
+        };
+        let mut columns = vec![
+            ("input".to_string(), text(|image| image.input.to_string_lossy().into_owned())),
+            ("output".to_string(), text(|image| image.output.to_string_lossy().into_owned())),
+            ("width".to_string(), Arc::new(Int32Array::from_iter_values(images.iter().map(|image| image.width))) as ArrayRef),
+            ("height".to_string(), Arc::new(Int32Array::from_iter_values(images.iter().map(|image| image.height)))),
+            ("edge_density".to_string(), number(&|image| Some(image.edge_density))),
+            ("colors".to_string(), Arc::new(Int32Array::from_iter_values(images.iter().map(|image| image.palette.len() as i32)))),
+            ("dominant_color".to_string(), text(|image| dominant(image).0.to_string())),
+            ("dominant_share".to_string(), number(&|image| Some(dominant(image).1))),
+            ("palette".to_string(), text(palette_field)),
+            ("seconds".to_string(), number(&|image| Some(seconds(image)))),
+        ];
+        for stage in self.stage_names() {
+            columns.push((format!("{}_seconds", stage), number(&|image| stage_seconds(image, stage))));
+        }
+        let batch = RecordBatch::try_from_iter(columns)?;
+        let mut writer = ArrowWriter::try_new(Vec::new(), batch.schema(), None)?;
+        writer.write(&batch)?;
+        Ok(writer.into_inner()?)
+    }
+
+    #[cfg(not(feature = "parquet"))]
+    fn to_parquet(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        Err("Parquet stats need the parquet feature, write a .csv instead".into())
+    }
+
+    /*
+     * Every stage any image ran, in the order they first ran
+     */
+    fn stage_names(&self) -> Vec<&'static str> {
+        let mut names: Vec<&'static str> = Vec::new();
+        for &(stage, _) in self.images.iter().flat_map(|image| &image.stages) {
+            if !names.contains(&stage) {
+                names.push(stage);
+            }
+        }
+        names
+    }
+}
+
+fn dominant(image: &ImageReport) -> (&str, f64) {
+    image.palette.first().map_or(("", 0.0), |(hex, share)| (hex.as_str(), *share))
+}
+
+/*
+ * palette as `#rrggbb:share` pairs separated by spaces, one field
+ */
+fn palette_field(image: &ImageReport) -> String {
+    image.palette.iter().map(|(hex, share)| format!("{}:{:.4}", hex, share)).collect::<Vec<_>>().join(" ")
+}
+
+fn seconds(image: &ImageReport) -> f64 {
+    image.stages.iter().map(|(_, seconds)| seconds).sum()
+}
+
+fn stage_seconds(image: &ImageReport, stage: &str) -> Option<f64> {
+    image.stages.iter().find(|(name, _)| *name == stage).map(|&(_, seconds)| seconds)
+}
+
+/*
+ * RFC 4180 quoting, only where needed
+ */
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
@@ -0,0 +1,185 @@
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use opencv::core::{Rect, Scalar, Size, CV_32F, CV_8UC3};
+use opencv::dnn::{blob_from_image, read_net_def, Net};
+use opencv::imgproc::{resize, INTER_LINEAR};
+use opencv::prelude::*;
+
+use crate::converter;
+
+/// ImageNet channel means, BGR, subtracted by the Torch fast-neural-style
+/// models.
+const TORCH_MEAN: [f64; 3] = [103.939, 116.779, 123.68];
+
+/// Context fed to the GAN around each tile and cropped from its output, so
+/// that tile borders do not show.
+const TILE_OVERLAP: i32 = 32;
+
+/// Neural stylization replacing the segment-and-outline path; the pre and
+/// post stages still apply.
+///
+/// Parses from and prints as `name:param[:param]`, e.g. `model:candy.onnx`
+/// or `gan:animeganv2.onnx:512`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "kebab-case"))]
+pub enum Style {
+    /// Feed-forward style transfer network (Johnson et al.), as a Torch
+    /// `.t7` from fast-neural-style or an ONNX export such as the model
+    /// zoo's `candy-9.onnx`.
+    Model(PathBuf),
+    /// Cartoonization GAN (CartoonGAN, AnimeGAN) taking and producing RGB in
+    /// [-1, 1]. Larger inputs are processed in overlapping tiles of at most
+    /// `tile` pixels per side.
+    Gan { model: PathBuf, tile: i32 },
+}
+
+impl fmt::Display for Style {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Style::Model(model) => write!(f, "model:{}", model.display()),
+            Style::Gan { model, tile } => write!(f, "gan:{}:{}", model.display(), tile),
+        }
+    }
+}
+
+impl FromStr for Style {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("model", model)) if !model.is_empty() => Ok(Style::Model(model.into())),
+            // the model path may itself contain colons
+            Some(("gan", model)) => {
+                let (model, tile) = match model.rsplit_once(':').map(|(path, t)| (path, t.parse::<i32>())) {
+                    Some((path, Ok(tile))) => (path, tile),
+                    _ => (model, 512),
+                };
+                if tile <= 2 * TILE_OVERLAP {
+                    return Err(format!("GAN tiles must be larger than {} pixels", 2 * TILE_OVERLAP).into());
+                }
+                Ok(Style::Gan { model: model.into(), tile })
+            }
+            _ => Err(format!("unknown style: {}", s).into()),
+        }
+    }
+}
+
+fn load(model: &Path) -> Result<Net, Box<dyn Error>> {
+    let path = model.to_str().ok_or("non UTF-8 model path")?;
+    let net = read_net_def(path)?;
+    if net.empty()? {
+        return Err(format!("failed to load style model {}", path).into());
+    }
+    Ok(net)
+}
+
+/*
+ * BGR image -> stylized BGR image
+ */
+pub(crate) fn transfer(input: &Mat, style: &Style) -> Result<Mat, Box<dyn Error>> {
+    match style {
+        Style::Model(model) => {
+            // Torch models take mean-subtracted BGR, ONNX exports plain RGB
+            let torch = model.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("t7"));
+            let mean = if torch { TORCH_MEAN } else { [0.0; 3] };
+            let mut net = converter::pooled("style", model, || load(model))?;
+            let size = Size::new(input.cols(), input.rows());
+            let blob = blob_from_image(
+                input,
+                1.0,
+                size,
+                Scalar::new(mean[0], mean[1], mean[2], 0.0),
+                !torch,
+                false,
+                CV_32F,
+            )?;
+            net.set_input_def(&blob)?;
+            let output = planes_to_bgr(&net.forward_single_def()?, 1.0, mean, !torch)?;
+            fit(output, size)
+        }
+        Style::Gan { model, tile } => {
+            let mut net = converter::pooled("style", model, || load(model))?;
+            tiled(input, *tile, |patch| {
+                // generators downsample by 4 to 8, so feed a multiple of 8
+                let size = Size::new(patch.cols(), patch.rows());
+                let padded = Size::new((size.width + 7) / 8 * 8, (size.height + 7) / 8 * 8);
+                let blob = blob_from_image(patch, 1.0 / 127.5, padded, Scalar::all(127.5), true, false, CV_32F)?;
+                net.set_input_def(&blob)?;
+                let output = planes_to_bgr(&net.forward_single_def()?, 127.5, [127.5; 3], true)?;
+                fit(output, size)
+            })
+        }
+    }
+}
+
+/// Resizes a network output back to the size of its input; networks may
+/// round sizes to their stride.
+fn fit(output: Mat, size: Size) -> Result<Mat, Box<dyn Error>> {
+    if output.size()? == size {
+        return Ok(output);
+    }
+    let mut resized = Mat::default();
+    resize(&output, &mut resized, size, 0.0, 0.0, INTER_LINEAR)?;
+    Ok(resized)
+}
+
+/*
+ * Runs `stylize` on overlapping tiles of at most `tile` pixels per side and
+ * stitches the tile centers back together.
+ */
+fn tiled(
+    input: &Mat,
+    tile: i32,
+    mut stylize: impl FnMut(&Mat) -> Result<Mat, Box<dyn Error>>,
+) -> Result<Mat, Box<dyn Error>> {
+    if input.cols() <= tile && input.rows() <= tile {
+        return stylize(input);
+    }
+    let step = tile - 2 * TILE_OVERLAP;
+    let mut output = Mat::new_rows_cols_with_default(input.rows(), input.cols(), CV_8UC3, Scalar::all(0.0))?;
+    for y in (0..input.rows()).step_by(step as usize) {
+        for x in (0..input.cols()).step_by(step as usize) {
+            let core = Rect::new(x, y, step.min(input.cols() - x), step.min(input.rows() - y));
+            let x0 = (x - TILE_OVERLAP).max(0);
+            let y0 = (y - TILE_OVERLAP).max(0);
+            let x1 = (core.x + core.width + TILE_OVERLAP).min(input.cols());
+            let y1 = (core.y + core.height + TILE_OVERLAP).min(input.rows());
+            let context = Rect::new(x0, y0, x1 - x0, y1 - y0);
+
+            let stylized = stylize(&Mat::roi(input, context)?.try_clone()?)?;
+            let center = Rect::new(x - x0, y - y0, core.width, core.height);
+            let mut target = Mat::roi_mut(&mut output, core)?;
+            Mat::roi(&stylized, center)?.copy_to(&mut target)?;
+        }
+    }
+    Ok(output)
+}
+
+/*
+ * 1x3xHxW float blob -> BGR image, as `v * scale + mean` and swapped from
+ * RGB when `rgb` is set
+ */
+fn planes_to_bgr(blob: &Mat, scale: f64, mean: [f64; 3], rgb: bool) -> Result<Mat, Box<dyn Error>> {
+    let dims = blob.mat_size();
+    if dims.len() != 4 || dims[1] != 3 {
+        return Err("style model must output a 1x3xHxW image".into());
+    }
+    let (height, width) = (dims[2], dims[3]);
+    let plane = (height * width) as usize;
+    let data = blob.data_typed::<f32>()?;
+    if data.len() != 3 * plane {
+        return Err("style model must output a 1x3xHxW image".into());
+    }
+
+    let mut output = Mat::new_rows_cols_with_default(height, width, CV_8UC3, Scalar::all(0.0))?;
+    for (i, px) in output.data_bytes_mut()?.chunks_exact_mut(3).enumerate() {
+        for c in 0..3 {
+            let v = data[c * plane + i] as f64 * scale + mean[c];
+            px[if rgb { 2 - c } else { c }] = v.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    Ok(output)
+}
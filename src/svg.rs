@@ -0,0 +1,92 @@
+use std::error::Error;
+use std::fmt::Write;
+
+use opencv::core::{Point, Scalar, Vector, CV_8UC1};
+use opencv::imgproc::{approx_poly_dp, contour_area, find_contours, CHAIN_APPROX_SIMPLE, RETR_LIST};
+use opencv::prelude::*;
+
+use crate::palette::{bgr_to_hex, kmeans_palette, palette_indices};
+
+/// SVG output: the color regions and outlines traced into vector paths,
+/// written next to the raster output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct SvgOptions {
+    /// Colors the base is quantized to before tracing.
+    pub colors: usize,
+    /// Maximum distance, in pixels, between a contour and its simplified path.
+    pub epsilon: f64,
+    /// Contours enclosing fewer pixels are dropped.
+    pub min_area: f64,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        SvgOptions { colors: 16, epsilon: 1.5, min_area: 4.0 }
+    }
+}
+
+/*
+ * BGR base + edge mask -> SVG document, one even-odd path per palette color
+ * and one black path for the outlines.
+ */
+pub(crate) fn trace(base: &Mat, edge: &Mat, options: &SvgOptions) -> Result<String, Box<dyn Error>> {
+    let (width, height) = (base.cols(), base.rows());
+    let colors = kmeans_palette(base, options.colors)?;
+    let indices = palette_indices(base, &colors)?;
+
+    let mut counts = vec![0usize; colors.len()];
+    for &i in &indices {
+        counts[i] += 1;
+    }
+    // the most common color fills the canvas, hiding seams between regions
+    let background = (0..colors.len()).max_by_key(|&i| counts[i]).unwrap_or(0);
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\">",
+        width, height
+    )?;
+    writeln!(svg, "<rect width=\"100%\" height=\"100%\" fill=\"{}\"/>", bgr_to_hex(colors[background]))?;
+    for (color, bgr) in colors.iter().enumerate() {
+        if color == background {
+            continue;
+        }
+        let mask: Vec<u8> = indices.iter().map(|&i| if i == color { 255 } else { 0 }).collect();
+        let data = region_path(&mask, width, height, options)?;
+        if !data.is_empty() {
+            writeln!(svg, "<path fill=\"{}\" fill-rule=\"evenodd\" d=\"{}\"/>", bgr_to_hex(*bgr), data)?;
+        }
+    }
+    // outlines are the zeros of the edge mask
+    let outline: Vec<u8> = edge.data_bytes()?.iter().map(|&m| if m == 0 { 255 } else { 0 }).collect();
+    let data = region_path(&outline, width, height, options)?;
+    if !data.is_empty() {
+        writeln!(svg, "<path fill=\"#000000\" fill-rule=\"evenodd\" d=\"{}\"/>", data)?;
+    }
+    svg.push_str("</svg>\n");
+    Ok(svg)
+}
+
+/// Traces a binary mask into path data, outer boundaries and holes alike.
+fn region_path(mask: &[u8], width: i32, height: i32, options: &SvgOptions) -> Result<String, Box<dyn Error>> {
+    let mut image = Mat::new_rows_cols_with_default(height, width, CV_8UC1, Scalar::all(0.0))?;
+    image.data_bytes_mut()?.copy_from_slice(mask);
+    let mut contours = Vector::<Vector<Point>>::new();
+    find_contours(&image, &mut contours, RETR_LIST, CHAIN_APPROX_SIMPLE, Point::new(0, 0))?;
+
+    let mut data = String::new();
+    for contour in &contours {
+        if contour_area(&contour, false)? < options.min_area {
+            continue;
+        }
+        let mut polygon = Vector::<Point>::new();
+        approx_poly_dp(&contour, &mut polygon, options.epsilon, true)?;
+        for (i, point) in polygon.iter().enumerate() {
+            write!(data, "{}{} {}", if i == 0 { "M" } else { "L" }, point.x, point.y)?;
+        }
+        data.push('Z');
+    }
+    Ok(data)
+}
@@ -0,0 +1,125 @@
+//! Tauri commands, behind the `tauri` feature, for desktop apps with a web
+//! frontend. Register them with
+//! `tauri::Builder::default().invoke_handler(nftimg::tauri_commands())`:
+//!
+//! ```js
+//! const { invoke } = window.__TAURI__.core
+//! const output = await invoke('convert', { input: '/photos/cat.jpg', preset: 'comic' })
+//! const png = await invoke('preview', { input: '/photos/cat.jpg', size: 256, recipe: 'spatial_radius=20' })
+//! const presets = await invoke('presets')
+//! ```
+//!
+//! `recipe` takes recipe lines applied on top of the preset; conversions
+//! run off the main thread, errors reject with their message.
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use opencv::core::Size;
+use opencv::imgproc::{resize, INTER_AREA};
+use opencv::prelude::*;
+use tauri::ipc::{Invoke, Response};
+use tauri::{async_runtime, Runtime};
+
+use crate::{convert_file, convert_image, encode_image, load, naming, ConvertOptions};
+
+/// Built-in presets by name, recipe lines applied on top of the defaults.
+pub const PRESETS: &[(&str, &str)] = &[
+    ("default", ""),
+    ("soft", "spatial_radius=20\ncolor_radius=30\nthreshold_c=12\ndilate_iterations=0"),
+    ("bold", "threshold_block_size=15\nthreshold_c=6\ndilate_kernel_size=5\nmin_edge_area=12"),
+    ("comic", "halftone_mode=mono\nadjust_saturation=1.3\nadjust_contrast=1.1"),
+    ("poster", "posterize_colors=8\nadjust_saturation=1.2"),
+    ("sketch", "hatch_spacing=6\nadjust_saturation=0"),
+];
+
+/// One of [`PRESETS`], as the `presets` command lists it.
+#[derive(serde::Serialize)]
+pub struct Preset {
+    pub name: &'static str,
+    pub recipe: &'static str,
+}
+
+/// The `convert`, `preview` and `presets` commands, for
+/// `tauri::Builder::invoke_handler`.
+pub fn tauri_commands<R: Runtime>() -> impl Fn(Invoke<R>) -> bool + Send + Sync + 'static {
+    tauri::generate_handler![convert, preview, presets]
+}
+
+/// Converts the file at `input` and resolves to the output path: `output`
+/// if given, else next to the input like the command line.
+#[tauri::command]
+async fn convert(
+    input: String,
+    output: Option<String>,
+    preset: Option<String>,
+    recipe: Option<String>,
+) -> Result<String, String> {
+    blocking(move || {
+        let options = preset_options(preset.as_deref(), recipe.as_deref())?;
+        let input = Path::new(&input);
+        let Some(output) = output else {
+            return Ok(convert_file(input, &options)?.output_path.display().to_string());
+        };
+        let encoded = crate::convert_buffer(&fs::read(input)?, &options)?;
+        naming::write_atomic(Path::new(&output), &encoded)?;
+        Ok(output)
+    })
+    .await
+}
+
+/// Converts the file at `input` reduced to `size` pixels on its longer
+/// side, for thumbnails, and resolves to the PNG as an `ArrayBuffer`.
+#[tauri::command]
+async fn preview(
+    input: String,
+    size: u32,
+    preset: Option<String>,
+    recipe: Option<String>,
+) -> Result<Response, String> {
+    let png = blocking(move || {
+        let options = preset_options(preset.as_deref(), recipe.as_deref())?;
+        thumbnail(Path::new(&input), size, &options)
+    })
+    .await?;
+    Ok(Response::new(png))
+}
+
+/// Lists the built-in presets.
+#[tauri::command]
+fn presets() -> Vec<Preset> {
+    PRESETS.iter().map(|&(name, recipe)| Preset { name, recipe }).collect()
+}
+
+/*
+ * Runs a conversion on the blocking pool, errors as messages
+ */
+async fn blocking<T: Send + 'static>(
+    run: impl FnOnce() -> Result<T, Box<dyn Error>> + Send + 'static,
+) -> Result<T, String> {
+    async_runtime::spawn_blocking(move || run().map_err(|e| e.to_string())).await.map_err(|e| e.to_string())?
+}
+
+/*
+ * preset name + recipe lines -> ConvertOptions
+ */
+fn preset_options(preset: Option<&str>, recipe: Option<&str>) -> Result<ConvertOptions, Box<dyn Error>> {
+    let base = match preset {
+        Some(name) => PRESETS.iter().find(|(preset, _)| *preset == name).ok_or_else(|| format!("no preset {}", name))?,
+        None => &("", ""),
+    };
+    ConvertOptions::from_recipe(base.1)?.with_recipe(recipe.unwrap_or_default())
+}
+
+/*
+ * image file -> PNG of its conversion at `size` pixels on the longer side
+ */
+fn thumbnail(input: &Path, size: u32, options: &ConvertOptions) -> Result<Vec<u8>, Box<dyn Error>> {
+    let image = load(&fs::read(input)?, input, options)?;
+    let scale = (size as f64 / image.cols().max(image.rows()) as f64).min(1.0);
+    let mut reduced = Mat::default();
+    resize(&image, &mut reduced, Size::default(), scale, scale, INTER_AREA)?;
+    encode_image("png", &convert_image(&reduced, options)?)
+}
+
@@ -0,0 +1,139 @@
+//! Fixtures and comparisons for regression-testing presets, behind the
+//! `test-utils` feature.
+//!
+//! ```ignore
+//! write_fixture(Path::new("target/shapes.png"), Fixture::Shapes, 256, 192)?;
+//! nftimg::convert_with_options("target/shapes.png", &preset)?;
+//! let output = opencv::imgcodecs::imread("target/shapes.nft.png", IMREAD_UNCHANGED)?;
+//! check_golden(&output, Path::new("tests/golden/shapes.png"), 0.0)?;
+//! ```
+
+use std::env;
+use std::error::Error;
+use std::path::Path;
+
+use opencv::core::{absdiff, no_array, norm, psnr, Point, Rect, Scalar, CV_8UC3, NORM_INF};
+use opencv::imgcodecs::IMREAD_UNCHANGED;
+use opencv::imgproc::{circle, line, rectangle, FILLED, LINE_8};
+use opencv::prelude::*;
+
+use crate::{read_image, write_image};
+
+/// Set to rewrite golden files from the current outputs instead of
+/// comparing against them.
+pub const UPDATE_GOLDEN_VAR: &str = "NFTIMG_UPDATE_GOLDEN";
+
+/// Synthetic BGR inputs, the same on every platform.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fixture {
+    /// Blue to the right, green downwards, red diagonally.
+    Gradient,
+    /// Black and white squares of 16 pixels.
+    Checkerboard,
+    /// Flat colored shapes and thin lines on gray, for outline and
+    /// segmentation stages.
+    Shapes,
+}
+
+/// Builds a `width` x `height` fixture image.
+pub fn fixture(kind: Fixture, width: i32, height: i32) -> Result<Mat, Box<dyn Error>> {
+    if width < 1 || height < 1 {
+        return Err(format!("fixture of {}x{} pixels is empty", width, height).into());
+    }
+    let mut image = Mat::new_rows_cols_with_default(height, width, CV_8UC3, Scalar::all(128.0))?;
+    match kind {
+        Fixture::Gradient | Fixture::Checkerboard => {
+            let columns = width as usize;
+            for (i, px) in image.data_bytes_mut()?.chunks_exact_mut(3).enumerate() {
+                let (x, y) = ((i % columns) as i32, (i / columns) as i32);
+                if kind == Fixture::Gradient {
+                    px[0] = (x * 255 / (width - 1).max(1)) as u8;
+                    px[1] = (y * 255 / (height - 1).max(1)) as u8;
+                    px[2] = ((x + y) * 255 / (width + height - 2).max(1)) as u8;
+                } else {
+                    px.fill(if (x / 16 + y / 16) % 2 == 0 { 0 } else { 255 });
+                }
+            }
+        }
+        Fixture::Shapes => {
+            let (w, h) = (width, height);
+            let red = Scalar::new(40.0, 40.0, 220.0, 0.0);
+            let blue = Scalar::new(200.0, 120.0, 30.0, 0.0);
+            let dark = Scalar::all(20.0);
+            rectangle(&mut image, Rect::new(w / 8, h / 8, w * 3 / 8, h * 3 / 8), blue, FILLED, LINE_8, 0)?;
+            circle(&mut image, Point::new(w * 2 / 3, h * 2 / 3), w.min(h) / 4, red, FILLED, LINE_8, 0)?;
+            line(&mut image, Point::new(0, h - 1), Point::new(w - 1, h / 2), dark, 1, LINE_8, 0)?;
+        }
+    }
+    Ok(image)
+}
+
+/// Writes a fixture to `path`, encoded by its extension, as an input for
+/// [`crate::convert_with_options`].
+pub fn write_fixture(path: &Path, kind: Fixture, width: i32, height: i32) -> Result<(), Box<dyn Error>> {
+    write_image(path, &fixture(kind, width, height)?)
+}
+
+/// How far two images are apart.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Difference {
+    /// Largest per-channel absolute difference, 0 for identical images.
+    pub max_abs: f64,
+    /// Peak signal-to-noise ratio in dB; OpenCV reports 361 for identical
+    /// images.
+    pub psnr: f64,
+}
+
+impl Difference {
+    pub fn within(&self, tolerance: f64) -> bool {
+        self.max_abs <= tolerance
+    }
+}
+
+/// Compares two 8-bit images of the same size and type.
+pub fn compare(a: &Mat, b: &Mat) -> Result<Difference, Box<dyn Error>> {
+    if a.size()? != b.size()? || a.typ() != b.typ() {
+        return Err(format!(
+            "cannot compare a {}x{} image of type {} with a {}x{} image of type {}",
+            a.cols(),
+            a.rows(),
+            a.typ(),
+            b.cols(),
+            b.rows(),
+            b.typ()
+        )
+        .into());
+    }
+    let mut diff = Mat::default();
+    absdiff(a, b, &mut diff)?;
+    Ok(Difference { max_abs: norm(&diff, NORM_INF, &no_array())?, psnr: psnr(a, b, 255.0)? })
+}
+
+/// Compares `output` with the golden image at `golden`, failing when a
+/// channel differs by more than `tolerance`.
+///
+/// With [`UPDATE_GOLDEN_VAR`] set the golden file is (re)written from
+/// `output` instead; a missing golden file is an error otherwise, so a
+/// forgotten fixture does not pass silently in CI.
+pub fn check_golden(output: &Mat, golden: &Path, tolerance: f64) -> Result<Difference, Box<dyn Error>> {
+    if env::var_os(UPDATE_GOLDEN_VAR).is_some() {
+        write_image(golden, output)?;
+        return Ok(Difference { max_abs: 0.0, psnr: 361.0 });
+    }
+    if !golden.exists() {
+        return Err(format!("missing golden {}, rerun with {}=1 to create it", golden.display(), UPDATE_GOLDEN_VAR).into());
+    }
+    let expected = read_image(golden, IMREAD_UNCHANGED)?;
+    let difference = compare(output, &expected)?;
+    if !difference.within(tolerance) {
+        return Err(format!(
+            "output differs from {} by up to {} (PSNR {:.1} dB), tolerance {}",
+            golden.display(),
+            difference.max_abs,
+            difference.psnr,
+            tolerance
+        )
+        .into());
+    }
+    Ok(difference)
+}
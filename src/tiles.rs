@@ -0,0 +1,92 @@
+use std::error::Error;
+
+use opencv::core::{Rect, Scalar, CV_8UC3};
+use opencv::prelude::*;
+
+use crate::{convert_image, AsciiMode, ConvertOptions, NftImgError};
+
+/// Context converted around each tile and cropped away again, so smoothing
+/// and outlines near the tile borders see their neighbourhood and the seams
+/// do not show.
+const TILE_OVERLAP: i32 = 32;
+
+/// Converts a decoded 8-bit BGR image tile by tile, row by row, and calls
+/// `on_tile` with each finished tile as soon as it is done: where it goes in
+/// the output and its pixels. Embedders can paint tiles onto a canvas or
+/// stream them to a client for a progressive preview long before the whole
+/// image is converted; an error from `on_tile` stops the conversion. The
+/// assembled output is returned at the end.
+///
+/// Tiles are `tile` pixels per side, fewer along the right and bottom
+/// edges. Options whose stages need the whole image at once (auto mode,
+/// posterize, pixel art, pop art, vignette, upscale and ASCII art images) are
+/// refused.
+///
+/// ```ignore
+/// let output = nftimg::convert_tiles(&image, &options, 256, |rect, tile| {
+///     canvas.draw(rect.x, rect.y, tile);
+///     Ok(())
+/// })?;
+/// ```
+pub fn convert_tiles(
+    image: &Mat,
+    options: &ConvertOptions,
+    tile: i32,
+    mut on_tile: impl FnMut(Rect, &Mat) -> Result<(), Box<dyn Error>>,
+) -> Result<Mat, Box<dyn Error>> {
+    if tile < 1 {
+        return Err(NftImgError::InvalidOption { name: "tile", value: tile.to_string(), expected: "at least 1 pixel" }
+            .into());
+    }
+    if let Some(stage) = whole_image_stage(options) {
+        return Err(format!("{} needs the whole image and cannot be converted in tiles", stage).into());
+    }
+    if image.channels() != 3 {
+        return Err(NftImgError::UnexpectedChannels { expected: 3, found: image.channels() }.into());
+    }
+    let mut output = Mat::new_rows_cols_with_default(image.rows(), image.cols(), CV_8UC3, Scalar::all(0.0))?;
+    for y in (0..image.rows()).step_by(tile as usize) {
+        for x in (0..image.cols()).step_by(tile as usize) {
+            let core = Rect::new(x, y, tile.min(image.cols() - x), tile.min(image.rows() - y));
+            let x0 = (x - TILE_OVERLAP).max(0);
+            let y0 = (y - TILE_OVERLAP).max(0);
+            let x1 = (core.x + core.width + TILE_OVERLAP).min(image.cols());
+            let y1 = (core.y + core.height + TILE_OVERLAP).min(image.rows());
+            let context = Rect::new(x0, y0, x1 - x0, y1 - y0);
+
+            let converted = convert_image(&Mat::roi(image, context)?.try_clone()?, options)?;
+            let center = Rect::new(x - x0, y - y0, core.width, core.height);
+            let finished = Mat::roi(&converted, center)?.try_clone()?;
+            let mut target = Mat::roi_mut(&mut output, core)?;
+            finished.copy_to(&mut target)?;
+            on_tile(core, &finished)?;
+        }
+    }
+    Ok(output)
+}
+
+/*
+ * First enabled stage whose result depends on the whole image: parameters,
+ * a palette, a grid or a size fitted to it, or its position in the frame
+ */
+fn whole_image_stage(options: &ConvertOptions) -> Option<&'static str> {
+    #[cfg(feature = "superres")]
+    if options.upscale.is_some() {
+        return Some("upscale");
+    }
+    if options.auto {
+        Some("auto mode")
+    } else if options.posterize.is_some() {
+        Some("posterize")
+    } else if options.pixelart.is_some() {
+        Some("pixel art")
+    } else if options.popart.is_some() {
+        Some("pop art")
+    } else if options.vignette.is_some() {
+        Some("vignette")
+    } else if options.ascii.as_ref().is_some_and(|a| a.mode == AsciiMode::Image) {
+        Some("ASCII art")
+    } else {
+        None
+    }
+}
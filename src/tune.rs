@@ -0,0 +1,193 @@
+use std::error::Error;
+use std::path::Path;
+
+use opencv::core::Size;
+use opencv::imgcodecs::IMREAD_COLOR;
+use opencv::imgproc::{resize, INTER_AREA};
+use opencv::prelude::*;
+
+use crate::progress;
+use crate::{compare, convert_image, read_image, BlockSize, ConvertOptions, Iterations, NftImgError, Radius};
+
+/// Range each searched parameter is sampled from, in the order of
+/// [`options_at`]. Trials place a point in the unit cube, mapped onto these.
+const SPACE: [(f64, f64); 7] = [
+    (2.0, 40.0), // spatial_radius
+    (4.0, 80.0), // color_radius
+    (1.0, 30.0), // diffusion_iterations
+    (3.0, 31.0), // threshold_block_size
+    (1.0, 20.0), // threshold_c
+    (1.0, 5.0),  // dilate_kernel_size
+    (0.0, 3.0),  // dilate_iterations
+];
+/// Step of the refining trials around the best point, shrinking from the
+/// first to the last.
+const REFINE_STEP: (f64, f64) = (0.5, 0.05);
+
+/// Search budget of [`tune`].
+#[derive(Debug, Clone)]
+pub struct TuneOptions {
+    /// Conversions tried, the starting options included: the first half
+    /// samples the whole range of every parameter, the second half refines
+    /// around the best so far.
+    pub trials: u32,
+    /// Seed of the sampling; the same seed, images and options give the
+    /// same result.
+    pub seed: u64,
+}
+
+impl Default for TuneOptions {
+    fn default() -> TuneOptions {
+        TuneOptions { trials: 48, seed: 1 }
+    }
+}
+
+/// Best options [`tune`] found.
+#[derive(Debug, Clone)]
+pub struct TuneReport {
+    /// The starting options with the searched parameters replaced; save
+    /// [`ConvertOptions::recipe`] as a preset.
+    pub options: ConvertOptions,
+    /// `(1 - SSIM) + RMSE / 255` between their output and the reference; 0
+    /// is a perfect match.
+    pub distance: f64,
+    /// Distance of the starting options, for comparison.
+    pub start_distance: f64,
+}
+
+/// Searches the smoothing and outline parameters for those whose output of
+/// `input` comes closest to `reference`, a stylized version of it made by
+/// hand or by another tool, and returns them as a preset for similar images.
+/// Both are 8-bit BGR; the reference is resized to the output if needed.
+///
+/// The search starts from `base`, whose other options are kept, and runs
+/// `tune_options.trials` full conversions: keep the input small, a crop of
+/// 512 pixels or so, for a search of a few minutes. Each trial is reported
+/// to [`crate::convert_with_progress`] callbacks as the `tune` step.
+pub fn tune(
+    input: &Mat,
+    reference: &Mat,
+    base: &ConvertOptions,
+    tune_options: &TuneOptions,
+) -> Result<TuneReport, Box<dyn Error>> {
+    if tune_options.trials < 1 {
+        return Err(NftImgError::InvalidOption {
+            name: "trials",
+            value: tune_options.trials.to_string(),
+            expected: "at least 1",
+        }
+        .into());
+    }
+    // intermediates of every trial would bury the ones asked for
+    let trial_base = ConvertOptions { debug_dir: None, ..base.clone() };
+    let mut rng = Rng::new(tune_options.seed);
+
+    let start = position(base);
+    let start_distance = distance(&convert_image(input, &options_at(&trial_base, &start)?)?, reference)?;
+    let (mut best, mut best_distance) = (start, start_distance);
+    progress::advance("tune", 1, tune_options.trials);
+    let explore = tune_options.trials / 2;
+    for trial in 1..tune_options.trials {
+        let point = if trial <= explore {
+            [(); SPACE.len()].map(|_| rng.uniform())
+        } else {
+            let t = f64::from(trial - explore) / f64::from((tune_options.trials - explore).max(1));
+            let step = REFINE_STEP.0 + (REFINE_STEP.1 - REFINE_STEP.0) * t;
+            best.map(|x| (x + (rng.uniform() - 0.5) * step).clamp(0.0, 1.0))
+        };
+        let d = distance(&convert_image(input, &options_at(&trial_base, &point)?)?, reference)?;
+        if d < best_distance {
+            (best, best_distance) = (point, d);
+        }
+        progress::advance("tune", trial + 1, tune_options.trials);
+    }
+    Ok(TuneReport { options: options_at(base, &best)?, distance: best_distance, start_distance })
+}
+
+/// [`tune`] on an input and a reference file, decoded as BGR.
+pub fn tune_files(
+    input: &Path,
+    reference: &Path,
+    base: &ConvertOptions,
+    tune_options: &TuneOptions,
+) -> Result<TuneReport, Box<dyn Error>> {
+    let decode = |path: &Path| -> Result<Mat, Box<dyn Error>> {
+        let image = read_image(path, IMREAD_COLOR)?;
+        if image.empty() {
+            return Err(NftImgError::DecodeFailed { path: path.to_path_buf() }.into());
+        }
+        Ok(image)
+    };
+    tune(&decode(input)?, &decode(reference)?, base, tune_options)
+}
+
+/*
+ * (1 - SSIM) + RMSE / 255 of an output against the reference, both in 0..1
+ */
+fn distance(output: &Mat, reference: &Mat) -> Result<f64, Box<dyn Error>> {
+    let report = if output.size()? == reference.size()? {
+        compare(output, reference)?
+    } else {
+        let mut resized = Mat::default();
+        resize(reference, &mut resized, Size::new(output.cols(), output.rows()), 0.0, 0.0, INTER_AREA)?;
+        compare(output, &resized)?
+    };
+    // psnr = 20 log10(255 / rmse)
+    Ok((1.0 - report.ssim) + 10f64.powf(-report.psnr / 20.0))
+}
+
+/*
+ * `base` with the searched parameters at `point`, rounded to what each takes
+ */
+fn options_at(base: &ConvertOptions, point: &[f64; SPACE.len()]) -> Result<ConvertOptions, NftImgError> {
+    let value = |i: usize| SPACE[i].0 + point[i] * (SPACE[i].1 - SPACE[i].0);
+    Ok(ConvertOptions {
+        spatial_radius: Radius::new(value(0))?,
+        color_radius: Radius::new(value(1))?,
+        diffusion_iterations: Iterations::new(value(2).round() as u32)?,
+        threshold_block_size: BlockSize::new(value(3).round() as u16 | 1)?,
+        threshold_c: value(4),
+        dilate_kernel_size: value(5).round() as i32,
+        dilate_iterations: Iterations::optional(value(6).round() as i64)?,
+        ..base.clone()
+    })
+}
+
+/*
+ * Inverse of options_at, values outside the searched ranges clamped
+ */
+fn position(options: &ConvertOptions) -> [f64; SPACE.len()] {
+    let values = [
+        options.spatial_radius.get(),
+        options.color_radius.get(),
+        f64::from(options.diffusion_iterations.get()),
+        f64::from(options.threshold_block_size.get()),
+        options.threshold_c,
+        f64::from(options.dilate_kernel_size),
+        f64::from(options.dilate_iterations.map_or(0, Iterations::get)),
+    ];
+    let mut point = [0.0; SPACE.len()];
+    for (x, (value, (min, max))) in point.iter_mut().zip(values.into_iter().zip(SPACE)) {
+        *x = ((value - min) / (max - min)).clamp(0.0, 1.0);
+    }
+    point
+}
+
+/*
+ * xorshift64*, uniform in 0..1
+ */
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        // the all-zero state never leaves zero
+        Rng(seed.wrapping_add(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    fn uniform(&mut self) -> f64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
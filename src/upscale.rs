@@ -0,0 +1,52 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+use opencv::dnn_superres::DnnSuperResImpl;
+use opencv::prelude::*;
+
+use crate::converter;
+
+/// Algorithms known to dnn_superres, as spelled in the pretrained model names.
+const ALGORITHMS: &[&str] = &["edsr", "espcn", "fsrcnn", "lapsrn"];
+
+/// Super-resolution upscale of the result, for print sizes from small inputs.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UpscaleOptions {
+    /// Pretrained model, e.g. `EDSR_x4.pb` or `FSRCNN_x2.pb`.
+    pub model: PathBuf,
+    /// `edsr`, `espcn`, `fsrcnn` or `lapsrn`.
+    pub algorithm: String,
+    /// Factor the model was trained for, 2 to 4 (8 for LapSRN).
+    pub scale: i32,
+}
+
+impl UpscaleOptions {
+    /// Takes the algorithm and scale from the file name of one of OpenCV's
+    /// pretrained models, `<ALGORITHM>_x<SCALE>.pb`.
+    pub fn from_model(model: PathBuf) -> Result<UpscaleOptions, Box<dyn Error>> {
+        let stem = model.file_stem().and_then(|s| s.to_str()).unwrap_or_default().to_lowercase();
+        let (algorithm, scale) = stem
+            .rsplit_once("_x")
+            .filter(|(algorithm, _)| ALGORITHMS.contains(algorithm))
+            .ok_or_else(|| format!("cannot tell the algorithm and scale of {}", model.display()))?;
+        let scale = scale.parse()?;
+        Ok(UpscaleOptions { algorithm: algorithm.to_string(), scale, model })
+    }
+}
+
+/*
+ * BGR image -> BGR image `scale` times larger
+ */
+pub(crate) fn upscale(input: &Mat, options: &UpscaleOptions) -> Result<Mat, Box<dyn Error>> {
+    let model = options.model.to_str().ok_or("non UTF-8 model path")?;
+    let mut sr = converter::pooled("upscale", &options.model, || {
+        let mut sr = DnnSuperResImpl::create()?;
+        sr.read_model(model)?;
+        Ok(sr)
+    })?;
+    sr.set_model(&options.algorithm, options.scale)?;
+    let mut output = Mat::default();
+    sr.upsample(input, &mut output)?;
+    Ok(output)
+}
@@ -0,0 +1,91 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use ureq::AgentBuilder;
+
+use crate::metrics;
+
+/// Whole-request timeout; a slow receiver must not stall the next job.
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time spent in each stage of a job, summed over its images.
+#[derive(Default)]
+pub(crate) struct Timings {
+    pub elapsed: Duration,
+    pub stages: BTreeMap<&'static str, Duration>,
+}
+
+impl Timings {
+    pub(crate) fn add(&mut self, stage: &'static str, elapsed: Duration) {
+        *self.stages.entry(stage).or_default() += elapsed;
+    }
+}
+
+/// One output of a job: where it is and, when readable here, its SHA-256.
+pub(crate) struct Output {
+    pub location: String,
+    pub sha256: Option<String>,
+}
+
+impl Output {
+    /*
+     * Output written to `location`, hashed if it is a local file
+     */
+    pub(crate) fn written(location: String) -> Output {
+        let sha256 = fs::read(&location).ok().map(|bytes| sha256(&bytes));
+        Output { location, sha256 }
+    }
+
+    pub(crate) fn in_memory(location: String, bytes: &[u8]) -> Output {
+        Output { location, sha256: Some(sha256(bytes)) }
+    }
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/*
+ * Runs `run` and times it and the stages it runs on this thread
+ */
+pub(crate) fn timed<T>(run: impl FnOnce() -> T) -> (T, Timings) {
+    let started = Instant::now();
+    let timings = Rc::new(RefCell::new(Timings::default()));
+    let listener = timings.clone();
+    let result = metrics::with_stage_listener(move |stage, _, elapsed| listener.borrow_mut().add(stage, elapsed), run);
+    let mut timings = timings.take();
+    timings.elapsed = started.elapsed();
+    (result, timings)
+}
+
+/*
+ * POSTs the end of job `id` to `url` as JSON:
+ * `{"id":..,"state":"done"|"failed","outputs":[{"location":..,"sha256":..}],
+ * "error":..,"seconds":..,"stages":{"decode":..}}`. Delivery failures are
+ * reported, never fatal
+ */
+pub(crate) fn notify(url: &str, id: &str, outputs: &[Output], error: Option<String>, timings: &Timings) {
+    let outputs: Vec<Value> = outputs
+        .iter()
+        .map(|output| json!({ "location": output.location, "sha256": output.sha256 }))
+        .collect();
+    let stages: Map<String, Value> =
+        timings.stages.iter().map(|(stage, elapsed)| (stage.to_string(), json!(elapsed.as_secs_f64()))).collect();
+    let payload = json!({
+        "id": id,
+        "state": if error.is_some() { "failed" } else { "done" },
+        "outputs": outputs,
+        "error": error,
+        "seconds": timings.elapsed.as_secs_f64(),
+        "stages": stages,
+    });
+    let agent = AgentBuilder::new().timeout(NOTIFY_TIMEOUT).build();
+    if let Err(e) = agent.post(url).set("Content-Type", "application/json").send_string(&payload.to_string()) {
+        eprintln!("warning: webhook for {} failed: {}", id, e);
+    }
+}
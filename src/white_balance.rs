@@ -0,0 +1,68 @@
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use opencv::prelude::*;
+use opencv::xphoto::{create_grayworld_wb, create_learning_based_wb, create_learning_based_wb_def, create_simple_wb};
+
+/// xphoto white-balance algorithm, run first so that color casts do not
+/// get baked into the flattened colors.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "kebab-case"))]
+pub enum WhiteBalance {
+    /// Stretches each channel to the full range.
+    Simple,
+    /// Assumes the scene averages to gray.
+    #[default]
+    GrayWorld,
+    /// Learned illuminant estimation, with OpenCV's bundled model or a
+    /// trained one.
+    LearningBased { model: Option<PathBuf> },
+}
+
+impl fmt::Display for WhiteBalance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WhiteBalance::Simple => write!(f, "simple"),
+            WhiteBalance::GrayWorld => write!(f, "grayworld"),
+            WhiteBalance::LearningBased { model: None } => write!(f, "learning"),
+            WhiteBalance::LearningBased { model: Some(model) } => write!(f, "learning:{}", model.display()),
+        }
+    }
+}
+
+impl FromStr for WhiteBalance {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("learning", model)) => Ok(WhiteBalance::LearningBased { model: Some(model.into()) }),
+            _ => match s {
+                "simple" => Ok(WhiteBalance::Simple),
+                "grayworld" => Ok(WhiteBalance::GrayWorld),
+                "learning" => Ok(WhiteBalance::LearningBased { model: None }),
+                _ => Err(format!("unknown white balance: {}", s).into()),
+            },
+        }
+    }
+}
+
+/*
+ * BGR image -> white-balanced BGR image
+ */
+pub(crate) fn balance(input: &Mat, algorithm: &WhiteBalance) -> Result<Mat, Box<dyn Error>> {
+    let mut output = Mat::default();
+    match algorithm {
+        WhiteBalance::Simple => create_simple_wb()?.balance_white(input, &mut output)?,
+        WhiteBalance::GrayWorld => create_grayworld_wb()?.balance_white(input, &mut output)?,
+        WhiteBalance::LearningBased { model: None } => {
+            create_learning_based_wb_def()?.balance_white(input, &mut output)?
+        }
+        WhiteBalance::LearningBased { model: Some(model) } => {
+            let model = model.to_str().ok_or("non UTF-8 model path")?;
+            create_learning_based_wb(model)?.balance_white(input, &mut output)?
+        }
+    }
+    Ok(output)
+}
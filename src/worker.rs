@@ -0,0 +1,148 @@
+use std::error::Error;
+use std::path::Path;
+
+use redis::{Client, Connection};
+
+use crate::metrics;
+#[cfg(feature = "webhook")]
+use crate::webhook::{self, Output};
+use crate::{convert_collection, convert_file, ConvertOptions, Converter};
+
+/// Seconds a pop waits for a job before it is retried, keeping idle
+/// connections alive.
+const POP_TIMEOUT_SECONDS: f64 = 5.0;
+
+/// A queued conversion: `id=`, `input=` and optional `output=` lines, the
+/// other lines a recipe applied on top of the worker's options.
+struct Job {
+    id: String,
+    input: String,
+    output: Option<String>,
+    recipe: String,
+}
+
+impl Job {
+    fn parse(payload: &str) -> Result<Job, Box<dyn Error>> {
+        let (mut id, mut input, mut output, mut recipe) = (None, None, None, String::new());
+        for line in payload.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            match line.split_once('=') {
+                Some(("id", value)) => id = Some(value.to_string()),
+                Some(("input", value)) => input = Some(value.to_string()),
+                Some(("output", value)) => output = Some(value.to_string()),
+                // malformed lines are reported by the recipe parser
+                _ => {
+                    recipe.push_str(line);
+                    recipe.push('\n');
+                }
+            }
+        }
+        Ok(Job { id: id.ok_or("job without id=")?, input: input.ok_or("job without input=")?, output, recipe })
+    }
+
+    /*
+     * Converts the input like the command line does and lists the outputs;
+     * `output` is a directory or an object URI prefix
+     */
+    fn run(&self, base: &ConvertOptions) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut options = base.with_recipe(&self.recipe)?;
+        let objects = |uri: &str| uri.starts_with("s3://") || uri.starts_with("gs://");
+        let output_uri = self.output.as_deref().filter(|output| objects(output));
+        if let Some(dir) = self.output.as_deref().filter(|output| !objects(output)) {
+            options.output_dir = Some(dir.into());
+        }
+        if !cfg!(feature = "cloud") && (output_uri.is_some() || objects(&self.input)) {
+            return Err("s3:// and gs:// URIs need the cloud feature".into());
+        }
+
+        #[cfg(feature = "cloud")]
+        if crate::is_object_uri(&self.input) {
+            return crate::convert_objects(&self.input, output_uri, &options);
+        }
+        #[cfg(feature = "http")]
+        if crate::is_url(&self.input) {
+            return Ok(vec![crate::convert_url(&self.input, &options)?.display().to_string()]);
+        }
+        let input = Path::new(&self.input);
+        #[cfg(feature = "cloud")]
+        if let Some(prefix) = output_uri {
+            return Ok(vec![crate::convert_to_object(input, prefix, &options)?]);
+        }
+        if input.is_dir() {
+            let report = convert_collection(input, &options)?;
+            report.write(input)?;
+            if let Some(error) = report.error() {
+                return Err(error.into());
+            }
+            return Ok(report.images.iter().map(|image| image.output.display().to_string()).collect());
+        }
+        Ok(vec![convert_file(input, &options)?.output_path.display().to_string()])
+    }
+}
+
+/// Converts jobs from the Redis list `queue` at `redis_url` until the
+/// process is stopped; `options` are the defaults of every job.
+///
+/// A job is a string of `key=value` lines: `id=`, `input=` (a path, folder,
+/// URL or object URI, as on the command line), optionally `output=` (a
+/// directory or object URI prefix) and any recipe keys. Producers `LPUSH`
+/// jobs; a worker moves each into `<queue>:processing` while it runs, so
+/// several workers can share a queue. Progress is kept in the hash
+/// `<queue>:status` under the job id, as `state=running`, `state=done` with
+/// one `output=` line per output, or `state=failed` with an `error=` line.
+pub fn run_worker(redis_url: &str, queue: &str, options: &ConvertOptions) -> Result<(), Box<dyn Error>> {
+    let mut connection = Client::open(redis_url)?.get_connection()?;
+    // jobs reuse the models earlier ones loaded
+    let converter = Converter::new(options.clone());
+    let processing = format!("{}:processing", queue);
+    let status = format!("{}:status", queue);
+    eprintln!("waiting for jobs on {}", queue);
+    loop {
+        let payload: Option<String> = redis::cmd("BLMOVE")
+            .arg(queue)
+            .arg(&processing)
+            .arg("RIGHT")
+            .arg("LEFT")
+            .arg(POP_TIMEOUT_SECONDS)
+            .query(&mut connection)?;
+        let queued: u64 = redis::cmd("LLEN").arg(queue).query(&mut connection)?;
+        metrics::set_queue_depth(queued);
+        let Some(payload) = payload else { continue };
+        match Job::parse(&payload) {
+            Ok(job) => {
+                println!("job={}", job.id);
+                set_status(&mut connection, &status, &job.id, "state=running\n")?;
+                #[cfg(feature = "webhook")]
+                let (result, timings) = webhook::timed(|| converter.cached(|| job.run(options)));
+                #[cfg(not(feature = "webhook"))]
+                let result = converter.cached(|| job.run(options));
+                metrics::count_request(result.is_err());
+                #[cfg(feature = "webhook")]
+                if let Some(url) = &options.webhook {
+                    let outputs: Vec<Output> = match &result {
+                        Ok(outputs) => outputs.iter().cloned().map(Output::written).collect(),
+                        Err(_) => Vec::new(),
+                    };
+                    webhook::notify(url, &job.id, &outputs, result.as_ref().err().map(|e| e.to_string()), &timings);
+                }
+                let report = match result {
+                    Ok(outputs) => outputs.iter().fold("state=done\n".to_string(), |report, output| {
+                        println!("output={}", output);
+                        report + "output=" + output + "\n"
+                    }),
+                    Err(e) => {
+                        eprintln!("warning: job {} failed: {}", job.id, e);
+                        format!("state=failed\nerror={}\n", e.to_string().replace(['\n', '\r'], " "))
+                    }
+                };
+                set_status(&mut connection, &status, &job.id, &report)?;
+            }
+            Err(e) => eprintln!("warning: dropped malformed job: {}", e),
+        }
+        redis::cmd("LREM").arg(&processing).arg(1).arg(&payload).query::<()>(&mut connection)?;
+    }
+}
+
+fn set_status(connection: &mut Connection, status: &str, id: &str, report: &str) -> Result<(), Box<dyn Error>> {
+    redis::cmd("HSET").arg(status).arg(id).arg(report).query::<()>(connection)?;
+    Ok(())
+}